@@ -6,18 +6,28 @@ use std::path::PathBuf;
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = SimdArg::Auto,
+        help = "SIMD backend (auto = widest the CPU supports)"
+    )]
+    pub simd: SimdArg,
 }
 
 #[derive(Debug, Subcommand)]
 pub enum Commands {
-    Run(RunArgs),
+    Run(Box<RunArgs>),
     Geneset(GenesetArgs),
     Validate(ValidateArgs),
+    Cache(CacheArgs),
 }
 
 #[derive(Debug, Args)]
 pub struct RunArgs {
-    #[arg(long, num_args = 1.., help = "Input directory (10x MTX) or .h5ad file (repeatable)")]
+    #[arg(long, num_args = 1.., help = "Input directory (10x MTX), .h5ad, 10x .h5, .loom or dense CSV/TSV file (repeatable)")]
     pub input: Vec<PathBuf>,
 
     #[arg(long)]
@@ -32,9 +42,51 @@ pub struct RunArgs {
     #[arg(long)]
     pub geneset: Option<PathBuf>,
 
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = GenesetVersionArg::V1,
+        help = "Built-in geneset version"
+    )]
+    pub geneset_version: GenesetVersionArg,
+
+    #[arg(
+        long,
+        help = "Scoring profile JSON with axis and integrated score weights (default: built-in)"
+    )]
+    pub profile: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Risk rules JSON; rules override built-in rules of the same name"
+    )]
+    pub risk_rules: Option<PathBuf>,
+
     #[arg(long, default_value_t = false)]
     pub no_log1p: bool,
 
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = NormalizeArg::Auto,
+        help = "Per-cell normalization applied before scoring (log1p follows unless --no-log1p; auto = cp10k for counts, prenormalized for normalized h5ad matrices)"
+    )]
+    pub normalize: NormalizeArg,
+
+    #[arg(
+        long,
+        default_value = "X",
+        help = "H5AD matrix to score: X, raw or layers/<name>"
+    )]
+    pub h5ad_layer: String,
+
+    #[arg(
+        long,
+        default_value = "Gene Expression",
+        help = "10x feature_type to score (all = keep every feature row)"
+    )]
+    pub feature_type: String,
+
     #[arg(long, default_value_t = false)]
     pub json: bool,
 
@@ -44,9 +96,31 @@ pub struct RunArgs {
     #[arg(long, default_value_t = 0, help = "Number of threads (0 = auto)")]
     pub threads: usize,
 
+    #[arg(
+        long,
+        value_parser = parse_byte_size,
+        help = "Memory budget for building expr.bin from MTX, e.g. 8G or 512M (default: unbounded)"
+    )]
+    pub max_memory: Option<u64>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ExprEncodingArg::Plain,
+        help = "expr.bin encoding: plain (u32 index + f32 value) or compact (varint index, u16 counts)"
+    )]
+    pub expr_encoding: ExprEncodingArg,
+
     #[arg(long, default_value_t = 4096, help = "Cache block size (cells)")]
     pub cache_block: usize,
 
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Fixed-order compensated summation: identical scores for any SIMD backend, thread count or fusion mode"
+    )]
+    pub deterministic: bool,
+
     #[arg(
         long,
         default_value_t = false,
@@ -56,16 +130,77 @@ pub struct RunArgs {
 
     #[arg(
         long,
-        default_value = "off",
-        help = "Enable fusion mode: off|proteo|mito+proteo"
+        value_enum,
+        default_value_t = FusionArg::Proteo,
+        help = "Fused reduction: off reduces each geneset separately; mito+proteo adds the mitochondrial axis"
     )]
-    pub fusion: String,
+    pub fusion: FusionArg,
 
     #[arg(long, value_enum, default_value_t = RunModeArg::Standalone)]
     pub run_mode: RunModeArg,
 
     #[arg(long, help = "Path to shared cache file (kira-organelle.bin)")]
     pub cache: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Directory for expr.bin caches keyed by source fingerprint (default: the output dir)"
+    )]
+    pub cache_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Cluster labels: barcode<TAB>label TSV, or obs:<column> for .h5ad inputs"
+    )]
+    pub clusters: Option<String>,
+
+    #[arg(long, help = "Sample identifier (default: input file/directory name)")]
+    pub sample_id: Option<String>,
+
+    #[arg(long, help = "Condition label for this sample (default: unknown)")]
+    pub condition: Option<String>,
+
+    #[arg(
+        long,
+        help = "Per-barcode metadata TSV with a header: barcode, sample and/or condition"
+    )]
+    pub metadata: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = SpeciesArg::Auto,
+        help = "Species for built-in genesets (auto = infer from gene symbols)"
+    )]
+    pub species: SpeciesArg,
+
+    #[command(flatten)]
+    pub filters: FilterArgs,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct FilterArgs {
+    #[arg(
+        long,
+        help = "Keep only the barcodes listed in this file (one per line, first column)"
+    )]
+    pub cells: Option<PathBuf>,
+
+    #[arg(long, help = "Drop the barcodes listed in this file")]
+    pub exclude_cells: Option<PathBuf>,
+
+    #[arg(long, help = "Drop cells with fewer total counts than this")]
+    pub min_umis: Option<f64>,
+
+    #[arg(long, help = "Drop cells with fewer detected genes than this")]
+    pub min_genes: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "REGEX|FILE",
+        help = "Drop genes listed in FILE (symbols or IDs) or whose symbol matches REGEX"
+    )]
+    pub exclude_genes: Option<String>,
 }
 
 #[derive(Debug, Args)]
@@ -81,11 +216,94 @@ pub enum GenesetCommand {
 
 #[derive(Debug, Args)]
 pub struct ValidateArgs {
-    #[arg(long, help = "Input directory (10x MTX) or .h5ad file")]
+    #[arg(
+        long,
+        help = "Input directory (10x MTX), .h5ad, 10x .h5, .loom or dense CSV/TSV file"
+    )]
     pub input: PathBuf,
 
     #[arg(long, value_enum, default_value_t = RunModeArg::Standalone)]
     pub run_mode: RunModeArg,
+
+    #[arg(
+        long,
+        default_value = "X",
+        help = "H5AD matrix to check: X, raw or layers/<name>"
+    )]
+    pub h5ad_layer: String,
+
+    #[arg(
+        long,
+        default_value = "Gene Expression",
+        help = "10x feature_type to check (all = keep every feature row)"
+    )]
+    pub feature_type: String,
+
+    #[command(flatten)]
+    pub filters: FilterArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    pub command: CacheCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+    Build(CacheBuildArgs),
+    Inspect(CacheInspectArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct CacheBuildArgs {
+    #[arg(long, help = "Input directory (10x MTX), .h5ad or 10x .h5 file")]
+    pub input: PathBuf,
+
+    #[arg(long, help = "Dataset prefix (default: detected from input)")]
+    pub prefix: Option<String>,
+
+    #[arg(
+        long,
+        help = "Output path (default: <prefix>.kira-organelle.bin next to the input)"
+    )]
+    pub out: Option<PathBuf>,
+
+    #[arg(long, help = "Overwrite an existing cache file")]
+    pub force: bool,
+
+    #[arg(
+        long,
+        default_value = "X",
+        help = "H5AD matrix holding raw counts: X, raw or layers/<name>"
+    )]
+    pub h5ad_layer: String,
+
+    #[arg(
+        long,
+        default_value = "Gene Expression",
+        help = "10x feature_type written to the cache (all = keep every feature row)"
+    )]
+    pub feature_type: String,
+}
+
+#[derive(Debug, Args)]
+pub struct CacheInspectArgs {
+    #[arg(
+        long,
+        help = "Shared cache or expr.bin file, or directory to resolve a shared cache in"
+    )]
+    pub input: PathBuf,
+
+    #[arg(long, help = "Dataset prefix used when --input is a directory")]
+    pub prefix: Option<String>,
+
+    #[arg(
+        long,
+        default_value_t = 5,
+        help = "Number of genes and barcodes to list"
+    )]
+    pub sample: usize,
 }
 
 #[derive(Debug, Args)]
@@ -95,6 +313,19 @@ pub struct GenesetShowArgs {
 
     #[arg(long, help = "Optional geneset TSV to overlay on built-in sets")]
     pub geneset: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = SpeciesArg::Auto)]
+    pub species: SpeciesArg,
+
+    #[arg(long, value_enum, default_value_t = GenesetVersionArg::V1)]
+    pub geneset_version: GenesetVersionArg,
+
+    #[arg(
+        long,
+        default_value = "X",
+        help = "H5AD matrix whose genes are resolved: X, raw or layers/<name>"
+    )]
+    pub h5ad_layer: String,
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
@@ -103,8 +334,78 @@ pub enum ModeArg {
     Sample,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NormalizeArg {
+    Auto,
+    Raw,
+    Cp10k,
+    Cpm,
+    Prenormalized,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SpeciesArg {
+    Auto,
+    Human,
+    Mouse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GenesetVersionArg {
+    V1,
+    V2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExprEncodingArg {
+    Plain,
+    Compact,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum RunModeArg {
     Standalone,
     Pipeline,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FusionArg {
+    Off,
+    Proteo,
+    #[value(name = "mito+proteo")]
+    MitoProteo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SimdArg {
+    Auto,
+    Scalar,
+    #[value(name = "sse4.1")]
+    Sse41,
+    Avx2,
+    Avx512,
+    Neon,
+}
+
+// Accepts plain bytes or a K/M/G/T suffix (binary units, optional trailing B).
+pub fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let t = s.trim().to_ascii_uppercase();
+    let t = t.strip_suffix('B').unwrap_or(&t);
+    let (digits, shift) = match t.chars().last() {
+        Some('K') => (&t[..t.len() - 1], 10),
+        Some('M') => (&t[..t.len() - 1], 20),
+        Some('G') => (&t[..t.len() - 1], 30),
+        Some('T') => (&t[..t.len() - 1], 40),
+        _ => (t, 0),
+    };
+    let value = digits
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| format!("invalid size '{}' (expected e.g. 512M or 8G)", s))?;
+    if value == 0 {
+        return Err("size must be greater than zero".to_string());
+    }
+    value
+        .checked_mul(1u64 << shift)
+        .ok_or_else(|| format!("size '{}' is too large", s))
+}
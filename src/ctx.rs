@@ -1,15 +1,26 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
-use anyhow::Context;
 use memmap2::Mmap;
 
-use crate::expr::layout::ExprHeaderV1;
-use crate::expr::reader;
-use crate::expr::reader::ExprReader;
-use crate::geneset::GenesetCollection;
+use crate::expr::cell_qc::CellQc;
+use crate::expr::filter::{ExcludedCell, FilterOptions};
+use crate::expr::layout::{ExprEncoding, ExprHeaderV2};
+use crate::expr::normalize::{CellNormalizer, NormalizationMethod};
+use crate::expr::reader::{ExprReader, GeneSlice};
+use crate::fusion::{FusedReduction, FusionMode};
+use crate::geneset::{GenesetCollection, GenesetVersion};
+use crate::io::clusters::ClusterSource;
+use crate::io::dense::DenseLayout;
+use crate::io::features::{GENE_EXPRESSION, RetainedFeatures};
+use crate::io::h5ad_layer::{H5adLayer, MatrixValues};
+use crate::io::shared_cache::SharedCache;
+use crate::metadata::{SampleMetadata, Species};
+use crate::metrics::mito::MitoAxisResult;
 use crate::metrics::proteostasis_extension::ProteostasisExtensionResult;
-use crate::schema::v1::{Mode, ProteoQcV1};
+use crate::schema::v1::{FilterSummary, Mode, ProteoQcV1};
+use crate::scores::profile::{ScoringProfile, builtin_profile};
+use crate::scores::rules::{RiskRule, builtin_rules};
 use crate::scores::{AxisRawScores, IntegratedScores, PfsContributions, RiskFlag};
 use crate::scores::{TimecourseResult, TimepointSummary};
 
@@ -23,14 +34,30 @@ pub enum RunMode {
 pub enum InputFormat {
     Mtx10x,
     H5ad,
+    // Cell Ranger filtered/raw_feature_bc_matrix.h5
+    Tenx5,
+    Loom,
+    // Dense gene x cell (or cell x gene) CSV/TSV, optionally gzipped
+    DenseText,
 }
 
 impl InputFormat {
     pub fn detect(path: &Path) -> Self {
-        if path.extension().and_then(|s| s.to_str()) == Some("h5ad") {
-            Self::H5ad
-        } else {
-            Self::Mtx10x
+        if path.is_dir() {
+            return Self::Mtx10x;
+        }
+        let name = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let name = name.strip_suffix(".gz").unwrap_or(&name);
+        match Path::new(name).extension().and_then(|s| s.to_str()) {
+            Some("h5ad") => Self::H5ad,
+            Some("h5") => Self::Tenx5,
+            Some("loom") => Self::Loom,
+            Some("csv" | "tsv" | "txt") => Self::DenseText,
+            _ => Self::Mtx10x,
         }
     }
 }
@@ -56,37 +83,75 @@ pub struct Ctx {
     pub mode: Mode,
     pub timecourse: bool,
     pub geneset_path: Option<PathBuf>,
+    pub geneset_version: GenesetVersion,
     pub log1p: bool,
+    pub normalization: NormalizationMethod,
+    pub normalization_auto: bool,
+    pub h5ad_layer: H5adLayer,
+    pub matrix_values: Option<MatrixValues>,
     pub write_json: bool,
     pub write_tsv: bool,
     pub threads: usize,
+    pub max_memory: Option<u64>,
+    pub expr_encoding: ExprEncoding,
     pub cache_block: usize,
     pub prefetch: bool,
-    pub fusion: String,
+    pub fusion: FusionMode,
     pub run_mode: RunMode,
     pub cache_override: Option<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
+    pub clusters: Option<ClusterSource>,
+    pub sample_id: Option<String>,
+    pub condition: Option<String>,
+    pub metadata_path: Option<PathBuf>,
+    pub species: Option<Species>,
+    pub profile: ScoringProfile,
+    pub risk_rules: Vec<RiskRule>,
     pub input_prefix: Option<String>,
     pub genes: Vec<String>,
+    pub gene_ids: Vec<String>,
     pub cells: Vec<String>,
     pub nnz: usize,
     pub gene_index: HashMap<String, usize>,
+    pub gene_id_index: HashMap<String, usize>,
     pub warnings: Vec<String>,
+    // Expected, informational notices; printed like warnings but not held
+    // against confidence.
+    pub notes: Vec<String>,
     pub expr_path: PathBuf,
     pub mtx_matrix_path: Option<PathBuf>,
+    // Entries per kept gene, counted while Stage1 streams the MTX for nnz.
+    pub mtx_gene_counts: Option<Vec<u64>>,
     pub mtx_features_path: Option<PathBuf>,
     pub mtx_barcodes_path: Option<PathBuf>,
+    pub dense_layout: Option<DenseLayout>,
+    pub filters: FilterOptions,
+    pub filter_summary: Option<FilterSummary>,
+    // Cells removed by the filters, in input order; scored cells are `cells`.
+    pub excluded_cells: Vec<ExcludedCell>,
+    pub feature_type: String,
+    // Input row -> gene index when rows were dropped by feature type.
+    pub feature_row_map: Option<Vec<Option<u32>>>,
+    pub antibody_features: Option<RetainedFeatures>,
     pub shared_cache_path: Option<PathBuf>,
     pub shared_cache_used: bool,
-    pub expr_header: Option<ExprHeaderV1>,
+    pub shared_cache: Option<SharedCache>,
+    pub expr_header: Option<ExprHeaderV2>,
     pub expr_mmap: Option<Mmap>,
+    pub cell_qc: CellQc,
+    pub cell_clusters: Option<Vec<String>>,
+    pub metadata: SampleMetadata,
+    pub cell_normalizer: Option<CellNormalizer>,
     pub scratch_cell_buf: Vec<f32>,
-    pub scratch_shards: Vec<f32>,
     pub genesets: Option<GenesetCollection>,
+    // Shared per-cell geneset sums and panel values from the fused pass.
+    pub fused: Option<FusedReduction>,
     pub axis_raw: Option<AxisRawScores>,
     pub integrated_scores: Option<IntegratedScores>,
     pub pfs_contributions: Option<PfsContributions>,
     pub translation_load_z: Option<Vec<f32>>,
     pub proteostasis_extension: Option<ProteostasisExtensionResult>,
+    pub mito_axis: Option<MitoAxisResult>,
     pub risk_flags: Vec<RiskFlag>,
     pub timecourse_points: Vec<TimepointSummary>,
     pub timecourse_result: Option<TimecourseResult>,
@@ -117,37 +182,69 @@ impl Ctx {
             mode,
             timecourse,
             geneset_path,
+            geneset_version: GenesetVersion::default(),
             log1p,
+            normalization: NormalizationMethod::Cp10k,
+            normalization_auto: false,
+            h5ad_layer: H5adLayer::default(),
+            matrix_values: None,
             write_json,
             write_tsv,
             threads: 0,
+            max_memory: None,
+            expr_encoding: ExprEncoding::default(),
             cache_block: 4096,
             prefetch: false,
-            fusion: "off".to_string(),
+            fusion: FusionMode::default(),
             run_mode: RunMode::Standalone,
             cache_override: None,
+            cache_dir: None,
+            clusters: None,
+            sample_id: None,
+            condition: None,
+            metadata_path: None,
+            species: None,
+            profile: builtin_profile().clone(),
+            risk_rules: builtin_rules().to_vec(),
             input_prefix: None,
             genes: Vec::new(),
+            gene_ids: Vec::new(),
             cells: Vec::new(),
             nnz: 0,
             gene_index: HashMap::new(),
+            gene_id_index: HashMap::new(),
             warnings: Vec::new(),
+            notes: Vec::new(),
             expr_path,
             mtx_matrix_path: None,
+            mtx_gene_counts: None,
             mtx_features_path: None,
             mtx_barcodes_path: None,
+            dense_layout: None,
+            filters: FilterOptions::default(),
+            filter_summary: None,
+            excluded_cells: Vec::new(),
+            feature_type: GENE_EXPRESSION.to_string(),
+            feature_row_map: None,
+            antibody_features: None,
             shared_cache_path: None,
             shared_cache_used: false,
+            shared_cache: None,
             expr_header: None,
             expr_mmap: None,
+            cell_qc: CellQc::default(),
+            cell_clusters: None,
+            metadata: SampleMetadata::default(),
+            cell_normalizer: None,
             scratch_cell_buf: Vec::new(),
-            scratch_shards: Vec::new(),
             genesets: None,
+            fused: None,
             axis_raw: None,
             integrated_scores: None,
             pfs_contributions: None,
             translation_load_z: None,
             proteostasis_extension: None,
+            mito_axis: None,
             risk_flags: Vec::new(),
             timecourse_points: Vec::new(),
             timecourse_result: None,
@@ -165,23 +262,18 @@ impl Ctx {
         }
     }
 
-    pub fn gene_slice(&self, gene_id: usize) -> anyhow::Result<(&[u32], &[f32])> {
-        let header = self.expr_header.as_ref().context("expr header missing")?;
-        let mmap = self.expr_mmap.as_ref().context("expr mmap missing")?;
-        if gene_id >= header.n_genes as usize {
-            anyhow::bail!("gene_id out of range");
-        }
-        let gene_ptr = reader::gene_ptr_slice(mmap, header);
-        let cell_idx = reader::cell_idx_slice(mmap, header);
-        let values = reader::values_slice(mmap, header);
-        let start = gene_ptr[gene_id] as usize;
-        let end = gene_ptr[gene_id + 1] as usize;
-        Ok((&cell_idx[start..end], &values[start..end]))
+    pub fn gene_slice(&self, gene_id: usize) -> anyhow::Result<GeneSlice<'_>> {
+        self.expr_reader()?.gene_slice(gene_id)
     }
 
+    // Without an expr.bin, pipeline runs read the shared cache in place.
     pub fn expr_reader(&self) -> anyhow::Result<ExprReader<'_>> {
-        let header = self.expr_header.as_ref().context("expr header missing")?;
-        let mmap = self.expr_mmap.as_ref().context("expr mmap missing")?;
-        Ok(ExprReader::new(header, mmap))
+        let reader = match (&self.expr_header, &self.expr_mmap, &self.shared_cache) {
+            (Some(header), Some(mmap), _) => ExprReader::new(header, mmap),
+            (None, None, Some(cache)) => ExprReader::from_shared_cache(cache),
+            (None, _, _) => anyhow::bail!("expr header missing"),
+            (_, None, _) => anyhow::bail!("expr mmap missing"),
+        };
+        Ok(reader.with_normalizer(self.cell_normalizer.as_ref()))
     }
 }
@@ -0,0 +1,103 @@
+use anyhow::{Result, bail};
+
+use crate::expr::reader::ExprReader;
+
+#[derive(Debug, Clone, Default)]
+pub struct CellQc {
+    pub libsize: Vec<f64>,
+    pub nnz: Vec<u32>,
+    pub expressed_genes: Vec<u32>,
+    pub detected_genes: usize,
+}
+
+impl CellQc {
+    pub fn n_cells(&self) -> usize {
+        self.libsize.len()
+    }
+
+    pub fn total_libsize(&self) -> f64 {
+        self.libsize.iter().sum()
+    }
+
+    pub fn total_nnz(&self) -> u64 {
+        self.nnz.iter().map(|&n| n as u64).sum()
+    }
+
+    pub fn median_libsize(&self) -> f64 {
+        median(self.libsize.clone())
+    }
+
+    pub fn median_expressed_genes(&self) -> f64 {
+        median(self.expressed_genes.iter().map(|&n| n as f64).collect())
+    }
+}
+
+// One pass over the raw gene-major cache; stored explicit zeros count toward
+// nnz but not toward expressed_genes.
+pub fn compute(expr: &ExprReader<'_>) -> Result<CellQc> {
+    if expr.is_cell_major() {
+        return compute_cell_major(expr);
+    }
+    let n_cells = expr.n_cells();
+    let mut libsize = vec![0.0f64; n_cells];
+    let mut nnz = vec![0u32; n_cells];
+    let mut expressed_genes = vec![0u32; n_cells];
+    let mut detected_genes = 0usize;
+    for gene_id in 0..expr.n_genes() {
+        let mut detected = false;
+        for (cell, value) in expr.gene_entries(gene_id)? {
+            if value.is_nan() {
+                bail!("NaN encountered in expr.bin values");
+            }
+            let c = cell as usize;
+            libsize[c] += value as f64;
+            nnz[c] += 1;
+            if value > 0.0 {
+                expressed_genes[c] += 1;
+                detected = true;
+            }
+        }
+        if detected {
+            detected_genes += 1;
+        }
+    }
+    Ok(CellQc {
+        libsize,
+        nnz,
+        expressed_genes,
+        detected_genes,
+    })
+}
+
+// Same accumulation order per cell as the gene-major pass (genes ascending).
+fn compute_cell_major(expr: &ExprReader<'_>) -> Result<CellQc> {
+    let n_cells = expr.n_cells();
+    let mut libsize = vec![0.0f64; n_cells];
+    let mut nnz = vec![0u32; n_cells];
+    let mut expressed_genes = vec![0u32; n_cells];
+    let mut detected = vec![false; expr.n_genes()];
+    for c in 0..n_cells {
+        for (&gene, &count) in expr.cell_entries(c)? {
+            libsize[c] += count as f64;
+            nnz[c] += 1;
+            if count > 0 {
+                expressed_genes[c] += 1;
+                detected[gene as usize] = true;
+            }
+        }
+    }
+    Ok(CellQc {
+        libsize,
+        nnz,
+        expressed_genes,
+        detected_genes: detected.iter().filter(|&&d| d).count(),
+    })
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_unstable_by(f64::total_cmp);
+    values[(values.len() - 1) / 2]
+}
@@ -0,0 +1,288 @@
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+
+use crate::expr::reader::ExprReader;
+use crate::schema::v1::FilterSummary;
+
+#[derive(Debug, Clone, Default)]
+pub struct FilterOptions {
+    pub cells: Option<PathBuf>,
+    pub exclude_cells: Option<PathBuf>,
+    pub min_umis: Option<f64>,
+    pub min_genes: Option<u32>,
+    // Path to a gene list, otherwise a regex matched against symbols.
+    pub exclude_genes: Option<String>,
+}
+
+impl FilterOptions {
+    pub fn is_active(&self) -> bool {
+        self.cells.is_some()
+            || self.exclude_cells.is_some()
+            || self.min_umis.is_some()
+            || self.min_genes.is_some()
+            || self.exclude_genes.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExclusionReason {
+    NotInCells,
+    ExcludeCells,
+    MinUmis,
+    MinGenes,
+}
+
+impl ExclusionReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::NotInCells => "not_in_cells",
+            Self::ExcludeCells => "exclude_cells",
+            Self::MinUmis => "min_umis",
+            Self::MinGenes => "min_genes",
+        }
+    }
+
+    pub fn flag(self) -> &'static str {
+        match self {
+            Self::NotInCells => "EXCLUDED_NOT_IN_CELLS",
+            Self::ExcludeCells => "EXCLUDED_BY_LIST",
+            Self::MinUmis => "EXCLUDED_MIN_UMIS",
+            Self::MinGenes => "EXCLUDED_MIN_GENES",
+        }
+    }
+}
+
+// QC values are over the genes that survive --exclude-genes.
+#[derive(Debug, Clone)]
+pub struct ExcludedCell {
+    pub barcode: String,
+    // Filled from per-barcode metadata before it is narrowed to kept cells.
+    pub sample: String,
+    pub condition: String,
+    pub reason: ExclusionReason,
+    pub libsize: f64,
+    pub nnz: u32,
+    pub expressed_genes: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct FilterPlan {
+    pub gene_keep: Vec<bool>,
+    pub cell_keep: Vec<bool>,
+    // Stored entries of kept genes in kept cells.
+    pub nnz: usize,
+    pub excluded: Vec<ExcludedCell>,
+    pub summary: FilterSummary,
+    pub warnings: Vec<String>,
+}
+
+impl FilterPlan {
+    pub fn gene_map(&self) -> Vec<Option<u32>> {
+        index_map(&self.gene_keep)
+    }
+
+    pub fn cell_map(&self) -> Vec<Option<u32>> {
+        index_map(&self.cell_keep)
+    }
+}
+
+fn index_map(keep: &[bool]) -> Vec<Option<u32>> {
+    let mut next = 0u32;
+    keep.iter()
+        .map(|&k| {
+            k.then(|| {
+                next += 1;
+                next - 1
+            })
+        })
+        .collect()
+}
+
+// Genes are excluded first; per-cell UMIs and detected genes are then taken
+// over the remaining genes, as normalization will see them.
+pub fn plan(
+    options: &FilterOptions,
+    genes: &[String],
+    gene_ids: &[String],
+    cells: &[String],
+    expr: &ExprReader<'_>,
+) -> Result<FilterPlan> {
+    let mut warnings = Vec::new();
+    let gene_keep = match &options.exclude_genes {
+        Some(pattern) => excluded_genes(pattern, genes, gene_ids)?
+            .into_iter()
+            .map(|excluded| !excluded)
+            .collect::<Vec<_>>(),
+        None => vec![true; genes.len()],
+    };
+    let genes_kept = gene_keep.iter().filter(|&&k| k).count();
+    if genes_kept == 0 {
+        bail!("--exclude-genes removed every gene");
+    }
+
+    let n_cells = cells.len();
+    let mut libsize = vec![0.0f64; n_cells];
+    let mut nnz = vec![0u32; n_cells];
+    let mut expressed = vec![0u32; n_cells];
+    let mut add = |cell: usize, value: f32| {
+        libsize[cell] += value as f64;
+        nnz[cell] += 1;
+        if value > 0.0 {
+            expressed[cell] += 1;
+        }
+    };
+    if expr.is_cell_major() {
+        for cell in 0..n_cells {
+            for (&gene, &count) in expr.cell_entries(cell)? {
+                if gene_keep[gene as usize] {
+                    add(cell, count as f32);
+                }
+            }
+        }
+    } else {
+        for (gene, _) in gene_keep.iter().enumerate().filter(|(_, k)| **k) {
+            for (cell, value) in expr.gene_entries(gene)? {
+                add(cell as usize, value);
+            }
+        }
+    }
+
+    let listed = match &options.cells {
+        Some(path) => Some(read_barcode_list(path, "--cells", cells, &mut warnings)?),
+        None => None,
+    };
+    let excluded_list = match &options.exclude_cells {
+        Some(path) => Some(read_barcode_list(
+            path,
+            "--exclude-cells",
+            cells,
+            &mut warnings,
+        )?),
+        None => None,
+    };
+
+    let mut summary = FilterSummary {
+        cells_input: n_cells as u64,
+        genes_input: genes.len() as u64,
+        genes_kept: genes_kept as u64,
+        genes_removed_by_exclude_genes: (genes.len() - genes_kept) as u64,
+        ..FilterSummary::default()
+    };
+    let mut cell_keep = vec![true; n_cells];
+    let mut excluded = Vec::new();
+    for (c, barcode) in cells.iter().enumerate() {
+        let reason = if listed.as_ref().is_some_and(|l| !l.contains(barcode)) {
+            summary.removed_by_cells += 1;
+            ExclusionReason::NotInCells
+        } else if excluded_list.as_ref().is_some_and(|l| l.contains(barcode)) {
+            summary.removed_by_exclude_cells += 1;
+            ExclusionReason::ExcludeCells
+        } else if options.min_umis.is_some_and(|min| libsize[c] < min) {
+            summary.removed_by_min_umis += 1;
+            ExclusionReason::MinUmis
+        } else if options.min_genes.is_some_and(|min| expressed[c] < min) {
+            summary.removed_by_min_genes += 1;
+            ExclusionReason::MinGenes
+        } else {
+            continue;
+        };
+        cell_keep[c] = false;
+        excluded.push(ExcludedCell {
+            barcode: barcode.clone(),
+            sample: String::new(),
+            condition: String::new(),
+            reason,
+            libsize: libsize[c],
+            nnz: nnz[c],
+            expressed_genes: expressed[c],
+        });
+    }
+    summary.cells_kept = (n_cells - excluded.len()) as u64;
+    if summary.cells_kept == 0 {
+        bail!("cell filters removed every cell");
+    }
+
+    let kept_nnz = nnz
+        .iter()
+        .zip(&cell_keep)
+        .filter(|(_, k)| **k)
+        .map(|(&n, _)| n as usize)
+        .sum();
+    Ok(FilterPlan {
+        gene_keep,
+        cell_keep,
+        nnz: kept_nnz,
+        excluded,
+        summary,
+        warnings,
+    })
+}
+
+// An existing file is read as a gene list (symbols or IDs, one per line);
+// anything else must be a regex over gene symbols.
+fn excluded_genes(pattern: &str, genes: &[String], gene_ids: &[String]) -> Result<Vec<bool>> {
+    let path = Path::new(pattern);
+    if path.is_file() {
+        let names = read_list(path)?;
+        return Ok(genes
+            .iter()
+            .enumerate()
+            .map(|(i, g)| names.contains(g) || gene_ids.get(i).is_some_and(|id| names.contains(id)))
+            .collect());
+    }
+    let regex = Regex::new(pattern).with_context(|| {
+        format!(
+            "--exclude-genes '{}' is neither a file nor a regex",
+            pattern
+        )
+    })?;
+    Ok(genes.iter().map(|g| regex.is_match(g)).collect())
+}
+
+fn read_barcode_list(
+    path: &Path,
+    flag: &str,
+    cells: &[String],
+    warnings: &mut Vec<String>,
+) -> Result<HashSet<String>> {
+    let listed = read_list(path)?;
+    let known = cells.iter().map(String::as_str).collect::<HashSet<_>>();
+    let missing = listed
+        .iter()
+        .filter(|b| !known.contains(b.as_str()))
+        .count();
+    if missing > 0 {
+        warnings.push(format!(
+            "{} barcodes from {} {} are not in the input",
+            missing,
+            flag,
+            path.display()
+        ));
+    }
+    Ok(listed)
+}
+
+// One name per line; the first tab/comma field is used so QC tables can be
+// passed as-is. Blank lines and '#' comments are skipped.
+fn read_list(path: &Path) -> Result<HashSet<String>> {
+    let reader = kira_scio::open_maybe_gz_existing(path).map_err(|e| anyhow::anyhow!(e.message))?;
+    let mut names = HashSet::new();
+    for line in reader.lines() {
+        let line = line.with_context(|| format!("failed to read {}", path.display()))?;
+        let name = line
+            .split(['\t', ','])
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .trim_matches('"');
+        if name.is_empty() || name.starts_with('#') {
+            continue;
+        }
+        names.insert(name.to_string());
+    }
+    Ok(names)
+}
@@ -0,0 +1,79 @@
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+
+use crate::ctx::{Ctx, InputFormat};
+use crate::expr::filter::FilterPlan;
+use crate::io::features::GENE_EXPRESSION;
+use crate::io::h5ad_layer::H5adLayer;
+
+// Identifies what an expr cache was built from: the source file set with
+// sizes and modification times. Swapping or rewriting any input changes it.
+pub fn source_fingerprint(ctx: &Ctx) -> Result<u64> {
+    let (kind, files) = source_files(ctx);
+    let mut key = format!("kira-proteoqc expr v2\n{}\n", kind);
+    if ctx.input_format == InputFormat::H5ad
+        && ctx.shared_cache_path.is_none()
+        && ctx.h5ad_layer != H5adLayer::X
+    {
+        writeln!(key, "layer\t{}", ctx.h5ad_layer.matrix_path())?;
+    }
+    if ctx.shared_cache_path.is_none() && ctx.feature_type != GENE_EXPRESSION {
+        writeln!(key, "feature_type\t{}", ctx.feature_type)?;
+    }
+    for path in files {
+        let meta = std::fs::metadata(&path)
+            .with_context(|| format!("failed to stat {}", path.display()))?;
+        let mtime = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let path = path.canonicalize().unwrap_or(path);
+        writeln!(key, "{}\t{}\t{}", path.display(), meta.len(), mtime)?;
+    }
+    Ok(kira_shared_sc_cache::crc64_ecma(key.as_bytes()))
+}
+
+fn source_files(ctx: &Ctx) -> (&'static str, Vec<PathBuf>) {
+    if let Some(cache) = &ctx.shared_cache_path {
+        return ("shared_cache", vec![cache.clone()]);
+    }
+    match ctx.input_format {
+        InputFormat::Mtx10x => (
+            "mtx",
+            [
+                &ctx.mtx_matrix_path,
+                &ctx.mtx_features_path,
+                &ctx.mtx_barcodes_path,
+            ]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect(),
+        ),
+        InputFormat::H5ad => ("h5ad", vec![ctx.input.clone()]),
+        InputFormat::Tenx5 => ("tenx_h5", vec![ctx.input.clone()]),
+        InputFormat::Loom => ("loom", vec![ctx.input.clone()]),
+        InputFormat::DenseText => ("dense", vec![ctx.input.clone()]),
+    }
+}
+
+// A filtered cache is keyed by its source and the exact gene and cell masks,
+// so changed lists or thresholds that keep the same cells still reuse it.
+pub fn filtered_fingerprint(source: u64, plan: &FilterPlan) -> u64 {
+    let mut key = b"kira-proteoqc expr filtered\n".to_vec();
+    key.extend_from_slice(&source.to_le_bytes());
+    for mask in [&plan.gene_keep, &plan.cell_keep] {
+        key.extend_from_slice(&(mask.len() as u64).to_le_bytes());
+        key.extend(mask.iter().map(|&k| k as u8));
+    }
+    kira_shared_sc_cache::crc64_ecma(&key)
+}
+
+pub fn cache_file_name(fingerprint: u64) -> String {
+    format!("expr-{:016x}.bin", fingerprint)
+}
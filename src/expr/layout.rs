@@ -4,8 +4,48 @@ use anyhow::{Context, Result, bail};
 
 pub const MAGIC: [u8; 8] = *b"KIRAEXPR";
 pub const VERSION: u32 = 1;
+pub const VERSION_V2: u32 = 2;
 pub const LAYOUT_CSC: u32 = 1;
 pub const HEADER_SIZE: usize = 32;
+pub const HEADER_SIZE_V2: usize = 56;
+pub const DATA_CRC_OFFSET_V2: usize = 48;
+
+// Stored values are exactly what the input holds; --normalize is applied when
+// reading, so one cache serves every normalization setting.
+pub const NORMALIZATION_NONE: u32 = 0;
+
+// Bit flags in the v2 header word after normalization. Zero keeps the plain
+// u32 cell index + f32 value arrays.
+pub const ENCODING_PLAIN: u32 = 0;
+pub const ENCODING_VALUES_U16: u32 = 1;
+pub const ENCODING_INDEX_VARINT: u32 = 2;
+
+// Compact files carry section sizes (n_blocks, index_bytes, n_overflow)
+// right after the fixed header.
+pub const COMPACT_DIR_SIZE: usize = 24;
+
+// Cell indices are delta+varint coded in blocks of this many entries per
+// gene; each block restarts from an absolute first cell so readers can seek.
+pub const INDEX_BLOCK_LEN: usize = 128;
+
+// Counts that do not fit below this marker live in the overflow side-table.
+pub const COUNT_OVERFLOW: u16 = u16::MAX;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExprEncoding {
+    #[default]
+    Plain,
+    Compact,
+}
+
+impl ExprEncoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExprEncoding::Plain => "plain",
+            ExprEncoding::Compact => "compact",
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ExprHeaderV1 {
@@ -16,27 +56,146 @@ pub struct ExprHeaderV1 {
     pub layout: u32,
 }
 
-impl ExprHeaderV1 {
+// v2 appends provenance: what the values were built from and a CRC64 over
+// everything after the header. Headers read from v1 files carry zeros there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExprHeaderV2 {
+    pub version: u32,
+    pub n_genes: u32,
+    pub n_cells: u32,
+    pub nnz: u64,
+    pub layout: u32,
+    pub normalization: u32,
+    pub source_fingerprint: u64,
+    pub data_crc64: u64,
+    pub encoding: u32,
+    pub n_blocks: u64,
+    pub index_bytes: u64,
+    pub n_overflow: u64,
+}
+
+impl From<ExprHeaderV1> for ExprHeaderV2 {
+    fn from(h: ExprHeaderV1) -> Self {
+        Self {
+            version: h.version,
+            n_genes: h.n_genes,
+            n_cells: h.n_cells,
+            nnz: h.nnz,
+            layout: h.layout,
+            normalization: NORMALIZATION_NONE,
+            source_fingerprint: 0,
+            data_crc64: 0,
+            encoding: ENCODING_PLAIN,
+            n_blocks: 0,
+            index_bytes: 0,
+            n_overflow: 0,
+        }
+    }
+}
+
+impl ExprHeaderV2 {
+    pub fn new(n_genes: u32, n_cells: u32, nnz: u64, source_fingerprint: u64) -> Self {
+        Self {
+            version: VERSION_V2,
+            n_genes,
+            n_cells,
+            nnz,
+            layout: LAYOUT_CSC,
+            normalization: NORMALIZATION_NONE,
+            source_fingerprint,
+            data_crc64: 0,
+            encoding: ENCODING_PLAIN,
+            n_blocks: 0,
+            index_bytes: 0,
+            n_overflow: 0,
+        }
+    }
+
+    pub fn header_size(&self) -> usize {
+        if self.version == VERSION {
+            HEADER_SIZE
+        } else if self.encoding != ENCODING_PLAIN {
+            HEADER_SIZE_V2 + COMPACT_DIR_SIZE
+        } else {
+            HEADER_SIZE_V2
+        }
+    }
+
+    pub fn values_u16(&self) -> bool {
+        self.encoding & ENCODING_VALUES_U16 != 0
+    }
+
+    pub fn index_varint(&self) -> bool {
+        self.encoding & ENCODING_INDEX_VARINT != 0
+    }
+
     pub fn expected_len(&self) -> usize {
-        let gene_ptr_bytes = (self.n_genes as usize + 1) * 8;
-        let cell_idx_bytes = self.nnz as usize * 4;
-        let values_bytes = self.nnz as usize * 4;
-        HEADER_SIZE + gene_ptr_bytes + cell_idx_bytes + values_bytes
+        if self.values_u16() {
+            self.counts_offset() + self.nnz as usize * 2
+        } else {
+            self.values_offset() + self.nnz as usize * 4
+        }
     }
 
     pub fn gene_ptr_offset(&self) -> usize {
-        HEADER_SIZE
+        self.header_size()
     }
 
+    // Plain index: nnz u32 cell indices.
     pub fn cell_idx_offset(&self) -> usize {
         self.gene_ptr_offset() + (self.n_genes as usize + 1) * 8
     }
 
+    // Varint index: per-gene first block, per-block byte offsets into the
+    // index stream, per-block first cell, then the delta stream itself.
+    pub fn gene_block_ptr_offset(&self) -> usize {
+        self.cell_idx_offset()
+    }
+
+    pub fn block_ptr_offset(&self) -> usize {
+        self.gene_block_ptr_offset() + (self.n_genes as usize + 1) * 8
+    }
+
+    pub fn block_first_offset(&self) -> usize {
+        self.block_ptr_offset() + (self.n_blocks as usize + 1) * 8
+    }
+
+    pub fn index_stream_offset(&self) -> usize {
+        self.block_first_offset() + pad8(self.n_blocks as usize * 4)
+    }
+
+    fn index_end(&self) -> usize {
+        if self.index_varint() {
+            self.index_stream_offset() + pad8(self.index_bytes as usize)
+        } else {
+            self.cell_idx_offset() + self.nnz as usize * 4
+        }
+    }
+
+    // Plain values: nnz f32.
     pub fn values_offset(&self) -> usize {
-        self.cell_idx_offset() + self.nnz as usize * 4
+        self.index_end()
+    }
+
+    // u16 values: overflow entry positions (u64) and counts (u32), then the
+    // nnz u16 counts where COUNT_OVERFLOW defers to the side-table.
+    pub fn overflow_pos_offset(&self) -> usize {
+        pad8(self.index_end())
+    }
+
+    pub fn overflow_val_offset(&self) -> usize {
+        self.overflow_pos_offset() + self.n_overflow as usize * 8
+    }
+
+    pub fn counts_offset(&self) -> usize {
+        self.overflow_val_offset() + pad8(self.n_overflow as usize * 4)
     }
 }
 
+fn pad8(n: usize) -> usize {
+    n.div_ceil(8) * 8
+}
+
 pub fn write_header<W: Write>(mut w: W, header: &ExprHeaderV1) -> Result<()> {
     w.write_all(&MAGIC)?;
     w.write_all(&header.version.to_le_bytes())?;
@@ -47,14 +206,33 @@ pub fn write_header<W: Write>(mut w: W, header: &ExprHeaderV1) -> Result<()> {
     Ok(())
 }
 
-pub fn read_header<R: Read>(mut r: R) -> Result<ExprHeaderV1> {
+pub fn write_header_v2<W: Write>(mut w: W, header: &ExprHeaderV2) -> Result<()> {
+    w.write_all(&MAGIC)?;
+    w.write_all(&VERSION_V2.to_le_bytes())?;
+    w.write_all(&header.n_genes.to_le_bytes())?;
+    w.write_all(&header.n_cells.to_le_bytes())?;
+    w.write_all(&header.nnz.to_le_bytes())?;
+    w.write_all(&header.layout.to_le_bytes())?;
+    w.write_all(&header.normalization.to_le_bytes())?;
+    w.write_all(&header.encoding.to_le_bytes())?;
+    w.write_all(&header.source_fingerprint.to_le_bytes())?;
+    w.write_all(&header.data_crc64.to_le_bytes())?;
+    if header.encoding != ENCODING_PLAIN {
+        w.write_all(&header.n_blocks.to_le_bytes())?;
+        w.write_all(&header.index_bytes.to_le_bytes())?;
+        w.write_all(&header.n_overflow.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn read_header<R: Read>(mut r: R) -> Result<ExprHeaderV2> {
     let mut magic = [0u8; 8];
     r.read_exact(&mut magic)?;
     if magic != MAGIC {
         bail!("expr.bin magic mismatch");
     }
     let version = read_u32(&mut r)?;
-    if version != VERSION {
+    if version != VERSION && version != VERSION_V2 {
         bail!("unsupported expr.bin version {}", version);
     }
     let n_genes = read_u32(&mut r)?;
@@ -64,13 +242,36 @@ pub fn read_header<R: Read>(mut r: R) -> Result<ExprHeaderV1> {
     if layout != LAYOUT_CSC {
         bail!("unsupported expr.bin layout {}", layout);
     }
-    Ok(ExprHeaderV1 {
+    let v1 = ExprHeaderV1 {
         version,
         n_genes,
         n_cells,
         nnz,
         layout,
-    })
+    };
+    if version == VERSION {
+        return Ok(v1.into());
+    }
+    let normalization = read_u32(&mut r)?;
+    let encoding = read_u32(&mut r)?;
+    if encoding & !(ENCODING_VALUES_U16 | ENCODING_INDEX_VARINT) != 0 {
+        bail!("unsupported expr.bin encoding {}", encoding);
+    }
+    let source_fingerprint = read_u64(&mut r)?;
+    let data_crc64 = read_u64(&mut r)?;
+    let mut header = ExprHeaderV2 {
+        normalization,
+        source_fingerprint,
+        data_crc64,
+        encoding,
+        ..v1.into()
+    };
+    if encoding != ENCODING_PLAIN {
+        header.n_blocks = read_u64(&mut r)?;
+        header.index_bytes = read_u64(&mut r)?;
+        header.n_overflow = read_u64(&mut r)?;
+    }
+    Ok(header)
 }
 
 fn read_u32<R: Read>(mut r: R) -> Result<u32> {
@@ -1,6 +1,10 @@
+pub mod cell_qc;
+pub mod filter;
+pub mod fingerprint;
 pub mod layout;
+pub mod normalize;
 pub mod prefetch;
 pub mod reader;
 pub mod writer;
 
-pub use layout::ExprHeaderV1;
+pub use layout::{ExprHeaderV1, ExprHeaderV2};
@@ -0,0 +1,116 @@
+use crate::io::h5ad_layer::MatrixValues;
+use crate::schema::v1::Normalization;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalizationMethod {
+    Raw,
+    Cp10k,
+    Cpm,
+    Prenormalized,
+}
+
+impl NormalizationMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Raw => "raw",
+            Self::Cp10k => "cp10k",
+            Self::Cpm => "cpm",
+            Self::Prenormalized => "prenormalized",
+        }
+    }
+
+    pub fn target_sum(self) -> Option<f32> {
+        match self {
+            Self::Cp10k => Some(1.0e4),
+            Self::Cpm => Some(1.0e6),
+            Self::Raw | Self::Prenormalized => None,
+        }
+    }
+
+    // Pre-normalized inputs are consumed as-is; log1p is never stacked on top.
+    pub fn applies_log1p(self, log1p: bool) -> bool {
+        log1p && self != Self::Prenormalized
+    }
+}
+
+// `--normalize auto`: counts get CP10k; a matrix that is already normalized
+// is consumed as-is instead of being rescaled and log-transformed twice.
+pub fn auto_method(values: MatrixValues) -> NormalizationMethod {
+    match values {
+        MatrixValues::Counts => NormalizationMethod::Cp10k,
+        MatrixValues::Normalized => NormalizationMethod::Prenormalized,
+    }
+}
+
+// Applies `--normalize auto` to an input whose stored values were sampled.
+// An explicit rescaling method forced onto normalized values is kept but
+// reported, naming the matrix in `source`.
+pub fn resolve_for_values(
+    method: &mut NormalizationMethod,
+    auto: bool,
+    values: MatrixValues,
+    source: &str,
+) -> Option<String> {
+    if auto {
+        *method = auto_method(values);
+        return None;
+    }
+    if values == MatrixValues::Normalized && method.target_sum().is_some() {
+        return Some(format!(
+            "{} holds non-integer values but --normalize {} rescales them as counts",
+            source,
+            method.as_str()
+        ));
+    }
+    None
+}
+
+#[derive(Debug, Clone)]
+pub struct CellNormalizer {
+    scale: Option<Vec<f32>>,
+    log1p: bool,
+}
+
+impl CellNormalizer {
+    pub fn new(method: NormalizationMethod, log1p: bool, library_sizes: &[f64]) -> Option<Self> {
+        let scale = method.target_sum().map(|target| {
+            library_sizes
+                .iter()
+                .map(|&total| {
+                    if total > 0.0 {
+                        (target as f64 / total) as f32
+                    } else {
+                        0.0
+                    }
+                })
+                .collect::<Vec<f32>>()
+        });
+        let log1p = method.applies_log1p(log1p);
+        if scale.is_none() && !log1p {
+            return None;
+        }
+        Some(Self { scale, log1p })
+    }
+
+    #[inline]
+    pub fn apply(&self, cell: u32, value: f32) -> f32 {
+        let scaled = match &self.scale {
+            Some(scale) => value * scale[cell as usize],
+            None => value,
+        };
+        if self.log1p { scaled.ln_1p() } else { scaled }
+    }
+}
+
+pub fn describe(method: NormalizationMethod, log1p: bool) -> Normalization {
+    Normalization {
+        method: method.as_str().to_string(),
+        log1p: method.applies_log1p(log1p),
+        cp10k: method == NormalizationMethod::Cp10k,
+        cpm: method == NormalizationMethod::Cpm,
+        raw: matches!(
+            method,
+            NormalizationMethod::Raw | NormalizationMethod::Prenormalized
+        ),
+    }
+}
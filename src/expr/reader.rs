@@ -1,49 +1,386 @@
+use std::borrow::Cow;
 use std::fs::File;
 use std::path::Path;
+use std::slice;
 
 use anyhow::{Context, Result, bail};
 use memmap2::Mmap;
 
-use crate::expr::layout::{ExprHeaderV1, HEADER_SIZE, read_header};
+use crate::expr::layout::{
+    COUNT_OVERFLOW, ENCODING_PLAIN, ExprHeaderV2, HEADER_SIZE, HEADER_SIZE_V2, INDEX_BLOCK_LEN,
+    VERSION, read_header,
+};
+use crate::expr::normalize::CellNormalizer;
+use crate::io::shared_cache::SharedCache;
+
+pub type GeneSlice<'a> = (Cow<'a, [u32]>, Cow<'a, [f32]>);
 
 pub struct ExprReader<'a> {
-    header: &'a ExprHeaderV1,
-    mmap: &'a Mmap,
+    source: ExprSource<'a>,
+    normalizer: Option<&'a CellNormalizer>,
+}
+
+// expr.bin is gene-major; the shared cache is read in place, cell-major.
+#[derive(Clone, Copy)]
+enum ExprSource<'a> {
+    GeneMajor {
+        header: &'a ExprHeaderV2,
+        mmap: &'a Mmap,
+    },
+    CellMajor(&'a SharedCache),
 }
 
 impl<'a> ExprReader<'a> {
-    pub fn new(header: &'a ExprHeaderV1, mmap: &'a Mmap) -> Self {
-        Self { header, mmap }
+    pub fn new(header: &'a ExprHeaderV2, mmap: &'a Mmap) -> Self {
+        Self {
+            source: ExprSource::GeneMajor { header, mmap },
+            normalizer: None,
+        }
+    }
+
+    pub fn from_shared_cache(cache: &'a SharedCache) -> Self {
+        Self {
+            source: ExprSource::CellMajor(cache),
+            normalizer: None,
+        }
+    }
+
+    pub fn with_normalizer(mut self, normalizer: Option<&'a CellNormalizer>) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    // gene_slice returns raw stored values; reducers pass them through here.
+    #[inline]
+    pub fn value(&self, cell: u32, raw: f32) -> f32 {
+        match self.normalizer {
+            Some(n) => n.apply(cell, raw),
+            None => raw,
+        }
     }
 
     pub fn n_cells(&self) -> usize {
-        self.header.n_cells as usize
+        match self.source {
+            ExprSource::GeneMajor { header, .. } => header.n_cells as usize,
+            ExprSource::CellMajor(cache) => cache.header.n_cells as usize,
+        }
     }
 
     pub fn n_genes(&self) -> usize {
-        self.header.n_genes as usize
+        match self.source {
+            ExprSource::GeneMajor { header, .. } => header.n_genes as usize,
+            ExprSource::CellMajor(cache) => cache.header.n_genes as usize,
+        }
+    }
+
+    pub fn nnz(&self) -> usize {
+        match self.source {
+            ExprSource::GeneMajor { header, .. } => header.nnz as usize,
+            ExprSource::CellMajor(cache) => cache.header.nnz as usize,
+        }
+    }
+
+    pub fn is_cell_major(&self) -> bool {
+        matches!(self.source, ExprSource::CellMajor(_))
+    }
+
+    // Borrowed straight from the mmap for plain files; other sources are
+    // decoded into owned buffers. Hot loops should prefer gene_entries.
+    pub fn gene_slice(&self, gene_id: usize) -> Result<GeneSlice<'a>> {
+        if let ExprSource::GeneMajor { header, mmap } = self.source
+            && header.encoding == ENCODING_PLAIN
+        {
+            let (start, end) = gene_range(header, mmap, gene_id)?;
+            let cell_idx = cell_idx_slice(mmap, header);
+            let values = values_slice(mmap, header);
+            return Ok((
+                Cow::Borrowed(&cell_idx[start..end]),
+                Cow::Borrowed(&values[start..end]),
+            ));
+        }
+        let (cells, values) = self.gene_entries(gene_id)?.unzip();
+        Ok((Cow::Owned(cells), Cow::Owned(values)))
+    }
+
+    // (gene, raw value) pairs of one cell in gene order; cell-major only.
+    pub fn cell_entries(&self, cell: usize) -> Result<CellEntries<'a>> {
+        let ExprSource::CellMajor(cache) = self.source else {
+            bail!("cell_entries needs a cell-major expression source");
+        };
+        if cell >= self.n_cells() {
+            bail!("cell out of range");
+        }
+        let (genes, counts) = cache.cell(cell);
+        Ok(genes.iter().zip(counts.iter()))
+    }
+
+    // Raw value of one (cell, gene) pair, or None when it is not stored.
+    pub fn cell_value(&self, cell: usize, gene_id: usize) -> Result<Option<f32>> {
+        if let ExprSource::CellMajor(cache) = self.source {
+            if cell >= self.n_cells() || gene_id >= self.n_genes() {
+                bail!("cell or gene_id out of range");
+            }
+            let (genes, counts) = cache.cell(cell);
+            return Ok(genes
+                .binary_search(&(gene_id as u32))
+                .ok()
+                .map(|i| counts[i] as f32));
+        }
+        Ok(self
+            .gene_entries_from(gene_id, cell as u32)?
+            .next()
+            .filter(|&(c, _)| c as usize == cell)
+            .map(|(_, value)| value))
+    }
+
+    // Yields (cell, raw value) for one gene in cell order, decoding compact
+    // encodings on the fly.
+    pub fn gene_entries(&self, gene_id: usize) -> Result<GeneEntries<'a>> {
+        self.gene_entries_from(gene_id, 0)
+    }
+
+    // Like gene_entries, but starts at the first entry with cell >= first_cell.
+    // On a cell-major source this scans every cell from first_cell on.
+    pub fn gene_entries_from(&self, gene_id: usize, first_cell: u32) -> Result<GeneEntries<'a>> {
+        match self.source {
+            ExprSource::GeneMajor { header, mmap } => Ok(GeneEntries(Entries::Stored(
+                stored_entries_from(header, mmap, gene_id, first_cell)?,
+            ))),
+            ExprSource::CellMajor(cache) => {
+                if gene_id >= self.n_genes() {
+                    bail!("gene_id out of range");
+                }
+                Ok(GeneEntries(Entries::Scan(ScanEntries {
+                    cache,
+                    gene: gene_id as u32,
+                    cell: first_cell as usize,
+                })))
+            }
+        }
     }
+}
+
+pub type CellEntries<'a> = std::iter::Zip<slice::Iter<'a, u32>, slice::Iter<'a, u32>>;
 
-    pub fn gene_slice(&self, gene_id: usize) -> Result<(&[u32], &[f32])> {
-        if gene_id >= self.n_genes() {
-            bail!("gene_id out of range");
+fn stored_entries_from<'a>(
+    header: &'a ExprHeaderV2,
+    mmap: &'a Mmap,
+    gene_id: usize,
+    first_cell: u32,
+) -> Result<StoredEntries<'a>> {
+    let (start, end) = gene_range(header, mmap, gene_id)?;
+    let (pos, cells) = if header.index_varint() {
+        let gene_block_ptr = u64_slice(
+            mmap,
+            header.gene_block_ptr_offset(),
+            header.n_genes as usize + 1,
+        );
+        let n_blocks = header.n_blocks as usize;
+        let block_ptr = u64_slice(mmap, header.block_ptr_offset(), n_blocks + 1);
+        let block_first = u32_slice(mmap, header.block_first_offset(), n_blocks);
+        let b0 = gene_block_ptr[gene_id] as usize;
+        let b1 = gene_block_ptr[gene_id + 1] as usize;
+        let block = b0
+            + block_first[b0..b1]
+                .partition_point(|&c| c <= first_cell)
+                .saturating_sub(1);
+        let stream = header.index_stream_offset();
+        let bytes = &mmap[stream + block_ptr[block] as usize..stream + block_ptr[b1] as usize];
+        let cells = CellIter::Varint {
+            bytes,
+            block_first: &block_first[block..b1],
+            in_block: 0,
+            prev: 0,
+        };
+        (start + (block - b0) * INDEX_BLOCK_LEN, cells)
+    } else {
+        let cell_idx = &cell_idx_slice(mmap, header)[start..end];
+        let skip = cell_idx.partition_point(|&c| c < first_cell);
+        (start + skip, CellIter::Plain(cell_idx[skip..].iter()))
+    };
+    let values = if header.values_u16() {
+        let n_overflow = header.n_overflow as usize;
+        let overflow_pos = u64_slice(mmap, header.overflow_pos_offset(), n_overflow);
+        let overflow_val = u32_slice(mmap, header.overflow_val_offset(), n_overflow);
+        let counts = u16_slice(mmap, header.counts_offset(), header.nnz as usize);
+        let next = overflow_pos.partition_point(|&p| (p as usize) < pos);
+        ValueIter::Counts {
+            counts: counts[pos..end].iter(),
+            overflow: overflow_val[next..].iter(),
+        }
+    } else {
+        ValueIter::Plain(values_slice(mmap, header)[pos..end].iter())
+    };
+    let mut entries = StoredEntries {
+        cells,
+        values,
+        remaining: end - pos,
+    };
+    // Varint seeks land on a block start; step to the requested cell.
+    loop {
+        let mut probe = entries.clone();
+        match probe.next() {
+            Some((cell, _)) if cell < first_cell => entries = probe,
+            _ => break,
         }
-        let gene_ptr = gene_ptr_slice(self.mmap, self.header);
-        let cell_idx = cell_idx_slice(self.mmap, self.header);
-        let values = values_slice(self.mmap, self.header);
-        let start = gene_ptr[gene_id] as usize;
-        let end = gene_ptr[gene_id + 1] as usize;
-        Ok((&cell_idx[start..end], &values[start..end]))
     }
+    Ok(entries)
 }
 
-pub fn open_mmap(path: &Path) -> Result<(ExprHeaderV1, Mmap)> {
+fn gene_range(header: &ExprHeaderV2, mmap: &Mmap, gene_id: usize) -> Result<(usize, usize)> {
+    if gene_id >= header.n_genes as usize {
+        bail!("gene_id out of range");
+    }
+    let gene_ptr = gene_ptr_slice(mmap, header);
+    Ok((gene_ptr[gene_id] as usize, gene_ptr[gene_id + 1] as usize))
+}
+
+#[derive(Clone)]
+pub struct GeneEntries<'a>(Entries<'a>);
+
+#[derive(Clone)]
+enum Entries<'a> {
+    Stored(StoredEntries<'a>),
+    Scan(ScanEntries<'a>),
+}
+
+impl Iterator for GeneEntries<'_> {
+    type Item = (u32, f32);
+
+    #[inline]
+    fn next(&mut self) -> Option<(u32, f32)> {
+        match &mut self.0 {
+            Entries::Stored(it) => it.next(),
+            Entries::Scan(it) => it.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.0 {
+            Entries::Stored(it) => it.size_hint(),
+            Entries::Scan(it) => it.size_hint(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ScanEntries<'a> {
+    cache: &'a SharedCache,
+    gene: u32,
+    cell: usize,
+}
+
+impl Iterator for ScanEntries<'_> {
+    type Item = (u32, f32);
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n_cells = self.cache.header.n_cells as usize;
+        (0, Some(n_cells.saturating_sub(self.cell)))
+    }
+
+    fn next(&mut self) -> Option<(u32, f32)> {
+        while self.cell < self.cache.header.n_cells as usize {
+            let cell = self.cell;
+            self.cell += 1;
+            let (genes, counts) = self.cache.cell(cell);
+            if let Ok(i) = genes.binary_search(&self.gene) {
+                return Some((cell as u32, counts[i] as f32));
+            }
+        }
+        None
+    }
+}
+
+#[derive(Clone)]
+struct StoredEntries<'a> {
+    cells: CellIter<'a>,
+    values: ValueIter<'a>,
+    remaining: usize,
+}
+
+#[derive(Clone)]
+enum CellIter<'a> {
+    Plain(slice::Iter<'a, u32>),
+    Varint {
+        bytes: &'a [u8],
+        block_first: &'a [u32],
+        in_block: usize,
+        prev: u32,
+    },
+}
+
+#[derive(Clone)]
+enum ValueIter<'a> {
+    Plain(slice::Iter<'a, f32>),
+    Counts {
+        counts: slice::Iter<'a, u16>,
+        overflow: slice::Iter<'a, u32>,
+    },
+}
+
+impl Iterator for StoredEntries<'_> {
+    type Item = (u32, f32);
+
+    #[inline]
+    fn next(&mut self) -> Option<(u32, f32)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let cell = match &mut self.cells {
+            CellIter::Plain(it) => *it.next()?,
+            CellIter::Varint {
+                bytes,
+                block_first,
+                in_block,
+                prev,
+            } => {
+                if *in_block == 0 {
+                    *prev = *block_first.first()?;
+                    *block_first = &block_first[1..];
+                } else {
+                    let (delta, used) = decode_varint(bytes)?;
+                    *bytes = &bytes[used..];
+                    *prev += delta;
+                }
+                *in_block = (*in_block + 1) % INDEX_BLOCK_LEN;
+                *prev
+            }
+        };
+        let value = match &mut self.values {
+            ValueIter::Plain(it) => *it.next()?,
+            ValueIter::Counts { counts, overflow } => match *counts.next()? {
+                COUNT_OVERFLOW => *overflow.next()? as f32,
+                count => count as f32,
+            },
+        };
+        Some((cell, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+#[inline]
+fn decode_varint(bytes: &[u8]) -> Option<(u32, usize)> {
+    let mut value = 0u32;
+    for (i, &b) in bytes.iter().take(5).enumerate() {
+        value |= ((b & 0x7f) as u32) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+pub fn open_mmap(path: &Path) -> Result<(ExprHeaderV2, Mmap)> {
     let file = File::open(path).context("failed to open expr.bin")?;
     let mmap = unsafe { Mmap::map(&file).context("failed to mmap expr.bin")? };
     if mmap.len() < HEADER_SIZE {
         bail!("expr.bin too small");
     }
-    let header = read_header(&mmap[..HEADER_SIZE])?;
+    let header = read_header(&mmap[..])?;
     let expected = header.expected_len();
     if mmap.len() != expected {
         bail!(
@@ -55,22 +392,51 @@ pub fn open_mmap(path: &Path) -> Result<(ExprHeaderV1, Mmap)> {
     Ok((header, mmap))
 }
 
-pub fn gene_ptr_slice<'a>(mmap: &'a Mmap, header: &ExprHeaderV1) -> &'a [u64] {
-    let start = header.gene_ptr_offset();
-    let end = header.cell_idx_offset();
-    let bytes = &mmap[start..end];
-    unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const u64, bytes.len() / 8) }
+pub fn gene_ptr_slice<'a>(mmap: &'a Mmap, header: &ExprHeaderV2) -> &'a [u64] {
+    u64_slice(mmap, header.gene_ptr_offset(), header.n_genes as usize + 1)
 }
 
-pub fn cell_idx_slice<'a>(mmap: &'a Mmap, header: &ExprHeaderV1) -> &'a [u32] {
-    let start = header.cell_idx_offset();
-    let end = header.values_offset();
-    let bytes = &mmap[start..end];
-    unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const u32, bytes.len() / 4) }
+// The flat cell_idx/values arrays exist only in the plain encoding; use
+// ExprReader::gene_entries for encoding-independent access.
+pub fn cell_idx_slice<'a>(mmap: &'a Mmap, header: &ExprHeaderV2) -> &'a [u32] {
+    assert!(
+        !header.index_varint(),
+        "cell_idx_slice on a varint-indexed expr.bin"
+    );
+    u32_slice(mmap, header.cell_idx_offset(), header.nnz as usize)
 }
 
-pub fn values_slice<'a>(mmap: &'a Mmap, header: &ExprHeaderV1) -> &'a [f32] {
-    let start = header.values_offset();
-    let bytes = &mmap[start..];
+pub fn values_slice<'a>(mmap: &'a Mmap, header: &ExprHeaderV2) -> &'a [f32] {
+    assert!(
+        !header.values_u16(),
+        "values_slice on a u16-valued expr.bin"
+    );
+    let bytes = &mmap[header.values_offset()..header.values_offset() + header.nnz as usize * 4];
     unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const f32, bytes.len() / 4) }
 }
+
+fn u64_slice(mmap: &Mmap, offset: usize, len: usize) -> &[u64] {
+    let bytes = &mmap[offset..offset + len * 8];
+    unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const u64, len) }
+}
+
+fn u32_slice(mmap: &Mmap, offset: usize, len: usize) -> &[u32] {
+    let bytes = &mmap[offset..offset + len * 4];
+    unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const u32, len) }
+}
+
+fn u16_slice(mmap: &Mmap, offset: usize, len: usize) -> &[u16] {
+    let bytes = &mmap[offset..offset + len * 2];
+    unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const u16, len) }
+}
+
+// CRC64 over everything after the fixed header, including the compact
+// section directory; compared with data_crc64 on reuse.
+pub fn data_crc64(mmap: &Mmap, header: &ExprHeaderV2) -> u64 {
+    let start = if header.version == VERSION {
+        HEADER_SIZE
+    } else {
+        HEADER_SIZE_V2
+    };
+    kira_shared_sc_cache::crc64_ecma(&mmap[start..])
+}
@@ -1,51 +1,407 @@
-use std::fs::{self, File};
-use std::io::{BufWriter, Write};
-use std::path::Path;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result, bail};
 use tracing::info;
 
 use crate::ctx::{Ctx, InputFormat};
-use crate::expr::layout::{ExprHeaderV1, LAYOUT_CSC, VERSION, write_header};
+use crate::expr::filter::FilterPlan;
+use crate::expr::fingerprint;
+use crate::expr::layout::{
+    COUNT_OVERFLOW, DATA_CRC_OFFSET_V2, ENCODING_INDEX_VARINT, ENCODING_PLAIN, ENCODING_VALUES_U16,
+    ExprEncoding, ExprHeaderV2, INDEX_BLOCK_LEN, NORMALIZATION_NONE, VERSION_V2, write_header_v2,
+};
 use crate::expr::reader;
-use crate::io::{h5ad, mtx, shared_cache};
+use crate::io::h5ad_layer::SparseOrientation;
+use crate::io::{dense, h5ad, loom, mtx, shared_cache, tenx_h5};
+
+// Bytes held per matrix entry while transposing: u32 cell index + f32 value.
+const ENTRY_BYTES: u64 = 8;
 
 pub fn ensure_expr_cache(ctx: &mut Ctx) -> Result<()> {
+    let fingerprint = fingerprint::source_fingerprint(ctx)?;
+    if let Some(dir) = &ctx.cache_dir {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create cache dir {}", dir.display()))?;
+        ctx.expr_path = dir.join(fingerprint::cache_file_name(fingerprint));
+    }
+
     let path = &ctx.expr_path;
+    let dims = (ctx.genes.len(), ctx.cells.len(), ctx.nnz);
     if path.exists() {
-        if let Ok((header, mmap)) = reader::open_mmap(path) {
-            if header.n_genes as usize == ctx.genes.len()
-                && header.n_cells as usize == ctx.cells.len()
-                && header.nnz as usize == ctx.nnz
-            {
-                info!(expr = %path.display(), "expr_cache_reuse");
-                ctx.expr_header = Some(header);
-                ctx.expr_mmap = Some(mmap);
-                return Ok(());
-            }
+        match reader::open_mmap(path) {
+            Ok((header, mmap)) => match stale_reason(ctx, &header, fingerprint, dims) {
+                None => {
+                    info!(expr = %path.display(), "expr_cache_reuse");
+                    ctx.expr_header = Some(header);
+                    ctx.expr_mmap = Some(mmap);
+                    return Ok(());
+                }
+                Some(reason) => info!(expr = %path.display(), reason, "expr_cache_stale"),
+            },
+            Err(err) => info!(expr = %path.display(), error = %err, "expr_cache_unreadable"),
         }
         info!(expr = %path.display(), "expr_cache_rebuild");
     }
 
-    build_expr_cache(ctx)?;
-    let (header, mmap) = reader::open_mmap(path)?;
+    build_expr_cache(ctx, fingerprint)?;
+    let (header, mmap) = reader::open_mmap(&ctx.expr_path)?;
     ctx.expr_header = Some(header);
     ctx.expr_mmap = Some(mmap);
     Ok(())
 }
 
-fn build_expr_cache(ctx: &Ctx) -> Result<()> {
+// The data CRC is written at build time and checked by `cache inspect`;
+// hashing a multi-GB cache on every reuse would undo the point of mapping it,
+// and the source fingerprint already catches a changed input.
+fn stale_reason(
+    ctx: &Ctx,
+    header: &ExprHeaderV2,
+    fingerprint: u64,
+    (n_genes, n_cells, nnz): (usize, usize, usize),
+) -> Option<&'static str> {
+    if header.version != VERSION_V2 {
+        return Some("v1 cache has no provenance");
+    }
+    if header.source_fingerprint != fingerprint {
+        return Some("source fingerprint changed");
+    }
+    if header.normalization != NORMALIZATION_NONE {
+        return Some("unsupported stored normalization");
+    }
+    if header.index_varint() != (ctx.expr_encoding == ExprEncoding::Compact) {
+        return Some("encoding changed");
+    }
+    if header.n_genes as usize != n_genes
+        || header.n_cells as usize != n_cells
+        || header.nnz as usize != nnz
+    {
+        return Some("dimensions changed");
+    }
+    None
+}
+
+fn build_expr_cache(ctx: &Ctx, fingerprint: u64) -> Result<()> {
     fs::create_dir_all(&ctx.output.out_dir)?;
-    if let Some(cache_path) = &ctx.shared_cache_path {
-        return build_from_shared_cache(ctx, cache_path);
+    write_expr_cache(ctx, &ctx.expr_path, |tmp| {
+        match (&ctx.shared_cache_path, ctx.input_format) {
+            (Some(cache_path), _) => build_from_shared_cache(ctx, cache_path, tmp, fingerprint),
+            (None, InputFormat::Mtx10x) => build_from_mtx(ctx, tmp, fingerprint),
+            (None, InputFormat::H5ad) => build_from_h5ad(ctx, tmp, fingerprint),
+            (None, InputFormat::Tenx5) => build_from_tenx5(ctx, tmp, fingerprint),
+            (None, InputFormat::Loom) => build_from_loom(ctx, tmp, fingerprint),
+            (None, InputFormat::DenseText) => build_from_dense(ctx, tmp, fingerprint),
+        }
+    })
+}
+
+// Builds into a temporary file next to the target and renames it into place,
+// so concurrent runs sharing a --cache-dir never see a partial cache.
+fn write_expr_cache<F>(ctx: &Ctx, target: &Path, build: F) -> Result<()>
+where
+    F: FnOnce(&Path) -> Result<()>,
+{
+    let tmp = target.with_extension(format!("bin.tmp{}", std::process::id()));
+    let built = build(&tmp)
+        .and_then(|_| match ctx.expr_encoding {
+            ExprEncoding::Plain => Ok(()),
+            ExprEncoding::Compact => compact_in_place(&tmp),
+        })
+        .and_then(|_| seal_expr_file(&tmp))
+        .and_then(|_| {
+            fs::rename(&tmp, target)
+                .with_context(|| format!("failed to move expr cache to {}", target.display()))
+        });
+    if built.is_err() {
+        let _ = fs::remove_file(&tmp);
     }
-    match ctx.input_format {
-        InputFormat::Mtx10x => build_from_mtx(ctx),
-        InputFormat::H5ad => build_from_h5ad(ctx),
+    built
+}
+
+// Writes the filtered subset of the current expression source (expr.bin or
+// the mapped shared cache) as its own expr cache and switches the context to
+// it. The caller narrows genes and cells afterwards.
+pub fn ensure_filtered_expr_cache(ctx: &mut Ctx, plan: &FilterPlan) -> Result<()> {
+    let fingerprint =
+        fingerprint::filtered_fingerprint(fingerprint::source_fingerprint(ctx)?, plan);
+    let path = match &ctx.cache_dir {
+        Some(dir) => dir.join(fingerprint::cache_file_name(fingerprint)),
+        None => ctx.expr_path.with_file_name("expr.filtered.bin"),
+    };
+    let n_genes = plan.gene_keep.iter().filter(|&&k| k).count();
+    let n_cells = plan.cell_keep.iter().filter(|&&k| k).count();
+    let dims = (n_genes, n_cells, plan.nnz);
+
+    let mut reused = None;
+    if path.exists() {
+        match reader::open_mmap(&path) {
+            Ok((header, mmap)) => match stale_reason(ctx, &header, fingerprint, dims) {
+                None => reused = Some((header, mmap)),
+                Some(reason) => info!(expr = %path.display(), reason, "expr_cache_stale"),
+            },
+            Err(err) => info!(expr = %path.display(), error = %err, "expr_cache_unreadable"),
+        }
     }
+    let (header, mmap) = match reused {
+        Some(opened) => {
+            info!(expr = %path.display(), "filtered_expr_cache_reuse");
+            opened
+        }
+        None => {
+            fs::create_dir_all(&ctx.output.out_dir)?;
+            let expr = ctx.expr_reader()?;
+            write_expr_cache(ctx, &path, |tmp| {
+                build_filtered(&expr, plan, dims, tmp, fingerprint, ctx.max_memory)
+            })?;
+            reader::open_mmap(&path)?
+        }
+    };
+    ctx.expr_path = path;
+    ctx.expr_header = Some(header);
+    ctx.expr_mmap = Some(mmap);
+    ctx.shared_cache = None;
+    Ok(())
 }
 
-fn build_from_mtx(ctx: &Ctx) -> Result<()> {
+// Either source layout is subset through the gene and cell maps; gene-major
+// sources keep their cell order, cell-major ones are visited cell by cell.
+fn build_filtered(
+    expr: &reader::ExprReader<'_>,
+    plan: &FilterPlan,
+    (n_genes, n_cells, nnz): (usize, usize, usize),
+    out: &Path,
+    fingerprint: u64,
+    max_memory: Option<u64>,
+) -> Result<()> {
+    let gene_map = plan.gene_map();
+    let cell_map = plan.cell_map();
+    let kept_genes = (0..gene_map.len())
+        .filter(|&g| gene_map[g].is_some())
+        .collect::<Vec<_>>();
+
+    let mut gene_ptr = vec![0u64; n_genes + 1];
+    if expr.is_cell_major() {
+        for (cell, _) in cell_map.iter().enumerate().filter(|(_, c)| c.is_some()) {
+            for (&gene, _) in expr.cell_entries(cell)? {
+                if let Some(g) = gene_map[gene as usize] {
+                    gene_ptr[g as usize + 1] += 1;
+                }
+            }
+        }
+    } else {
+        for (g, &gene) in kept_genes.iter().enumerate() {
+            gene_ptr[g + 1] = expr
+                .gene_entries(gene)?
+                .filter(|(cell, _)| cell_map[*cell as usize].is_some())
+                .count() as u64;
+        }
+    }
+    for g in 0..n_genes {
+        gene_ptr[g + 1] += gene_ptr[g];
+    }
+    if gene_ptr[n_genes] as usize != nnz {
+        bail!(
+            "filtered nnz ({}) does not match planned nnz ({})",
+            gene_ptr[n_genes],
+            nnz
+        );
+    }
+
+    let header = ExprHeaderV2::new(n_genes as u32, n_cells as u32, nnz as u64, fingerprint);
+    write_gene_chunks(
+        out,
+        &header,
+        &gene_ptr,
+        max_memory,
+        "expr_filter",
+        |g0, g1, offsets, cell_idx, values| {
+            let mut push = |g: usize, cell: u32, value: f32| {
+                let slot = &mut offsets[g - g0];
+                cell_idx[*slot as usize] = cell;
+                values[*slot as usize] = value;
+                *slot += 1;
+            };
+            if expr.is_cell_major() {
+                for (cell, new_cell) in cell_map.iter().enumerate() {
+                    let Some(new_cell) = new_cell else {
+                        continue;
+                    };
+                    for (&gene, &count) in expr.cell_entries(cell)? {
+                        match gene_map[gene as usize] {
+                            Some(g) if (g0..g1).contains(&(g as usize)) => {
+                                push(g as usize, *new_cell, count as f32)
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            } else {
+                for (g, &gene) in kept_genes.iter().enumerate().take(g1).skip(g0) {
+                    for (cell, value) in expr.gene_entries(gene)? {
+                        if let Some(new_cell) = cell_map[cell as usize] {
+                            push(g, new_cell, value);
+                        }
+                    }
+                }
+            }
+            Ok(())
+        },
+    )
+}
+
+fn compact_in_place(path: &Path) -> Result<()> {
+    let mut compact = path.as_os_str().to_owned();
+    compact.push(".compact");
+    let compact = Path::new(&compact);
+    let written = write_compact_expr_file(path, compact).and_then(|header| {
+        info!(
+            encoding = header.encoding,
+            bytes = header.expected_len(),
+            "expr_cache_compact"
+        );
+        fs::rename(compact, path).context("failed to replace plain expr cache")
+    });
+    if written.is_err() {
+        let _ = fs::remove_file(compact);
+    }
+    written
+}
+
+// Re-encodes a plain expr.bin: cell indices always become block varints and
+// values become u16 counts when every value is a non-negative integer. All
+// sections are streamed from the plain mmap; only per-block tables are held.
+pub fn write_compact_expr_file(src: &Path, out: &Path) -> Result<ExprHeaderV2> {
+    let (plain, mmap) = reader::open_mmap(src)?;
+    if plain.encoding != ENCODING_PLAIN {
+        bail!("expr.bin is already compact");
+    }
+    let gene_ptr = reader::gene_ptr_slice(&mmap, &plain);
+    let cell_idx = reader::cell_idx_slice(&mmap, &plain);
+    let values = reader::values_slice(&mmap, &plain);
+    let n_genes = plain.n_genes as usize;
+
+    let mut gene_block_ptr = Vec::with_capacity(n_genes + 1);
+    let mut block_ptr = vec![0u64];
+    let mut block_first = Vec::new();
+    let mut index_bytes = 0u64;
+    gene_block_ptr.push(0u64);
+    for g in 0..n_genes {
+        let cells = &cell_idx[gene_ptr[g] as usize..gene_ptr[g + 1] as usize];
+        for block in cells.chunks(INDEX_BLOCK_LEN) {
+            block_first.push(block[0]);
+            for pair in block.windows(2) {
+                let delta = pair[1]
+                    .checked_sub(pair[0])
+                    .context("expr.bin cell indices are not sorted")?;
+                index_bytes += varint_len(delta) as u64;
+            }
+            block_ptr.push(index_bytes);
+        }
+        gene_block_ptr.push(block_first.len() as u64);
+    }
+
+    let counts = values
+        .iter()
+        .all(|&v| v >= 0.0 && v.fract() == 0.0 && v <= u32::MAX as f32);
+    let mut header = plain;
+    header.version = VERSION_V2;
+    header.data_crc64 = 0;
+    header.encoding = ENCODING_INDEX_VARINT;
+    header.n_blocks = block_first.len() as u64;
+    header.index_bytes = index_bytes;
+    if counts {
+        header.encoding |= ENCODING_VALUES_U16;
+        header.n_overflow = values
+            .iter()
+            .filter(|&&v| v >= COUNT_OVERFLOW as f32)
+            .count() as u64;
+    }
+
+    let file = File::create(out).context("failed to create compact expr.bin")?;
+    let mut writer = BufWriter::new(file);
+    write_header_v2(&mut writer, &header)?;
+    for v in gene_ptr.iter().chain(&gene_block_ptr).chain(&block_ptr) {
+        writer.write_all(&v.to_le_bytes())?;
+    }
+    for v in &block_first {
+        writer.write_all(&v.to_le_bytes())?;
+    }
+    write_pad8(&mut writer, block_first.len() * 4)?;
+    let mut buf = [0u8; 5];
+    for g in 0..n_genes {
+        let cells = &cell_idx[gene_ptr[g] as usize..gene_ptr[g + 1] as usize];
+        for block in cells.chunks(INDEX_BLOCK_LEN) {
+            for pair in block.windows(2) {
+                let len = encode_varint(pair[1] - pair[0], &mut buf);
+                writer.write_all(&buf[..len])?;
+            }
+        }
+    }
+    write_pad8(&mut writer, index_bytes as usize)?;
+
+    if counts {
+        let is_overflow = |v: f32| v >= COUNT_OVERFLOW as f32;
+        for (pos, _) in values.iter().enumerate().filter(|(_, v)| is_overflow(**v)) {
+            writer.write_all(&(pos as u64).to_le_bytes())?;
+        }
+        for &v in values.iter().filter(|v| is_overflow(**v)) {
+            writer.write_all(&(v as u32).to_le_bytes())?;
+        }
+        write_pad8(&mut writer, header.n_overflow as usize * 4)?;
+        for &v in values {
+            let count = if is_overflow(v) {
+                COUNT_OVERFLOW
+            } else {
+                v as u16
+            };
+            writer.write_all(&count.to_le_bytes())?;
+        }
+    } else {
+        for v in values {
+            writer.write_all(&v.to_le_bytes())?;
+        }
+    }
+    writer.flush()?;
+    Ok(header)
+}
+
+fn varint_len(v: u32) -> usize {
+    let bits = 32 - v.leading_zeros() as usize;
+    bits.div_ceil(7).max(1)
+}
+
+fn encode_varint(mut v: u32, buf: &mut [u8; 5]) -> usize {
+    let mut i = 0;
+    while v >= 0x80 {
+        buf[i] = (v as u8) | 0x80;
+        v >>= 7;
+        i += 1;
+    }
+    buf[i] = v as u8;
+    i + 1
+}
+
+fn write_pad8<W: Write>(w: &mut W, len: usize) -> Result<()> {
+    let pad = len.div_ceil(8) * 8 - len;
+    w.write_all(&[0u8; 8][..pad])?;
+    Ok(())
+}
+
+fn seal_expr_file(path: &Path) -> Result<()> {
+    let (header, mmap) = reader::open_mmap(path)?;
+    let crc = reader::data_crc64(&mmap, &header);
+    drop(mmap);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .context("failed to reopen expr.bin")?;
+    file.seek(SeekFrom::Start(DATA_CRC_OFFSET_V2 as u64))?;
+    file.write_all(&crc.to_le_bytes())?;
+    Ok(())
+}
+
+fn build_from_mtx(ctx: &Ctx, out: &Path, fingerprint: u64) -> Result<()> {
     let path = if let Some(path) = &ctx.mtx_matrix_path {
         path.clone()
     } else {
@@ -53,59 +409,318 @@ fn build_from_mtx(ctx: &Ctx) -> Result<()> {
     };
     let n_genes = ctx.genes.len();
     let n_cells = ctx.cells.len();
+    // Rows dropped by feature type are skipped; kept rows map to gene indices.
+    let row_map = ctx.feature_row_map.as_deref();
+    let n_rows = row_map.map_or(n_genes, |m| m.len());
+    let gene_of = |row: usize| match row_map {
+        Some(row_map) => row_map[row].map(|g| g as usize),
+        None => Some(row),
+    };
 
-    let mut per_gene: Vec<Vec<(u32, f32)>> = vec![Vec::new(); n_genes];
-    let summary = mtx::for_each_entry(&path, |row, col, value| {
-        if row >= n_genes || col >= n_cells {
-            bail!("MTX index out of bounds after context load");
+    // Stage1 counted entries per gene while streaming the file for nnz, so
+    // gene_ptr is known without another pass.
+    let counts = ctx
+        .mtx_gene_counts
+        .as_deref()
+        .filter(|counts| counts.len() == n_genes)
+        .context("MTX gene counts missing from context")?;
+    let mut gene_ptr = vec![0u64; n_genes + 1];
+    for (g, &count) in counts.iter().enumerate() {
+        gene_ptr[g + 1] = gene_ptr[g] + count;
+    }
+    let nnz = gene_ptr[n_genes] as usize;
+    if ctx.nnz != nnz {
+        bail!("MTX nnz ({}) does not match context nnz ({})", nnz, ctx.nnz);
+    }
+
+    let header = ExprHeaderV2::new(n_genes as u32, n_cells as u32, nnz as u64, fingerprint);
+    let check_dims = |summary: mtx::MtxSummary| {
+        if summary.nrows != n_rows || summary.ncols != n_cells {
+            bail!(
+                "MTX dimensions ({}, {}) do not match context ({}, {})",
+                summary.nrows,
+                summary.ncols,
+                n_rows,
+                n_cells
+            );
         }
-        per_gene[row].push((col as u32, value));
         Ok(())
-    })?;
+    };
 
-    if summary.nrows != n_genes || summary.ncols != n_cells {
-        bail!(
-            "MTX dimensions ({}, {}) do not match context ({}, {})",
-            summary.nrows,
-            summary.ncols,
-            n_genes,
-            n_cells
+    // One range fits the budget: a single pass scatters every entry in file
+    // order; the stable per-gene sort then yields the in-memory transpose.
+    if plan_gene_chunks(&gene_ptr, ctx.max_memory).len() == 1 {
+        return write_gene_chunks(
+            out,
+            &header,
+            &gene_ptr,
+            None,
+            "mtx_transpose",
+            |_, _, offsets, cell_idx, values| {
+                let summary = mtx::for_each_entry(&path, |row, col, value| {
+                    if row >= n_rows || col >= n_cells {
+                        bail!("MTX index out of bounds after context load");
+                    }
+                    let Some(gene) = gene_of(row) else {
+                        return Ok(());
+                    };
+                    let slot = &mut offsets[gene];
+                    if *slot >= gene_ptr[gene + 1] {
+                        bail!("MTX entries changed since context load");
+                    }
+                    cell_idx[*slot as usize] = col as u32;
+                    values[*slot as usize] = value;
+                    *slot += 1;
+                    Ok(())
+                })?;
+                check_dims(summary)
+            },
         );
     }
-    if ctx.nnz != summary.nnz {
-        bail!(
-            "MTX nnz ({}) does not match context nnz ({})",
-            summary.nnz,
-            ctx.nnz
-        );
+
+    // Otherwise the file is read once more into gene-sorted runs spilled next
+    // to the output, and each gene range is filled from the runs in turn.
+    let mut runs = RunSpill::new(out, ctx.max_memory, nnz);
+    let spilled = mtx::for_each_entry(&path, |row, col, value| {
+        if row >= n_rows || col >= n_cells {
+            bail!("MTX index out of bounds after context load");
+        }
+        match gene_of(row) {
+            Some(gene) => runs.push(gene as u32, col as u32, value),
+            None => Ok(()),
+        }
+    })
+    .and_then(check_dims)
+    .and_then(|_| runs.finish());
+    let built = spilled.and_then(|_| {
+        info!(runs = runs.runs.len(), "mtx_spill");
+        write_gene_chunks(
+            out,
+            &header,
+            &gene_ptr,
+            ctx.max_memory,
+            "mtx_transpose",
+            |g0, g1, offsets, cell_idx, values| {
+                // Runs hold consecutive stretches of the file, so draining
+                // them in order keeps file order within each gene. Only one
+                // run file is open at a time.
+                for run in &mut runs.runs {
+                    run.drain_below(g1, |gene, cell, value| {
+                        let gene = gene as usize;
+                        let slot = &mut offsets[gene - g0];
+                        if *slot >= gene_ptr[gene + 1] - gene_ptr[g0] {
+                            bail!("MTX entries changed since context load");
+                        }
+                        cell_idx[*slot as usize] = cell;
+                        values[*slot as usize] = value;
+                        *slot += 1;
+                        Ok(())
+                    })?;
+                }
+                Ok(())
+            },
+        )
+    });
+    runs.remove_files();
+    built
+}
+
+// Bytes per spilled run entry: u32 gene + u32 cell + f32 value.
+const RUN_ENTRY_BYTES: u64 = 12;
+// The stable sort may allocate scratch up to the buffer's own length (for
+// buffers under a few MB it does), so each buffered entry is budgeted twice.
+const RUN_SORT_BYTES: u64 = 2 * RUN_ENTRY_BYTES;
+
+// Buffers (gene, cell, value) entries within the memory budget, sort scratch
+// included, and writes each full buffer, stably sorted by gene, as its own
+// run file.
+struct RunSpill {
+    base: PathBuf,
+    capacity: usize,
+    buf: Vec<(u32, u32, f32)>,
+    runs: Vec<SpillRun>,
+}
+
+impl RunSpill {
+    fn new(out: &Path, max_memory: Option<u64>, nnz: usize) -> Self {
+        let capacity = max_memory.map_or(usize::MAX, |m| (m / RUN_SORT_BYTES).max(1) as usize);
+        Self {
+            base: out.to_path_buf(),
+            capacity,
+            buf: Vec::with_capacity(capacity.min(nnz)),
+            runs: Vec::new(),
+        }
     }
 
-    let nnz = summary.nnz;
-    let mut gene_ptr = Vec::with_capacity(n_genes + 1);
-    gene_ptr.push(0u64);
+    fn push(&mut self, gene: u32, cell: u32, value: f32) -> Result<()> {
+        self.buf.push((gene, cell, value));
+        if self.buf.len() >= self.capacity {
+            self.flush()?;
+        }
+        Ok(())
+    }
 
-    for entries in &mut per_gene {
-        entries.sort_by_key(|(cell, _)| *cell);
-        let next = gene_ptr.last().copied().unwrap() + entries.len() as u64;
-        gene_ptr.push(next);
+    fn flush(&mut self) -> Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.buf.sort_by_key(|(gene, _, _)| *gene);
+        let mut name = self.base.as_os_str().to_owned();
+        name.push(format!(".run{}", self.runs.len()));
+        let path = PathBuf::from(name);
+        self.runs.push(SpillRun {
+            path: path.clone(),
+            len: self.buf.len() as u64,
+            pos: 0,
+        });
+        let file = File::create(&path)
+            .with_context(|| format!("failed to create spill run {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        for (gene, cell, value) in self.buf.drain(..) {
+            writer.write_all(&gene.to_le_bytes())?;
+            writer.write_all(&cell.to_le_bytes())?;
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.flush()?;
+        Ok(())
     }
-    if *gene_ptr.last().unwrap_or(&0) as usize != nnz {
-        bail!("gene_ptr does not match nnz");
+
+    // Writes the last run and releases the buffer before the runs are read.
+    fn finish(&mut self) -> Result<()> {
+        self.flush()?;
+        self.buf = Vec::new();
+        Ok(())
     }
 
-    let header = ExprHeaderV1 {
-        version: VERSION,
-        n_genes: n_genes as u32,
-        n_cells: n_cells as u32,
-        nnz: nnz as u64,
-        layout: LAYOUT_CSC,
-    };
+    fn remove_files(&self) {
+        for run in &self.runs {
+            let _ = fs::remove_file(&run.path);
+        }
+    }
+}
+
+// A run file and how many of its entries earlier gene ranges consumed.
+struct SpillRun {
+    path: PathBuf,
+    len: u64,
+    pos: u64,
+}
+
+impl SpillRun {
+    // Reopens the run at its position and hands over entries while their
+    // gene is below `g1`.
+    fn drain_below<F>(&mut self, g1: usize, mut f: F) -> Result<()>
+    where
+        F: FnMut(u32, u32, f32) -> Result<()>,
+    {
+        if self.pos == self.len {
+            return Ok(());
+        }
+        let mut file = File::open(&self.path)
+            .with_context(|| format!("failed to open spill run {}", self.path.display()))?;
+        file.seek(SeekFrom::Start(self.pos * RUN_ENTRY_BYTES))?;
+        let mut reader = BufReader::new(file);
+        let mut rec = [0u8; RUN_ENTRY_BYTES as usize];
+        while self.pos < self.len {
+            reader
+                .read_exact(&mut rec)
+                .context("failed to read spill run")?;
+            let word = |i: usize| [rec[i], rec[i + 1], rec[i + 2], rec[i + 3]];
+            let gene = u32::from_le_bytes(word(0));
+            if gene as usize >= g1 {
+                break;
+            }
+            f(
+                gene,
+                u32::from_le_bytes(word(4)),
+                f32::from_le_bytes(word(8)),
+            )?;
+            self.pos += 1;
+        }
+        Ok(())
+    }
+}
+
+// Fills the pre-sized expr.bin one gene range at a time: `fill` scatters the
+// range's entries through per-gene write offsets (relative to the range),
+// then each slice is sorted by cell and written at its final position.
+fn write_gene_chunks<F>(
+    out: &Path,
+    header: &ExprHeaderV2,
+    gene_ptr: &[u64],
+    max_memory: Option<u64>,
+    label: &'static str,
+    mut fill: F,
+) -> Result<()>
+where
+    F: FnMut(usize, usize, &mut [u64], &mut [u32], &mut [f32]) -> Result<()>,
+{
+    let chunks = plan_gene_chunks(gene_ptr, max_memory);
+    info!(chunks = chunks.len(), max_memory = ?max_memory, "{}", label);
+
+    let file = File::create(out).context("failed to create expr.bin")?;
+    file.set_len(header.expected_len() as u64)?;
+    let mut writer = BufWriter::new(file);
+    write_header_v2(&mut writer, header)?;
+    for v in gene_ptr {
+        writer.write_all(&v.to_le_bytes())?;
+    }
+
+    for (g0, g1) in chunks {
+        let base = gene_ptr[g0];
+        let len = (gene_ptr[g1] - base) as usize;
+        let chunk_ptr = gene_ptr[g0..=g1]
+            .iter()
+            .map(|&p| p - base)
+            .collect::<Vec<_>>();
+        let mut offsets = chunk_ptr.clone();
+        let mut cell_idx = vec![0u32; len];
+        let mut values = vec![0f32; len];
+        fill(g0, g1, &mut offsets, &mut cell_idx, &mut values)?;
+        sort_gene_slices(&chunk_ptr, &mut cell_idx, &mut values);
+
+        writer.seek(SeekFrom::Start(
+            (header.cell_idx_offset() as u64) + base * 4,
+        ))?;
+        for v in &cell_idx {
+            writer.write_all(&v.to_le_bytes())?;
+        }
+        writer.seek(SeekFrom::Start((header.values_offset() as u64) + base * 4))?;
+        for v in &values {
+            writer.write_all(&v.to_le_bytes())?;
+        }
+    }
 
-    write_expr_file(&ctx.expr_path, &header, &gene_ptr, &per_gene)?;
+    writer.flush()?;
     Ok(())
 }
 
-fn build_from_shared_cache(ctx: &Ctx, cache_path: &Path) -> Result<()> {
+// Splits genes into contiguous ranges whose entries (cell index + value) fit
+// the memory budget. A single gene larger than the budget gets its own range.
+fn plan_gene_chunks(gene_ptr: &[u64], max_memory: Option<u64>) -> Vec<(usize, usize)> {
+    let n_genes = gene_ptr.len() - 1;
+    let Some(max_memory) = max_memory else {
+        return vec![(0, n_genes)];
+    };
+    let budget = (max_memory / ENTRY_BYTES).max(1);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for g in 0..n_genes {
+        if g > start && gene_ptr[g + 1] - gene_ptr[start] > budget {
+            chunks.push((start, g));
+            start = g;
+        }
+    }
+    chunks.push((start, n_genes));
+    chunks
+}
+
+fn build_from_shared_cache(
+    ctx: &Ctx,
+    cache_path: &Path,
+    out: &Path,
+    fingerprint: u64,
+) -> Result<()> {
     let cache = shared_cache::SharedCache::open(cache_path).with_context(|| {
         format!(
             "failed to read shared cache while building expr cache: {}",
@@ -128,51 +743,217 @@ fn build_from_shared_cache(ctx: &Ctx, cache_path: &Path) -> Result<()> {
         );
     }
 
-    // Shared cache is CSC by cell (col_ptr per cell, row_idx = gene),
-    // while expr.bin is CSC by gene. Build gene-major arrays deterministically.
+    // Shared cache is CSC by cell (col_ptr per cell, row_idx = gene), while
+    // expr.bin is CSC by gene. The cache stays mmapped; only the gene range
+    // being transposed is held, bounded by --max-memory like MTX inputs.
     let mut gene_ptr = vec![0u64; n_genes + 1];
-    let col_ptr = cache.col_ptr()?;
-    for cell in 0..n_cells {
-        let start = col_ptr[cell] as usize;
-        let end = col_ptr[cell + 1] as usize;
-        for k in start..end {
-            let g = cache.row_idx_at(k)? as usize;
-            gene_ptr[g + 1] += 1;
+    for &g in cache.row_idx() {
+        gene_ptr[g as usize + 1] += 1;
+    }
+    for g in 0..n_genes {
+        gene_ptr[g + 1] += gene_ptr[g];
+    }
+
+    let header = ExprHeaderV2::new(n_genes as u32, n_cells as u32, nnz as u64, fingerprint);
+    write_gene_chunks(
+        out,
+        &header,
+        &gene_ptr,
+        ctx.max_memory,
+        "shared_cache_transpose",
+        |g0, g1, offsets, cell_idx, values| {
+            for cell in 0..n_cells {
+                let (genes, counts) = cache.cell(cell);
+                let lo = genes.partition_point(|&g| (g as usize) < g0);
+                let hi = genes.partition_point(|&g| (g as usize) < g1);
+                for (&g, &count) in genes[lo..hi].iter().zip(&counts[lo..hi]) {
+                    let slot = &mut offsets[g as usize - g0];
+                    cell_idx[*slot as usize] = cell as u32;
+                    values[*slot as usize] = count as f32;
+                    *slot += 1;
+                }
+            }
+            Ok(())
+        },
+    )
+}
+
+// Cell Ranger .h5 is CSC by barcode; rows dropped by feature type are
+// skipped and the rest remapped to their gene index while transposing.
+fn build_from_tenx5(ctx: &Ctx, out: &Path, fingerprint: u64) -> Result<()> {
+    let matrix = tenx_h5::read_tenx_h5_matrix(&ctx.input)?;
+    let n_genes = ctx.genes.len();
+    let n_cells = ctx.cells.len();
+    if matrix.ncols != n_cells {
+        bail!(
+            "10x HDF5 barcodes ({}) do not match context cells ({})",
+            matrix.ncols,
+            n_cells
+        );
+    }
+    let row_map = match &ctx.feature_row_map {
+        Some(row_map) if row_map.len() == matrix.nrows => row_map.clone(),
+        Some(row_map) => bail!(
+            "feature map covers {} rows, 10x HDF5 has {}",
+            row_map.len(),
+            matrix.nrows
+        ),
+        None if matrix.nrows == n_genes => (0..n_genes as u32).map(Some).collect(),
+        None => bail!(
+            "10x HDF5 features ({}) do not match context genes ({})",
+            matrix.nrows,
+            n_genes
+        ),
+    };
+
+    let mut gene_ptr = vec![0u64; n_genes + 1];
+    for &row in &matrix.indices {
+        if let Some(gene) = row_map[row as usize] {
+            gene_ptr[gene as usize + 1] += 1;
         }
     }
     for g in 0..n_genes {
         gene_ptr[g + 1] += gene_ptr[g];
     }
+    let nnz = gene_ptr[n_genes] as usize;
+    if nnz != ctx.nnz {
+        bail!(
+            "10x HDF5 nnz ({}) does not match context nnz ({})",
+            nnz,
+            ctx.nnz
+        );
+    }
+
+    let header = ExprHeaderV2::new(n_genes as u32, n_cells as u32, nnz as u64, fingerprint);
+    write_gene_chunks(
+        out,
+        &header,
+        &gene_ptr,
+        ctx.max_memory,
+        "tenx_h5_transpose",
+        |g0, g1, offsets, cell_idx, values| {
+            for cell in 0..n_cells {
+                let start = matrix.indptr[cell] as usize;
+                let end = matrix.indptr[cell + 1] as usize;
+                for k in start..end {
+                    let Some(gene) = row_map[matrix.indices[k] as usize] else {
+                        continue;
+                    };
+                    let gene = gene as usize;
+                    if gene < g0 || gene >= g1 {
+                        continue;
+                    }
+                    let slot = &mut offsets[gene - g0];
+                    cell_idx[*slot as usize] = cell as u32;
+                    values[*slot as usize] = matrix.data[k];
+                    *slot += 1;
+                }
+            }
+            Ok(())
+        },
+    )
+}
 
-    let mut cell_idx = vec![0u32; nnz];
-    let mut values = vec![0f32; nnz];
-    let mut offsets = gene_ptr.clone();
-    for cell in 0..n_cells {
-        let start = col_ptr[cell] as usize;
-        let end = col_ptr[cell + 1] as usize;
-        for k in start..end {
-            let g = cache.row_idx_at(k)? as usize;
-            let pos = offsets[g] as usize;
-            cell_idx[pos] = cell as u32;
-            values[pos] = cache.value_u32_at(k)? as f32;
-            offsets[g] += 1;
+// Loom rows are genes, so each gene range is read straight from its rows and
+// cells come out sorted; only the counting pass covers the whole matrix.
+fn build_from_loom(ctx: &Ctx, out: &Path, fingerprint: u64) -> Result<()> {
+    let n_genes = ctx.genes.len();
+    let n_cells = ctx.cells.len();
+    let mut gene_ptr = vec![0u64; n_genes + 1];
+    loom::for_each_loom_row(&ctx.input, 0..n_genes, |gene, row| {
+        if row.len() != n_cells {
+            bail!(
+                "loom columns ({}) do not match context cells ({})",
+                row.len(),
+                n_cells
+            );
         }
+        gene_ptr[gene + 1] = gene_ptr[gene] + row.iter().filter(|v| **v != 0.0).count() as u64;
+        Ok(())
+    })?;
+    let nnz = gene_ptr[n_genes] as usize;
+    if nnz != ctx.nnz {
+        bail!(
+            "loom nnz ({}) does not match context nnz ({})",
+            nnz,
+            ctx.nnz
+        );
     }
 
-    let header = ExprHeaderV1 {
-        version: VERSION,
-        n_genes: n_genes as u32,
-        n_cells: n_cells as u32,
-        nnz: nnz as u64,
-        layout: LAYOUT_CSC,
-    };
-    write_expr_file_flat(&ctx.expr_path, &header, &gene_ptr, &cell_idx, &values)?;
-    Ok(())
+    let header = ExprHeaderV2::new(n_genes as u32, n_cells as u32, nnz as u64, fingerprint);
+    write_gene_chunks(
+        out,
+        &header,
+        &gene_ptr,
+        ctx.max_memory,
+        "loom_rows",
+        |g0, g1, offsets, cell_idx, values| {
+            loom::for_each_loom_row(&ctx.input, g0..g1, |gene, row| {
+                let slot = &mut offsets[gene - g0];
+                for (cell, &value) in row.iter().enumerate().filter(|(_, v)| **v != 0.0) {
+                    cell_idx[*slot as usize] = cell as u32;
+                    values[*slot as usize] = value;
+                    *slot += 1;
+                }
+                Ok(())
+            })
+        },
+    )
+}
+
+// Dense text is streamed like MTX: one counting pass, then one pass per gene
+// range. Either orientation yields entries in cell order within a gene.
+fn build_from_dense(ctx: &Ctx, out: &Path, fingerprint: u64) -> Result<()> {
+    let layout = ctx
+        .dense_layout
+        .context("dense layout missing from context")?;
+    let n_genes = ctx.genes.len();
+    let n_cells = ctx.cells.len();
+    let mut gene_ptr = vec![0u64; n_genes + 1];
+    dense::for_each_entry(&ctx.input, &layout, |gene, cell, _| {
+        if gene >= n_genes || cell >= n_cells {
+            bail!("dense index out of bounds after context load");
+        }
+        gene_ptr[gene + 1] += 1;
+        Ok(())
+    })?;
+    for g in 0..n_genes {
+        gene_ptr[g + 1] += gene_ptr[g];
+    }
+    let nnz = gene_ptr[n_genes] as usize;
+    if nnz != ctx.nnz {
+        bail!(
+            "dense nnz ({}) does not match context nnz ({})",
+            nnz,
+            ctx.nnz
+        );
+    }
+
+    let header = ExprHeaderV2::new(n_genes as u32, n_cells as u32, nnz as u64, fingerprint);
+    write_gene_chunks(
+        out,
+        &header,
+        &gene_ptr,
+        ctx.max_memory,
+        "dense_transpose",
+        |g0, g1, offsets, cell_idx, values| {
+            dense::for_each_entry(&ctx.input, &layout, |gene, cell, value| {
+                if gene < g0 || gene >= g1 {
+                    return Ok(());
+                }
+                let slot = &mut offsets[gene - g0];
+                cell_idx[*slot as usize] = cell as u32;
+                values[*slot as usize] = value;
+                *slot += 1;
+                Ok(())
+            })
+        },
+    )
 }
 
-fn build_from_h5ad(ctx: &Ctx) -> Result<()> {
-    let (summary, encoding, indptr_len, indices, data, indptr) =
-        h5ad::read_h5ad_sparse(&ctx.input)?;
+fn build_from_h5ad(ctx: &Ctx, out: &Path, fingerprint: u64) -> Result<()> {
+    let (summary, encoding, orientation, indices, data, indptr) =
+        h5ad::read_h5ad_sparse(&ctx.input, &ctx.h5ad_layer)?;
 
     if summary.nrows != ctx.genes.len() || summary.ncols != ctx.cells.len() {
         bail!(
@@ -198,19 +979,19 @@ fn build_from_h5ad(ctx: &Ctx) -> Result<()> {
         bail!("H5AD indptr last value does not equal nnz");
     }
 
-    let header = ExprHeaderV1 {
-        version: VERSION,
-        n_genes: n_genes as u32,
-        n_cells: n_cells as u32,
-        nnz: nnz as u64,
-        layout: LAYOUT_CSC,
-    };
+    let header = ExprHeaderV2::new(n_genes as u32, n_cells as u32, nnz as u64, fingerprint);
 
     let mut gene_ptr = Vec::with_capacity(n_genes + 1);
     let mut cell_idx: Vec<u32> = vec![0; nnz];
     let mut values: Vec<f32> = vec![0.0; nnz];
 
-    if indptr_len == n_genes + 1 {
+    if indptr.len() != orientation.indptr_len(n_genes, n_cells) {
+        bail!(
+            "H5AD indptr length does not match its {:?} orientation",
+            orientation
+        );
+    }
+    if orientation == SparseOrientation::GeneMajor {
         gene_ptr.extend(indptr.iter().copied());
         if *gene_ptr.last().unwrap_or(&0) as usize != nnz {
             bail!("H5AD indptr last value does not equal nnz");
@@ -224,7 +1005,7 @@ fn build_from_h5ad(ctx: &Ctx) -> Result<()> {
         cell_idx.copy_from_slice(&indices);
         values.copy_from_slice(&data);
         sort_gene_slices(&gene_ptr, &mut cell_idx, &mut values);
-    } else if indptr_len == n_cells + 1 {
+    } else {
         info!(
             encoding = %encoding,
             "transposing sparse layout to gene-major"
@@ -252,48 +1033,15 @@ fn build_from_h5ad(ctx: &Ctx) -> Result<()> {
                 offsets[gene] += 1;
             }
         }
-    } else {
-        bail!("H5AD indptr length does not match genes or cells");
     }
 
-    write_expr_file_flat(&ctx.expr_path, &header, &gene_ptr, &cell_idx, &values)?;
-    Ok(())
-}
-
-fn write_expr_file(
-    path: &Path,
-    header: &ExprHeaderV1,
-    gene_ptr: &[u64],
-    per_gene: &[Vec<(u32, f32)>],
-) -> Result<()> {
-    let file = File::create(path).context("failed to create expr.bin")?;
-    let mut writer = BufWriter::new(file);
-
-    write_header(&mut writer, header)?;
-
-    for v in gene_ptr {
-        writer.write_all(&v.to_le_bytes())?;
-    }
-
-    for entries in per_gene {
-        for (cell, _) in entries {
-            writer.write_all(&cell.to_le_bytes())?;
-        }
-    }
-
-    for entries in per_gene {
-        for (_, value) in entries {
-            writer.write_all(&value.to_le_bytes())?;
-        }
-    }
-
-    writer.flush()?;
+    write_expr_file_flat(out, &header, &gene_ptr, &cell_idx, &values)?;
     Ok(())
 }
 
 fn write_expr_file_flat(
     path: &Path,
-    header: &ExprHeaderV1,
+    header: &ExprHeaderV2,
     gene_ptr: &[u64],
     cell_idx: &[u32],
     values: &[f32],
@@ -301,7 +1049,7 @@ fn write_expr_file_flat(
     let file = File::create(path).context("failed to create expr.bin")?;
     let mut writer = BufWriter::new(file);
 
-    write_header(&mut writer, header)?;
+    write_header_v2(&mut writer, header)?;
     for v in gene_ptr {
         writer.write_all(&v.to_le_bytes())?;
     }
@@ -320,7 +1068,7 @@ fn sort_gene_slices(gene_ptr: &[u64], cell_idx: &mut [u32], values: &mut [f32])
     for g in 0..n_genes {
         let start = gene_ptr[g] as usize;
         let end = gene_ptr[g + 1] as usize;
-        if end - start <= 1 {
+        if end - start <= 1 || cell_idx[start..end].is_sorted() {
             continue;
         }
         let mut pairs: Vec<(u32, f32)> = cell_idx[start..end]
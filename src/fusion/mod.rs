@@ -1,9 +1,38 @@
 use std::collections::HashMap;
+use std::ops::Range;
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
+use tracing::info;
 
+use crate::ctx::Ctx;
 use crate::expr::reader::ExprReader;
 use crate::geneset::ResolvedGeneset;
+use crate::math::sum::{self, CompensatedSum};
+use crate::metrics::mito;
+use crate::metrics::proteostasis_extension::scores::resolve_panels;
+use crate::parallel;
+use crate::scores::axis_raw;
+
+const SHARD_CELLS: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FusionMode {
+    Off,
+    #[default]
+    Proteo,
+    // Proteo targets plus the mitochondrial targets of metrics::mito.
+    MitoProteo,
+}
+
+impl FusionMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Proteo => "proteo",
+            Self::MitoProteo => "mito+proteo",
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct TargetRef {
@@ -11,11 +40,18 @@ pub struct TargetRef {
     pub scale: f32,
 }
 
+// Targets are summed per cell; panels keep every per-gene value so that
+// consumers can take trimmed means.
 #[derive(Debug, Clone)]
 pub struct FusionPlan {
     pub targets: Vec<String>,
     pub gene_counts: Vec<usize>,
     pub membership: Vec<Vec<TargetRef>>,
+    // Raw targets sum stored counts instead of normalized values.
+    pub raw: Vec<bool>,
+    pub panels: Vec<String>,
+    pub panel_sizes: Vec<usize>,
+    pub panel_membership: Vec<Vec<usize>>,
     pub n_cells: usize,
 }
 
@@ -54,37 +90,320 @@ pub fn build_plan(
     }
 
     FusionPlan {
+        raw: vec![false; targets.len()],
         targets,
         gene_counts,
         membership,
+        panels: Vec::new(),
+        panel_sizes: Vec::new(),
+        panel_membership: vec![Vec::new(); n_genes],
         n_cells,
     }
 }
 
+impl FusionPlan {
+    pub fn add_target(&mut self, id: &str, genes: &[usize], raw: bool) {
+        let tid = self.targets.len();
+        self.targets.push(id.to_string());
+        self.gene_counts.push(genes.len());
+        self.raw.push(raw);
+        for &gene_id in genes {
+            self.membership[gene_id].push(TargetRef {
+                target_id: tid,
+                scale: 1.0,
+            });
+        }
+    }
+
+    // A gene listed twice contributes two values, as two dense rows would.
+    pub fn add_panel(&mut self, id: &str, genes: &[usize]) {
+        let pid = self.panels.len();
+        self.panels.push(id.to_string());
+        self.panel_sizes.push(genes.len());
+        for &gene_id in genes {
+            self.panel_membership[gene_id].push(pid);
+        }
+    }
+
+    fn touches(&self, gene_id: usize) -> bool {
+        !self.membership[gene_id].is_empty() || !self.panel_membership[gene_id].is_empty()
+    }
+}
+
+// Stored values of one panel, grouped by cell in ascending gene order.
+// Panel genes missing from `cell_values` are zero in that cell.
+#[derive(Debug, Clone)]
+pub struct PanelValues {
+    pub id: String,
+    pub n_genes: usize,
+    cell_ptr: Vec<usize>,
+    values: Vec<f32>,
+}
+
+impl PanelValues {
+    pub fn cell_values(&self, cell: usize) -> &[f32] {
+        &self.values[self.cell_ptr[cell]..self.cell_ptr[cell + 1]]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FusedReduction {
+    pub targets: Vec<String>,
+    pub gene_counts: Vec<usize>,
+    pub n_cells: usize,
+    // Per-cell sums, target-major.
+    pub sums: Vec<f32>,
+    pub panels: Vec<PanelValues>,
+}
+
+impl FusedReduction {
+    // build_plan lets the last of duplicated ids own the genes.
+    pub fn target_index(&self, id: &str) -> Option<usize> {
+        self.targets.iter().rposition(|t| t == id)
+    }
+
+    pub fn target_sums(&self, target: usize) -> &[f32] {
+        &self.sums[target * self.n_cells..(target + 1) * self.n_cells]
+    }
+
+    pub fn target_means(&self, target: usize) -> Vec<f32> {
+        let denom = self.gene_counts[target].max(1) as f32;
+        self.target_sums(target).iter().map(|v| v / denom).collect()
+    }
+
+    pub fn panel(&self, id: &str) -> Option<&PanelValues> {
+        self.panels.iter().find(|p| p.id == id)
+    }
+}
+
+pub fn enabled(ctx: &Ctx) -> bool {
+    cfg!(feature = "fusion") && ctx.fusion != FusionMode::Off
+}
+
+pub fn mito_enabled(ctx: &Ctx) -> bool {
+    enabled(ctx) && ctx.fusion == FusionMode::MitoProteo
+}
+
+// One pass over the matrix for every resolved geneset and extension panel,
+// the constitutive proteasome reference, plus the mitochondrial targets in
+// mito+proteo mode; later stages read the shared result from ctx.fused.
+pub fn ensure_fused(ctx: &mut Ctx) -> Result<()> {
+    if ctx.fused.is_some() || !enabled(ctx) {
+        return Ok(());
+    }
+    let fused = {
+        let genesets = ctx.genesets.as_ref().context("genesets not resolved")?;
+        let reader = ctx.expr_reader()?;
+        let ids = genesets
+            .resolved
+            .iter()
+            .map(|g| g.id.as_str())
+            .collect::<Vec<_>>();
+        let mut plan = build_plan(reader.n_genes(), reader.n_cells(), &genesets.resolved, &ids);
+        for (id, genes) in resolve_panels(ctx) {
+            plan.add_panel(id, &genes);
+        }
+        plan.add_target(
+            axis_raw::CONSTITUTIVE_TARGET,
+            &axis_raw::resolve_constitutive(ctx),
+            false,
+        );
+        if mito_enabled(ctx) {
+            for target in mito::resolve_targets(ctx) {
+                plan.add_target(&target.id, &target.genes, target.raw);
+            }
+        }
+        reduce(&reader, &plan, ctx.threads)?
+    };
+    info!(
+        targets = fused.targets.len(),
+        panels = fused.panels.len(),
+        "fused_reduction_ready"
+    );
+    ctx.fused = Some(fused);
+    Ok(())
+}
+
 pub fn fused_reduce(expr: &ExprReader<'_>, plan: &FusionPlan, out: &mut [f32]) -> Result<()> {
     let target_count = plan.targets.len();
     let expected = target_count * plan.n_cells;
     if out.len() != expected {
-        anyhow::bail!("fused output length mismatch");
+        bail!("fused output length mismatch");
     }
-    for v in out.iter_mut() {
-        *v = 0.0;
+    let fused = reduce(expr, plan, 1)?;
+    out.copy_from_slice(&fused.sums);
+    Ok(())
+}
+
+// Every cell sums its terms in ascending gene order on both source layouts
+// and for any shard split, so the thread count never changes the result.
+pub fn reduce(expr: &ExprReader<'_>, plan: &FusionPlan, threads: usize) -> Result<FusedReduction> {
+    if expr.n_cells() != plan.n_cells || expr.n_genes() != plan.membership.len() {
+        bail!("fusion plan does not match the expression matrix");
     }
+    let ranges = shard_ranges(plan.n_cells, threads);
+    let shards = parallel::try_map(threads, &ranges, |range| {
+        reduce_shard(expr, plan, range.clone())
+    })?;
 
-    for gene_id in 0..plan.membership.len() {
-        let targets = &plan.membership[gene_id];
-        if targets.is_empty() {
-            continue;
+    let n_cells = plan.n_cells;
+    let mut sums = vec![0.0f32; plan.targets.len() * n_cells];
+    let mut panels = plan
+        .panels
+        .iter()
+        .zip(&plan.panel_sizes)
+        .map(|(id, &n_genes)| PanelValues {
+            id: id.clone(),
+            n_genes,
+            cell_ptr: vec![0],
+            values: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+    for (range, shard) in ranges.iter().zip(shards) {
+        let len = range.len();
+        for t in 0..plan.targets.len() {
+            sums[t * n_cells + range.start..t * n_cells + range.end]
+                .copy_from_slice(&shard.sums[t * len..(t + 1) * len]);
         }
-        let (cells, values) = expr.gene_slice(gene_id)?;
-        for (cell, value) in cells.iter().zip(values.iter()) {
-            let cell_idx = *cell as usize;
-            for t in targets {
-                let offset = t.target_id * plan.n_cells + cell_idx;
-                out[offset] += *value * t.scale;
+        for (panel, (counts, values)) in panels.iter_mut().zip(shard.panels) {
+            for count in counts {
+                let last = *panel.cell_ptr.last().unwrap_or(&0);
+                panel.cell_ptr.push(last + count);
             }
+            panel.values.extend(values);
         }
     }
 
-    Ok(())
+    Ok(FusedReduction {
+        targets: plan.targets.clone(),
+        gene_counts: plan.gene_counts.clone(),
+        n_cells,
+        sums,
+        panels,
+    })
+}
+
+fn shard_ranges(n_cells: usize, threads: usize) -> Vec<Range<usize>> {
+    if threads == 1 || !cfg!(feature = "fusion-mt") || n_cells <= SHARD_CELLS {
+        return std::iter::once(0..n_cells).collect();
+    }
+    (0..n_cells)
+        .step_by(SHARD_CELLS)
+        .map(|start| start..(start + SHARD_CELLS).min(n_cells))
+        .collect()
+}
+
+struct Shard {
+    // targets x range.len(), target-major.
+    sums: Vec<f32>,
+    // Per panel: value count of each cell in the range, then the values.
+    panels: Vec<(Vec<usize>, Vec<f32>)>,
+}
+
+trait Accumulator: Copy + Default {
+    fn add(&mut self, value: f32);
+    fn get(&self) -> f32;
+}
+
+impl Accumulator for f32 {
+    #[inline]
+    fn add(&mut self, value: f32) {
+        *self += value;
+    }
+
+    fn get(&self) -> f32 {
+        *self
+    }
+}
+
+impl Accumulator for CompensatedSum {
+    #[inline]
+    fn add(&mut self, value: f32) {
+        CompensatedSum::add(self, value as f64);
+    }
+
+    fn get(&self) -> f32 {
+        self.value() as f32
+    }
+}
+
+fn reduce_shard(expr: &ExprReader<'_>, plan: &FusionPlan, cells: Range<usize>) -> Result<Shard> {
+    if sum::deterministic() {
+        reduce_shard_with::<CompensatedSum>(expr, plan, cells)
+    } else {
+        reduce_shard_with::<f32>(expr, plan, cells)
+    }
+}
+
+fn reduce_shard_with<A: Accumulator>(
+    expr: &ExprReader<'_>,
+    plan: &FusionPlan,
+    cells: Range<usize>,
+) -> Result<Shard> {
+    let len = cells.len();
+    let mut acc = vec![A::default(); plan.targets.len() * len];
+    let mut entries: Vec<Vec<(u32, f32)>> = vec![Vec::new(); plan.panels.len()];
+    let mut visit = |cell: u32, gene_id: usize, raw: f32| {
+        let local = cell as usize - cells.start;
+        let value = expr.value(cell, raw);
+        for t in &plan.membership[gene_id] {
+            let v = if plan.raw[t.target_id] { raw } else { value };
+            acc[t.target_id * len + local].add(v * t.scale);
+        }
+        for &p in &plan.panel_membership[gene_id] {
+            entries[p].push((cell, value));
+        }
+    };
+
+    if expr.is_cell_major() {
+        for cell in cells.clone() {
+            for (&gene, &count) in expr.cell_entries(cell)? {
+                if plan.touches(gene as usize) {
+                    visit(cell as u32, gene as usize, count as f32);
+                }
+            }
+        }
+    } else {
+        for gene_id in (0..plan.membership.len()).filter(|&g| plan.touches(g)) {
+            for (cell, value) in expr
+                .gene_entries_from(gene_id, cells.start as u32)?
+                .take_while(|&(cell, _)| (cell as usize) < cells.end)
+            {
+                if value.is_nan() {
+                    bail!("NaN encountered in expr.bin values");
+                }
+                visit(cell, gene_id, value);
+            }
+        }
+    }
+
+    let panels = entries
+        .into_iter()
+        .map(|e| group_by_cell(e, &cells))
+        .collect();
+    Ok(Shard {
+        sums: acc.iter().map(A::get).collect(),
+        panels,
+    })
+}
+
+// Stable counting sort: keeps the gene order within each cell.
+fn group_by_cell(entries: Vec<(u32, f32)>, cells: &Range<usize>) -> (Vec<usize>, Vec<f32>) {
+    let mut counts = vec![0usize; cells.len()];
+    for &(cell, _) in &entries {
+        counts[cell as usize - cells.start] += 1;
+    }
+    let mut next = Vec::with_capacity(counts.len());
+    let mut offset = 0usize;
+    for &c in &counts {
+        next.push(offset);
+        offset += c;
+    }
+    let mut values = vec![0.0f32; entries.len()];
+    for (cell, value) in entries {
+        let slot = &mut next[cell as usize - cells.start];
+        values[*slot] = value;
+        *slot += 1;
+    }
+    (counts, values)
 }
@@ -1,13 +1,63 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 
 use anyhow::{Context, Result, bail};
 
-use crate::geneset::GenesetDef;
+use crate::geneset::{GenesetDef, GenesetVersion};
+use crate::metadata::Species;
 
-pub fn load_builtin_v1() -> Result<Vec<GenesetDef>> {
-    let content = include_str!("../../assets/genesets/proteoqc_v1.tsv");
-    parse_geneset_tsv(content, "built-in v1")
+struct BuiltinAsset {
+    version: GenesetVersion,
+    species: Species,
+    source: &'static str,
+    content: &'static str,
+}
+
+const BUILTIN_ASSETS: [BuiltinAsset; 4] = [
+    BuiltinAsset {
+        version: GenesetVersion::V1,
+        species: Species::Human,
+        source: "built-in v1",
+        content: include_str!("../../assets/genesets/proteoqc_v1.tsv"),
+    },
+    BuiltinAsset {
+        version: GenesetVersion::V1,
+        species: Species::Mouse,
+        source: "built-in v1 (mouse)",
+        content: include_str!("../../assets/genesets/proteoqc_v1_mouse.tsv"),
+    },
+    BuiltinAsset {
+        version: GenesetVersion::V2,
+        species: Species::Human,
+        source: "built-in v2",
+        content: include_str!("../../assets/genesets/proteoqc_v2.tsv"),
+    },
+    BuiltinAsset {
+        version: GenesetVersion::V2,
+        species: Species::Mouse,
+        source: "built-in v2 (mouse)",
+        content: include_str!("../../assets/genesets/proteoqc_v2_mouse.tsv"),
+    },
+];
+
+// Unknown species fall back to the human asset; resolution is case-insensitive
+// and ortholog-aware either way.
+pub fn load_builtin_defs(version: GenesetVersion, species: Species) -> Result<Vec<GenesetDef>> {
+    let species = match species {
+        Species::Mouse => Species::Mouse,
+        Species::Human | Species::Unknown => Species::Human,
+    };
+    let asset = BUILTIN_ASSETS
+        .iter()
+        .find(|a| a.version == version && a.species == species)
+        .with_context(|| {
+            format!(
+                "no built-in {} genesets for {}",
+                version.as_str(),
+                species.as_str()
+            )
+        })?;
+    parse_geneset_tsv(asset.content, asset.source)
 }
 
 pub fn load_geneset_tsv(path: &Path) -> Result<Vec<GenesetDef>> {
@@ -50,8 +100,12 @@ fn parse_geneset_tsv(content: &str, source: &str) -> Result<Vec<GenesetDef>> {
             continue;
         }
         let parts: Vec<&str> = trimmed.split('\t').collect();
-        if parts.len() != 3 {
-            bail!("{}:{} malformed TSV (expected 3 columns)", source, line_no);
+        if parts.len() != 3 && parts.len() != 4 {
+            bail!(
+                "{}:{} malformed TSV (expected 3 or 4 columns)",
+                source,
+                line_no
+            );
         }
         let id = parts[0].trim();
         let axis_str = parts[1].trim();
@@ -75,6 +129,7 @@ fn parse_geneset_tsv(content: &str, source: &str) -> Result<Vec<GenesetDef>> {
                     id: id.to_string(),
                     axis,
                     genes: Vec::new(),
+                    aliases: BTreeMap::new(),
                 },
             );
         }
@@ -84,6 +139,17 @@ fn parse_geneset_tsv(content: &str, source: &str) -> Result<Vec<GenesetDef>> {
             bail!("{}:{} axis mismatch for geneset '{}'", source, line_no, id);
         }
         def.genes.push(gene.to_string());
+        if let Some(aliases) = parts.get(3) {
+            let aliases = aliases
+                .split(',')
+                .map(str::trim)
+                .filter(|a| !a.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>();
+            if !aliases.is_empty() {
+                def.aliases.insert(gene.to_string(), aliases);
+            }
+        }
     }
 
     let mut out = Vec::with_capacity(order.len());
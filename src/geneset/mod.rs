@@ -1,18 +1,42 @@
 mod loader;
+mod orthologs;
 mod resolve;
 
+use std::collections::BTreeMap;
 use std::path::Path;
 
 use anyhow::Result;
 
-pub use loader::{load_builtin_v1, load_geneset_tsv, merge_defs};
-pub use resolve::{ResolvedGeneset, resolve_collection};
+use crate::metadata::Species;
+
+pub use loader::{load_builtin_defs, load_geneset_tsv, merge_defs};
+pub use resolve::{
+    GeneMatch, MatchKind, ResolvedGeneset, SymbolResolver, build_id_index, resolve_collection,
+    resolve_collection_with,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GenesetVersion {
+    #[default]
+    V1,
+    V2,
+}
+
+impl GenesetVersion {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::V1 => "v1",
+            Self::V2 => "v2",
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct GenesetDef {
     pub id: String,
     pub axis: char,
     pub genes: Vec<String>,
+    pub aliases: BTreeMap<String, Vec<String>>,
 }
 
 #[derive(Debug, Clone)]
@@ -22,15 +46,18 @@ pub struct GenesetCollection {
     pub resolved: Vec<ResolvedGeneset>,
 }
 
-pub fn load_builtin() -> Result<GenesetCollection> {
-    let defs = load_builtin_v1()?;
+pub fn load_builtin(version: GenesetVersion, species: Species) -> Result<GenesetCollection> {
     Ok(GenesetCollection {
-        version: "v1".to_string(),
-        defs,
+        version: version.as_str().to_string(),
+        defs: load_builtin_defs(version, species)?,
         resolved: Vec::new(),
     })
 }
 
+pub fn load_builtin_for(species: Species) -> Result<GenesetCollection> {
+    load_builtin(GenesetVersion::default(), species)
+}
+
 pub fn load_user(path: &Path) -> Result<Vec<GenesetDef>> {
     load_geneset_tsv(path)
 }
@@ -0,0 +1,27 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// Upper-cased symbol -> upper-cased partner, both directions.
+pub fn ortholog_table() -> &'static HashMap<String, String> {
+    static TABLE: OnceLock<HashMap<String, String>> = OnceLock::new();
+    TABLE.get_or_init(|| parse_ortholog_tsv(include_str!("../../assets/orthologs/human_mouse.tsv")))
+}
+
+fn parse_ortholog_tsv(content: &str) -> HashMap<String, String> {
+    let mut table = HashMap::new();
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let mut parts = trimmed.split('\t');
+        let (Some(human), Some(mouse)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let human = human.trim().to_ascii_uppercase();
+        let mouse = mouse.trim().to_ascii_uppercase();
+        table.insert(mouse.clone(), human.clone());
+        table.insert(human, mouse);
+    }
+    table
+}
@@ -1,6 +1,37 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
+use crate::geneset::orthologs::ortholog_table;
 use crate::geneset::{GenesetCollection, GenesetDef};
+use crate::schema::v1::GeneMatchOut;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    Id,
+    Symbol,
+    CaseInsensitive,
+    Ortholog,
+    Alias,
+}
+
+impl MatchKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Symbol => "symbol",
+            Self::CaseInsensitive => "case_insensitive",
+            Self::Ortholog => "ortholog",
+            Self::Alias => "alias",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneMatch {
+    pub requested: String,
+    pub key: String,
+    pub via: MatchKind,
+    pub gene_id: usize,
+}
 
 #[derive(Debug, Clone)]
 pub struct ResolvedGeneset {
@@ -9,35 +40,149 @@ pub struct ResolvedGeneset {
     pub gene_ids: Vec<usize>,
     pub missing: Vec<String>,
     pub total: usize,
+    pub matches: Vec<GeneMatch>,
+}
+
+impl ResolvedGeneset {
+    pub fn coverage_matches(&self) -> Vec<GeneMatchOut> {
+        self.matches
+            .iter()
+            .map(|m| GeneMatchOut {
+                gene: m.requested.clone(),
+                key: m.key.clone(),
+                via: m.via.as_str().to_string(),
+            })
+            .collect()
+    }
+}
+
+// Feature IDs are keyed without the Ensembl version suffix; first row wins.
+pub fn build_id_index(ids: &[String]) -> HashMap<String, usize> {
+    let mut index = HashMap::with_capacity(ids.len());
+    for (i, id) in ids.iter().enumerate() {
+        index.entry(strip_version(id).to_string()).or_insert(i);
+    }
+    index
+}
+
+fn strip_version(id: &str) -> &str {
+    if id.starts_with("ENS")
+        && let Some((base, version)) = id.rsplit_once('.')
+        && !version.is_empty()
+        && version.chars().all(|c| c.is_ascii_digit())
+    {
+        return base;
+    }
+    id
+}
+
+// Gene lookup shared by geneset and panel resolution: feature ID, exact
+// symbol, case-insensitive symbol, then the human<->mouse ortholog table.
+pub struct SymbolResolver<'a> {
+    exact: &'a HashMap<String, usize>,
+    ids: Option<&'a HashMap<String, usize>>,
+    folded: HashMap<String, usize>,
+}
+
+impl<'a> SymbolResolver<'a> {
+    pub fn new(gene_index: &'a HashMap<String, usize>) -> Self {
+        let mut by_row = gene_index.iter().collect::<Vec<_>>();
+        by_row.sort_unstable_by_key(|(_, idx)| **idx);
+        let mut folded = HashMap::with_capacity(by_row.len());
+        for (symbol, &idx) in by_row {
+            folded.entry(symbol.to_ascii_uppercase()).or_insert(idx);
+        }
+        Self {
+            exact: gene_index,
+            ids: None,
+            folded,
+        }
+    }
+
+    pub fn with_ids(mut self, id_index: &'a HashMap<String, usize>) -> Self {
+        if !id_index.is_empty() {
+            self.ids = Some(id_index);
+        }
+        self
+    }
+
+    pub fn resolve(&self, key: &str) -> Option<usize> {
+        self.lookup(key).map(|(idx, _)| idx)
+    }
+
+    pub fn lookup(&self, key: &str) -> Option<(usize, MatchKind)> {
+        if let Some(&idx) = self.ids.and_then(|ids| ids.get(strip_version(key))) {
+            return Some((idx, MatchKind::Id));
+        }
+        if let Some(&idx) = self.exact.get(key) {
+            let via = if key.starts_with("ENS") {
+                MatchKind::Id
+            } else {
+                MatchKind::Symbol
+            };
+            return Some((idx, via));
+        }
+        let upper = key.to_ascii_uppercase();
+        if let Some(&idx) = self.folded.get(&upper) {
+            return Some((idx, MatchKind::CaseInsensitive));
+        }
+        ortholog_table()
+            .get(&upper)
+            .and_then(|partner| self.folded.get(partner))
+            .map(|&idx| (idx, MatchKind::Ortholog))
+    }
 }
 
 pub fn resolve_collection(
+    collection: GenesetCollection,
+    gene_index: &HashMap<String, usize>,
+) -> GenesetCollection {
+    resolve_collection_with(collection, &SymbolResolver::new(gene_index))
+}
+
+pub fn resolve_collection_with(
     mut collection: GenesetCollection,
-    gene_index: &std::collections::HashMap<String, usize>,
+    resolver: &SymbolResolver<'_>,
 ) -> GenesetCollection {
     let mut resolved = Vec::with_capacity(collection.defs.len());
     for def in &collection.defs {
-        resolved.push(resolve_def(def, gene_index));
+        resolved.push(resolve_def(def, resolver));
     }
     collection.resolved = resolved;
     collection
 }
 
-fn resolve_def(
-    def: &GenesetDef,
-    gene_index: &std::collections::HashMap<String, usize>,
-) -> ResolvedGeneset {
+fn resolve_def(def: &GenesetDef, resolver: &SymbolResolver<'_>) -> ResolvedGeneset {
     let mut gene_ids = Vec::new();
     let mut missing = Vec::new();
+    let mut matches = Vec::new();
     let mut seen = HashSet::new();
 
-    for symbol in &def.genes {
-        if let Some(&gid) = gene_index.get(symbol) {
-            if seen.insert(gid) {
-                gene_ids.push(gid);
+    for gene in &def.genes {
+        let aliases = def.aliases.get(gene).map(Vec::as_slice).unwrap_or(&[]);
+        let hit = resolver
+            .lookup(gene)
+            .map(|(gid, via)| (gid, gene, via))
+            .or_else(|| {
+                aliases.iter().find_map(|alias| {
+                    resolver
+                        .resolve(alias)
+                        .map(|gid| (gid, alias, MatchKind::Alias))
+                })
+            });
+        match hit {
+            Some((gid, key, via)) => {
+                if seen.insert(gid) {
+                    gene_ids.push(gid);
+                }
+                matches.push(GeneMatch {
+                    requested: gene.clone(),
+                    key: key.clone(),
+                    via,
+                    gene_id: gid,
+                });
             }
-        } else {
-            missing.push(symbol.clone());
+            None => missing.push(gene.clone()),
         }
     }
 
@@ -49,5 +194,6 @@ fn resolve_def(
         gene_ids,
         missing,
         total: def.genes.len(),
+        matches,
     }
 }
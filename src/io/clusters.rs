@@ -0,0 +1,126 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::io::h5ad;
+
+pub const UNASSIGNED_CLUSTER: &str = "unassigned";
+pub const ALL_CELLS_CLUSTER: &str = "all_cells";
+
+const HEADER_KEYS: [&str; 5] = ["barcode", "barcodes", "cell", "cell_id", "obs_names"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClusterSource {
+    File(PathBuf),
+    Obs(String),
+}
+
+impl ClusterSource {
+    pub fn parse(spec: &str) -> Result<Self> {
+        if let Some(column) = spec.strip_prefix("obs:") {
+            if column.is_empty() {
+                bail!("--clusters obs: requires a column name");
+            }
+            return Ok(Self::Obs(column.to_string()));
+        }
+        if spec.is_empty() {
+            bail!("--clusters requires a file path or obs:<column>");
+        }
+        Ok(Self::File(PathBuf::from(spec)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClusterAssignment {
+    pub labels: Vec<String>,
+    pub unassigned: usize,
+    pub unknown_barcodes: usize,
+}
+
+pub fn load_clusters(
+    source: &ClusterSource,
+    input: &Path,
+    cells: &[String],
+) -> Result<ClusterAssignment> {
+    let pairs = match source {
+        ClusterSource::File(path) => read_cluster_tsv(path)?,
+        ClusterSource::Obs(column) => {
+            let (barcodes, labels) = h5ad::read_h5ad_obs_column(input, column)?;
+            barcodes.into_iter().zip(labels).collect()
+        }
+    };
+    assign_clusters(cells, &pairs)
+}
+
+pub fn read_cluster_tsv(path: &Path) -> Result<Vec<(String, String)>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read clusters file {}", path.display()))?;
+    let mut pairs = Vec::new();
+    for (line_no, line) in content.lines().enumerate() {
+        let trimmed = line.trim_end_matches('\r');
+        if trimmed.trim().is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let mut parts = trimmed.split('\t');
+        let barcode = parts.next().unwrap_or("").trim();
+        let label = parts.next().map(str::trim).unwrap_or("");
+        if pairs.is_empty() && HEADER_KEYS.contains(&barcode.to_ascii_lowercase().as_str()) {
+            continue;
+        }
+        if barcode.is_empty() || label.is_empty() {
+            bail!(
+                "clusters file {} line {}: expected <barcode>\\t<label>",
+                path.display(),
+                line_no + 1
+            );
+        }
+        pairs.push((barcode.to_string(), label.to_string()));
+    }
+    if pairs.is_empty() {
+        bail!("clusters file {} is empty", path.display());
+    }
+    Ok(pairs)
+}
+
+pub fn assign_clusters(cells: &[String], pairs: &[(String, String)]) -> Result<ClusterAssignment> {
+    let mut by_barcode: HashMap<&str, &str> = HashMap::with_capacity(pairs.len());
+    for (barcode, label) in pairs {
+        if let Some(prev) = by_barcode.insert(barcode.as_str(), label.as_str())
+            && prev != label
+        {
+            bail!(
+                "barcode {} has conflicting cluster labels: {} vs {}",
+                barcode,
+                prev,
+                label
+            );
+        }
+    }
+
+    let mut labels = Vec::with_capacity(cells.len());
+    let mut matched: HashSet<&str> = HashSet::new();
+    let mut unassigned = 0usize;
+    for cell in cells {
+        match by_barcode.get(cell.as_str()) {
+            Some(label) => {
+                matched.insert(cell.as_str());
+                labels.push((*label).to_string());
+            }
+            None => {
+                unassigned += 1;
+                labels.push(UNASSIGNED_CLUSTER.to_string());
+            }
+        }
+    }
+    if !cells.is_empty() && unassigned == cells.len() {
+        bail!("no cell barcodes matched the cluster annotation");
+    }
+
+    Ok(ClusterAssignment {
+        labels,
+        unassigned,
+        unknown_barcodes: by_barcode.len() - matched.len(),
+    })
+}
@@ -0,0 +1,279 @@
+use std::io::BufRead;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use kira_scio::normalize::{normalize_barcode, normalize_gene_symbol};
+
+use crate::io::h5ad_layer::{MatrixValues, VALUE_SAMPLE, classify_values};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenseOrientation {
+    // One line per gene, one column per cell (R's default for a count matrix).
+    GenesByCells,
+    CellsByGenes,
+}
+
+impl DenseOrientation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::GenesByCells => "genes_x_cells",
+            Self::CellsByGenes => "cells_x_genes",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DenseLayout {
+    pub delimiter: char,
+    pub orientation: DenseOrientation,
+}
+
+#[derive(Debug)]
+pub struct DenseSummary {
+    pub layout: DenseLayout,
+    pub genes: Vec<String>,
+    pub cells: Vec<String>,
+    pub nnz: usize,
+    pub values: MatrixValues,
+}
+
+// One streaming pass: the header gives the column names, every data line
+// contributes its row name and nonzero count. Orientation is only decided at
+// the end, once both name sets are known.
+pub fn read_dense_summary(path: &Path) -> Result<DenseSummary> {
+    let mut header: Option<(char, Vec<String>)> = None;
+    let mut row_names = Vec::new();
+    let mut width: Option<usize> = None;
+    let mut nnz = 0usize;
+    let mut sample = Vec::new();
+
+    for_each_line(path, |line_no, line| {
+        let Some((delimiter, columns)) = &header else {
+            let delimiter = detect_delimiter(line);
+            header = Some((
+                delimiter,
+                split_line(line, delimiter).map(unquote).collect(),
+            ));
+            return Ok(());
+        };
+        let mut fields = split_line(line, *delimiter);
+        let name = fields.next().map(unquote).unwrap_or_default();
+        let mut n_values = 0usize;
+        for (col, token) in fields.enumerate() {
+            let value = parse_value(token, path, line_no, col)?;
+            n_values += 1;
+            if value != 0.0 {
+                nnz += 1;
+                if sample.len() < VALUE_SAMPLE {
+                    sample.push(value);
+                }
+            }
+        }
+        check_width(path, line_no, columns.len(), &mut width, n_values)?;
+        row_names.push(name);
+        Ok(())
+    })?;
+
+    let Some((delimiter, mut columns)) = header else {
+        bail!("{}: dense matrix is empty", path.display());
+    };
+    if row_names.is_empty() {
+        bail!("{}: dense matrix has no data lines", path.display());
+    }
+    // A header one field wider than the values carries a corner cell naming
+    // the row-name column; R's write.table omits it.
+    let corner = if Some(columns.len()) != width {
+        Some(columns.remove(0))
+    } else {
+        None
+    };
+
+    let orientation = detect_orientation(corner.as_deref(), &columns, &row_names);
+    let (genes, cells) = match orientation {
+        DenseOrientation::GenesByCells => (row_names, columns),
+        DenseOrientation::CellsByGenes => (columns, row_names),
+    };
+    let genes = genes
+        .iter()
+        .enumerate()
+        .map(|(i, g)| normalize_gene_symbol(g, Some(g), i))
+        .collect();
+    let cells = cells
+        .iter()
+        .enumerate()
+        .map(|(i, c)| normalize_barcode(c, i))
+        .collect();
+
+    Ok(DenseSummary {
+        layout: DenseLayout {
+            delimiter,
+            orientation,
+        },
+        genes,
+        cells,
+        nnz,
+        values: classify_values(&sample),
+    })
+}
+
+// Streams nonzero entries as (gene, cell, value) in file order.
+pub fn for_each_entry<F>(path: &Path, layout: &DenseLayout, mut f: F) -> Result<()>
+where
+    F: FnMut(usize, usize, f32) -> Result<()>,
+{
+    let mut header_seen = false;
+    let mut row = 0usize;
+    for_each_line(path, |line_no, line| {
+        if !header_seen {
+            header_seen = true;
+            return Ok(());
+        }
+        for (col, token) in split_line(line, layout.delimiter).skip(1).enumerate() {
+            let value = parse_value(token, path, line_no, col)?;
+            if value == 0.0 {
+                continue;
+            }
+            match layout.orientation {
+                DenseOrientation::GenesByCells => f(row, col, value)?,
+                DenseOrientation::CellsByGenes => f(col, row, value)?,
+            }
+        }
+        row += 1;
+        Ok(())
+    })
+}
+
+fn for_each_line<F>(path: &Path, mut f: F) -> Result<()>
+where
+    F: FnMut(usize, &str) -> Result<()>,
+{
+    let mut reader =
+        kira_scio::open_maybe_gz_existing(path).map_err(|e| anyhow::anyhow!(e.message))?;
+    let mut line = String::new();
+    let mut line_no = 0usize;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        line_no += 1;
+        let t = line.trim_end_matches(['\r', '\n']);
+        if t.trim().is_empty() || t.starts_with('#') {
+            continue;
+        }
+        f(line_no, t)?;
+    }
+    Ok(())
+}
+
+// Data lines hold a row name plus one value per column; the header either
+// has a corner cell (one field more than the values) or not. The first data
+// line fixes the width for the rest.
+fn check_width(
+    path: &Path,
+    line_no: usize,
+    header: usize,
+    width: &mut Option<usize>,
+    n_values: usize,
+) -> Result<()> {
+    let expected = *width.get_or_insert(n_values);
+    if n_values != expected || (n_values != header && n_values + 1 != header) {
+        bail!(
+            "{}: line {} has {} values, header has {} columns",
+            path.display(),
+            line_no,
+            n_values,
+            header
+        );
+    }
+    Ok(())
+}
+
+fn detect_delimiter(header: &str) -> char {
+    if header.contains('\t') { '\t' } else { ',' }
+}
+
+fn split_line(line: &str, delimiter: char) -> impl Iterator<Item = &str> {
+    line.split(delimiter).map(str::trim)
+}
+
+fn unquote(token: &str) -> String {
+    token
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .unwrap_or(token)
+        .to_string()
+}
+
+fn parse_value(token: &str, path: &Path, line_no: usize, col: usize) -> Result<f32> {
+    if token.is_empty() {
+        return Ok(0.0);
+    }
+    let value = token.parse::<f32>().with_context(|| {
+        format!(
+            "{}: invalid value '{}' at line {} column {}",
+            path.display(),
+            token,
+            line_no,
+            col + 2
+        )
+    })?;
+    if !value.is_finite() {
+        bail!(
+            "{}: non-finite value '{}' at line {} column {}",
+            path.display(),
+            token,
+            line_no,
+            col + 2
+        );
+    }
+    Ok(value)
+}
+
+// A corner cell names the row-name column outright; otherwise whichever
+// side carries more sequencing barcodes is the cell axis.
+fn detect_orientation(
+    corner: Option<&str>,
+    columns: &[String],
+    row_names: &[String],
+) -> DenseOrientation {
+    match corner.map(|c| c.trim().to_ascii_lowercase()).as_deref() {
+        Some("gene" | "genes" | "gene_id" | "gene_symbol" | "symbol" | "feature" | "features") => {
+            return DenseOrientation::GenesByCells;
+        }
+        Some("cell" | "cells" | "cell_id" | "cellid" | "barcode" | "barcodes") => {
+            return DenseOrientation::CellsByGenes;
+        }
+        _ => {}
+    }
+    if barcode_fraction(row_names) > barcode_fraction(columns) {
+        DenseOrientation::CellsByGenes
+    } else {
+        DenseOrientation::GenesByCells
+    }
+}
+
+fn barcode_fraction(names: &[String]) -> f64 {
+    if names.is_empty() {
+        return 0.0;
+    }
+    let hits = names.iter().filter(|n| looks_like_barcode(n)).count();
+    hits as f64 / names.len() as f64
+}
+
+// 10x/Drop-seq barcodes contain a run of at least 12 nucleotides, possibly
+// with a sample prefix or a "-1"/".1" suffix added by R.
+pub fn looks_like_barcode(name: &str) -> bool {
+    let mut run = 0usize;
+    for c in name.chars() {
+        if matches!(c, 'A' | 'C' | 'G' | 'T' | 'N') {
+            run += 1;
+            if run >= 12 {
+                return true;
+            }
+        } else {
+            run = 0;
+        }
+    }
+    false
+}
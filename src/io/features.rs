@@ -1,23 +1,152 @@
+use std::io::BufRead;
 use std::path::Path;
 
 use anyhow::{Result, bail};
-use kira_scio::api::{Reader, ReaderOptions};
-use kira_scio::detect::DetectedFormat;
+use kira_scio::normalize::normalize_gene_symbol;
+
+#[derive(Debug, Clone, Default)]
+pub struct FeatureTable {
+    pub ids: Vec<String>,
+    pub symbols: Vec<String>,
+    pub feature_types: Vec<String>,
+}
+
+pub const GENE_EXPRESSION: &str = "Gene Expression";
+pub const ANTIBODY_CAPTURE: &str = "Antibody Capture";
+// `--feature-type all` disables filtering.
+pub const ALL_FEATURE_TYPES: &str = "all";
+
+// Feature rows kept aside from scoring, with their input row numbers.
+#[derive(Debug, Clone, Default)]
+pub struct RetainedFeatures {
+    pub table: FeatureTable,
+    pub rows: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FeatureSelection {
+    pub table: FeatureTable,
+    // Input row -> kept row; None when nothing was dropped.
+    pub row_map: Option<Vec<Option<u32>>>,
+    // Dropped rows per feature type, in first-seen order.
+    pub dropped: Vec<(String, usize)>,
+    // Dropped Antibody Capture rows (CITE-seq ADTs) for protein-level use.
+    pub antibody: Option<RetainedFeatures>,
+}
+
+impl FeatureSelection {
+    pub fn n_input_rows(&self) -> usize {
+        match &self.row_map {
+            Some(row_map) => row_map.len(),
+            None => self.table.symbols.len(),
+        }
+    }
+
+    pub fn gene_of(&self, row: usize) -> Option<u32> {
+        match &self.row_map {
+            Some(row_map) => row_map.get(row).copied().flatten(),
+            None => Some(row as u32),
+        }
+    }
+
+    pub fn dropped_warning(&self) -> Option<String> {
+        if self.dropped.is_empty() {
+            return None;
+        }
+        let total: usize = self.dropped.iter().map(|(_, n)| n).sum();
+        let parts = self
+            .dropped
+            .iter()
+            .map(|(kind, n)| format!("{} x{}", kind, n))
+            .collect::<Vec<_>>();
+        Some(format!(
+            "dropped {} features by feature_type: {}",
+            total,
+            parts.join(", ")
+        ))
+    }
+}
+
+// Keeps the rows whose feature_type equals `keep` (case-insensitive).
+pub fn select_feature_type(table: FeatureTable, keep: &str) -> Result<FeatureSelection> {
+    let keeps = |kind: &str| keep == ALL_FEATURE_TYPES || kind.eq_ignore_ascii_case(keep);
+    if table.feature_types.iter().all(|t| keeps(t)) {
+        return Ok(FeatureSelection {
+            table,
+            ..FeatureSelection::default()
+        });
+    }
+
+    let mut selected = FeatureTable::default();
+    let mut antibody = RetainedFeatures::default();
+    let mut row_map = Vec::with_capacity(table.feature_types.len());
+    let mut dropped: Vec<(String, usize)> = Vec::new();
+    for (i, kind) in table.feature_types.iter().enumerate() {
+        let target = if keeps(kind) {
+            row_map.push(Some(selected.symbols.len() as u32));
+            &mut selected
+        } else {
+            row_map.push(None);
+            match dropped.iter_mut().find(|(k, _)| k == kind) {
+                Some((_, n)) => *n += 1,
+                None => dropped.push((kind.clone(), 1)),
+            }
+            if kind != ANTIBODY_CAPTURE {
+                continue;
+            }
+            antibody.rows.push(i as u32);
+            &mut antibody.table
+        };
+        target.ids.push(table.ids[i].clone());
+        target.symbols.push(table.symbols[i].clone());
+        target.feature_types.push(kind.clone());
+    }
+    if selected.symbols.is_empty() {
+        bail!("no '{}' features in input", keep);
+    }
+    Ok(FeatureSelection {
+        table: selected,
+        row_map: Some(row_map),
+        dropped,
+        antibody: (!antibody.rows.is_empty()).then_some(antibody),
+    })
+}
 
 pub fn read_features(path: &Path) -> Result<Vec<String>> {
-    let md = Reader::with_options(
-        path,
-        ReaderOptions {
-            force_format: Some(DetectedFormat::Mtx10x),
-            strict: true,
-        },
-    )
-    .read_metadata()
-    .map_err(|e| anyhow::anyhow!(e.message))?;
-
-    if md.gene_symbols.is_empty() {
+    Ok(read_feature_table(path)?.symbols)
+}
+
+// features.tsv: id, symbol, feature_type (legacy genes.tsv has the first two).
+// Symbols follow the kira-scio normalization so gene_index keys are unchanged.
+pub fn read_feature_table(path: &Path) -> Result<FeatureTable> {
+    let reader = kira_scio::open_maybe_gz_existing(path).map_err(|e| anyhow::anyhow!(e.message))?;
+    let mut table = FeatureTable::default();
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        let t = line.trim();
+        if t.is_empty() {
+            continue;
+        }
+        let cols = t.split('\t').collect::<Vec<_>>();
+        let id = cols.first().copied().unwrap_or_default();
+        // An empty symbol column falls back to the feature ID.
+        let symbol = cols.get(1).copied().filter(|s| !s.trim().is_empty());
+        table.ids.push(normalize_gene_symbol(id, Some(id), line_no));
+        table
+            .symbols
+            .push(normalize_gene_symbol(id, symbol, line_no));
+        table.feature_types.push(
+            cols.get(2)
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .unwrap_or("Gene Expression")
+                .to_string(),
+        );
+    }
+
+    if table.symbols.is_empty() {
         bail!("features.tsv is empty");
     }
 
-    Ok(md.gene_symbols)
+    Ok(table)
 }
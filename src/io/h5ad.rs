@@ -1,16 +1,22 @@
 use std::path::Path;
 
-use anyhow::Result;
-use kira_scio::api::{Reader, ReaderOptions};
-use kira_scio::detect::DetectedFormat;
+use anyhow::{Context, Result, bail};
+use hdf5::types::{VarLenAscii, VarLenUnicode};
+use kira_scio::normalize::{normalize_barcode, normalize_gene_symbol};
+
+use crate::io::h5ad_layer::{
+    H5adLayer, MatrixValues, SparseOrientation, VALUE_SAMPLE, classify_values,
+};
 
 #[derive(Debug)]
 pub struct H5adSummary {
     pub genes: Vec<String>,
+    pub gene_ids: Vec<String>,
     pub cells: Vec<String>,
     pub nnz: usize,
     pub nrows: usize,
     pub ncols: usize,
+    pub values: MatrixValues,
     pub warnings: Vec<String>,
 }
 
@@ -21,62 +27,357 @@ pub struct H5adSparseMeta {
     pub nnz: usize,
 }
 
-pub fn read_h5ad_summary(path: &Path) -> Result<H5adSummary> {
-    let reader = Reader::with_options(
-        path,
-        ReaderOptions {
-            force_format: Some(DetectedFormat::H5ad),
-            strict: true,
-        },
-    );
-    let canonical = reader.read_all().map_err(|e| anyhow::anyhow!(e.message))?;
+// A layer is either an anndata sparse group (data/indices/indptr) or a dense
+// n_obs x n_vars dataset.
+enum LayerMatrix {
+    Sparse(hdf5::Group),
+    Dense(hdf5::Dataset),
+}
+
+// Reads names, dimensions, nnz and a sample of stored values without
+// loading the matrix itself (dense layers are the exception: their nnz is
+// only known after a scan).
+pub fn read_h5ad_summary(path: &Path, layer: &H5adLayer) -> Result<H5adSummary> {
+    let file =
+        hdf5::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let (cells, gene_ids, genes, warnings) = read_names(&file, layer)?;
+
+    let (nnz, values) = match open_layer(&file, layer)? {
+        LayerMatrix::Sparse(group) => {
+            sparse_orientation(&group, layer, genes.len(), cells.len())?;
+            let data = group
+                .dataset("data")
+                .with_context(|| format!("missing {}/data", layer.matrix_path()))?;
+            let nnz = data.size();
+            let sample = data
+                .read_slice_1d::<f32, _>(0..nnz.min(VALUE_SAMPLE))?
+                .to_vec();
+            (nnz, classify_values(&sample))
+        }
+        LayerMatrix::Dense(ds) => {
+            check_dense_shape(&ds, layer, genes.len(), cells.len())?;
+            let dense = ds.read_raw::<f32>()?;
+            let stored = dense.into_iter().filter(|&v| v != 0.0).collect::<Vec<_>>();
+            (stored.len(), classify_values(&stored))
+        }
+    };
 
     Ok(H5adSummary {
-        genes: canonical.metadata.gene_symbols,
-        cells: canonical.metadata.barcodes,
-        nnz: canonical.matrix.values.len(),
-        nrows: canonical.matrix.n_genes,
-        ncols: canonical.matrix.n_cells,
-        warnings: Vec::new(),
+        nrows: genes.len(),
+        ncols: cells.len(),
+        genes,
+        gene_ids,
+        cells,
+        nnz,
+        values,
+        warnings,
     })
 }
 
 pub fn read_h5ad_sparse(
     path: &Path,
-) -> Result<(H5adSparseMeta, String, usize, Vec<u32>, Vec<f32>, Vec<u64>)> {
-    let matrix = Reader::with_options(
-        path,
-        ReaderOptions {
-            force_format: Some(DetectedFormat::H5ad),
-            strict: true,
-        },
-    )
-    .read_matrix()
-    .map_err(|e| anyhow::anyhow!(e.message))?;
-
-    let indices = matrix
-        .row_idx
-        .iter()
-        .map(|&x| u32::try_from(x).map_err(|_| anyhow::anyhow!("row index exceeds u32")))
-        .collect::<Result<Vec<_>>>()?;
-    let indptr = matrix
-        .col_ptr
-        .iter()
-        .map(|&x| u64::try_from(x).map_err(|_| anyhow::anyhow!("indptr exceeds u64")))
-        .collect::<Result<Vec<_>>>()?;
+    layer: &H5adLayer,
+) -> Result<(
+    H5adSparseMeta,
+    String,
+    SparseOrientation,
+    Vec<u32>,
+    Vec<f32>,
+    Vec<u64>,
+)> {
+    let file =
+        hdf5::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let n_cells = frame_len(&file, "obs")?;
+    let n_genes = frame_len(&file, layer.var_path())?;
+
+    let (encoding, orientation, indices, data, indptr) = match open_layer(&file, layer)? {
+        LayerMatrix::Sparse(group) => {
+            let (encoding, orientation) = sparse_orientation(&group, layer, n_genes, n_cells)?;
+            let indptr = group.dataset("indptr")?.read_raw::<u64>()?;
+            let indices = group.dataset("indices")?.read_raw::<u32>()?;
+            let data = group.dataset("data")?.read_raw::<f32>()?;
+            (encoding, orientation, indices, data, indptr)
+        }
+        LayerMatrix::Dense(ds) => {
+            check_dense_shape(&ds, layer, n_genes, n_cells)?;
+            // Row-major n_obs x n_vars, so rows become cell-major entries.
+            let dense = ds.read_raw::<f32>()?;
+            let mut indptr = Vec::with_capacity(n_cells + 1);
+            let mut indices = Vec::new();
+            let mut data = Vec::new();
+            indptr.push(0u64);
+            for row in dense.chunks(n_genes.max(1)).take(n_cells) {
+                for (gene, &value) in row.iter().enumerate() {
+                    if value != 0.0 {
+                        indices.push(gene as u32);
+                        data.push(value);
+                    }
+                }
+                indptr.push(indices.len() as u64);
+            }
+            (
+                "dense".to_string(),
+                SparseOrientation::CellMajor,
+                indices,
+                data,
+                indptr,
+            )
+        }
+    };
+    if indices.len() != data.len() {
+        bail!(
+            "{}: indices length {} != data length {}",
+            layer.matrix_path(),
+            indices.len(),
+            data.len()
+        );
+    }
 
     let meta = H5adSparseMeta {
-        nrows: matrix.n_genes,
-        ncols: matrix.n_cells,
-        nnz: matrix.values.len(),
+        nrows: n_genes,
+        ncols: n_cells,
+        nnz: data.len(),
+    };
+    Ok((meta, encoding, orientation, indices, data, indptr))
+}
+
+fn open_layer(file: &hdf5::File, layer: &H5adLayer) -> Result<LayerMatrix> {
+    let matrix_path = layer.matrix_path();
+    if let Ok(group) = file.group(&matrix_path) {
+        return Ok(LayerMatrix::Sparse(group));
+    }
+    match file.dataset(&matrix_path) {
+        Ok(ds) => Ok(LayerMatrix::Dense(ds)),
+        Err(_) => bail!("h5ad has no {} matrix", matrix_path),
+    }
+}
+
+// Orientation from `encoding-type` (or h5sparse's `h5sparse_format`),
+// checked against the indptr length; returns the encoding name for logs.
+fn sparse_orientation(
+    group: &hdf5::Group,
+    layer: &H5adLayer,
+    n_genes: usize,
+    n_cells: usize,
+) -> Result<(String, SparseOrientation)> {
+    let len = group
+        .dataset("indptr")
+        .with_context(|| format!("missing {}/indptr", layer.matrix_path()))?
+        .size();
+    let encoding = read_attr_string(group, "encoding-type")
+        .or_else(|| read_attr_string(group, "h5sparse_format").map(|f| f + "_matrix"));
+    let orientation = SparseOrientation::resolve(encoding.as_deref(), len, n_genes, n_cells)
+        .with_context(|| format!("{}: cannot orient sparse matrix", layer.matrix_path()))?;
+    let encoding = encoding.unwrap_or_else(|| match orientation {
+        SparseOrientation::CellMajor => "csr_matrix".to_string(),
+        SparseOrientation::GeneMajor => "csc_matrix".to_string(),
+    });
+    Ok((encoding, orientation))
+}
+
+fn check_dense_shape(
+    ds: &hdf5::Dataset,
+    layer: &H5adLayer,
+    n_genes: usize,
+    n_cells: usize,
+) -> Result<()> {
+    let shape = ds.shape();
+    if shape != [n_cells, n_genes] {
+        bail!(
+            "{}: dense shape {:?} does not match ({}, {})",
+            layer.matrix_path(),
+            shape,
+            n_cells,
+            n_genes
+        );
+    }
+    Ok(())
+}
+
+// (barcodes, gene ids, gene symbols, warnings) for the selected layer. raw/X
+// takes its genes from raw/var.
+fn read_names(
+    file: &hdf5::File,
+    layer: &H5adLayer,
+) -> Result<(Vec<String>, Vec<String>, Vec<String>, Vec<String>)> {
+    let obs = file.group("obs").context("h5ad has no obs group")?;
+    let cells = read_index(&obs)
+        .context("failed to read obs names")?
+        .iter()
+        .enumerate()
+        .map(|(i, b)| normalize_barcode(b, i))
+        .collect::<Vec<_>>();
+
+    let var_path = layer.var_path();
+    let var = file
+        .group(var_path)
+        .with_context(|| format!("h5ad has no {} group", var_path))?;
+    let mut warnings = Vec::new();
+    let symbols = match read_var_symbols(&var) {
+        Ok(symbols) => symbols,
+        Err(e) => {
+            warnings.push(format!("failed to read {} gene symbols: {e}", var_path));
+            None
+        }
+    };
+
+    // var/_index usually holds Ensembl IDs when a symbol column is present.
+    let gene_ids = match (read_index(&var), &symbols) {
+        (Ok(ids), _) => ids
+            .iter()
+            .enumerate()
+            .map(|(i, g)| normalize_gene_symbol(g, Some(g), i))
+            .collect::<Vec<_>>(),
+        (Err(_), Some(symbols)) => symbols.clone(),
+        (Err(e), None) => return Err(e.context(format!("failed to read {} names", var_path))),
     };
+    let genes = match symbols {
+        Some(symbols) if symbols.len() == gene_ids.len() => symbols
+            .iter()
+            .zip(gene_ids.iter())
+            .enumerate()
+            .map(|(i, (symbol, id))| {
+                let symbol = Some(symbol.as_str()).filter(|s| !s.trim().is_empty());
+                normalize_gene_symbol(id, symbol, i)
+            })
+            .collect(),
+        Some(symbols) => {
+            warnings.push(format!(
+                "{} symbol column length {} != n_vars {}",
+                var_path,
+                symbols.len(),
+                gene_ids.len()
+            ));
+            gene_ids.clone()
+        }
+        None => gene_ids.clone(),
+    };
+    Ok((cells, gene_ids, genes, warnings))
+}
+
+fn frame_len(file: &hdf5::File, frame: &str) -> Result<usize> {
+    let group = file
+        .group(frame)
+        .with_context(|| format!("h5ad has no {} group", frame))?;
+    let name = index_name(&group);
+    if let Ok(ds) = group.dataset(&name) {
+        return Ok(ds.size());
+    }
+    // Frames written without an index: fall back to the first symbol column.
+    Ok(read_var_symbols(&group)?
+        .with_context(|| format!("h5ad {} has no index", frame))?
+        .len())
+}
+
+fn index_name(frame: &hdf5::Group) -> String {
+    read_attr_string(frame, "_index").unwrap_or_else(|| "_index".to_string())
+}
+
+fn read_index(frame: &hdf5::Group) -> Result<Vec<String>> {
+    let name = index_name(frame);
+    read_string_column(&frame.dataset(&name)?).with_context(|| format!("failed to read {}", name))
+}
+
+fn read_attr_string(group: &hdf5::Group, name: &str) -> Option<String> {
+    let attr = group.attr(name).ok()?;
+    if let Ok(value) = attr.read_scalar::<VarLenUnicode>() {
+        return Some(value.as_str().to_string());
+    }
+    attr.read_scalar::<VarLenAscii>()
+        .ok()
+        .map(|v| v.as_str().to_string())
+}
+
+// Returns (obs_names, labels) for an obs column. Handles anndata >= 0.8
+// categoricals (group with categories/codes), legacy `__categories` codes,
+// and plain string or numeric columns.
+pub fn read_h5ad_obs_column(path: &Path, column: &str) -> Result<(Vec<String>, Vec<String>)> {
+    let file =
+        hdf5::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let obs = file.group("obs").context("h5ad has no obs group")?;
+
+    let index_name = index_name(&obs);
+    let barcodes = read_string_column(&obs.dataset(&index_name)?)
+        .with_context(|| format!("failed to read obs/{}", index_name))?;
+
+    if !obs.link_exists(column) {
+        bail!("obs column '{}' not found in {}", column, path.display());
+    }
+    let labels = read_frame_column(&obs, column)?;
+
+    if labels.len() != barcodes.len() {
+        bail!(
+            "obs column '{}' length {} != n_obs {}",
+            column,
+            labels.len(),
+            barcodes.len()
+        );
+    }
+    Ok((barcodes, labels))
+}
+
+// Symbol columns written by scanpy/cellxgene, in order of preference.
+const VAR_SYMBOL_COLUMNS: [&str; 4] = ["gene_symbols", "feature_name", "gene_name", "symbol"];
+
+fn read_var_symbols(var: &hdf5::Group) -> Result<Option<Vec<String>>> {
+    for column in VAR_SYMBOL_COLUMNS {
+        if !var.link_exists(column) {
+            continue;
+        }
+        let symbols = read_frame_column(var, column)
+            .with_context(|| format!("failed to read var/{}", column))?;
+        return Ok(Some(symbols));
+    }
+    Ok(None)
+}
+
+fn read_frame_column(frame: &hdf5::Group, column: &str) -> Result<Vec<String>> {
+    if let Ok(group) = frame.group(column) {
+        let categories = read_string_column(&group.dataset("categories")?)?;
+        let codes = group.dataset("codes")?.read_raw::<i64>()?;
+        return decode_categorical(&categories, &codes);
+    }
+    let ds = frame.dataset(column)?;
+    let legacy = frame
+        .group("__categories")
+        .ok()
+        .and_then(|g| g.dataset(column).ok());
+    match legacy {
+        Some(categories) => {
+            let categories = read_string_column(&categories)?;
+            decode_categorical(&categories, &ds.read_raw::<i64>()?)
+        }
+        None => read_string_column(&ds),
+    }
+}
+
+fn read_string_column(ds: &hdf5::Dataset) -> Result<Vec<String>> {
+    if let Ok(values) = ds.read_raw::<VarLenUnicode>() {
+        return Ok(values.iter().map(|v| v.as_str().to_string()).collect());
+    }
+    if let Ok(values) = ds.read_raw::<VarLenAscii>() {
+        return Ok(values.iter().map(|v| v.as_str().to_string()).collect());
+    }
+    if let Ok(values) = ds.read_raw::<i64>() {
+        return Ok(values.iter().map(|v| v.to_string()).collect());
+    }
+    let values = ds
+        .read_raw::<f64>()
+        .context("unsupported obs column dtype")?;
+    Ok(values.iter().map(|v| v.to_string()).collect())
+}
 
-    Ok((
-        meta,
-        "csc_matrix".to_string(),
-        indptr.len(),
-        indices,
-        matrix.values,
-        indptr,
-    ))
+fn decode_categorical(categories: &[String], codes: &[i64]) -> Result<Vec<String>> {
+    let mut labels = Vec::with_capacity(codes.len());
+    for &code in codes {
+        if code < 0 {
+            labels.push(crate::io::clusters::UNASSIGNED_CLUSTER.to_string());
+            continue;
+        }
+        let label = categories
+            .get(code as usize)
+            .with_context(|| format!("categorical code {} out of range", code))?;
+        labels.push(label.clone());
+    }
+    Ok(labels)
 }
@@ -0,0 +1,141 @@
+use std::fmt;
+
+use anyhow::{Result, bail};
+
+// Which matrix of an .h5ad file is scored: `X`, `raw/X`, or `layers/<name>`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum H5adLayer {
+    #[default]
+    X,
+    Raw,
+    Layer(String),
+}
+
+impl H5adLayer {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "X" => Ok(Self::X),
+            "raw" | "raw/X" => Ok(Self::Raw),
+            _ => match value.strip_prefix("layers/") {
+                Some(name) if !name.is_empty() && !name.contains('/') => {
+                    Ok(Self::Layer(name.to_string()))
+                }
+                _ => bail!(
+                    "invalid h5ad layer '{}': expected X, raw or layers/<name>",
+                    value
+                ),
+            },
+        }
+    }
+
+    pub fn matrix_path(&self) -> String {
+        match self {
+            Self::X => "X".to_string(),
+            Self::Raw => "raw/X".to_string(),
+            Self::Layer(name) => format!("layers/{}", name),
+        }
+    }
+
+    // raw/X carries its own gene frame, which may be wider than var.
+    pub fn var_path(&self) -> &'static str {
+        match self {
+            Self::Raw => "raw/var",
+            Self::X | Self::Layer(_) => "var",
+        }
+    }
+}
+
+impl fmt::Display for H5adLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Raw => f.write_str("raw"),
+            _ => f.write_str(&self.matrix_path()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatrixValues {
+    Counts,
+    Normalized,
+}
+
+impl MatrixValues {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Counts => "counts",
+            Self::Normalized => "normalized",
+        }
+    }
+}
+
+// Number of stored values inspected when classifying a matrix.
+pub const VALUE_SAMPLE: usize = 100_000;
+
+// Raw UMI counts are non-negative integers; log1p, scaled or CPM matrices
+// show fractional (or negative) values almost immediately.
+pub fn classify_values(values: &[f32]) -> MatrixValues {
+    let counts = values
+        .iter()
+        .take(VALUE_SAMPLE)
+        .all(|&v| v >= 0.0 && v.fract() == 0.0);
+    if counts {
+        MatrixValues::Counts
+    } else {
+        MatrixValues::Normalized
+    }
+}
+
+// Which axis a matrix's `indptr` runs over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SparseOrientation {
+    // One indptr entry per cell: `csr_matrix` and dense layers.
+    CellMajor,
+    // One indptr entry per gene: `csc_matrix`.
+    GeneMajor,
+}
+
+impl SparseOrientation {
+    pub fn indptr_len(self, n_genes: usize, n_cells: usize) -> usize {
+        match self {
+            Self::CellMajor => n_cells + 1,
+            Self::GeneMajor => n_genes + 1,
+        }
+    }
+
+    // The anndata `encoding-type` decides; the indptr length is only a
+    // fallback when the attribute is missing, and must agree otherwise.
+    // Without the attribute a square matrix is read as anndata's default CSR.
+    pub fn resolve(
+        encoding: Option<&str>,
+        indptr_len: usize,
+        n_genes: usize,
+        n_cells: usize,
+    ) -> Result<Self> {
+        let fits = |o: Self| o.indptr_len(n_genes, n_cells) == indptr_len;
+        let orientation = match encoding {
+            Some("csr_matrix") => Self::CellMajor,
+            Some("csc_matrix") => Self::GeneMajor,
+            Some(other) => bail!("unsupported sparse encoding-type '{}'", other),
+            None => match (fits(Self::CellMajor), fits(Self::GeneMajor)) {
+                (true, _) => Self::CellMajor,
+                (false, true) => Self::GeneMajor,
+                (false, false) => bail!(
+                    "indptr length {} matches neither {} genes nor {} cells",
+                    indptr_len,
+                    n_genes,
+                    n_cells
+                ),
+            },
+        };
+        if !fits(orientation) {
+            bail!(
+                "encoding-type {} expects indptr length {} but found {}",
+                encoding.unwrap_or_default(),
+                orientation.indptr_len(n_genes, n_cells),
+                indptr_len
+            );
+        }
+        Ok(orientation)
+    }
+}
@@ -3,9 +3,11 @@ use std::path::Path;
 use anyhow::{Context, Result, bail};
 
 use crate::ctx::Ctx;
+use crate::expr::normalize;
 use crate::schema::v1::{
-    DeltaSummary, Explainability, GenesetCoverage, InputMeta, Mode, Normalization, PerSampleScore,
-    PfsContributions, ProteoQcV1, RiskFlag, Scores, TimecourseResult, TimepointSummary,
+    DeltaSummary, Explainability, GenesetCoverage, InputMeta, Mode, PerSampleScore,
+    PfsContributions, ProteoQcV1, RiskFlag, Scores, ScoringProfileMeta, TimecourseResult,
+    TimepointSummary,
 };
 
 pub fn build_report(ctx: &Ctx) -> Result<ProteoQcV1> {
@@ -13,13 +15,13 @@ pub fn build_report(ctx: &Ctx) -> Result<ProteoQcV1> {
         genes: ctx.input_meta.genes,
         cells: ctx.input_meta.cells,
         nnz: ctx.input_meta.nnz,
-        normalization: Normalization {
-            log1p: ctx.log1p,
-            cp10k: true,
-            raw: true,
-        },
+        normalization: normalize::describe(ctx.normalization, ctx.log1p),
         mode: ctx.mode.clone(),
         timecourse: ctx.timecourse,
+        sample_id: Some(ctx.metadata.sample_id.clone()),
+        condition: Some(ctx.metadata.condition.clone()),
+        species: Some(ctx.metadata.species.as_str().to_string()),
+        filters: ctx.filter_summary.clone(),
     };
 
     let axis = ctx.axis_raw.as_ref().context("axis raw scores missing")?;
@@ -29,7 +31,7 @@ pub fn build_report(ctx: &Ctx) -> Result<ProteoQcV1> {
         .context("integrated scores missing")?;
 
     let per_sample = vec![PerSampleScore {
-        id: "sample".to_string(),
+        id: ctx.metadata.sample_id.clone(),
         pcs_raw: Some(mean_vec(&axis.pcs)? as f64),
         utp_raw: Some(mean_vec(&axis.utp)? as f64),
         cls_raw: Some(mean_vec(&axis.cls)? as f64),
@@ -38,6 +40,8 @@ pub fn build_report(ctx: &Ctx) -> Result<ProteoQcV1> {
         capacity_raw: Some(mean_vec(&integrated.capacity_raw)? as f64),
         pii_raw: Some(mean_vec(&integrated.pii_raw)? as f64),
         pfs_raw: Some(mean_vec(&integrated.pfs_raw)? as f64),
+        ips_raw: Some(mean_vec(&axis.ips)? as f64),
+        ips_ratio: Some(mean_vec(&integrated.ips_ratio)? as f64),
     }];
 
     let per_cell_tsv_path = if matches!(ctx.mode, Mode::Cell) && ctx.write_tsv {
@@ -75,6 +79,7 @@ pub fn build_report(ctx: &Ctx) -> Result<ProteoQcV1> {
                 } else {
                     g.gene_ids.len() as f64 / g.total as f64
                 },
+                matches: g.coverage_matches(),
             })
             .collect::<Vec<_>>()
     } else {
@@ -131,6 +136,12 @@ pub fn build_report(ctx: &Ctx) -> Result<ProteoQcV1> {
         version: env!("CARGO_PKG_VERSION").to_string(),
         schema_version: "v1".to_string(),
         input_meta,
+        scoring_profile: Some(ScoringProfileMeta {
+            name: ctx.profile.name.clone(),
+            version: ctx.profile.version.clone(),
+            hash: ctx.profile.hash(),
+        }),
+        geneset_version: Some(ctx.geneset_version.as_str().to_string()),
         scores,
         risk_flags,
         explainability,
@@ -139,6 +150,7 @@ pub fn build_report(ctx: &Ctx) -> Result<ProteoQcV1> {
             .proteostasis_extension
             .as_ref()
             .map(|ext| ext.summary.clone()),
+        mito_axis: ctx.mito_axis.as_ref().map(|m| m.summary.clone()),
     })
 }
 
@@ -0,0 +1,148 @@
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use kira_scio::normalize::{normalize_barcode, normalize_gene_symbol};
+
+use crate::io::h5ad_layer::{MatrixValues, VALUE_SAMPLE, classify_values};
+use crate::io::tenx_h5::read_strings;
+
+// Dense rows are read in blocks of about this many bytes.
+const ROW_BLOCK_BYTES: usize = 64 << 20;
+
+#[derive(Debug)]
+pub struct LoomSummary {
+    pub genes: Vec<String>,
+    pub gene_ids: Vec<String>,
+    pub cells: Vec<String>,
+    pub nrows: usize,
+    pub ncols: usize,
+    pub nnz: usize,
+    pub values: MatrixValues,
+}
+
+// Loom stores `/matrix` dense, genes x cells, with names under row_attrs and
+// col_attrs. loompy uses Gene/Accession/CellID; older exporters differ.
+pub fn read_loom_summary(path: &Path) -> Result<LoomSummary> {
+    let file =
+        hdf5::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let (nrows, ncols) = matrix_shape(&file)?;
+
+    let row_attrs = file.group("row_attrs").context("loom has no row_attrs")?;
+    let col_attrs = file.group("col_attrs").context("loom has no col_attrs")?;
+    let symbols = read_attr(&row_attrs, &["Gene", "gene", "gene_name", "var_names"])?;
+    let ids = read_attr(&row_attrs, &["Accession", "gene_id", "gene_ids"]).ok();
+    let cells = read_attr(&col_attrs, &["CellID", "cell_id", "obs_names", "barcode"])?;
+    if symbols.len() != nrows || cells.len() != ncols {
+        bail!(
+            "loom matrix shape ({}, {}) does not match row_attrs ({}) and col_attrs ({})",
+            nrows,
+            ncols,
+            symbols.len(),
+            cells.len()
+        );
+    }
+    if let Some(ids) = &ids
+        && ids.len() != nrows
+    {
+        bail!(
+            "loom gene ids ({}) do not match matrix rows ({})",
+            ids.len(),
+            nrows
+        );
+    }
+
+    let mut genes = Vec::with_capacity(nrows);
+    let mut gene_ids = Vec::with_capacity(nrows);
+    for (i, symbol) in symbols.iter().enumerate() {
+        let id = ids.as_ref().map_or(symbol, |ids| &ids[i]);
+        let name = Some(symbol.as_str()).filter(|s| !s.trim().is_empty());
+        genes.push(normalize_gene_symbol(id, name, i));
+        gene_ids.push(normalize_gene_symbol(id, Some(id), i));
+    }
+    let cells = cells
+        .iter()
+        .enumerate()
+        .map(|(i, c)| normalize_barcode(c, i))
+        .collect();
+
+    // Dense storage has no nnz; it is counted while sampling values.
+    let mut nnz = 0usize;
+    let mut sample = Vec::new();
+    for_each_row(&file, 0..nrows, |_, row| {
+        for &v in row.iter().filter(|v| **v != 0.0) {
+            nnz += 1;
+            if sample.len() < VALUE_SAMPLE {
+                sample.push(v);
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(LoomSummary {
+        genes,
+        gene_ids,
+        cells,
+        nrows,
+        ncols,
+        nnz,
+        values: classify_values(&sample),
+    })
+}
+
+// Streams the dense rows (genes) in `rows`, one block at a time.
+pub fn for_each_loom_row<F>(path: &Path, rows: Range<usize>, f: F) -> Result<()>
+where
+    F: FnMut(usize, &[f32]) -> Result<()>,
+{
+    let file =
+        hdf5::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let (nrows, _) = matrix_shape(&file)?;
+    if rows.end > nrows {
+        bail!("loom row range {:?} exceeds {} rows", rows, nrows);
+    }
+    for_each_row(&file, rows, f)
+}
+
+fn for_each_row<F>(file: &hdf5::File, rows: Range<usize>, mut f: F) -> Result<()>
+where
+    F: FnMut(usize, &[f32]) -> Result<()>,
+{
+    let matrix = file.dataset("matrix").context("loom has no /matrix")?;
+    let (_, ncols) = matrix_shape(file)?;
+    let block = (ROW_BLOCK_BYTES / (ncols.max(1) * 4)).max(1);
+    let mut r0 = rows.start;
+    while r0 < rows.end {
+        let r1 = (r0 + block).min(rows.end);
+        let values = matrix
+            .read_slice_2d::<f32, _>((r0..r1, ..))
+            .with_context(|| format!("failed to read loom rows {}..{}", r0, r1))?;
+        for (i, row) in values.rows().into_iter().enumerate() {
+            let row = row.to_vec();
+            f(r0 + i, &row)?;
+        }
+        r0 = r1;
+    }
+    Ok(())
+}
+
+fn matrix_shape(file: &hdf5::File) -> Result<(usize, usize)> {
+    let shape = file
+        .dataset("matrix")
+        .context("loom has no /matrix")?
+        .shape();
+    if shape.len() != 2 {
+        bail!("loom /matrix must be 2-D, got {} dims", shape.len());
+    }
+    Ok((shape[0], shape[1]))
+}
+
+fn read_attr(group: &hdf5::Group, names: &[&str]) -> Result<Vec<String>> {
+    for name in names {
+        if group.link_exists(name) {
+            return read_strings(&group.dataset(name)?)
+                .with_context(|| format!("failed to read {}/{}", group.name(), name));
+        }
+    }
+    bail!("loom {} has none of {}", group.name(), names.join(", "))
+}
@@ -7,21 +7,28 @@ use anyhow::Result;
 use crate::schema::v1::ProteoQcV1;
 
 pub mod barcodes;
+pub mod clusters;
+pub mod dense;
 pub mod features;
 #[cfg(feature = "hdf5")]
 pub mod h5ad;
+pub mod h5ad_layer;
 #[cfg(not(feature = "hdf5"))]
 pub mod h5ad {
     use anyhow::{Result, bail};
     use std::path::Path;
 
+    use crate::io::h5ad_layer::{H5adLayer, MatrixValues, SparseOrientation};
+
     #[derive(Debug)]
     pub struct H5adSummary {
         pub genes: Vec<String>,
+        pub gene_ids: Vec<String>,
         pub cells: Vec<String>,
         pub nnz: usize,
         pub nrows: usize,
         pub ncols: usize,
+        pub values: MatrixValues,
         pub warnings: Vec<String>,
     }
 
@@ -32,21 +39,104 @@ pub mod h5ad {
         pub nnz: usize,
     }
 
-    pub fn read_h5ad_summary(_path: &Path) -> Result<H5adSummary> {
+    pub fn read_h5ad_summary(_path: &Path, _layer: &H5adLayer) -> Result<H5adSummary> {
         bail!("H5AD support not enabled. Rebuild with --features hdf5");
     }
 
     pub fn read_h5ad_sparse(
         _path: &Path,
-    ) -> Result<(H5adSparseMeta, String, usize, Vec<u32>, Vec<f32>, Vec<u64>)> {
+        _layer: &H5adLayer,
+    ) -> Result<(
+        H5adSparseMeta,
+        String,
+        SparseOrientation,
+        Vec<u32>,
+        Vec<f32>,
+        Vec<u64>,
+    )> {
+        bail!("H5AD support not enabled. Rebuild with --features hdf5");
+    }
+
+    pub fn read_h5ad_obs_column(_path: &Path, _column: &str) -> Result<(Vec<String>, Vec<String>)> {
         bail!("H5AD support not enabled. Rebuild with --features hdf5");
     }
 }
 pub mod json_writer;
+#[cfg(feature = "hdf5")]
+pub mod loom;
+#[cfg(not(feature = "hdf5"))]
+pub mod loom {
+    use anyhow::{Result, bail};
+    use std::ops::Range;
+    use std::path::Path;
+
+    use crate::io::h5ad_layer::MatrixValues;
+
+    #[derive(Debug)]
+    pub struct LoomSummary {
+        pub genes: Vec<String>,
+        pub gene_ids: Vec<String>,
+        pub cells: Vec<String>,
+        pub nrows: usize,
+        pub ncols: usize,
+        pub nnz: usize,
+        pub values: MatrixValues,
+    }
+
+    pub fn read_loom_summary(_path: &Path) -> Result<LoomSummary> {
+        bail!("Loom support not enabled. Rebuild with --features hdf5");
+    }
+
+    pub fn for_each_loom_row<F>(_path: &Path, _rows: Range<usize>, _f: F) -> Result<()>
+    where
+        F: FnMut(usize, &[f32]) -> Result<()>,
+    {
+        bail!("Loom support not enabled. Rebuild with --features hdf5");
+    }
+}
 pub mod mtx;
 pub mod pipeline_output;
 pub mod shared_cache;
 pub mod summary;
+#[cfg(feature = "hdf5")]
+pub mod tenx_h5;
+#[cfg(not(feature = "hdf5"))]
+pub mod tenx_h5 {
+    use anyhow::{Result, bail};
+    use std::path::Path;
+
+    use crate::io::features::FeatureTable;
+
+    #[derive(Debug)]
+    pub struct TenxH5Summary {
+        pub features: FeatureTable,
+        pub cells: Vec<String>,
+        pub nrows: usize,
+        pub ncols: usize,
+        pub nnz: usize,
+    }
+
+    #[derive(Debug)]
+    pub struct TenxH5Matrix {
+        pub nrows: usize,
+        pub ncols: usize,
+        pub indptr: Vec<u64>,
+        pub indices: Vec<u32>,
+        pub data: Vec<f32>,
+    }
+
+    pub fn read_tenx_h5_summary(_path: &Path) -> Result<TenxH5Summary> {
+        bail!("10x HDF5 support not enabled. Rebuild with --features hdf5");
+    }
+
+    pub fn read_tenx_h5_indices(_path: &Path) -> Result<Vec<u32>> {
+        bail!("10x HDF5 support not enabled. Rebuild with --features hdf5");
+    }
+
+    pub fn read_tenx_h5_matrix(_path: &Path) -> Result<TenxH5Matrix> {
+        bail!("10x HDF5 support not enabled. Rebuild with --features hdf5");
+    }
+}
 pub mod tsv_writer;
 
 pub fn write_json(path: &Path, report: &ProteoQcV1) -> Result<()> {
@@ -1,8 +1,7 @@
+use std::io::BufRead;
 use std::path::Path;
 
-use anyhow::{Result, bail};
-use kira_scio::api::{Reader, ReaderOptions};
-use kira_scio::detect::DetectedFormat;
+use anyhow::{Context, Result, bail};
 
 #[derive(Debug, Clone, Copy)]
 pub struct MtxSummary {
@@ -12,51 +11,68 @@ pub struct MtxSummary {
 }
 
 pub fn read_mtx_summary(path: &Path) -> Result<MtxSummary> {
-    let matrix = Reader::with_options(
-        path,
-        ReaderOptions {
-            force_format: Some(DetectedFormat::Mtx10x),
-            strict: true,
-        },
-    )
-    .read_matrix()
-    .map_err(|e| anyhow::anyhow!(e.message))?;
-
-    Ok(MtxSummary {
-        nrows: matrix.n_genes,
-        ncols: matrix.n_cells,
-        nnz: matrix.values.len(),
-    })
+    for_each_entry(path, |_, _, _| Ok(()))
 }
 
+// Streams coordinate entries in file order without materializing the matrix.
+// Follows the kira-scio strict reader: 1-based indices, out-of-range entries
+// are errors and explicit zeros are dropped (and not counted in nnz).
 pub fn for_each_entry<F>(path: &Path, mut f: F) -> Result<MtxSummary>
 where
     F: FnMut(usize, usize, f32) -> Result<()>,
 {
-    let matrix = Reader::with_options(
-        path,
-        ReaderOptions {
-            force_format: Some(DetectedFormat::Mtx10x),
-            strict: true,
-        },
-    )
-    .read_matrix()
-    .map_err(|e| anyhow::anyhow!(e.message))?;
-
-    for (col, window) in matrix.col_ptr.windows(2).enumerate() {
-        for idx in window[0]..window[1] {
-            let row = matrix.row_idx[idx];
-            let value = matrix.values[idx];
-            if row >= matrix.n_genes || col >= matrix.n_cells {
-                bail!("MTX index out of bounds: ({}, {})", row + 1, col + 1);
+    let mut reader =
+        kira_scio::open_maybe_gz_existing(path).map_err(|e| anyhow::anyhow!(e.message))?;
+    let mut dims: Option<(usize, usize)> = None;
+    let mut nnz = 0usize;
+    let mut line = String::new();
+    let mut line_no = 0usize;
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        line_no += 1;
+        let t = line.trim();
+        if t.is_empty() || t.starts_with('%') {
+            continue;
+        }
+
+        let mut parts = t.split_whitespace();
+        let Some((nrows, ncols)) = dims else {
+            let header = parts.collect::<Vec<_>>();
+            if header.len() != 3 {
+                bail!("{}: invalid MTX header line {}", path.display(), line_no);
             }
-            f(row, col, value)?;
+            let nrows = header[0].parse::<usize>().context("invalid n_rows")?;
+            let ncols = header[1].parse::<usize>().context("invalid n_cols")?;
+            dims = Some((nrows, ncols));
+            continue;
+        };
+
+        let (Some(row), Some(col), Some(value)) = (parts.next(), parts.next(), parts.next()) else {
+            bail!(
+                "{}: malformed coordinate at line {}",
+                path.display(),
+                line_no
+            );
+        };
+        let row = row.parse::<usize>().context("invalid row index")?;
+        let col = col.parse::<usize>().context("invalid col index")?;
+        let value = value.parse::<f32>().context("invalid value")?;
+        if row == 0 || col == 0 {
+            bail!("MTX is 1-based; found zero index");
+        }
+        if row > nrows || col > ncols {
+            bail!("MTX index out of bounds: ({}, {})", row, col);
+        }
+        if value != 0.0 {
+            f(row - 1, col - 1, value)?;
+            nnz += 1;
         }
     }
 
-    Ok(MtxSummary {
-        nrows: matrix.n_genes,
-        ncols: matrix.n_cells,
-        nnz: matrix.values.len(),
-    })
+    let (nrows, ncols) = dims.context("missing MTX header")?;
+    Ok(MtxSummary { nrows, ncols, nnz })
 }
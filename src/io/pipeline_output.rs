@@ -7,23 +7,36 @@ use anyhow::{Context, Result, bail};
 use serde::Serialize;
 
 use crate::ctx::Ctx;
+use crate::expr::cell_qc::CellQc;
+use crate::io::clusters::ALL_CELLS_CLUSTER;
 use crate::math::reduce::GeneSetReducer;
+use crate::metrics::mito::{self, MitoAxisScores, MitoAxisSummary};
 use crate::metrics::proteostasis_extension::aggregate::ProteostasisExtensionSummary;
+use crate::parallel;
+use crate::schema::v1::FilterSummary;
+use crate::scores::rules::{Condition, ips_switch_condition};
 
 const PIPELINE_DIR: &str = "kira-proteoqc";
 const IO_BUF_CAPACITY: usize = 1 << 20; // 1 MiB
+const LOW_QC_MEDIAN_FRACTION: f64 = 0.25;
+const EXCLUDED_REGIME: &str = "Excluded";
+const EXCLUDED_CLUSTER: &str = "NA";
 
 #[derive(Debug, Clone, Serialize)]
 struct ToolMeta {
     name: String,
     version: String,
     simd: String,
+    deterministic: bool,
 }
 
 #[derive(Debug, Clone, Serialize)]
 struct SummaryInput {
     n_cells: usize,
+    sample_id: String,
+    condition: String,
     species: String,
+    filters: Option<FilterSummary>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -43,16 +56,19 @@ struct Regimes {
 struct SummaryQc {
     low_confidence_fraction: f64,
     low_chaperone_signal_fraction: f64,
+    empty_cell_fraction: f64,
 }
 
 #[derive(Debug, Clone, Serialize)]
 struct PipelineSummary {
     tool: ToolMeta,
+    geneset_version: String,
     input: SummaryInput,
     distributions: SummaryDistributions,
     regimes: Regimes,
     qc: SummaryQc,
     proteostasis_extension: Option<ProteostasisExtensionSummary>,
+    mito_axis: Option<MitoAxisSummary>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -60,6 +76,10 @@ struct SummaryDistributions {
     proteostasis_load: DistStats,
     misfolded_protein_burden: DistStats,
     stress_proteostasis_index: DistStats,
+    immunoproteasome_ratio: DistStats,
+    libsize: DistStats,
+    nnz: DistStats,
+    expressed_genes: DistStats,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -88,6 +108,7 @@ struct PipelineCellMetrics {
 #[derive(Debug, Clone, Serialize)]
 struct PipelineStep {
     tool: PipelineStepTool,
+    geneset_version: String,
     artifacts: PipelineArtifacts,
     cell_metrics: PipelineCellMetrics,
     regimes: Vec<String>,
@@ -99,11 +120,19 @@ pub fn ensure_pipeline_out_dir(base_out: &Path) -> Result<std::path::PathBuf> {
     Ok(out)
 }
 
+type Writer = fn(&Ctx, &Path) -> Result<()>;
+
+// Each writer reads ctx and owns one file, so they run concurrently.
 pub fn write_pipeline_outputs(ctx: &Ctx, out_dir: &Path) -> Result<()> {
-    write_pipeline_cell_tsv(ctx, &out_dir.join("proteoqc.tsv"))?;
-    write_panels_report(ctx, &out_dir.join("panels_report.tsv"))?;
-    write_summary_json(ctx, &out_dir.join("summary.json"))?;
-    write_pipeline_step_json(&out_dir.join("pipeline_step.json"))?;
+    let writers: [(&str, Writer); 4] = [
+        ("proteoqc.tsv", write_pipeline_cell_tsv),
+        ("panels_report.tsv", write_panels_report),
+        ("summary.json", write_summary_json),
+        ("pipeline_step.json", write_pipeline_step_json),
+    ];
+    parallel::try_map(ctx.threads, &writers, |(name, write)| {
+        write(ctx, &out_dir.join(name))
+    })?;
     Ok(())
 }
 
@@ -119,6 +148,8 @@ fn write_pipeline_cell_tsv(ctx: &Ctx, path: &Path) -> Result<()> {
             ("axis pcs", axis.pcs.len()),
             ("axis cls", axis.cls.len()),
             ("axis ribo", axis.ribo.len()),
+            ("axis ips", axis.ips.len()),
+            ("integrated ips_ratio", integrated.ips_ratio.len()),
             ("integrated capacity", integrated.capacity_raw.len()),
             ("integrated pii", integrated.pii_raw.len()),
             ("integrated pfs", integrated.pfs_raw.len()),
@@ -131,15 +162,31 @@ fn write_pipeline_cell_tsv(ctx: &Ctx, path: &Path) -> Result<()> {
         1
     };
     let extension = ctx.proteostasis_extension.as_ref().map(|e| &e.scores);
+    let mito_axis = ctx.mito_axis.as_ref().map(|m| &m.scores);
+    if let Some(m) = mito_axis {
+        for (name, values) in mito::metric_views(m) {
+            if row_mode == RowMode::PerCell && values.len() != n_cells {
+                bail!("{} length mismatch: {} != {}", name, values.len(), n_cells);
+            }
+        }
+    }
+    let qc_ref = QcReference::new(&ctx.cell_qc, n_cells);
+    let ips_switch = ips_switch_condition(&ctx.risk_rules, row_mode == RowMode::PerCell);
 
     let file =
         File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
     let mut w = BufWriter::with_capacity(IO_BUF_CAPACITY, file);
 
-    writeln!(
+    write!(
         w,
-        "barcode\tsample\tcondition\tspecies\tlibsize\tnnz\texpressed_genes\tproteostasis_load\tmisfolded_protein_burden\tchaperone_capacity\tproteasome_activity_proxy\tprotein_quality_balance\tstress_proteostasis_index\tregime\tflags\tconfidence\tchaperone_core\tproteasome_core\tupr_core\tagg_core\tCCI\tPCI\tUPR_A\tPLS\tSCI\tPCP\tchaperone_high\tproteasome_high\tupr_active\tproteotoxic_high\timbalance_high\tcollapse_risk"
+        "barcode\tsample\tcondition\tspecies\tlibsize\tnnz\texpressed_genes\tproteostasis_load\tmisfolded_protein_burden\tchaperone_capacity\tproteasome_activity_proxy\tprotein_quality_balance\tstress_proteostasis_index\tregime\tflags\tconfidence\tchaperone_core\tproteasome_core\tupr_core\tagg_core\tCCI\tPCI\tUPR_A\tPLS\tSCI\tPCP\tchaperone_high\tproteasome_high\tupr_active\tproteotoxic_high\timbalance_high\tcollapse_risk\tcluster\timmunoproteasome_proxy\timmunoproteasome_ratio"
     )?;
+    if let Some(m) = mito_axis {
+        for (name, _) in mito::metric_views(m) {
+            write!(w, "\t{}", name)?;
+        }
+    }
+    writeln!(w)?;
 
     let row_indices = if row_mode == RowMode::PerCell {
         let mut ordered = (0..n_rows).collect::<Vec<usize>>();
@@ -151,25 +198,53 @@ fn write_pipeline_cell_tsv(ctx: &Ctx, path: &Path) -> Result<()> {
 
     for i in row_indices {
         let idx = if row_mode == RowMode::PerCell { i } else { 0 };
-        let libsize = 0u64;
-        let nnz = 0u64;
-        let expressed_genes = 0u64;
+        let (libsize, nnz, expressed_genes) = match (row_mode, &qc_ref) {
+            (RowMode::PerCell, Some(_)) => (
+                ctx.cell_qc.libsize[idx].round() as u64,
+                ctx.cell_qc.nnz[idx] as u64,
+                ctx.cell_qc.expressed_genes[idx] as u64,
+            ),
+            (RowMode::PerSample, Some(_)) => (
+                ctx.cell_qc.total_libsize().round() as u64,
+                ctx.cell_qc.total_nnz(),
+                ctx.cell_qc.detected_genes as u64,
+            ),
+            (_, None) => (0, 0, 0),
+        };
 
         let misfolded = sigmoid(integrated.pii_raw[idx]) as f64;
         let chaperone = sigmoid(axis.cls[idx]) as f64;
         let proteasome = sigmoid(axis.pcs[idx]) as f64;
+        let immunoproteasome = sigmoid(axis.ips[idx]) as f64;
+        let ips_ratio = integrated.ips_ratio[idx] as f64;
         let load = sigmoid(0.5 * integrated.pii_raw[idx] + 0.5 * axis.ribo[idx]) as f64;
         let balance = sigmoid(integrated.capacity_raw[idx] - integrated.pii_raw[idx]) as f64;
         let stress = sigmoid(integrated.pfs_raw[idx]) as f64;
 
-        let confidence = compute_confidence(ctx, chaperone);
-        let regime = classify_regime(stress, misfolded, proteasome);
+        let confidence =
+            compute_confidence(ctx, chaperone, cell_qc_penalty(&qc_ref, row_mode, idx));
+        let regime = classify_regime(stress, misfolded, proteasome, ips_ratio, ips_switch);
         let flags = build_flags(confidence, chaperone);
         let barcode = if row_mode == RowMode::PerCell {
             ctx.cells[i].as_str()
         } else {
             "sample"
         };
+        let (sample, condition) = if row_mode == RowMode::PerCell {
+            (
+                ctx.metadata.cell_sample(idx),
+                ctx.metadata.cell_condition(idx),
+            )
+        } else {
+            (
+                ctx.metadata.sample_id.as_str(),
+                ctx.metadata.condition.as_str(),
+            )
+        };
+        let cluster = match (&ctx.cell_clusters, row_mode) {
+            (Some(labels), RowMode::PerCell) => labels[idx].as_str(),
+            _ => ALL_CELLS_CLUSTER,
+        };
         let (
             chaperone_core,
             proteasome_core,
@@ -248,10 +323,13 @@ fn write_pipeline_cell_tsv(ctx: &Ctx, path: &Path) -> Result<()> {
             )
         };
 
-        writeln!(
+        write!(
             w,
-            "{}\tsample\tunknown\tunknown\t{}\t{}\t{}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{}\t{}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{}\t{}\t{}\t{}\t{}\t{}",
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{}\t{}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.6}\t{:.6}",
             barcode,
+            sample,
+            condition,
+            ctx.metadata.species.as_str(),
             libsize,
             nnz,
             expressed_genes,
@@ -279,7 +357,65 @@ fn write_pipeline_cell_tsv(ctx: &Ctx, path: &Path) -> Result<()> {
             upr_active,
             proteotoxic_high,
             imbalance_high,
-            collapse_risk
+            collapse_risk,
+            cluster,
+            immunoproteasome,
+            ips_ratio
+        )?;
+        let mito_values = mito_axis.map(|m| {
+            if row_mode == RowMode::PerCell {
+                mito::cell_values(m, idx)
+            } else {
+                mito::sample_values(m)
+            }
+        });
+        for v in mito_values.into_iter().flatten() {
+            write!(w, "\t{:.6}", v)?;
+        }
+        writeln!(w)?;
+    }
+    if row_mode == RowMode::PerCell {
+        write_excluded_rows(ctx, mito_axis, &mut w)?;
+    }
+    Ok(())
+}
+
+// Filtered-out cells follow the scored rows, sorted by barcode, so every
+// input barcode appears once: QC columns are filled, scores are NaN, the
+// regime is `Excluded` and the flag names the filter that removed the cell.
+fn write_excluded_rows<W: Write>(
+    ctx: &Ctx,
+    mito_axis: Option<&MitoAxisScores>,
+    w: &mut W,
+) -> Result<()> {
+    let mut excluded = ctx.excluded_cells.iter().collect::<Vec<_>>();
+    excluded.sort_unstable_by(|a, b| a.barcode.cmp(&b.barcode));
+    let scores = ["NaN"; 6].join("\t");
+    let extension_scores = ["NaN"; 10].join("\t");
+    let extension_flags = ["false"; 6].join("\t");
+    let mito_scores = match mito_axis {
+        Some(m) => mito::metric_views(m).map(|_| "\tNaN").concat(),
+        None => String::new(),
+    };
+    for cell in excluded {
+        writeln!(
+            w,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.6}\t{}\t{}\t{}\tNaN\tNaN{}",
+            cell.barcode,
+            cell.sample,
+            cell.condition,
+            ctx.metadata.species.as_str(),
+            cell.libsize.round() as u64,
+            cell.nnz,
+            cell.expressed_genes,
+            scores,
+            EXCLUDED_REGIME,
+            cell.reason.flag(),
+            0.0,
+            extension_scores,
+            extension_flags,
+            EXCLUDED_CLUSTER,
+            mito_scores
         )?;
     }
     Ok(())
@@ -288,7 +424,20 @@ fn write_pipeline_cell_tsv(ctx: &Ctx, path: &Path) -> Result<()> {
 fn write_panels_report(ctx: &Ctx, path: &Path) -> Result<()> {
     let collection = ctx.genesets.as_ref().context("genesets missing")?;
     let expr = ctx.expr_reader()?;
-    let mut reducer = GeneSetReducer::new(&expr, ctx.threads, ctx.cache_block, ctx.prefetch);
+    // Without the fused pass, the non-empty genesets are reduced here together.
+    let mut reduced = if ctx.fused.is_some() {
+        Vec::new()
+    } else {
+        let mut reducer = GeneSetReducer::new(&expr, ctx.threads, ctx.cache_block, ctx.prefetch);
+        let gene_lists = collection
+            .resolved
+            .iter()
+            .filter(|gs| !gs.gene_ids.is_empty())
+            .map(|gs| gs.gene_ids.as_slice())
+            .collect::<Vec<_>>();
+        reducer.per_cell_raw_many(&gene_lists)?
+    }
+    .into_iter();
 
     let file =
         File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
@@ -299,14 +448,23 @@ fn write_panels_report(ctx: &Ctx, path: &Path) -> Result<()> {
     )?;
 
     for gs in &collection.resolved {
-        let mut raw = vec![0.0f32; expr.n_cells()];
-        if !gs.gene_ids.is_empty() {
-            reducer.per_cell_raw(&gs.gene_ids, &mut raw)?;
-        }
-        let mut sums = raw
-            .iter()
-            .map(|v| *v as f64 * gs.gene_ids.len() as f64)
-            .collect::<Vec<_>>();
+        let fused_sums = ctx
+            .fused
+            .as_ref()
+            .and_then(|f| f.target_index(&gs.id).map(|t| f.target_sums(t)));
+        let mut sums = match fused_sums {
+            Some(sums) => sums.iter().map(|v| *v as f64).collect::<Vec<_>>(),
+            None => {
+                let raw = if gs.gene_ids.is_empty() {
+                    vec![0.0f32; expr.n_cells()]
+                } else {
+                    reduced.next().context("geneset reduction missing")?
+                };
+                raw.iter()
+                    .map(|v| *v as f64 * gs.gene_ids.len() as f64)
+                    .collect::<Vec<_>>()
+            }
+        };
         let mut cov = vec![
             if gs.total == 0 {
                 0.0
@@ -360,6 +518,8 @@ fn write_summary_json(ctx: &Ctx, path: &Path) -> Result<()> {
             ("axis pcs", axis.pcs.len()),
             ("axis cls", axis.cls.len()),
             ("axis ribo", axis.ribo.len()),
+            ("axis ips", axis.ips.len()),
+            ("integrated ips_ratio", integrated.ips_ratio.len()),
             ("integrated capacity", integrated.capacity_raw.len()),
             ("integrated pii", integrated.pii_raw.len()),
             ("integrated pfs", integrated.pfs_raw.len()),
@@ -371,6 +531,8 @@ fn write_summary_json(ctx: &Ctx, path: &Path) -> Result<()> {
     } else {
         1
     };
+    let qc_ref = QcReference::new(&ctx.cell_qc, n_cells);
+    let ips_switch = ips_switch_condition(&ctx.risk_rules, row_mode == RowMode::PerCell);
     let mut regimes_count: BTreeMap<String, usize> = BTreeMap::new();
     let mut low_conf = 0usize;
     let mut low_chaperone = 0usize;
@@ -378,6 +540,7 @@ fn write_summary_json(ctx: &Ctx, path: &Path) -> Result<()> {
     let mut load_vals = Vec::with_capacity(n);
     let mut misfolded_vals = Vec::with_capacity(n);
     let mut stress_vals = Vec::with_capacity(n);
+    let mut ips_ratio_vals = Vec::with_capacity(n);
     for i in 0..n {
         let idx = if row_mode == RowMode::PerCell { i } else { 0 };
         let misfolded = sigmoid(integrated.pii_raw[idx]) as f64;
@@ -385,8 +548,11 @@ fn write_summary_json(ctx: &Ctx, path: &Path) -> Result<()> {
         let proteasome = sigmoid(axis.pcs[idx]) as f64;
         let load = sigmoid(0.5 * integrated.pii_raw[idx] + 0.5 * axis.ribo[idx]) as f64;
         let stress = sigmoid(integrated.pfs_raw[idx]) as f64;
-        let confidence = compute_confidence(ctx, chaperone);
-        let regime = classify_regime(stress, misfolded, proteasome).to_string();
+        let ips_ratio = integrated.ips_ratio[idx] as f64;
+        let confidence =
+            compute_confidence(ctx, chaperone, cell_qc_penalty(&qc_ref, row_mode, idx));
+        let regime =
+            classify_regime(stress, misfolded, proteasome, ips_ratio, ips_switch).to_string();
 
         *regimes_count.entry(regime).or_insert(0) += 1;
         if confidence < 0.5 {
@@ -398,12 +564,27 @@ fn write_summary_json(ctx: &Ctx, path: &Path) -> Result<()> {
         load_vals.push(load);
         misfolded_vals.push(misfolded);
         stress_vals.push(stress);
+        ips_ratio_vals.push(ips_ratio);
     }
 
+    let qc = &ctx.cell_qc;
+    let mut libsize_vals = qc.libsize.clone();
+    let mut nnz_vals = qc.nnz.iter().map(|&v| v as f64).collect::<Vec<_>>();
+    let mut expressed_vals = qc
+        .expressed_genes
+        .iter()
+        .map(|&v| v as f64)
+        .collect::<Vec<_>>();
+    let empty_cells = qc.nnz.iter().filter(|&&v| v == 0).count();
+
     let dist = SummaryDistributions {
         proteostasis_load: stats_from_values(&mut load_vals),
         misfolded_protein_burden: stats_from_values(&mut misfolded_vals),
         stress_proteostasis_index: stats_from_values(&mut stress_vals),
+        immunoproteasome_ratio: stats_from_values(&mut ips_ratio_vals),
+        libsize: stats_from_values(&mut libsize_vals),
+        nnz: stats_from_values(&mut nnz_vals),
+        expressed_genes: stats_from_values(&mut expressed_vals),
     };
 
     let mut regimes_fraction = BTreeMap::new();
@@ -420,10 +601,15 @@ fn write_summary_json(ctx: &Ctx, path: &Path) -> Result<()> {
             name: "kira-proteoqc".to_string(),
             version: env!("CARGO_PKG_VERSION").to_string(),
             simd: crate::simd::backend_name().to_string(),
+            deterministic: crate::math::sum::deterministic(),
         },
+        geneset_version: ctx.geneset_version.as_str().to_string(),
         input: SummaryInput {
             n_cells,
-            species: "unknown".to_string(),
+            sample_id: ctx.metadata.sample_id.clone(),
+            condition: ctx.metadata.condition.clone(),
+            species: ctx.metadata.species.as_str().to_string(),
+            filters: ctx.filter_summary.clone(),
         },
         distributions: dist,
         regimes: Regimes {
@@ -433,11 +619,13 @@ fn write_summary_json(ctx: &Ctx, path: &Path) -> Result<()> {
         qc: SummaryQc {
             low_confidence_fraction: round6(low_conf as f64 / n.max(1) as f64),
             low_chaperone_signal_fraction: round6(low_chaperone as f64 / n.max(1) as f64),
+            empty_cell_fraction: round6(empty_cells as f64 / qc.n_cells().max(1) as f64),
         },
         proteostasis_extension: ctx
             .proteostasis_extension
             .as_ref()
             .map(|ext| ext.summary.clone()),
+        mito_axis: ctx.mito_axis.as_ref().map(|m| m.summary.clone()),
     };
 
     let file =
@@ -447,13 +635,14 @@ fn write_summary_json(ctx: &Ctx, path: &Path) -> Result<()> {
     Ok(())
 }
 
-fn write_pipeline_step_json(path: &Path) -> Result<()> {
+fn write_pipeline_step_json(ctx: &Ctx, path: &Path) -> Result<()> {
     let step = PipelineStep {
         tool: PipelineStepTool {
             name: "kira-proteoqc".to_string(),
             stage: "proteostasis".to_string(),
             version: env!("CARGO_PKG_VERSION").to_string(),
         },
+        geneset_version: ctx.geneset_version.as_str().to_string(),
         artifacts: PipelineArtifacts {
             summary: "summary.json".to_string(),
             primary_metrics: "proteoqc.tsv".to_string(),
@@ -475,9 +664,17 @@ fn write_pipeline_step_json(path: &Path) -> Result<()> {
     Ok(())
 }
 
-fn classify_regime(stress: f64, misfolded: f64, proteasome: f64) -> &'static str {
+fn classify_regime(
+    stress: f64,
+    misfolded: f64,
+    proteasome: f64,
+    ips_ratio: f64,
+    ips_switch: Option<&Condition>,
+) -> &'static str {
     if stress < 0.25 && misfolded < 0.30 {
         "BalancedProteostasis"
+    } else if ips_switch.is_some_and(|c| c.holds(ips_ratio as f32)) && stress < 0.60 {
+        "ImmunoproteasomeSwitch"
     } else if stress < 0.50 && proteasome >= 0.50 {
         "CompensatedStress"
     } else if stress >= 0.50 && misfolded >= 0.55 && proteasome >= 0.45 {
@@ -491,9 +688,10 @@ fn classify_regime(stress: f64, misfolded: f64, proteasome: f64) -> &'static str
     }
 }
 
-fn regime_names() -> [&'static str; 6] {
+fn regime_names() -> [&'static str; 7] {
     [
         "BalancedProteostasis",
+        "ImmunoproteasomeSwitch",
         "CompensatedStress",
         "ProteotoxicStress",
         "ProteasomeOverload",
@@ -513,8 +711,49 @@ fn build_flags(confidence: f64, chaperone_capacity: f64) -> String {
     flags.join(",")
 }
 
-fn compute_confidence(ctx: &Ctx, chaperone_capacity: f64) -> f64 {
-    let mut score = 1.0f64;
+// Per-cell QC is judged against the dataset medians so that targeted panels
+// and pre-normalized inputs are not penalized by absolute count thresholds.
+struct QcReference<'a> {
+    qc: &'a CellQc,
+    median_libsize: f64,
+    median_expressed_genes: f64,
+}
+
+impl<'a> QcReference<'a> {
+    fn new(qc: &'a CellQc, n_cells: usize) -> Option<Self> {
+        if qc.n_cells() != n_cells || n_cells == 0 {
+            return None;
+        }
+        Some(Self {
+            qc,
+            median_libsize: qc.median_libsize(),
+            median_expressed_genes: qc.median_expressed_genes(),
+        })
+    }
+}
+
+fn cell_qc_penalty(qc_ref: &Option<QcReference<'_>>, row_mode: RowMode, idx: usize) -> f64 {
+    let Some(r) = qc_ref else {
+        return 0.0;
+    };
+    if row_mode != RowMode::PerCell {
+        return 0.0;
+    }
+    if r.qc.nnz[idx] == 0 {
+        return 1.0;
+    }
+    let mut penalty = 0.0;
+    if r.qc.libsize[idx] < LOW_QC_MEDIAN_FRACTION * r.median_libsize {
+        penalty += 0.15;
+    }
+    if (r.qc.expressed_genes[idx] as f64) < LOW_QC_MEDIAN_FRACTION * r.median_expressed_genes {
+        penalty += 0.15;
+    }
+    penalty
+}
+
+fn compute_confidence(ctx: &Ctx, chaperone_capacity: f64, qc_penalty: f64) -> f64 {
+    let mut score = 1.0f64 - qc_penalty;
     if !ctx.warnings.is_empty() {
         score -= 0.15;
     }
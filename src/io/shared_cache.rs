@@ -1,6 +1,18 @@
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
+use kira_shared_sc_cache::SharedCacheMmap;
+use tracing::info;
+
+use crate::ctx::InputFormat;
+use crate::input;
+use crate::io::h5ad_layer::{H5adLayer, SparseOrientation};
+use crate::io::{barcodes, features, h5ad, mtx, tenx_h5};
+
+pub const HEADER_BYTES: usize = 256;
+const HEADER_CRC_OFFSET: usize = 120;
 
 #[derive(Debug, Clone, Copy)]
 pub struct SharedCacheHeaderV1 {
@@ -23,58 +35,64 @@ pub struct SharedCacheHeaderV1 {
     pub data_crc64: u64,
 }
 
+// Zero-copy view: col_ptr/row_idx/values stay in the mmap, which the
+// kira-shared-sc-cache reader validates (CSC by cell, rows strictly
+// increasing within a cell).
 #[derive(Debug)]
 pub struct SharedCache {
     pub header: SharedCacheHeaderV1,
     pub genes: Vec<String>,
     pub barcodes: Vec<String>,
-    col_ptr: Vec<u64>,
-    row_idx: Vec<u32>,
-    values_u32: Vec<u32>,
+    mapped: SharedCacheMmap,
 }
 
 impl SharedCache {
     pub fn open(path: &Path) -> Result<Self> {
-        let owned = kira_shared_sc_cache::read_shared_cache_owned(path)
+        // Checked here so a damaged header reports as a CRC failure rather
+        // than the mapper's raw field name.
+        let (header, header_crc) = read_header(path)?;
+        if header.header_crc64 != header_crc {
+            bail!(
+                "{}: shared cache header CRC mismatch (stored {:016x}, computed {:016x})",
+                path.display(),
+                header.header_crc64,
+                header_crc
+            );
+        }
+        let mut mapped = kira_shared_sc_cache::mmap_shared_cache(path)
             .map_err(|e| anyhow::anyhow!(e.to_string()))?;
 
-        let header = SharedCacheHeaderV1 {
-            version_major: 1,
-            version_minor: 0,
-            n_genes: owned.n_genes,
-            n_cells: owned.n_cells,
-            nnz: owned.nnz,
-            genes_table_offset: 0,
-            genes_table_bytes: 0,
-            barcodes_table_offset: 0,
-            barcodes_table_bytes: 0,
-            col_ptr_offset: 0,
-            row_idx_offset: 0,
-            values_u32_offset: 0,
-            n_blocks: 0,
-            blocks_offset: 0,
-            file_bytes: 0,
-            header_crc64: 0,
-            data_crc64: 0,
-        };
-
         Ok(Self {
             header,
-            genes: owned.genes,
-            barcodes: owned.barcodes,
-            col_ptr: owned.col_ptr,
-            row_idx: owned.row_idx,
-            values_u32: owned.values_u32,
+            genes: std::mem::take(&mut mapped.genes),
+            barcodes: std::mem::take(&mut mapped.barcodes),
+            mapped,
         })
     }
 
-    pub fn col_ptr(&self) -> Result<Vec<u64>> {
-        Ok(self.col_ptr.clone())
+    pub fn col_ptr(&self) -> &[u64] {
+        self.mapped.col_ptr()
+    }
+
+    pub fn row_idx(&self) -> &[u32] {
+        self.mapped.row_idx()
+    }
+
+    pub fn values_u32(&self) -> &[u32] {
+        self.mapped.values_u32()
+    }
+
+    // (gene, count) pairs of one cell, in gene order.
+    pub fn cell(&self, cell: usize) -> (&[u32], &[u32]) {
+        let col_ptr = self.col_ptr();
+        let start = col_ptr[cell] as usize;
+        let end = col_ptr[cell + 1] as usize;
+        (&self.row_idx()[start..end], &self.values_u32()[start..end])
     }
 
     pub fn row_idx_at(&self, idx: usize) -> Result<u32> {
         let value = self
-            .row_idx
+            .row_idx()
             .get(idx)
             .copied()
             .ok_or_else(|| anyhow::anyhow!("row_idx index out of bounds"))?;
@@ -83,7 +101,7 @@ impl SharedCache {
 
     pub fn value_u32_at(&self, idx: usize) -> Result<u32> {
         let value = self
-            .values_u32
+            .values_u32()
             .get(idx)
             .copied()
             .ok_or_else(|| anyhow::anyhow!("values_u32 index out of bounds"))?;
@@ -91,6 +109,312 @@ impl SharedCache {
     }
 }
 
+// Parses the fixed header without validating it, and returns it with the
+// CRC64 recomputed over the header (header_crc64 field zeroed).
+pub fn read_header(path: &Path) -> Result<(SharedCacheHeaderV1, u64)> {
+    let mut file =
+        File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut buf = [0u8; HEADER_BYTES];
+    file.read_exact(&mut buf)
+        .with_context(|| format!("{}: shared cache header truncated", path.display()))?;
+    if &buf[0..4] != b"KORG" {
+        bail!("{}: not a kira-organelle shared cache", path.display());
+    }
+    let u64_at = |offset: usize| u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap());
+    let header = SharedCacheHeaderV1 {
+        version_major: u16::from_le_bytes([buf[4], buf[5]]),
+        version_minor: u16::from_le_bytes([buf[6], buf[7]]),
+        n_genes: u64_at(16),
+        n_cells: u64_at(24),
+        nnz: u64_at(32),
+        genes_table_offset: u64_at(40),
+        genes_table_bytes: u64_at(48),
+        barcodes_table_offset: u64_at(56),
+        barcodes_table_bytes: u64_at(64),
+        col_ptr_offset: u64_at(72),
+        row_idx_offset: u64_at(80),
+        values_u32_offset: u64_at(88),
+        n_blocks: u64_at(96),
+        blocks_offset: u64_at(104),
+        file_bytes: u64_at(112),
+        header_crc64: u64_at(HEADER_CRC_OFFSET),
+        data_crc64: u64_at(128),
+    };
+    buf[HEADER_CRC_OFFSET..HEADER_CRC_OFFSET + 8].fill(0);
+    Ok((header, kira_shared_sc_cache::crc64_ecma(&buf)))
+}
+
+// Default target for `cache build`: the name pipeline mode looks for. MTX
+// directories use the detected (or given) prefix; an .h5ad file defaults to
+// its file stem as prefix, next to the file.
+pub fn default_build_path(input: &Path, prefix: Option<&str>) -> Result<PathBuf> {
+    if input.is_dir() {
+        let detected = match prefix {
+            Some(p) => Some(p.to_string()),
+            None => input::detect_prefix(input)?,
+        };
+        return Ok(input::resolve_shared_cache_path(input, detected.as_deref()));
+    }
+    let stem = input.file_stem().and_then(|s| s.to_str());
+    let dir = input.parent().unwrap_or(Path::new("."));
+    Ok(input::resolve_shared_cache_path(dir, prefix.or(stem)))
+}
+
+// Converts an MTX directory, .h5ad or 10x .h5 file into a shared cache at `out`. The
+// file is written next to the target, validated by reopening it, then
+// renamed into place.
+pub fn build_from_input(
+    input: &Path,
+    layer: &H5adLayer,
+    feature_type: &str,
+    out: &Path,
+) -> Result<SharedCache> {
+    let matrix = match InputFormat::detect(input) {
+        _ if input.is_dir() => load_mtx(input, feature_type)?,
+        InputFormat::Tenx5 => load_tenx5(input, feature_type)?,
+        InputFormat::H5ad => load_h5ad(input, layer)?,
+        InputFormat::Mtx10x | InputFormat::Loom | InputFormat::DenseText => bail!(
+            "cache build supports MTX directories, .h5ad and 10x .h5 inputs, not {}",
+            input.display()
+        ),
+    };
+    info!(
+        genes = matrix.genes.len(),
+        cells = matrix.barcodes.len(),
+        nnz = matrix.row_idx.len(),
+        "shared_cache_build"
+    );
+
+    let mut tmp = out.as_os_str().to_owned();
+    tmp.push(format!(".tmp{}", std::process::id()));
+    let tmp = PathBuf::from(tmp);
+    let built = write_shared_cache(
+        &tmp,
+        &matrix.genes,
+        &matrix.barcodes,
+        &matrix.col_ptr,
+        &matrix.row_idx,
+        &matrix.values_u32,
+    )
+    .and_then(|_| SharedCache::open(&tmp).context("written shared cache failed validation"))
+    .and_then(|cache| {
+        fs::rename(&tmp, out)
+            .with_context(|| format!("failed to move shared cache to {}", out.display()))?;
+        Ok(cache)
+    });
+    if built.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    built
+}
+
+struct CellMajorMatrix {
+    genes: Vec<String>,
+    barcodes: Vec<String>,
+    col_ptr: Vec<u64>,
+    row_idx: Vec<u32>,
+    values_u32: Vec<u32>,
+}
+
+fn load_mtx(dir: &Path, feature_type: &str) -> Result<CellMajorMatrix> {
+    let (matrix_path, features_path, barcodes_path) = input::resolve_mtx_input_files(dir, None);
+    let matrix_path = matrix_path.context("missing matrix.mtx or matrix.mtx.gz")?;
+    let features_path = features_path.context("missing features.tsv/genes.tsv (or .gz)")?;
+    let barcodes_path = barcodes_path.context("missing barcodes.tsv or barcodes.tsv.gz")?;
+    let selection =
+        features::select_feature_type(features::read_feature_table(&features_path)?, feature_type)?;
+    if let Some(warning) = selection.dropped_warning() {
+        info!(%warning, "shared_cache_build_features");
+    }
+    let barcodes = barcodes::read_barcodes(&barcodes_path)?;
+
+    // Pass 1 counts entries per cell; pass 2 scatters them cell-major.
+    let mut col_ptr = vec![0u64; barcodes.len() + 1];
+    let summary = mtx::for_each_entry(&matrix_path, |row, col, value| {
+        count_value(value, row, col)?;
+        if col >= barcodes.len() {
+            bail!("MTX has more columns than barcodes");
+        }
+        if selection.gene_of(row).is_some() {
+            col_ptr[col + 1] += 1;
+        }
+        Ok(())
+    })?;
+    if summary.nrows != selection.n_input_rows() || summary.ncols != barcodes.len() {
+        bail!(
+            "MTX dimensions ({}, {}) do not match features ({}) and barcodes ({})",
+            summary.nrows,
+            summary.ncols,
+            selection.n_input_rows(),
+            barcodes.len()
+        );
+    }
+    for c in 0..barcodes.len() {
+        col_ptr[c + 1] += col_ptr[c];
+    }
+    let nnz = col_ptr[barcodes.len()] as usize;
+    let mut offsets = col_ptr.clone();
+    let mut row_idx = vec![0u32; nnz];
+    let mut values_u32 = vec![0u32; nnz];
+    mtx::for_each_entry(&matrix_path, |row, col, value| {
+        let Some(gene) = selection.gene_of(row) else {
+            return Ok(());
+        };
+        let slot = &mut offsets[col];
+        row_idx[*slot as usize] = gene;
+        values_u32[*slot as usize] = count_value(value, row, col)?;
+        *slot += 1;
+        Ok(())
+    })?;
+    sort_cells(&col_ptr, &mut row_idx, &mut values_u32)?;
+    Ok(CellMajorMatrix {
+        genes: selection.table.symbols,
+        barcodes,
+        col_ptr,
+        row_idx,
+        values_u32,
+    })
+}
+
+fn load_h5ad(path: &Path, layer: &H5adLayer) -> Result<CellMajorMatrix> {
+    let summary = h5ad::read_h5ad_summary(path, layer)?;
+    let (meta, _encoding, orientation, indices, data, indptr) =
+        h5ad::read_h5ad_sparse(path, layer)?;
+    let n_genes = summary.genes.len();
+    let n_cells = summary.cells.len();
+    if meta.nrows != n_genes || meta.ncols != n_cells {
+        bail!(
+            "H5AD shape ({}, {}) does not match var/obs ({}, {})",
+            meta.nrows,
+            meta.ncols,
+            n_genes,
+            n_cells
+        );
+    }
+    let values = data
+        .iter()
+        .map(|&v| count_value(v, 0, 0))
+        .collect::<Result<Vec<_>>>()
+        .with_context(|| format!("H5AD {} must hold non-negative integer counts", layer))?;
+
+    if indptr.len() != orientation.indptr_len(n_genes, n_cells) {
+        bail!(
+            "H5AD indptr length does not match its {:?} orientation",
+            orientation
+        );
+    }
+    let (col_ptr, mut row_idx, mut values_u32) = if orientation == SparseOrientation::GeneMajor {
+        let mut col_ptr = vec![0u64; n_cells + 1];
+        for &cell in &indices {
+            if cell as usize >= n_cells {
+                bail!("cell index out of bounds in indices");
+            }
+            col_ptr[cell as usize + 1] += 1;
+        }
+        for c in 0..n_cells {
+            col_ptr[c + 1] += col_ptr[c];
+        }
+        let mut offsets = col_ptr.clone();
+        let mut row_idx = vec![0u32; indices.len()];
+        let mut values_u32 = vec![0u32; indices.len()];
+        for gene in 0..n_genes {
+            for k in indptr[gene] as usize..indptr[gene + 1] as usize {
+                let slot = &mut offsets[indices[k] as usize];
+                row_idx[*slot as usize] = gene as u32;
+                values_u32[*slot as usize] = values[k];
+                *slot += 1;
+            }
+        }
+        (col_ptr, row_idx, values_u32)
+    } else {
+        (indptr, indices, values)
+    };
+    sort_cells(&col_ptr, &mut row_idx, &mut values_u32)?;
+    Ok(CellMajorMatrix {
+        genes: summary.genes,
+        barcodes: summary.cells,
+        col_ptr,
+        row_idx,
+        values_u32,
+    })
+}
+
+fn load_tenx5(path: &Path, feature_type: &str) -> Result<CellMajorMatrix> {
+    let summary = tenx_h5::read_tenx_h5_summary(path)?;
+    let selection = features::select_feature_type(summary.features, feature_type)?;
+    if let Some(warning) = selection.dropped_warning() {
+        info!(%warning, "shared_cache_build_features");
+    }
+    let matrix = tenx_h5::read_tenx_h5_matrix(path)?;
+
+    // Already cell-major; only dropped rows need to be filtered out.
+    let mut col_ptr = Vec::with_capacity(matrix.ncols + 1);
+    let mut row_idx = Vec::with_capacity(matrix.indices.len());
+    let mut values_u32 = Vec::with_capacity(matrix.data.len());
+    col_ptr.push(0u64);
+    for cell in 0..matrix.ncols {
+        for k in matrix.indptr[cell] as usize..matrix.indptr[cell + 1] as usize {
+            let row = matrix.indices[k];
+            let Some(gene) = selection.gene_of(row as usize) else {
+                continue;
+            };
+            row_idx.push(gene);
+            values_u32.push(count_value(matrix.data[k], row as usize, cell)?);
+        }
+        col_ptr.push(row_idx.len() as u64);
+    }
+    sort_cells(&col_ptr, &mut row_idx, &mut values_u32)?;
+    Ok(CellMajorMatrix {
+        genes: selection.table.symbols,
+        barcodes: summary.cells,
+        col_ptr,
+        row_idx,
+        values_u32,
+    })
+}
+
+// The shared cache stores raw UMI counts as u32.
+fn count_value(value: f32, row: usize, col: usize) -> Result<u32> {
+    if value < 0.0 || value.fract() != 0.0 || value > u32::MAX as f32 {
+        bail!(
+            "value {} at ({}, {}) is not a non-negative integer count",
+            value,
+            row + 1,
+            col + 1
+        );
+    }
+    Ok(value as u32)
+}
+
+// Genes must be strictly increasing within each cell; duplicates are
+// rejected rather than summed.
+fn sort_cells(col_ptr: &[u64], row_idx: &mut [u32], values_u32: &mut [u32]) -> Result<()> {
+    for cell in 0..col_ptr.len() - 1 {
+        let start = col_ptr[cell] as usize;
+        let end = col_ptr[cell + 1] as usize;
+        if !row_idx[start..end].is_sorted() {
+            let mut pairs = row_idx[start..end]
+                .iter()
+                .copied()
+                .zip(values_u32[start..end].iter().copied())
+                .collect::<Vec<_>>();
+            pairs.sort_by_key(|(gene, _)| *gene);
+            for (i, (gene, value)) in pairs.into_iter().enumerate() {
+                row_idx[start + i] = gene;
+                values_u32[start + i] = value;
+            }
+        }
+        if let Some(pair) = row_idx[start..end].windows(2).find(|w| w[0] == w[1]) {
+            bail!(
+                "duplicate entry for gene {} in cell {}",
+                pair[0] + 1,
+                cell + 1
+            );
+        }
+    }
+    Ok(())
+}
+
 pub fn write_shared_cache(
     path: &Path,
     genes: &[String],
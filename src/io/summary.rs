@@ -1,7 +1,7 @@
 use anyhow::{Result, bail};
 
 use crate::ctx::Ctx;
-use crate::schema::v1::Mode;
+use crate::schema::v1::{FilterSummary, Mode};
 
 pub fn format_summary(ctx: &Ctx) -> Result<String> {
     let version = env!("CARGO_PKG_VERSION");
@@ -23,7 +23,20 @@ pub fn format_summary(ctx: &Ctx) -> Result<String> {
         "Input: {} genes, {} cells, mode={}\n",
         genes, cells, mode
     ));
+    out.push_str(&format!("Genesets: {}\n", ctx.geneset_version.as_str()));
     out.push_str(&format!("PFS: {:+.2}\n", pfs));
+    if let Some(mito) = &ctx.mito_axis {
+        let median = |name: &str| mito.summary.metrics.get(name).map_or(0.0, |s| s.median);
+        out.push_str(&format!(
+            "Mito: MT- fraction {:.3}, MPI {:+.2} (medians)\n",
+            median("mt_fraction"),
+            median("MPI")
+        ));
+    }
+
+    if let Some(filters) = &ctx.filter_summary {
+        out.push_str(&format_filters(filters));
+    }
 
     if let Some(tc) = &ctx.timecourse_result {
         out.push_str(&format!("Trajectory: {}\n", tc.trajectory));
@@ -44,6 +57,21 @@ pub fn format_summary(ctx: &Ctx) -> Result<String> {
     Ok(out)
 }
 
+pub fn format_filters(filters: &FilterSummary) -> String {
+    format!(
+        "Filters: kept {}/{} cells (--cells -{}, --exclude-cells -{}, --min-umis -{}, --min-genes -{}), {}/{} genes (--exclude-genes -{})\n",
+        filters.cells_kept,
+        filters.cells_input,
+        filters.removed_by_cells,
+        filters.removed_by_exclude_cells,
+        filters.removed_by_min_umis,
+        filters.removed_by_min_genes,
+        filters.genes_kept,
+        filters.genes_input,
+        filters.genes_removed_by_exclude_genes
+    )
+}
+
 fn mean_vec(values: &[f32]) -> Result<f32> {
     if values.is_empty() {
         return Ok(0.0);
@@ -0,0 +1,169 @@
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use hdf5::types::{FixedAscii, FixedUnicode, VarLenAscii, VarLenUnicode};
+use kira_scio::normalize::{normalize_barcode, normalize_gene_symbol};
+
+use crate::io::features::{FeatureTable, GENE_EXPRESSION};
+
+#[derive(Debug)]
+pub struct TenxH5Summary {
+    pub features: FeatureTable,
+    pub cells: Vec<String>,
+    pub nrows: usize,
+    pub ncols: usize,
+    pub nnz: usize,
+}
+
+// CSC by barcode, as Cell Ranger stores it: indptr per cell, indices = feature row.
+#[derive(Debug)]
+pub struct TenxH5Matrix {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub indptr: Vec<u64>,
+    pub indices: Vec<u32>,
+    pub data: Vec<f32>,
+}
+
+// Cell Ranger >= 3 writes `matrix/` with a `features/` group; v2 files have
+// one group per genome holding `genes`/`gene_names` directly.
+fn matrix_group(file: &hdf5::File) -> Result<hdf5::Group> {
+    if let Ok(group) = file.group("matrix") {
+        return Ok(group);
+    }
+    for group in file.groups()? {
+        if group.link_exists("indptr") && group.link_exists("genes") {
+            return Ok(group);
+        }
+    }
+    bail!("not a 10x HDF5 matrix: no matrix group")
+}
+
+pub fn read_tenx_h5_summary(path: &Path) -> Result<TenxH5Summary> {
+    let file =
+        hdf5::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let matrix = matrix_group(&file)?;
+
+    let (ids, names, feature_types) = if let Ok(features) = matrix.group("features") {
+        let ids = read_strings(&features.dataset("id")?).context("failed to read features/id")?;
+        let names =
+            read_strings(&features.dataset("name")?).context("failed to read features/name")?;
+        let feature_types = match features.dataset("feature_type") {
+            Ok(ds) => read_strings(&ds).context("failed to read features/feature_type")?,
+            Err(_) => vec![GENE_EXPRESSION.to_string(); ids.len()],
+        };
+        (ids, names, feature_types)
+    } else {
+        let ids = read_strings(&matrix.dataset("genes")?).context("failed to read genes")?;
+        let names =
+            read_strings(&matrix.dataset("gene_names")?).context("failed to read gene_names")?;
+        let feature_types = vec![GENE_EXPRESSION.to_string(); ids.len()];
+        (ids, names, feature_types)
+    };
+    if names.len() != ids.len() || feature_types.len() != ids.len() {
+        bail!(
+            "10x HDF5 feature columns differ in length: id {}, name {}, feature_type {}",
+            ids.len(),
+            names.len(),
+            feature_types.len()
+        );
+    }
+
+    let mut features = FeatureTable::default();
+    for (i, ((id, name), kind)) in ids.iter().zip(&names).zip(feature_types).enumerate() {
+        let name = Some(name.as_str()).filter(|s| !s.trim().is_empty());
+        features.ids.push(normalize_gene_symbol(id, Some(id), i));
+        features.symbols.push(normalize_gene_symbol(id, name, i));
+        features.feature_types.push(kind);
+    }
+
+    let cells = read_strings(&matrix.dataset("barcodes")?)
+        .context("failed to read barcodes")?
+        .iter()
+        .enumerate()
+        .map(|(i, b)| normalize_barcode(b, i))
+        .collect::<Vec<_>>();
+
+    let (nrows, ncols) = read_shape(&matrix)?;
+    if nrows != features.symbols.len() || ncols != cells.len() {
+        bail!(
+            "10x HDF5 shape ({}, {}) does not match features ({}) and barcodes ({})",
+            nrows,
+            ncols,
+            features.symbols.len(),
+            cells.len()
+        );
+    }
+    let nnz = matrix.dataset("data")?.size();
+
+    Ok(TenxH5Summary {
+        features,
+        cells,
+        nrows,
+        ncols,
+        nnz,
+    })
+}
+
+pub fn read_tenx_h5_indices(path: &Path) -> Result<Vec<u32>> {
+    let file =
+        hdf5::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    Ok(matrix_group(&file)?.dataset("indices")?.read_raw::<u32>()?)
+}
+
+pub fn read_tenx_h5_matrix(path: &Path) -> Result<TenxH5Matrix> {
+    let file =
+        hdf5::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let matrix = matrix_group(&file)?;
+    let (nrows, ncols) = read_shape(&matrix)?;
+    let indptr = matrix.dataset("indptr")?.read_raw::<u64>()?;
+    let indices = matrix.dataset("indices")?.read_raw::<u32>()?;
+    let data = matrix.dataset("data")?.read_raw::<f32>()?;
+
+    if indptr.len() != ncols + 1 {
+        bail!(
+            "10x HDF5 indptr length {} != barcodes + 1 ({})",
+            indptr.len(),
+            ncols + 1
+        );
+    }
+    if indices.len() != data.len() || *indptr.last().unwrap() as usize != data.len() {
+        bail!("10x HDF5 indptr/indices/data lengths are inconsistent");
+    }
+    if let Some(&row) = indices.iter().find(|&&row| row as usize >= nrows) {
+        bail!("10x HDF5 feature index {} out of bounds", row);
+    }
+
+    Ok(TenxH5Matrix {
+        nrows,
+        ncols,
+        indptr,
+        indices,
+        data,
+    })
+}
+
+fn read_shape(matrix: &hdf5::Group) -> Result<(usize, usize)> {
+    let shape = matrix.dataset("shape")?.read_raw::<u64>()?;
+    if shape.len() != 2 {
+        bail!("10x HDF5 shape must have 2 entries, got {}", shape.len());
+    }
+    Ok((shape[0] as usize, shape[1] as usize))
+}
+
+// Cell Ranger writes fixed-length byte strings; other writers use vlen.
+pub fn read_strings(ds: &hdf5::Dataset) -> Result<Vec<String>> {
+    if let Ok(values) = ds.read_raw::<FixedAscii<256>>() {
+        return Ok(values.iter().map(|v| v.as_str().to_string()).collect());
+    }
+    if let Ok(values) = ds.read_raw::<FixedUnicode<256>>() {
+        return Ok(values.iter().map(|v| v.as_str().to_string()).collect());
+    }
+    if let Ok(values) = ds.read_raw::<VarLenUnicode>() {
+        return Ok(values.iter().map(|v| v.as_str().to_string()).collect());
+    }
+    let values = ds
+        .read_raw::<VarLenAscii>()
+        .context("unsupported string dtype")?;
+    Ok(values.iter().map(|v| v.as_str().to_string()).collect())
+}
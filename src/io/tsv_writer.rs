@@ -4,6 +4,8 @@ use std::path::Path;
 use anyhow::{Context, Result, bail};
 
 use crate::ctx::Ctx;
+use crate::io::clusters::ALL_CELLS_CLUSTER;
+use crate::metrics::mito::{self, MitoAxisScores};
 use crate::schema::v1::Mode;
 
 pub fn write_tsv(path: &Path, ctx: &Ctx) -> Result<()> {
@@ -17,6 +19,7 @@ pub fn write_tsv(path: &Path, ctx: &Ctx) -> Result<()> {
         .as_ref()
         .context("integrated scores missing")?;
     let extension = ctx.proteostasis_extension.as_ref().map(|r| &r.scores);
+    let mito_axis = ctx.mito_axis.as_ref().map(|m| &m.scores);
 
     match ctx.mode {
         Mode::Cell => {
@@ -26,6 +29,8 @@ pub fn write_tsv(path: &Path, ctx: &Ctx) -> Result<()> {
             ensure_len(axis.cls.len(), n, "cls")?;
             ensure_len(axis.erad.len(), n, "erad")?;
             ensure_len(axis.ribo.len(), n, "ribo")?;
+            ensure_len(axis.ips.len(), n, "ips")?;
+            ensure_len(integrated.ips_ratio.len(), n, "ips_ratio")?;
             ensure_len(integrated.capacity_raw.len(), n, "capacity")?;
             ensure_len(integrated.pii_raw.len(), n, "pii")?;
             ensure_len(integrated.pfs_raw.len(), n, "pfs")?;
@@ -35,6 +40,11 @@ pub fn write_tsv(path: &Path, ctx: &Ctx) -> Result<()> {
                 .as_ref()
                 .context("PFS_z missing in per-cell mode")?;
             ensure_len(pfs_z.len(), n, "pfs_z")?;
+            let ips_z = integrated
+                .ips_z
+                .as_ref()
+                .context("IPS_z missing in per-cell mode")?;
+            ensure_len(ips_z.len(), n, "ips_z")?;
             if let Some(ext) = extension {
                 ensure_len(ext.chaperone_core.len(), n, "chaperone_core")?;
                 ensure_len(ext.proteasome_core.len(), n, "proteasome_core")?;
@@ -53,12 +63,25 @@ pub fn write_tsv(path: &Path, ctx: &Ctx) -> Result<()> {
                 ensure_len(ext.imbalance_high.len(), n, "imbalance_high")?;
                 ensure_len(ext.collapse_risk.len(), n, "collapse_risk")?;
             }
+            if let Some(m) = mito_axis {
+                for (name, values) in mito::metric_views(m) {
+                    ensure_len(values.len(), n, name)?;
+                }
+            }
 
-            writeln!(
+            write!(
                 w,
-                "cell_id\tPCS_raw\tUTP_raw\tCLS_raw\tERAD_raw\tRibo_raw\tCapacity_raw\tPII_raw\tPFS_raw\tPFS_z\tchaperone_core\tproteasome_core\tupr_core\tagg_core\tCCI\tPCI\tUPR_A\tPLS\tSCI\tPCP\tchaperone_high\tproteasome_high\tupr_active\tproteotoxic_high\timbalance_high\tcollapse_risk"
+                "cell_id\tPCS_raw\tUTP_raw\tCLS_raw\tERAD_raw\tRibo_raw\tCapacity_raw\tPII_raw\tPFS_raw\tPFS_z\tchaperone_core\tproteasome_core\tupr_core\tagg_core\tCCI\tPCI\tUPR_A\tPLS\tSCI\tPCP\tchaperone_high\tproteasome_high\tupr_active\tproteotoxic_high\timbalance_high\tcollapse_risk\tcluster\tIPS_raw\tIPS_z\tIPS_ratio"
             )?;
+            write_mito_header(&mut w, mito_axis)?;
+            if let Some(labels) = &ctx.cell_clusters {
+                ensure_len(labels.len(), n, "clusters")?;
+            }
             for i in 0..n {
+                let cluster = ctx
+                    .cell_clusters
+                    .as_ref()
+                    .map_or(ALL_CELLS_CLUSTER, |labels| labels[i].as_str());
                 let (
                     chaperone_core,
                     proteasome_core,
@@ -115,9 +138,9 @@ pub fn write_tsv(path: &Path, ctx: &Ctx) -> Result<()> {
                         false,
                     )
                 };
-                writeln!(
+                write!(
                     w,
-                    "{}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    "{}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.6}\t{:.6}\t{:.6}",
                     ctx.cells[i],
                     axis.pcs[i],
                     axis.utp[i],
@@ -143,20 +166,28 @@ pub fn write_tsv(path: &Path, ctx: &Ctx) -> Result<()> {
                     upr_active,
                     proteotoxic_high,
                     imbalance_high,
-                    collapse_risk
+                    collapse_risk,
+                    cluster,
+                    axis.ips[i],
+                    ips_z[i],
+                    integrated.ips_ratio[i]
                 )?;
+                write_mito_values(&mut w, mito_axis.map(|m| mito::cell_values(m, i)))?;
             }
         }
         Mode::Sample => {
-            writeln!(
+            write!(
                 w,
-                "sample\tPCS_raw\tUTP_raw\tCLS_raw\tERAD_raw\tRibo_raw\tCapacity_raw\tPII_raw\tPFS_raw\tchaperone_core\tproteasome_core\tupr_core\tagg_core\tCCI\tPCI\tUPR_A\tPLS\tSCI\tPCP\tchaperone_high\tproteasome_high\tupr_active\tproteotoxic_high\timbalance_high\tcollapse_risk"
+                "sample\tPCS_raw\tUTP_raw\tCLS_raw\tERAD_raw\tRibo_raw\tCapacity_raw\tPII_raw\tPFS_raw\tchaperone_core\tproteasome_core\tupr_core\tagg_core\tCCI\tPCI\tUPR_A\tPLS\tSCI\tPCP\tchaperone_high\tproteasome_high\tupr_active\tproteotoxic_high\timbalance_high\tcollapse_risk\tIPS_raw\tIPS_ratio"
             )?;
+            write_mito_header(&mut w, mito_axis)?;
             let pcs = mean(&axis.pcs)?;
             let utp = mean(&axis.utp)?;
             let cls = mean(&axis.cls)?;
             let erad = mean(&axis.erad)?;
             let ribo = mean(&axis.ribo)?;
+            let ips = mean(&axis.ips)?;
+            let ips_ratio = mean(&integrated.ips_ratio)?;
             let capacity = mean(&integrated.capacity_raw)?;
             let pii = mean(&integrated.pii_raw)?;
             let pfs = mean(&integrated.pfs_raw)?;
@@ -177,9 +208,10 @@ pub fn write_tsv(path: &Path, ctx: &Ctx) -> Result<()> {
                 frac_true(extension.map(|e| e.proteotoxic_high.as_slice())) >= 0.5;
             let imbalance_high = frac_true(extension.map(|e| e.imbalance_high.as_slice())) >= 0.5;
             let collapse_risk = frac_true(extension.map(|e| e.collapse_risk.as_slice())) >= 0.5;
-            writeln!(
+            write!(
                 w,
-                "sample\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{}\t{}\t{}\t{}\t{}\t{}",
+                "{}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.6}\t{:.6}",
+                ctx.metadata.sample_id,
                 pcs,
                 utp,
                 cls,
@@ -203,14 +235,36 @@ pub fn write_tsv(path: &Path, ctx: &Ctx) -> Result<()> {
                 upr_active,
                 proteotoxic_high,
                 imbalance_high,
-                collapse_risk
+                collapse_risk,
+                ips,
+                ips_ratio
             )?;
+            write_mito_values(&mut w, mito_axis.map(mito::sample_values))?;
         }
     }
 
     Ok(())
 }
 
+// Mitochondrial axis columns exist only in mito+proteo runs.
+fn write_mito_header<W: Write>(w: &mut W, mito_axis: Option<&MitoAxisScores>) -> Result<()> {
+    if let Some(m) = mito_axis {
+        for (name, _) in mito::metric_views(m) {
+            write!(w, "\t{}", name)?;
+        }
+    }
+    writeln!(w)?;
+    Ok(())
+}
+
+fn write_mito_values<W: Write>(w: &mut W, values: Option<[f32; 4]>) -> Result<()> {
+    for v in values.into_iter().flatten() {
+        write!(w, "\t{:.6}", v)?;
+    }
+    writeln!(w)?;
+    Ok(())
+}
+
 fn ensure_len(got: usize, expected: usize, name: &str) -> Result<()> {
     if got != expected {
         bail!("{} length mismatch: {} != {}", name, got, expected);
@@ -6,7 +6,9 @@ pub mod geneset;
 pub mod input;
 pub mod io;
 pub mod math;
+pub mod metadata;
 pub mod metrics;
+pub mod parallel;
 pub mod pipeline;
 pub mod schema;
 pub mod scores;
@@ -1,16 +1,31 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use tracing_subscriber::EnvFilter;
 
-use kira_proteoqc::cli::{Cli, Commands, ModeArg, RunModeArg};
-use kira_proteoqc::ctx::{Ctx, RunMode};
-use kira_proteoqc::geneset;
+use kira_proteoqc::cli::{
+    CacheBuildArgs, CacheCommand, CacheInspectArgs, Cli, Commands, ExprEncodingArg, FilterArgs,
+    FusionArg, GenesetVersionArg, ModeArg, NormalizeArg, RunModeArg, SimdArg, SpeciesArg,
+};
+use kira_proteoqc::ctx::{Ctx, InputFormat, RunMode};
+use kira_proteoqc::expr::filter::FilterOptions;
+use kira_proteoqc::expr::layout::{self, ExprEncoding};
+use kira_proteoqc::expr::normalize::NormalizationMethod;
+use kira_proteoqc::expr::reader;
+use kira_proteoqc::fusion::FusionMode;
+use kira_proteoqc::geneset::{self, GenesetVersion};
+use kira_proteoqc::input;
 use kira_proteoqc::io;
+use kira_proteoqc::io::clusters::ClusterSource;
+use kira_proteoqc::io::h5ad_layer::H5adLayer;
+use kira_proteoqc::io::shared_cache;
+use kira_proteoqc::metadata::Species;
 use kira_proteoqc::pipeline::Pipeline;
 use kira_proteoqc::pipeline::stage0_scaffold::Stage0Scaffold;
 use kira_proteoqc::pipeline::stage1_input::Stage1Input;
 use kira_proteoqc::pipeline::stage2_h5ad::Stage2H5ad;
+use kira_proteoqc::pipeline::stage2b_metadata::Stage2bMetadata;
 use kira_proteoqc::pipeline::stage3_expr_cache::Stage3ExprCache;
 use kira_proteoqc::pipeline::stage4_geneset::Stage4Geneset;
 use kira_proteoqc::pipeline::stage5_math::Stage5Math;
@@ -22,6 +37,9 @@ use kira_proteoqc::pipeline::stage9_timecourse::Stage9Timecourse;
 use kira_proteoqc::pipeline::stage10_output::Stage10Output;
 use kira_proteoqc::schema::v1::Mode;
 use kira_proteoqc::scores::TimepointSummary;
+use kira_proteoqc::scores::profile::{builtin_profile, load_profile};
+use kira_proteoqc::scores::rules::{builtin_rules, load_rules, merge_rules};
+use kira_proteoqc::simd::Backend;
 
 fn main() -> Result<()> {
     tracing_subscriber::fmt()
@@ -31,6 +49,9 @@ fn main() -> Result<()> {
         .init();
 
     let cli = Cli::parse();
+    if let Some(backend) = simd_backend(cli.simd) {
+        kira_proteoqc::simd::set_backend(backend)?;
+    }
     tracing::info!(
         simd_backend = %kira_proteoqc::simd::backend_name(),
         "simd backend selected"
@@ -38,11 +59,29 @@ fn main() -> Result<()> {
 
     match cli.command {
         Commands::Run(args) => {
+            kira_proteoqc::math::sum::set_deterministic(args.deterministic);
             let mode = match args.mode {
                 ModeArg::Cell => Mode::Cell,
                 ModeArg::Sample => Mode::Sample,
             };
             let log1p = !args.no_log1p;
+            let normalization = normalization_method(args.normalize);
+            let h5ad_layer = H5adLayer::parse(&args.h5ad_layer)?;
+            let species = species_override(args.species);
+            let geneset_version = geneset_version(args.geneset_version);
+            let profile = match &args.profile {
+                Some(path) => load_profile(path)?,
+                None => builtin_profile().clone(),
+            };
+            let risk_rules = match &args.risk_rules {
+                Some(path) => merge_rules(builtin_rules(), load_rules(path)?),
+                None => builtin_rules().to_vec(),
+            };
+            let clusters = args
+                .clusters
+                .as_deref()
+                .map(ClusterSource::parse)
+                .transpose()?;
 
             if args.timecourse && args.input.len() < 2 {
                 anyhow::bail!("--timecourse requires at least 2 --input values");
@@ -65,14 +104,30 @@ fn main() -> Result<()> {
                     env!("CARGO_PKG_VERSION"),
                 );
                 master_ctx.threads = args.threads;
+                master_ctx.max_memory = args.max_memory;
+                master_ctx.expr_encoding = expr_encoding(args.expr_encoding);
                 master_ctx.cache_block = args.cache_block;
                 master_ctx.prefetch = args.prefetch;
-                master_ctx.fusion = args.fusion.clone();
+                master_ctx.fusion = fusion_mode(args.fusion);
+                master_ctx.normalization = normalization.unwrap_or(NormalizationMethod::Cp10k);
+                master_ctx.normalization_auto = normalization.is_none();
+                master_ctx.h5ad_layer = h5ad_layer.clone();
+                master_ctx.feature_type = args.feature_type.clone();
+                master_ctx.filters = filter_options(&args.filters);
                 master_ctx.run_mode = match args.run_mode {
                     RunModeArg::Standalone => RunMode::Standalone,
                     RunModeArg::Pipeline => RunMode::Pipeline,
                 };
                 master_ctx.cache_override = args.cache.clone();
+                master_ctx.cache_dir = args.cache_dir.clone();
+                master_ctx.clusters = clusters.clone();
+                master_ctx.sample_id = args.sample_id.clone();
+                master_ctx.condition = args.condition.clone();
+                master_ctx.metadata_path = args.metadata.clone();
+                master_ctx.species = species;
+                master_ctx.geneset_version = geneset_version;
+                master_ctx.profile = profile.clone();
+                master_ctx.risk_rules = risk_rules.clone();
 
                 for input in ordered_inputs {
                     let label = label_from_path(&input);
@@ -89,18 +144,35 @@ fn main() -> Result<()> {
                         env!("CARGO_PKG_VERSION"),
                     );
                     ctx.threads = args.threads;
+                    ctx.max_memory = args.max_memory;
+                    ctx.expr_encoding = expr_encoding(args.expr_encoding);
                     ctx.cache_block = args.cache_block;
                     ctx.prefetch = args.prefetch;
-                    ctx.fusion = args.fusion.clone();
+                    ctx.fusion = fusion_mode(args.fusion);
+                    ctx.normalization = normalization.unwrap_or(NormalizationMethod::Cp10k);
+                    ctx.normalization_auto = normalization.is_none();
+                    ctx.h5ad_layer = h5ad_layer.clone();
+                    ctx.feature_type = args.feature_type.clone();
+                    ctx.filters = filter_options(&args.filters);
                     ctx.run_mode = match args.run_mode {
                         RunModeArg::Standalone => RunMode::Standalone,
                         RunModeArg::Pipeline => RunMode::Pipeline,
                     };
                     ctx.cache_override = args.cache.clone();
+                    ctx.cache_dir = args.cache_dir.clone();
+                    ctx.clusters = clusters.clone();
+                    ctx.sample_id = args.sample_id.clone();
+                    ctx.condition = args.condition.clone();
+                    ctx.metadata_path = args.metadata.clone();
+                    ctx.species = species;
+                    ctx.geneset_version = geneset_version;
+                    ctx.profile = profile.clone();
+                    ctx.risk_rules = risk_rules.clone();
                     let pipeline = Pipeline::new(vec![
                         Box::new(Stage0Scaffold::new()),
                         Box::new(Stage1Input::new()),
                         Box::new(Stage2H5ad::new()),
+                        Box::new(Stage2bMetadata::new()),
                         Box::new(Stage3ExprCache::new()),
                         Box::new(Stage4Geneset::new()),
                         Box::new(Stage5Math::new()),
@@ -132,19 +204,36 @@ fn main() -> Result<()> {
                     env!("CARGO_PKG_VERSION"),
                 );
                 ctx.threads = args.threads;
+                ctx.max_memory = args.max_memory;
+                ctx.expr_encoding = expr_encoding(args.expr_encoding);
                 ctx.cache_block = args.cache_block;
                 ctx.prefetch = args.prefetch;
-                ctx.fusion = args.fusion;
+                ctx.fusion = fusion_mode(args.fusion);
+                ctx.normalization = normalization.unwrap_or(NormalizationMethod::Cp10k);
+                ctx.normalization_auto = normalization.is_none();
+                ctx.h5ad_layer = h5ad_layer;
+                ctx.feature_type = args.feature_type.clone();
+                ctx.filters = filter_options(&args.filters);
                 ctx.run_mode = match args.run_mode {
                     RunModeArg::Standalone => RunMode::Standalone,
                     RunModeArg::Pipeline => RunMode::Pipeline,
                 };
                 ctx.cache_override = args.cache.clone();
+                ctx.cache_dir = args.cache_dir.clone();
+                ctx.clusters = clusters;
+                ctx.sample_id = args.sample_id;
+                ctx.condition = args.condition;
+                ctx.metadata_path = args.metadata;
+                ctx.species = species;
+                ctx.geneset_version = geneset_version;
+                ctx.profile = profile;
+                ctx.risk_rules = risk_rules;
 
                 let pipeline = Pipeline::new(vec![
                     Box::new(Stage0Scaffold::new()),
                     Box::new(Stage1Input::new()),
                     Box::new(Stage2H5ad::new()),
+                    Box::new(Stage2bMetadata::new()),
                     Box::new(Stage3ExprCache::new()),
                     Box::new(Stage4Geneset::new()),
                     Box::new(Stage5Math::new()),
@@ -181,10 +270,28 @@ fn main() -> Result<()> {
                 RunModeArg::Standalone => RunMode::Standalone,
                 RunModeArg::Pipeline => RunMode::Pipeline,
             };
+            ctx.h5ad_layer = H5adLayer::parse(&args.h5ad_layer)?;
+            ctx.feature_type = args.feature_type;
+            ctx.filters = filter_options(&args.filters);
+
+            // H5AD validation stops at metadata: names, shape, nnz and a
+            // sample of stored values, without reading the matrix. Filters
+            // need the matrix, so they take the full path.
+            if ctx.input_format == InputFormat::H5ad && !ctx.filters.is_active() {
+                let pipeline = Pipeline::new(vec![
+                    Box::new(Stage1Input::new()),
+                    Box::new(Stage2H5ad::new()),
+                    Box::new(Stage2bMetadata::new()),
+                ]);
+                pipeline.run(&mut ctx)?;
+                print_validate_summary(&ctx);
+                return Ok(());
+            }
 
             let pipeline = Pipeline::new(vec![
                 Box::new(Stage1Input::new()),
                 Box::new(Stage2H5ad::new()),
+                Box::new(Stage2bMetadata::new()),
                 Box::new(Stage3ExprCache::new()),
                 Box::new(Stage4Geneset::new()),
                 Box::new(Stage5Math::new()),
@@ -198,20 +305,83 @@ fn main() -> Result<()> {
 
             print_validate_summary(&ctx);
         }
+        Commands::Cache(args) => match args.command {
+            CacheCommand::Build(build) => handle_cache_build(build)?,
+            CacheCommand::Inspect(inspect) => handle_cache_inspect(inspect)?,
+        },
     }
 
     Ok(())
 }
 
+fn filter_options(args: &FilterArgs) -> FilterOptions {
+    FilterOptions {
+        cells: args.cells.clone(),
+        exclude_cells: args.exclude_cells.clone(),
+        min_umis: args.min_umis,
+        min_genes: args.min_genes,
+        exclude_genes: args.exclude_genes.clone(),
+    }
+}
+
+// `None` detects the best backend at runtime.
+fn simd_backend(arg: SimdArg) -> Option<Backend> {
+    match arg {
+        SimdArg::Auto => None,
+        SimdArg::Scalar => Some(Backend::Scalar),
+        SimdArg::Sse41 => Some(Backend::Sse41),
+        SimdArg::Avx2 => Some(Backend::Avx2),
+        SimdArg::Avx512 => Some(Backend::Avx512),
+        SimdArg::Neon => Some(Backend::Neon),
+    }
+}
+
+// `None` leaves the choice to Stage2H5ad's value detection.
+fn normalization_method(arg: NormalizeArg) -> Option<NormalizationMethod> {
+    match arg {
+        NormalizeArg::Auto => None,
+        NormalizeArg::Raw => Some(NormalizationMethod::Raw),
+        NormalizeArg::Cp10k => Some(NormalizationMethod::Cp10k),
+        NormalizeArg::Cpm => Some(NormalizationMethod::Cpm),
+        NormalizeArg::Prenormalized => Some(NormalizationMethod::Prenormalized),
+    }
+}
+
+fn geneset_version(arg: GenesetVersionArg) -> GenesetVersion {
+    match arg {
+        GenesetVersionArg::V1 => GenesetVersion::V1,
+        GenesetVersionArg::V2 => GenesetVersion::V2,
+    }
+}
+
+fn expr_encoding(arg: ExprEncodingArg) -> ExprEncoding {
+    match arg {
+        ExprEncodingArg::Plain => ExprEncoding::Plain,
+        ExprEncodingArg::Compact => ExprEncoding::Compact,
+    }
+}
+
+fn fusion_mode(arg: FusionArg) -> FusionMode {
+    match arg {
+        FusionArg::Off => FusionMode::Off,
+        FusionArg::Proteo => FusionMode::Proteo,
+        FusionArg::MitoProteo => FusionMode::MitoProteo,
+    }
+}
+
+fn species_override(arg: SpeciesArg) -> Option<Species> {
+    match arg {
+        SpeciesArg::Auto => None,
+        SpeciesArg::Human => Some(Species::Human),
+        SpeciesArg::Mouse => Some(Species::Mouse),
+    }
+}
+
 fn print_summary(ctx: &Ctx) -> Result<()> {
     let summary = io::summary::format_summary(ctx)?;
     print!("{}", summary);
-    if !ctx.warnings.is_empty() {
-        println!("warnings:");
-        for warning in &ctx.warnings {
-            println!("- {}", warning);
-        }
-    }
+    print_notices("warnings", &ctx.warnings);
+    print_notices("notes", &ctx.notes);
     Ok(())
 }
 
@@ -220,11 +390,151 @@ fn print_validate_summary(ctx: &Ctx) {
     println!("genes: {}", ctx.genes.len());
     println!("cells: {}", ctx.cells.len());
     println!("nnz: {}", ctx.nnz);
-    if !ctx.warnings.is_empty() {
-        println!("warnings:");
-        for warning in &ctx.warnings {
-            println!("- {}", warning);
+    if let Some(values) = ctx.matrix_values {
+        if ctx.input_format == InputFormat::H5ad {
+            println!("h5ad_layer: {}", ctx.h5ad_layer);
         }
+        println!("values: {}", values.as_str());
+        println!("normalization: {}", ctx.normalization.as_str());
+    }
+    if let Some(filters) = &ctx.filter_summary {
+        print!("{}", io::summary::format_filters(filters));
+    }
+    print_notices("warnings", &ctx.warnings);
+    print_notices("notes", &ctx.notes);
+}
+
+fn print_notices(label: &str, notices: &[String]) {
+    if !notices.is_empty() {
+        println!("{}:", label);
+        for notice in notices {
+            println!("- {}", notice);
+        }
+    }
+}
+
+fn handle_cache_build(args: CacheBuildArgs) -> Result<()> {
+    let out = match args.out {
+        Some(path) => path,
+        None => shared_cache::default_build_path(&args.input, args.prefix.as_deref())?,
+    };
+    if out.exists() && !args.force {
+        anyhow::bail!(
+            "{} already exists (use --force to overwrite)",
+            out.display()
+        );
+    }
+    let layer = H5adLayer::parse(&args.h5ad_layer)?;
+    let cache = shared_cache::build_from_input(&args.input, &layer, &args.feature_type, &out)?;
+    println!("kira-proteoqc cache build ok");
+    println!("path: {}", out.display());
+    println!("genes: {}", cache.header.n_genes);
+    println!("cells: {}", cache.header.n_cells);
+    println!("nnz: {}", cache.header.nnz);
+    Ok(())
+}
+
+fn handle_cache_inspect(args: CacheInspectArgs) -> Result<()> {
+    if args.input.is_file() && is_expr_cache(&args.input)? {
+        return inspect_expr_cache(&args.input);
+    }
+    let path = if args.input.is_dir() {
+        let prefix = match args.prefix {
+            Some(p) => Some(p),
+            None => input::detect_prefix(&args.input)?,
+        };
+        input::resolve_shared_cache_path(&args.input, prefix.as_deref())
+    } else {
+        args.input
+    };
+    let (header, header_crc) = shared_cache::read_header(&path)?;
+    let actual_bytes = std::fs::metadata(&path)
+        .with_context(|| format!("failed to stat {}", path.display()))?
+        .len();
+
+    println!("path: {}", path.display());
+    println!("version: {}.{}", header.version_major, header.version_minor);
+    println!("genes: {}", header.n_genes);
+    println!("cells: {}", header.n_cells);
+    println!("nnz: {}", header.nnz);
+    println!(
+        "genes_table: offset {} bytes {}",
+        header.genes_table_offset, header.genes_table_bytes
+    );
+    println!(
+        "barcodes_table: offset {} bytes {}",
+        header.barcodes_table_offset, header.barcodes_table_bytes
+    );
+    println!("col_ptr_offset: {}", header.col_ptr_offset);
+    println!("row_idx_offset: {}", header.row_idx_offset);
+    println!("values_u32_offset: {}", header.values_u32_offset);
+    println!(
+        "file_bytes: {} (actual {})",
+        header.file_bytes, actual_bytes
+    );
+    if header.header_crc64 == header_crc {
+        println!("header_crc64: {:016x} ok", header.header_crc64);
+    } else {
+        println!(
+            "header_crc64: {:016x} mismatch (computed {:016x})",
+            header.header_crc64, header_crc
+        );
+    }
+    println!("data_crc64: {:016x}", header.data_crc64);
+
+    let cache = match shared_cache::SharedCache::open(&path) {
+        Ok(cache) => cache,
+        Err(err) => {
+            println!("validation: failed");
+            return Err(err.context(format!("{} is not a valid shared cache", path.display())));
+        }
+    };
+    println!("validation: ok");
+    println!("genes_sample:");
+    for gene in cache.genes.iter().take(args.sample) {
+        println!("- {}", gene);
+    }
+    println!("barcodes_sample:");
+    for barcode in cache.barcodes.iter().take(args.sample) {
+        println!("- {}", barcode);
+    }
+    Ok(())
+}
+
+fn is_expr_cache(path: &Path) -> Result<bool> {
+    let mut magic = [0u8; 8];
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    Ok(file.read_exact(&mut magic).is_ok() && magic == layout::MAGIC)
+}
+
+// The only place the data CRC of an expr.bin is recomputed: reuse trusts the
+// fingerprint so it never has to read the whole file.
+fn inspect_expr_cache(path: &Path) -> Result<()> {
+    let (header, mmap) = reader::open_mmap(path)?;
+    println!("path: {}", path.display());
+    println!("version: {}", header.version);
+    println!("genes: {}", header.n_genes);
+    println!("cells: {}", header.n_cells);
+    println!("nnz: {}", header.nnz);
+    if header.version == layout::VERSION {
+        println!("data_crc64: none (v1 cache)");
+        println!("validation: ok");
+        return Ok(());
+    }
+    println!("source_fingerprint: {:016x}", header.source_fingerprint);
+    let computed = reader::data_crc64(&mmap, &header);
+    if computed == header.data_crc64 {
+        println!("data_crc64: {:016x} ok", header.data_crc64);
+        println!("validation: ok");
+        Ok(())
+    } else {
+        println!(
+            "data_crc64: {:016x} mismatch (computed {:016x})",
+            header.data_crc64, computed
+        );
+        println!("validation: failed");
+        anyhow::bail!("{} has a corrupted payload", path.display())
     }
 }
 
@@ -241,9 +551,13 @@ fn handle_geneset_show(args: kira_proteoqc::cli::GenesetShowArgs) -> Result<()>
             false,
             env!("CARGO_PKG_VERSION"),
         );
+        ctx.species = species_override(args.species);
+        ctx.geneset_version = geneset_version(args.geneset_version);
+        ctx.h5ad_layer = H5adLayer::parse(&args.h5ad_layer)?;
         let pipeline = Pipeline::new(vec![
             Box::new(Stage1Input::new()),
             Box::new(Stage2H5ad::new()),
+            Box::new(Stage2bMetadata::new()),
             Box::new(Stage4Geneset::new()),
         ]);
         pipeline.run(&mut ctx)?;
@@ -251,7 +565,8 @@ fn handle_geneset_show(args: kira_proteoqc::cli::GenesetShowArgs) -> Result<()>
         return Ok(());
     }
 
-    let mut collection = geneset::load_builtin()?;
+    let species = species_override(args.species).unwrap_or(Species::Human);
+    let mut collection = geneset::load_builtin(geneset_version(args.geneset_version), species)?;
     if let Some(path) = args.geneset {
         let user_defs = geneset::load_user(&path)?;
         collection.defs = geneset::merge_defs(collection.defs, user_defs);
@@ -2,3 +2,4 @@ pub mod reduce;
 pub mod reduce_blocked;
 pub mod reduce_mt;
 pub mod stats;
+pub mod sum;
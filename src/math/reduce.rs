@@ -6,6 +6,8 @@ use crate::expr::reader::ExprReader;
 use crate::math::reduce_blocked;
 #[cfg(feature = "mt")]
 use crate::math::reduce_mt;
+use crate::math::sum::{self, CompensatedSum};
+use crate::parallel;
 use crate::simd;
 
 pub struct GeneSetReducer<'a> {
@@ -13,8 +15,6 @@ pub struct GeneSetReducer<'a> {
     pub threads: usize,
     pub cache_block: usize,
     pub prefetch: bool,
-    #[cfg(feature = "mt")]
-    scratch_shards: Vec<f32>,
 }
 
 impl<'a> GeneSetReducer<'a> {
@@ -29,12 +29,26 @@ impl<'a> GeneSetReducer<'a> {
             threads,
             cache_block,
             prefetch,
-            #[cfg(feature = "mt")]
-            scratch_shards: Vec::new(),
         }
     }
 
     pub fn per_cell_raw(&mut self, genes: &[usize], out: &mut [f32]) -> Result<()> {
+        self.reduce_into(genes, out)
+    }
+
+    // One output per geneset, reduced concurrently on the shared pool; each
+    // geneset goes through the same path per_cell_raw would take.
+    pub fn per_cell_raw_many(&mut self, genesets: &[&[usize]]) -> Result<Vec<Vec<f32>>> {
+        let n_cells = self.expr.n_cells();
+        let this = &*self;
+        parallel::try_map(self.threads, genesets, |genes| {
+            let mut out = vec![0.0f32; n_cells];
+            this.reduce_into(genes, &mut out)?;
+            Ok(out)
+        })
+    }
+
+    fn reduce_into(&self, genes: &[usize], out: &mut [f32]) -> Result<()> {
         if out.len() != self.expr.n_cells() {
             bail!("output buffer length does not match n_cells");
         }
@@ -46,16 +60,18 @@ impl<'a> GeneSetReducer<'a> {
             return Ok(());
         }
 
+        if sum::deterministic() {
+            return self.per_cell_raw_deterministic(genes, out);
+        }
+
+        if self.expr.is_cell_major() {
+            return self.per_cell_raw_cell_major(genes, out);
+        }
+
         #[cfg(feature = "mt")]
         {
-            if self.threads > 1 {
-                return reduce_mt::per_cell_raw_mt(
-                    self.expr,
-                    genes,
-                    out,
-                    self.threads,
-                    &mut self.scratch_shards,
-                );
+            if parallel::enabled(self.threads) {
+                return reduce_mt::per_cell_raw_mt(self.expr, genes, out);
             }
         }
 
@@ -83,15 +99,15 @@ impl<'a> GeneSetReducer<'a> {
         let mut dense = vec![0.0f32; n_cells];
 
         for &gene_id in genes {
-            let (cells, values) = self.expr.gene_slice(gene_id)?;
-            for (&cell, &value) in cells.iter().zip(values.iter()) {
+            let entries = self.expr.gene_entries(gene_id)?;
+            for (cell, value) in entries.clone() {
                 if value.is_nan() {
                     bail!("NaN encountered in expr.bin values");
                 }
-                dense[cell as usize] += value;
+                dense[cell as usize] += self.expr.value(cell, value);
             }
             simd::add_scaled(out, &dense, 1.0);
-            for &cell in cells {
+            for (cell, _) in entries {
                 dense[cell as usize] = 0.0;
             }
         }
@@ -103,10 +119,65 @@ impl<'a> GeneSetReducer<'a> {
         Ok(())
     }
 
+    // Each cell looks its geneset genes up in its own sorted gene list and sums
+    // them in geneset order, matching the gene-major paths bit for bit.
+    fn per_cell_raw_cell_major(&self, genes: &[usize], out: &mut [f32]) -> Result<()> {
+        let denom = genes.len() as f32;
+        for (cell, slot) in out.iter_mut().enumerate() {
+            let mut acc = 0.0f32;
+            for &gene_id in genes {
+                if let Some(value) = self.expr.cell_value(cell, gene_id)? {
+                    if value.is_nan() {
+                        bail!("NaN encountered in expr.bin values");
+                    }
+                    acc += self.expr.value(cell as u32, value);
+                }
+            }
+            *slot = acc / denom;
+        }
+        Ok(())
+    }
+
+    // Genes are summed in ascending order whatever the source layout, the
+    // same order the fused reducer uses, and each sum is rounded to f32
+    // before the division.
+    fn per_cell_raw_deterministic(&self, genes: &[usize], out: &mut [f32]) -> Result<()> {
+        let mut sorted = genes.to_vec();
+        sorted.sort_unstable();
+        let mut acc = vec![CompensatedSum::default(); out.len()];
+        if self.expr.is_cell_major() {
+            for (cell, slot) in acc.iter_mut().enumerate() {
+                for &gene_id in &sorted {
+                    if let Some(value) = self.expr.cell_value(cell, gene_id)? {
+                        if value.is_nan() {
+                            bail!("NaN encountered in expr.bin values");
+                        }
+                        slot.add(self.expr.value(cell as u32, value) as f64);
+                    }
+                }
+            }
+        } else {
+            for &gene_id in &sorted {
+                for (cell, value) in self.expr.gene_entries(gene_id)? {
+                    if value.is_nan() {
+                        bail!("NaN encountered in expr.bin values");
+                    }
+                    acc[cell as usize].add(self.expr.value(cell, value) as f64);
+                }
+            }
+        }
+
+        let denom = genes.len() as f32;
+        for (o, a) in out.iter_mut().zip(&acc) {
+            *o = a.value() as f32 / denom;
+        }
+        Ok(())
+    }
+
     pub fn per_sample_raw(&mut self, genes: &[usize]) -> Result<f32> {
         let mut buf = vec![0.0f32; self.expr.n_cells()];
         self.per_cell_raw(genes, &mut buf)?;
-        let sum = simd::sum_f32(&buf);
+        let sum = sum::sum_f32(&buf);
         if buf.is_empty() {
             Ok(0.0)
         } else {
@@ -1,6 +1,6 @@
 use anyhow::{Result, bail};
 
-use crate::expr::prefetch::{prefetch_read, prefetch_write};
+use crate::expr::prefetch::prefetch_write;
 use crate::expr::reader::ExprReader;
 
 pub fn per_cell_raw_blocked(
@@ -23,28 +23,20 @@ pub fn per_cell_raw_blocked(
     let n_cells = expr.n_cells();
     let block = block_size.max(1);
 
+    // Entries arrive in cell order, so each cell block of `out` is touched
+    // as one run; prefetch its start when a new run begins.
     for &gene_id in genes {
-        let (cells, values) = expr.gene_slice(gene_id)?;
-        let mut idx = 0usize;
-        while idx < cells.len() {
-            let cell = cells[idx] as usize;
-            let block_start = (cell / block) * block;
-            let block_end = (block_start + block).min(n_cells);
-            let mut end = idx + 1;
-            while end < cells.len() && (cells[end] as usize) < block_end {
-                end += 1;
+        let mut block_end = 0usize;
+        for (cell, value) in expr.gene_entries(gene_id)? {
+            let c = cell as usize;
+            if c >= block_end {
+                let block_start = (c / block) * block;
+                block_end = (block_start + block).min(n_cells);
+                if do_prefetch {
+                    prefetch_write(out.as_ptr().wrapping_add(block_start) as *const u8);
+                }
             }
-            if do_prefetch {
-                let p = out.as_ptr().wrapping_add(block_start);
-                prefetch_write(p as *const u8);
-                let p2 = values.as_ptr().wrapping_add(end.min(values.len() - 1));
-                prefetch_read(p2 as *const u8);
-            }
-            for i in idx..end {
-                let c = cells[i] as usize;
-                out[c] += values[i];
-            }
-            idx = end;
+            out[c] += expr.value(cell, value);
         }
     }
 
@@ -7,109 +7,48 @@ use rayon::prelude::*;
 
 const SHARD_LEN: usize = 4096;
 
-pub fn per_cell_raw_mt(
-    expr: &ExprReader<'_>,
-    genes: &[usize],
-    out: &mut [f32],
-    threads: usize,
-    scratch_shards: &mut Vec<f32>,
-) -> Result<()> {
+// Cell shards write straight into their slice of `out` on the global pool;
+// each shard adds its genes in geneset order, like the scalar path.
+pub fn per_cell_raw_mt(expr: &ExprReader<'_>, genes: &[usize], out: &mut [f32]) -> Result<()> {
     if out.len() != expr.n_cells() {
         bail!("output buffer length does not match n_cells");
     }
-    for v in out.iter_mut() {
-        *v = 0.0;
-    }
     if genes.is_empty() {
+        out.fill(0.0);
         return Ok(());
     }
 
-    let n_cells = expr.n_cells();
-    let shard_len = SHARD_LEN;
-    let shard_count = (n_cells + shard_len - 1) / shard_len;
-    let total_len = shard_count * shard_len;
-    if scratch_shards.len() != total_len {
-        *scratch_shards = vec![0.0f32; total_len];
-    } else {
-        for v in scratch_shards.iter_mut() {
-            *v = 0.0;
-        }
-    }
-
     #[cfg(feature = "mt")]
-    {
-        let pool = rayon::ThreadPoolBuilder::new()
-            .num_threads(threads.max(1))
-            .build()
-            .map_err(|e| anyhow::anyhow!("failed to build thread pool: {}", e))?;
-        pool.install(|| {
-            scratch_shards
-                .par_chunks_mut(shard_len)
-                .enumerate()
-                .for_each(|(shard_id, shard)| {
-                    let shard_start = shard_id * shard_len;
-                    let shard_end = (shard_start + shard_len).min(n_cells);
-                    for &gene_id in genes {
-                        if let Ok((cells, values)) = expr.gene_slice(gene_id) {
-                            let start = lower_bound(cells, shard_start as u32);
-                            let end = lower_bound(cells, shard_end as u32);
-                            for idx in start..end {
-                                let cell = cells[idx] as usize - shard_start;
-                                shard[cell] += values[idx];
-                            }
-                        }
-                    }
-                    let denom = genes.len() as f32;
-                    for v in &mut shard[..(shard_end - shard_start)] {
-                        *v /= denom;
-                    }
-                });
-        });
-    }
-
+    let shards = out.par_chunks_mut(SHARD_LEN);
     #[cfg(not(feature = "mt"))]
-    {
-        let _ = threads;
-        for shard_id in 0..shard_count {
-            let shard_start = shard_id * shard_len;
-            let shard_end = (shard_start + shard_len).min(n_cells);
-            let shard = &mut scratch_shards[shard_id * shard_len..shard_id * shard_len + shard_len];
-            for &gene_id in genes {
-                let (cells, values) = expr.gene_slice(gene_id)?;
-                let start = lower_bound(cells, shard_start as u32);
-                let end = lower_bound(cells, shard_end as u32);
-                for idx in start..end {
-                    let cell = cells[idx] as usize - shard_start;
-                    shard[cell] += values[idx];
-                }
-            }
-            let denom = genes.len() as f32;
-            for v in &mut shard[..(shard_end - shard_start)] {
-                *v /= denom;
+    let shards = out.chunks_mut(SHARD_LEN);
+    shards
+        .enumerate()
+        .try_for_each(|(shard_id, shard)| reduce_shard(expr, genes, shard_id * SHARD_LEN, shard))
+}
+
+fn reduce_shard(
+    expr: &ExprReader<'_>,
+    genes: &[usize],
+    start: usize,
+    shard: &mut [f32],
+) -> Result<()> {
+    shard.fill(0.0);
+    let end = start + shard.len();
+    for &gene_id in genes {
+        for (cell, value) in expr
+            .gene_entries_from(gene_id, start as u32)?
+            .take_while(|&(cell, _)| (cell as usize) < end)
+        {
+            if value.is_nan() {
+                bail!("NaN encountered in expr.bin values");
             }
+            shard[cell as usize - start] += expr.value(cell, value);
         }
     }
-
-    for shard_id in 0..shard_count {
-        let shard_start = shard_id * shard_len;
-        let shard_end = (shard_start + shard_len).min(n_cells);
-        let shard = &scratch_shards[shard_id * shard_len..shard_id * shard_len + shard_len];
-        out[shard_start..shard_end].copy_from_slice(&shard[..(shard_end - shard_start)]);
+    let denom = genes.len() as f32;
+    for v in shard.iter_mut() {
+        *v /= denom;
     }
-
     Ok(())
 }
-
-fn lower_bound(slice: &[u32], value: u32) -> usize {
-    let mut left = 0usize;
-    let mut right = slice.len();
-    while left < right {
-        let mid = (left + right) / 2;
-        if slice[mid] < value {
-            left = mid + 1;
-        } else {
-            right = mid;
-        }
-    }
-    left
-}
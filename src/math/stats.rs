@@ -2,6 +2,7 @@
 //!
 //! Note: Functions may reorder the input slice.
 
+use crate::math::sum;
 use crate::simd;
 
 pub fn trimmed_mean(values: &mut [f32], p: f32) -> f32 {
@@ -52,5 +53,5 @@ fn mean(values: &[f32]) -> f32 {
     if values.is_empty() {
         return 0.0;
     }
-    simd::sum_f32(values) / values.len() as f32
+    sum::sum_f32(values) / values.len() as f32
 }
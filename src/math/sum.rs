@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::simd;
+
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+
+// In deterministic mode every reduction adds its terms in one fixed order
+// (ascending gene, then cell) into a compensated f64 accumulator, so results
+// do not depend on the SIMD backend, thread count or cache blocking.
+pub fn set_deterministic(on: bool) {
+    DETERMINISTIC.store(on, Ordering::Relaxed);
+}
+
+pub fn deterministic() -> bool {
+    DETERMINISTIC.load(Ordering::Relaxed)
+}
+
+// Neumaier's variant of Kahan summation; also exact when a term is larger
+// than the running sum.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompensatedSum {
+    sum: f64,
+    comp: f64,
+}
+
+impl CompensatedSum {
+    pub fn add(&mut self, value: f64) {
+        let t = self.sum + value;
+        if self.sum.abs() >= value.abs() {
+            self.comp += (self.sum - t) + value;
+        } else {
+            self.comp += (value - t) + self.sum;
+        }
+        self.sum = t;
+    }
+
+    pub fn value(&self) -> f64 {
+        self.sum + self.comp
+    }
+}
+
+pub fn compensated_sum(values: &[f32]) -> f32 {
+    let mut acc = CompensatedSum::default();
+    for &v in values {
+        acc.add(v as f64);
+    }
+    acc.value() as f32
+}
+
+pub fn sum_f32(values: &[f32]) -> f32 {
+    if deterministic() {
+        compensated_sum(values)
+    } else {
+        simd::sum_f32(values)
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+pub const UNKNOWN: &str = "unknown";
+
+const BARCODE_KEYS: [&str; 4] = ["barcode", "cell", "cell_id", "obs_names"];
+const SAMPLE_KEYS: [&str; 2] = ["sample", "sample_id"];
+const CONDITION_KEYS: [&str; 1] = ["condition"];
+
+// Minimum number of informative gene identifiers and the share one species
+// must hold before we commit to a call.
+const MIN_SPECIES_EVIDENCE: usize = 20;
+const SPECIES_MAJORITY: f64 = 0.8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Species {
+    Human,
+    Mouse,
+    Unknown,
+}
+
+impl Species {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Human => "human",
+            Self::Mouse => "mouse",
+            Self::Unknown => UNKNOWN,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CellMetadata {
+    pub sample: Vec<String>,
+    pub condition: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SampleMetadata {
+    pub sample_id: String,
+    pub condition: String,
+    pub species: Species,
+    pub cells: Option<CellMetadata>,
+}
+
+impl Default for SampleMetadata {
+    fn default() -> Self {
+        Self {
+            sample_id: "sample".to_string(),
+            condition: UNKNOWN.to_string(),
+            species: Species::Unknown,
+            cells: None,
+        }
+    }
+}
+
+impl SampleMetadata {
+    pub fn cell_sample(&self, idx: usize) -> &str {
+        match &self.cells {
+            Some(cells) => cells.sample[idx].as_str(),
+            None => self.sample_id.as_str(),
+        }
+    }
+
+    pub fn cell_condition(&self, idx: usize) -> &str {
+        match &self.cells {
+            Some(cells) => cells.condition[idx].as_str(),
+            None => self.condition.as_str(),
+        }
+    }
+}
+
+pub fn infer_species(genes: &[String]) -> Species {
+    let mut human = 0usize;
+    let mut mouse = 0usize;
+    for gene in genes {
+        match symbol_species(gene) {
+            Species::Human => human += 1,
+            Species::Mouse => mouse += 1,
+            Species::Unknown => {}
+        }
+    }
+    let total = human + mouse;
+    if total < MIN_SPECIES_EVIDENCE.min(genes.len().max(1)) {
+        return Species::Unknown;
+    }
+    if human as f64 >= SPECIES_MAJORITY * total as f64 {
+        Species::Human
+    } else if mouse as f64 >= SPECIES_MAJORITY * total as f64 {
+        Species::Mouse
+    } else {
+        Species::Unknown
+    }
+}
+
+// Human symbols are upper case (HSPA8, MT-CO1); mouse symbols are title case
+// (Hspa8, mt-Co1). Ensembl gene IDs carry the species in their prefix.
+fn symbol_species(gene: &str) -> Species {
+    if gene.starts_with("ENSMUSG") {
+        return Species::Mouse;
+    }
+    if gene.starts_with("ENSG") {
+        return Species::Human;
+    }
+    if gene.starts_with("MT-") {
+        return Species::Human;
+    }
+    if gene.starts_with("mt-") {
+        return Species::Mouse;
+    }
+    let letters = gene
+        .chars()
+        .filter(|c| c.is_ascii_alphabetic())
+        .collect::<Vec<_>>();
+    if letters.len() < 2 {
+        return Species::Unknown;
+    }
+    if letters.iter().all(|c| c.is_ascii_uppercase()) {
+        return Species::Human;
+    }
+    let first = gene.chars().next().unwrap_or(' ');
+    if first.is_ascii_uppercase() && letters[1..].iter().all(|c| c.is_ascii_lowercase()) {
+        return Species::Mouse;
+    }
+    Species::Unknown
+}
+
+pub fn read_cell_metadata(
+    path: &Path,
+    cells: &[String],
+    default_sample: &str,
+    default_condition: &str,
+) -> Result<(CellMetadata, usize)> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read metadata file {}", path.display()))?;
+    let mut lines = content
+        .lines()
+        .map(|l| l.trim_end_matches('\r'))
+        .filter(|l| !l.trim().is_empty() && !l.starts_with('#'));
+    let header = lines
+        .next()
+        .with_context(|| format!("metadata file {} is empty", path.display()))?
+        .split('\t')
+        .map(|h| h.trim().to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    let find = |keys: &[&str]| header.iter().position(|h| keys.contains(&h.as_str()));
+    let barcode_col = find(&BARCODE_KEYS).unwrap_or(0);
+    let sample_col = find(&SAMPLE_KEYS);
+    let condition_col = find(&CONDITION_KEYS);
+    if sample_col.is_none() && condition_col.is_none() {
+        bail!(
+            "metadata file {} needs a sample or condition column",
+            path.display()
+        );
+    }
+
+    let mut rows: HashMap<String, (Option<String>, Option<String>)> = HashMap::new();
+    for line in lines {
+        let fields = line.split('\t').map(str::trim).collect::<Vec<_>>();
+        let Some(barcode) = fields.get(barcode_col).filter(|b| !b.is_empty()) else {
+            continue;
+        };
+        let value = |col: Option<usize>| {
+            col.and_then(|c| fields.get(c))
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_string())
+        };
+        rows.insert(
+            barcode.to_string(),
+            (value(sample_col), value(condition_col)),
+        );
+    }
+
+    let mut sample = Vec::with_capacity(cells.len());
+    let mut condition = Vec::with_capacity(cells.len());
+    let mut unmatched = 0usize;
+    for cell in cells {
+        let (s, c) = match rows.get(cell) {
+            Some(row) => row.clone(),
+            None => {
+                unmatched += 1;
+                (None, None)
+            }
+        };
+        sample.push(s.unwrap_or_else(|| default_sample.to_string()));
+        condition.push(c.unwrap_or_else(|| default_condition.to_string()));
+    }
+    if !cells.is_empty() && unmatched == cells.len() {
+        bail!("no cell barcodes matched metadata file {}", path.display());
+    }
+    Ok((CellMetadata { sample, condition }, unmatched))
+}
@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::ctx::Ctx;
+use crate::geneset::SymbolResolver;
+use crate::metrics::proteostasis_extension::aggregate::{MetricStat, stats};
+use crate::metrics::proteostasis_extension::scores::robust_z_vec;
+
+pub const MT_PREFIX: &str = "MT-";
+
+// OXPHOS complexes I-V by nuclear-encoded subunit prefix. Assembly factors
+// and chaperones sharing a prefix are not subunits and are skipped: NDUFAF*,
+// SDHAF*, COX10-COX20 and the ATPase inhibitor ATP5IF1.
+pub const OXPHOS_COMPLEXES: [(&str, &str, &[&str]); 5] = [
+    ("ci", "NDUF", &["NDUFAF"]),
+    ("cii", "SDH", &["SDHAF"]),
+    ("ciii", "UQCR", &[]),
+    ("civ", "COX", &["COX1", "COX2"]),
+    ("cv", "ATP5", &["ATP5IF"]),
+];
+
+pub const MITO_PROTEOSTASIS_GENES: &[&str] = &["LONP1", "CLPP", "HSPD1", "HSPE1", "YME1L1"];
+
+// Fused target ids; the prefix keeps them apart from geneset ids.
+pub const MT_TARGET: &str = "mito:mt";
+pub const PROTEOSTASIS_TARGET: &str = "mito:proteostasis";
+const OXPHOS_TARGET_PREFIX: &str = "mito:oxphos_";
+
+const MIN_GENES: usize = 2;
+
+#[derive(Debug, Clone)]
+pub struct MitoTarget {
+    pub id: String,
+    pub genes: Vec<usize>,
+    // MT- fraction is taken over stored counts, like the libsize it divides.
+    pub raw: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct MitoAxisScores {
+    pub mt_fraction: Vec<f32>,
+    pub oxphos_core: Vec<f32>,
+    pub mito_proteostasis_core: Vec<f32>,
+    pub mpi: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MitoTargetOut {
+    pub target: String,
+    pub mapped_genes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MitoAxisSummary {
+    pub targets: Vec<MitoTargetOut>,
+    pub metrics: BTreeMap<String, MetricStat>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MitoAxisResult {
+    pub scores: MitoAxisScores,
+    pub summary: MitoAxisSummary,
+}
+
+// Symbols are matched case-insensitively, so mouse mt-/Nduf genes resolve too.
+pub fn resolve_targets(ctx: &Ctx) -> Vec<MitoTarget> {
+    let upper = ctx
+        .genes
+        .iter()
+        .map(|g| g.to_ascii_uppercase())
+        .collect::<Vec<_>>();
+    let by_prefix = |prefix: &str, skip: &[&str]| {
+        upper
+            .iter()
+            .enumerate()
+            .filter(|(_, g)| g.starts_with(prefix) && !skip.iter().any(|s| g.starts_with(s)))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>()
+    };
+
+    let mut targets = vec![MitoTarget {
+        id: MT_TARGET.to_string(),
+        genes: by_prefix(MT_PREFIX, &[]),
+        raw: true,
+    }];
+    for (complex, prefix, skip) in OXPHOS_COMPLEXES {
+        targets.push(MitoTarget {
+            id: format!("{}{}", OXPHOS_TARGET_PREFIX, complex),
+            genes: by_prefix(prefix, skip),
+            raw: false,
+        });
+    }
+    let resolver = SymbolResolver::new(&ctx.gene_index).with_ids(&ctx.gene_id_index);
+    targets.push(MitoTarget {
+        id: PROTEOSTASIS_TARGET.to_string(),
+        genes: MITO_PROTEOSTASIS_GENES
+            .iter()
+            .filter_map(|g| resolver.resolve(g))
+            .collect(),
+        raw: false,
+    });
+    targets
+}
+
+// Reads the mitochondrial targets of the fused pass: MT- count fraction,
+// OXPHOS load (mean of the per-complex means) and mito-proteostasis capacity.
+// MPI is OXPHOS load in excess of that capacity, on robust z scales.
+pub fn compute_mito_axis(ctx: &Ctx) -> Result<MitoAxisResult> {
+    let fused = ctx.fused.as_ref().context("fused reduction missing")?;
+    let n_cells = ctx.cells.len();
+    if fused.n_cells != n_cells {
+        bail!("fused reduction does not match the cell count");
+    }
+    let target = |id: &str| {
+        fused
+            .target_index(id)
+            .with_context(|| format!("fused target {} missing", id))
+    };
+
+    let mt = target(MT_TARGET)?;
+    let libsize = &ctx.cell_qc.libsize;
+    let mt_fraction = fused
+        .target_sums(mt)
+        .iter()
+        .enumerate()
+        .map(|(cell, &sum)| match libsize.get(cell) {
+            Some(&total) if total > 0.0 => (sum as f64 / total) as f32,
+            _ => 0.0,
+        })
+        .collect::<Vec<_>>();
+
+    let mut complexes = Vec::new();
+    for (complex, _, _) in OXPHOS_COMPLEXES {
+        let t = target(&format!("{}{}", OXPHOS_TARGET_PREFIX, complex))?;
+        if fused.gene_counts[t] > 0 {
+            complexes.push(fused.target_means(t));
+        }
+    }
+    let oxphos_core = if complexes.is_empty() {
+        vec![f32::NAN; n_cells]
+    } else {
+        (0..n_cells)
+            .map(|cell| complexes.iter().map(|c| c[cell]).sum::<f32>() / complexes.len() as f32)
+            .collect()
+    };
+
+    let prot = target(PROTEOSTASIS_TARGET)?;
+    let mito_proteostasis_core = if fused.gene_counts[prot] < MIN_GENES {
+        vec![f32::NAN; n_cells]
+    } else {
+        fused.target_means(prot)
+    };
+
+    let oxphos_z = robust_z_vec(&oxphos_core);
+    let prot_z = robust_z_vec(&mito_proteostasis_core);
+    let mpi = oxphos_z
+        .iter()
+        .zip(&prot_z)
+        .map(|(&o, &p)| {
+            if o.is_nan() || p.is_nan() {
+                f32::NAN
+            } else {
+                (o - p).max(0.0)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let scores = MitoAxisScores {
+        mt_fraction,
+        oxphos_core,
+        mito_proteostasis_core,
+        mpi,
+    };
+    let mut metrics = BTreeMap::new();
+    for (name, values) in metric_views(&scores) {
+        metrics.insert(name.to_string(), stats(values));
+    }
+    let targets = fused
+        .targets
+        .iter()
+        .zip(&fused.gene_counts)
+        .filter(|(id, _)| id.starts_with("mito:"))
+        .map(|(id, &n)| MitoTargetOut {
+            target: id.clone(),
+            mapped_genes: n as u64,
+        })
+        .collect();
+
+    Ok(MitoAxisResult {
+        scores,
+        summary: MitoAxisSummary { targets, metrics },
+    })
+}
+
+pub fn metric_views(scores: &MitoAxisScores) -> [(&'static str, &[f32]); 4] {
+    [
+        ("mt_fraction", &scores.mt_fraction),
+        ("oxphos_core", &scores.oxphos_core),
+        ("mito_proteostasis_core", &scores.mito_proteostasis_core),
+        ("MPI", &scores.mpi),
+    ]
+}
+
+pub fn cell_values(scores: &MitoAxisScores, cell: usize) -> [f32; 4] {
+    metric_views(scores).map(|(_, values)| values[cell])
+}
+
+// Sample rows skip cells whose score is undefined.
+pub fn sample_values(scores: &MitoAxisScores) -> [f32; 4] {
+    metric_views(scores).map(|(_, values)| {
+        let finite = values.iter().filter(|v| !v.is_nan()).collect::<Vec<_>>();
+        if finite.is_empty() {
+            f32::NAN
+        } else {
+            finite.iter().copied().sum::<f32>() / finite.len() as f32
+        }
+    })
+}
@@ -1 +1,2 @@
+pub mod mito;
 pub mod proteostasis_extension;
@@ -1,9 +1,14 @@
 use std::collections::BTreeMap;
 
+use anyhow::{Result, bail};
 use serde::{Deserialize, Serialize};
 
+use crate::io::clusters::ALL_CELLS_CLUSTER;
+
 use super::scores::{ProteostasisMissingness, ProteostasisScores, ProteostasisThresholds};
 
+const TOP_CLUSTERS: usize = 10;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ThresholdsOut {
     pub chaperone_high: f64,
@@ -30,6 +35,7 @@ pub struct GlobalStatsOut {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClusterStatsOut {
     pub cluster: String,
+    pub n_cells: u64,
     pub metrics: BTreeMap<String, MetricStat>,
     pub flag_fractions: BTreeMap<String, f64>,
 }
@@ -37,7 +43,9 @@ pub struct ClusterStatsOut {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TopClusterOut {
     pub cluster: String,
+    pub n_cells: u64,
     pub collapse_risk_median: f64,
+    pub collapse_risk_fraction: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -69,47 +77,87 @@ pub struct ProteostasisExtensionSummary {
 pub fn build_summary(
     panel_version: &str,
     scores: &ProteostasisScores,
-) -> ProteostasisExtensionSummary {
+    clusters: Option<&[String]>,
+) -> Result<ProteostasisExtensionSummary> {
     let metrics = metric_views(scores);
+    let flags = flag_views(scores);
 
     let mut global = BTreeMap::new();
     for (name, values) in &metrics {
         global.insert((*name).to_string(), stats(values));
     }
 
-    let mut flag_fractions = BTreeMap::new();
-    for (name, flags) in flag_views(scores) {
-        flag_fractions.insert(name.to_string(), fraction_true(flags));
-    }
-
-    let mut cluster_metrics = BTreeMap::new();
-    for (name, values) in &metrics {
-        cluster_metrics.insert((*name).to_string(), stats(values));
-    }
-    let all_cells = ClusterStatsOut {
-        cluster: "all_cells".to_string(),
-        metrics: cluster_metrics,
-        flag_fractions,
+    let groups = match clusters {
+        Some(labels) => {
+            if labels.len() != scores.pcp.len() {
+                bail!(
+                    "cluster labels length mismatch: {} != {}",
+                    labels.len(),
+                    scores.pcp.len()
+                );
+            }
+            let mut groups: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+            for (i, label) in labels.iter().enumerate() {
+                groups.entry(label.as_str()).or_default().push(i);
+            }
+            groups
+        }
+        None => BTreeMap::from([(ALL_CELLS_CLUSTER, (0..scores.pcp.len()).collect())]),
     };
 
+    let mut cluster_stats = Vec::with_capacity(groups.len());
     let mut top_clusters = Vec::new();
-    let pcp_stat = stats(&scores.pcp);
-    if pcp_stat.median.is_finite() {
-        top_clusters.push(TopClusterOut {
-            cluster: "all_cells".to_string(),
-            collapse_risk_median: pcp_stat.median,
+    for (cluster, idx) in &groups {
+        let mut cluster_metrics = BTreeMap::new();
+        for (name, values) in &metrics {
+            cluster_metrics.insert((*name).to_string(), stats(&gather(values, idx)));
+        }
+        let mut flag_fractions = BTreeMap::new();
+        for (name, values) in &flags {
+            flag_fractions.insert((*name).to_string(), fraction_true(&gather(values, idx)));
+        }
+
+        let pcp_stat = stats(&gather(&scores.pcp, idx));
+        if pcp_stat.median.is_finite() {
+            top_clusters.push(TopClusterOut {
+                cluster: (*cluster).to_string(),
+                n_cells: idx.len() as u64,
+                collapse_risk_median: pcp_stat.median,
+                collapse_risk_fraction: flag_fractions.get("collapse_risk").copied().unwrap_or(0.0),
+            });
+        }
+        cluster_stats.push(ClusterStatsOut {
+            cluster: (*cluster).to_string(),
+            n_cells: idx.len() as u64,
+            metrics: cluster_metrics,
+            flag_fractions,
         });
     }
 
-    ProteostasisExtensionSummary {
+    top_clusters.sort_by(|a, b| {
+        b.collapse_risk_median
+            .total_cmp(&a.collapse_risk_median)
+            .then_with(|| {
+                b.collapse_risk_fraction
+                    .total_cmp(&a.collapse_risk_fraction)
+            })
+            .then_with(|| a.cluster.cmp(&b.cluster))
+    });
+    top_clusters.truncate(TOP_CLUSTERS);
+
+    Ok(ProteostasisExtensionSummary {
         panel_version: panel_version.to_string(),
         thresholds: thresholds(scores.thresholds),
         global_stats: GlobalStatsOut { metrics: global },
-        cluster_stats: vec![all_cells],
+        cluster_stats,
         top_clusters_by_collapse_risk: top_clusters,
         missingness: missingness_out(&scores.missingness),
         translation_source: scores.translation_source.clone(),
-    }
+    })
+}
+
+fn gather<T: Copy>(values: &[T], idx: &[usize]) -> Vec<T> {
+    idx.iter().map(|&i| values[i]).collect()
 }
 
 fn thresholds(t: ProteostasisThresholds) -> ThresholdsOut {
@@ -145,7 +193,7 @@ fn flag_views(scores: &ProteostasisScores) -> Vec<(&'static str, &[bool])> {
     ]
 }
 
-fn stats(values: &[f32]) -> MetricStat {
+pub fn stats(values: &[f32]) -> MetricStat {
     let mut finite = Vec::with_capacity(values.len());
     for &v in values {
         if !v.is_nan() {
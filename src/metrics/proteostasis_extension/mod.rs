@@ -18,6 +18,10 @@ pub struct ProteostasisExtensionResult {
 
 pub fn compute_extension(ctx: &Ctx) -> Result<ProteostasisExtensionResult> {
     let scores = compute_scores(ctx)?;
-    let summary = build_summary(PROTEO_EXTENSION_PANEL_V1, &scores);
+    let summary = build_summary(
+        PROTEO_EXTENSION_PANEL_V1,
+        &scores,
+        ctx.cell_clusters.as_deref(),
+    )?;
     Ok(ProteostasisExtensionResult { scores, summary })
 }
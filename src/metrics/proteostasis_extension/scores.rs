@@ -1,9 +1,12 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::BTreeMap;
 
 use anyhow::{Result, bail};
 
 use crate::ctx::Ctx;
+use crate::fusion::PanelValues;
+use crate::geneset::SymbolResolver;
 use crate::math::stats::{mad, median};
+use crate::parallel;
 
 use super::panels::{AGGREGATION_PANEL, CHAPERONE_PANEL, ERAD_PANEL, PROTEASOME_PANEL, UPR_PANEL};
 
@@ -76,26 +79,28 @@ pub struct ProteostasisScores {
 pub fn compute_scores(ctx: &Ctx) -> Result<ProteostasisScores> {
     let n_cells = ctx.cells.len();
     let thresholds = ProteostasisThresholds::default();
-    let upper = uppercase_gene_index(ctx);
-
-    let chaperone_genes = resolve_panel(&ctx.gene_index, &upper, CHAPERONE_PANEL);
-    let proteasome_genes = resolve_panel(&ctx.gene_index, &upper, PROTEASOME_PANEL);
-    let upr_genes = resolve_panel(&ctx.gene_index, &upper, UPR_PANEL);
-    let erad_genes = resolve_panel(&ctx.gene_index, &upper, ERAD_PANEL);
-    let agg_genes = resolve_panel(&ctx.gene_index, &upper, AGGREGATION_PANEL);
-
-    let chaperone_dense = build_panel_dense(ctx, &chaperone_genes)?;
-    let proteasome_dense = build_panel_dense(ctx, &proteasome_genes)?;
-    let upr_dense = build_panel_dense(ctx, &upr_genes)?;
-    let erad_dense = build_panel_dense(ctx, &erad_genes)?;
-    let agg_dense = build_panel_dense(ctx, &agg_genes)?;
-
-    let mut scratch = Vec::new();
-    let chaperone_core = panel_trimmed_mean(&chaperone_dense, n_cells, &mut scratch);
-    let proteasome_core = panel_trimmed_mean(&proteasome_dense, n_cells, &mut scratch);
-    let upr_core = panel_trimmed_mean(&upr_dense, n_cells, &mut scratch);
-    let erad_core = panel_trimmed_mean(&erad_dense, n_cells, &mut scratch);
-    let agg_core = panel_trimmed_mean(&agg_dense, n_cells, &mut scratch);
+    let panels = resolve_panels(ctx);
+
+    // Panels are independent; each worker densifies or reads its own.
+    let cores = parallel::try_map(ctx.threads, &panels, |(id, genes)| {
+        panel_core(ctx, id, genes, &mut Vec::new())
+    })?;
+    let [
+        (_, chaperone_genes),
+        (_, proteasome_genes),
+        (_, upr_genes),
+        (_, erad_genes),
+        (_, agg_genes),
+    ] = panels;
+    let [
+        chaperone_core,
+        proteasome_core,
+        upr_core,
+        erad_core,
+        agg_core,
+    ]: [Vec<f32>; 5] = cores
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("panel count mismatch"))?;
 
     let cci = robust_z_vec(&chaperone_core);
     let pci = robust_z_vec(&proteasome_core);
@@ -212,27 +217,37 @@ pub fn compute_scores(ctx: &Ctx) -> Result<ProteostasisScores> {
     })
 }
 
-fn uppercase_gene_index(ctx: &Ctx) -> HashMap<String, usize> {
-    let mut map = HashMap::with_capacity(ctx.gene_index.len());
-    for (idx, gene) in ctx.genes.iter().enumerate() {
-        let up = gene.to_ascii_uppercase();
-        map.entry(up).or_insert(idx);
+// Panel ids match PanelMissingness::panel_id and the fused plan.
+pub fn resolve_panels(ctx: &Ctx) -> [(&'static str, Vec<usize>); 5] {
+    let resolver = SymbolResolver::new(&ctx.gene_index).with_ids(&ctx.gene_id_index);
+    [
+        ("chaperone", resolve_panel(&resolver, CHAPERONE_PANEL)),
+        ("proteasome", resolve_panel(&resolver, PROTEASOME_PANEL)),
+        ("upr", resolve_panel(&resolver, UPR_PANEL)),
+        ("erad", resolve_panel(&resolver, ERAD_PANEL)),
+        ("aggregation", resolve_panel(&resolver, AGGREGATION_PANEL)),
+    ]
+}
+
+// Reads the fused pass when it ran; otherwise densifies the panel genes.
+fn panel_core(
+    ctx: &Ctx,
+    panel_id: &str,
+    genes: &[usize],
+    scratch: &mut Vec<f32>,
+) -> Result<Vec<f32>> {
+    let n_cells = ctx.cells.len();
+    if let Some(panel) = ctx.fused.as_ref().and_then(|f| f.panel(panel_id)) {
+        return Ok(fused_panel_trimmed_mean(panel, n_cells, scratch));
     }
-    map
+    let dense = build_panel_dense(ctx, genes)?;
+    Ok(panel_trimmed_mean(&dense, n_cells, scratch))
 }
 
-fn resolve_panel(
-    direct: &HashMap<String, usize>,
-    uppercase: &HashMap<String, usize>,
-    panel: &[&str],
-) -> Vec<usize> {
+fn resolve_panel(resolver: &SymbolResolver<'_>, panel: &[&str]) -> Vec<usize> {
     let mut out = Vec::with_capacity(panel.len());
     for gene in panel {
-        if let Some(&idx) = direct.get(*gene) {
-            out.push(idx);
-            continue;
-        }
-        if let Some(&idx) = uppercase.get(&gene.to_ascii_uppercase()) {
+        if let Some(idx) = resolver.resolve(gene) {
             out.push(idx);
         }
     }
@@ -241,15 +256,15 @@ fn resolve_panel(
 
 fn build_panel_dense(ctx: &Ctx, genes: &[usize]) -> Result<Vec<Vec<f32>>> {
     let n_cells = ctx.cells.len();
+    let expr = ctx.expr_reader()?;
     let mut dense = Vec::with_capacity(genes.len());
     for &gene_id in genes {
         let mut row = vec![0.0f32; n_cells];
-        let (cells, values) = ctx.gene_slice(gene_id)?;
-        for (&cell, &value) in cells.iter().zip(values.iter()) {
+        for (cell, value) in expr.gene_entries(gene_id)? {
             if value.is_nan() {
                 bail!("NaN encountered in expression matrix");
             }
-            row[cell as usize] = value;
+            row[cell as usize] = expr.value(cell, value);
         }
         dense.push(row);
     }
@@ -278,6 +293,25 @@ fn panel_trimmed_mean(
     out
 }
 
+// Same multiset per cell as the dense rows: stored values plus zeros.
+fn fused_panel_trimmed_mean(
+    panel: &PanelValues,
+    n_cells: usize,
+    scratch: &mut Vec<f32>,
+) -> Vec<f32> {
+    if panel.n_genes < MIN_GENES {
+        return vec![f32::NAN; n_cells];
+    }
+    let mut out = vec![f32::NAN; n_cells];
+    for (cell, slot) in out.iter_mut().enumerate() {
+        scratch.clear();
+        scratch.extend_from_slice(panel.cell_values(cell));
+        scratch.resize(panel.n_genes, 0.0);
+        *slot = trimmed_mean_in_place(scratch);
+    }
+    out
+}
+
 fn trimmed_mean_in_place(values: &mut [f32]) -> f32 {
     if values.is_empty() {
         return f32::NAN;
@@ -303,7 +337,7 @@ fn trimmed_mean_in_place(values: &mut [f32]) -> f32 {
     }
 }
 
-fn robust_z_vec(values: &[f32]) -> Vec<f32> {
+pub fn robust_z_vec(values: &[f32]) -> Vec<f32> {
     let mut finite = Vec::with_capacity(values.len());
     for &v in values {
         if !v.is_nan() {
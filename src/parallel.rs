@@ -0,0 +1,70 @@
+use anyhow::Result;
+
+#[cfg(feature = "mt")]
+use rayon::prelude::*;
+
+// Parallel work runs on rayon's global pool, sized once from Ctx::threads
+// (0 = one worker per core). `threads == 1` keeps everything on the caller's
+// thread, as do builds without the `mt` feature.
+#[cfg(feature = "mt")]
+pub fn init(threads: usize) {
+    use std::sync::OnceLock;
+    use tracing::{info, warn};
+
+    static CONFIGURED: OnceLock<usize> = OnceLock::new();
+    let configured = *CONFIGURED.get_or_init(|| {
+        match rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("proteoqc-{}", i))
+            .build_global()
+        {
+            Ok(()) => info!(threads = rayon::current_num_threads(), "thread_pool_ready"),
+            // Something already ran on the default global pool; keep using it.
+            Err(err) => warn!(error = %err, "thread pool already initialized"),
+        }
+        threads
+    });
+    if configured != threads {
+        warn!(
+            requested = threads,
+            configured, "thread pool is sized once per process; keeping the first size"
+        );
+    }
+}
+
+#[cfg(not(feature = "mt"))]
+pub fn init(_threads: usize) {}
+
+pub fn enabled(threads: usize) -> bool {
+    cfg!(feature = "mt") && threads != 1
+}
+
+pub fn join<A, B, RA, RB>(threads: usize, a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA + Send,
+    B: FnOnce() -> RB + Send,
+    RA: Send,
+    RB: Send,
+{
+    #[cfg(feature = "mt")]
+    if enabled(threads) {
+        return rayon::join(a, b);
+    }
+    let _ = threads;
+    (a(), b())
+}
+
+// Results keep the order of `items`; any failed item fails the whole call.
+pub fn try_map<T, R, F>(threads: usize, items: &[T], f: F) -> Result<Vec<R>>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> Result<R> + Sync + Send,
+{
+    #[cfg(feature = "mt")]
+    if enabled(threads) {
+        return items.par_iter().map(f).collect();
+    }
+    let _ = threads;
+    items.iter().map(f).collect()
+}
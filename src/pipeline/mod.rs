@@ -8,6 +8,7 @@ pub mod stage0_scaffold;
 pub mod stage10_output;
 pub mod stage1_input;
 pub mod stage2_h5ad;
+pub mod stage2b_metadata;
 pub mod stage3_expr_cache;
 pub mod stage4_geneset;
 pub mod stage5_math;
@@ -32,8 +33,11 @@ impl Pipeline {
     }
 
     pub fn run(&self, ctx: &mut Ctx) -> Result<()> {
+        crate::parallel::init(ctx.threads);
         info!(
             simd_backend = %crate::simd::backend_name(),
+            deterministic = crate::math::sum::deterministic(),
+            fusion = ctx.fusion.as_str(),
             "compute backend selected"
         );
         for stage in &self.stages {
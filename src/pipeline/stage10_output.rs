@@ -3,6 +3,7 @@ use tracing::info;
 
 use crate::ctx::{Ctx, RunMode};
 use crate::io::{json_writer, pipeline_output, tsv_writer};
+use crate::parallel;
 use crate::pipeline::Stage;
 
 pub struct Stage10Output;
@@ -29,12 +30,25 @@ impl Stage for Stage10Output {
         let report = json_writer::build_report(ctx)?;
         ctx.report = report;
 
-        if ctx.write_json {
-            json_writer::write_json(&ctx.output.json_path, ctx)?;
-        }
-        if ctx.write_tsv {
-            tsv_writer::write_tsv(&ctx.output.tsv_path, ctx)?;
-        }
+        // The writers only read ctx and write separate files.
+        let shared = &*ctx;
+        let (json, tsv) = parallel::join(
+            ctx.threads,
+            || {
+                if shared.write_json {
+                    json_writer::write_json(&shared.output.json_path, shared)?;
+                }
+                Ok::<_, anyhow::Error>(())
+            },
+            || {
+                if shared.write_tsv {
+                    tsv_writer::write_tsv(&shared.output.tsv_path, shared)?;
+                }
+                Ok::<_, anyhow::Error>(())
+            },
+        );
+        json?;
+        tsv?;
 
         info!("stage10_output_ready");
         Ok(())
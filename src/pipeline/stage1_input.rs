@@ -3,9 +3,11 @@ use std::collections::HashMap;
 use anyhow::{Context, Result, bail};
 use tracing::{info, warn};
 
-use crate::ctx::{Ctx, RunMode};
+use crate::ctx::{Ctx, InputFormat, RunMode};
+use crate::expr::normalize;
+use crate::geneset::build_id_index;
 use crate::input;
-use crate::io::{barcodes, features, mtx, shared_cache};
+use crate::io::{barcodes, dense, features, mtx, shared_cache};
 use crate::pipeline::Stage;
 
 pub struct Stage1Input;
@@ -22,19 +24,27 @@ impl Stage for Stage1Input {
     }
 
     fn run(&self, ctx: &mut Ctx) -> Result<()> {
-        if ctx.input_format == crate::ctx::InputFormat::H5ad {
-            return Ok(());
-        }
-        let (genes, cells, nrows, ncols, nnz) = match ctx.run_mode {
-            RunMode::Standalone => load_from_mtx(ctx, None)?,
-            RunMode::Pipeline => load_pipeline_input(ctx)?,
+        let LoadedInput {
+            genes,
+            gene_ids,
+            cells,
+            n_features,
+            nrows,
+            ncols,
+            nnz,
+        } = match (ctx.input_format, ctx.run_mode) {
+            (InputFormat::DenseText, _) => load_from_dense(ctx)?,
+            (InputFormat::Mtx10x, RunMode::Standalone) => load_from_mtx(ctx, None)?,
+            (InputFormat::Mtx10x, RunMode::Pipeline) => load_pipeline_input(ctx)?,
+            // HDF5 inputs (h5ad, 10x .h5, loom) are loaded by Stage2H5ad.
+            (InputFormat::H5ad | InputFormat::Tenx5 | InputFormat::Loom, _) => return Ok(()),
         };
 
-        if nrows != genes.len() {
+        if nrows != n_features {
             bail!(
                 "MTX rows ({}) do not match features.tsv lines ({})",
                 nrows,
-                genes.len()
+                n_features
             );
         }
         if ncols != cells.len() {
@@ -48,22 +58,22 @@ impl Stage for Stage1Input {
         // Gene index is first-win for duplicates to preserve deterministic ordering.
         let (gene_index, warnings) = build_gene_index(&genes);
 
+        ctx.gene_id_index = build_id_index(&gene_ids);
         ctx.genes = genes;
+        ctx.gene_ids = gene_ids;
         ctx.cells = cells;
         ctx.nnz = nnz;
         ctx.gene_index = gene_index;
         ctx.warnings.extend(warnings);
 
-        ctx.input_meta.genes = Some(nrows as u64);
+        ctx.input_meta.genes = Some(ctx.genes.len() as u64);
         ctx.input_meta.cells = Some(ncols as u64);
         ctx.input_meta.nnz = Some(nnz as u64);
 
         ctx.report.input_meta.genes = ctx.input_meta.genes;
         ctx.report.input_meta.cells = ctx.input_meta.cells;
         ctx.report.input_meta.nnz = ctx.input_meta.nnz;
-        ctx.report.input_meta.normalization.log1p = ctx.log1p;
-        ctx.report.input_meta.normalization.cp10k = true;
-        ctx.report.input_meta.normalization.raw = true;
+        ctx.report.input_meta.normalization = normalize::describe(ctx.normalization, ctx.log1p);
         ctx.report.input_meta.mode = ctx.mode.clone();
         ctx.report.input_meta.timecourse = ctx.timecourse;
 
@@ -71,7 +81,18 @@ impl Stage for Stage1Input {
     }
 }
 
-fn load_pipeline_input(ctx: &mut Ctx) -> Result<(Vec<String>, Vec<String>, usize, usize, usize)> {
+struct LoadedInput {
+    genes: Vec<String>,
+    gene_ids: Vec<String>,
+    cells: Vec<String>,
+    // Rows in the feature table before feature-type filtering.
+    n_features: usize,
+    nrows: usize,
+    ncols: usize,
+    nnz: usize,
+}
+
+fn load_pipeline_input(ctx: &mut Ctx) -> Result<LoadedInput> {
     if let Some(cache_override) = &ctx.cache_override {
         let cache = shared_cache::SharedCache::open(cache_override).with_context(|| {
             format!(
@@ -82,13 +103,7 @@ fn load_pipeline_input(ctx: &mut Ctx) -> Result<(Vec<String>, Vec<String>, usize
         ctx.shared_cache_path = Some(cache_override.clone());
         ctx.shared_cache_used = true;
         info!(cache = %cache_override.display(), "shared_cache_loaded_override");
-        return Ok((
-            cache.genes,
-            cache.barcodes,
-            cache.header.n_genes as usize,
-            cache.header.n_cells as usize,
-            cache.header.nnz as usize,
-        ));
+        return Ok(keep_shared_cache(ctx, cache));
     }
 
     let prefix = input::detect_prefix(&ctx.input)?;
@@ -105,13 +120,7 @@ fn load_pipeline_input(ctx: &mut Ctx) -> Result<(Vec<String>, Vec<String>, usize
         ctx.shared_cache_path = Some(expected_cache.clone());
         ctx.shared_cache_used = true;
         info!(cache = %expected_cache.display(), "shared_cache_loaded");
-        return Ok((
-            cache.genes,
-            cache.barcodes,
-            cache.header.n_genes as usize,
-            cache.header.n_cells as usize,
-            cache.header.nnz as usize,
-        ));
+        return Ok(keep_shared_cache(ctx, cache));
     }
 
     warn!(
@@ -126,12 +135,28 @@ fn load_pipeline_input(ctx: &mut Ctx) -> Result<(Vec<String>, Vec<String>, usize
     load_from_mtx(ctx, prefix.as_deref())
 }
 
-fn load_from_mtx(
-    ctx: &mut Ctx,
-    prefix: Option<&str>,
-) -> Result<(Vec<String>, Vec<String>, usize, usize, usize)> {
+// The mapped cache stays on the context so later stages can read it in place.
+fn keep_shared_cache(ctx: &mut Ctx, cache: shared_cache::SharedCache) -> LoadedInput {
+    let loaded = LoadedInput {
+        genes: cache.genes.clone(),
+        gene_ids: Vec::new(),
+        cells: cache.barcodes.clone(),
+        n_features: cache.genes.len(),
+        nrows: cache.header.n_genes as usize,
+        ncols: cache.header.n_cells as usize,
+        nnz: cache.header.nnz as usize,
+    };
+    ctx.shared_cache = Some(cache);
+    loaded
+}
+
+fn load_from_mtx(ctx: &mut Ctx, prefix: Option<&str>) -> Result<LoadedInput> {
     ctx.shared_cache_used = false;
     ctx.shared_cache_path = None;
+    ctx.shared_cache = None;
+    ctx.feature_row_map = None;
+    ctx.mtx_gene_counts = None;
+    ctx.antibody_features = None;
     let input_dir = &ctx.input;
     let (matrix_path, features_path, barcodes_path) =
         input::resolve_mtx_input_files(input_dir, prefix);
@@ -150,16 +175,77 @@ fn load_from_mtx(
     ctx.mtx_features_path = Some(features_path.clone());
     ctx.mtx_barcodes_path = Some(barcodes_path.clone());
 
-    let genes = features::read_features(&features_path)?;
+    let selection = features::select_feature_type(
+        features::read_feature_table(&features_path)?,
+        &ctx.feature_type,
+    )?;
     let cells = barcodes::read_barcodes(&barcodes_path)?;
-    let mtx_summary = mtx::read_mtx_summary(&matrix_path)?;
-    Ok((
-        genes,
+    // nnz counts only entries of kept feature rows; the per-gene counts let
+    // the expr cache build skip its own counting pass.
+    let mut gene_counts = vec![0u64; selection.table.symbols.len()];
+    let mtx_summary = mtx::for_each_entry(&matrix_path, |row, _, _| {
+        // Rows past the feature table fail the dimension check after loading.
+        if let Some(count) = selection
+            .gene_of(row)
+            .and_then(|gene| gene_counts.get_mut(gene as usize))
+        {
+            *count += 1;
+        }
+        Ok(())
+    })?;
+    let nnz = gene_counts.iter().sum::<u64>() as usize;
+
+    if let Some(warning) = selection.dropped_warning() {
+        info!(feature_type = %ctx.feature_type, %warning, "feature_type_filter");
+        ctx.notes.push(warning);
+    }
+    let n_features = selection.n_input_rows();
+    ctx.feature_row_map = selection.row_map;
+    ctx.antibody_features = selection.antibody;
+    ctx.mtx_gene_counts = Some(gene_counts);
+    Ok(LoadedInput {
+        genes: selection.table.symbols,
+        gene_ids: selection.table.ids,
         cells,
-        mtx_summary.nrows,
-        mtx_summary.ncols,
-        mtx_summary.nnz,
-    ))
+        n_features,
+        nrows: mtx_summary.nrows,
+        ncols: mtx_summary.ncols,
+        nnz,
+    })
+}
+
+// Dense text carries its names in the header and first column; values are
+// sampled on the way so `--normalize auto` works as for h5ad.
+fn load_from_dense(ctx: &mut Ctx) -> Result<LoadedInput> {
+    let summary = dense::read_dense_summary(&ctx.input)?;
+    info!(
+        delimiter = ?summary.layout.delimiter,
+        orientation = summary.layout.orientation.as_str(),
+        genes = summary.genes.len(),
+        cells = summary.cells.len(),
+        nnz = summary.nnz,
+        values = summary.values.as_str(),
+        "dense_summary"
+    );
+    ctx.warnings.extend(normalize::resolve_for_values(
+        &mut ctx.normalization,
+        ctx.normalization_auto,
+        summary.values,
+        &format!("dense input {}", ctx.input.display()),
+    ));
+    ctx.matrix_values = Some(summary.values);
+    ctx.dense_layout = Some(summary.layout);
+    ctx.feature_row_map = None;
+
+    Ok(LoadedInput {
+        n_features: summary.genes.len(),
+        nrows: summary.genes.len(),
+        ncols: summary.cells.len(),
+        genes: summary.genes.clone(),
+        gene_ids: summary.genes,
+        cells: summary.cells,
+        nnz: summary.nnz,
+    })
 }
 
 fn build_gene_index(genes: &[String]) -> (HashMap<String, usize>, Vec<String>) {
@@ -4,7 +4,9 @@ use anyhow::Result;
 use tracing::info;
 
 use crate::ctx::{Ctx, InputFormat};
-use crate::io::h5ad;
+use crate::expr::normalize;
+use crate::geneset::build_id_index;
+use crate::io::{features, h5ad, loom, tenx_h5};
 use crate::pipeline::Stage;
 
 pub struct Stage2H5ad;
@@ -21,21 +23,37 @@ impl Stage for Stage2H5ad {
     }
 
     fn run(&self, ctx: &mut Ctx) -> Result<()> {
-        if ctx.input_format != InputFormat::H5ad {
-            return Ok(());
+        match ctx.input_format {
+            InputFormat::H5ad => {}
+            InputFormat::Tenx5 => return load_tenx5(ctx),
+            InputFormat::Loom => return load_loom(ctx),
+            // Text inputs are loaded by Stage1Input.
+            InputFormat::Mtx10x | InputFormat::DenseText => return Ok(()),
         }
 
-        let summary = h5ad::read_h5ad_summary(&ctx.input)?;
+        let summary = h5ad::read_h5ad_summary(&ctx.input, &ctx.h5ad_layer)?;
         info!(
+            layer = %ctx.h5ad_layer,
             nrows = summary.nrows,
             ncols = summary.ncols,
             nnz = summary.nnz,
+            values = summary.values.as_str(),
             "h5ad_summary"
         );
 
         let (gene_index, mut warnings) = build_gene_index(&summary.genes);
         warnings.extend(summary.warnings.into_iter());
 
+        warnings.extend(normalize::resolve_for_values(
+            &mut ctx.normalization,
+            ctx.normalization_auto,
+            summary.values,
+            &format!("h5ad {}", ctx.h5ad_layer),
+        ));
+        ctx.matrix_values = Some(summary.values);
+
+        ctx.gene_id_index = build_id_index(&summary.gene_ids);
+        ctx.gene_ids = summary.gene_ids;
         ctx.genes = summary.genes;
         ctx.cells = summary.cells;
         ctx.nnz = summary.nnz;
@@ -49,9 +67,7 @@ impl Stage for Stage2H5ad {
         ctx.report.input_meta.genes = ctx.input_meta.genes;
         ctx.report.input_meta.cells = ctx.input_meta.cells;
         ctx.report.input_meta.nnz = ctx.input_meta.nnz;
-        ctx.report.input_meta.normalization.log1p = ctx.log1p;
-        ctx.report.input_meta.normalization.cp10k = true;
-        ctx.report.input_meta.normalization.raw = true;
+        ctx.report.input_meta.normalization = normalize::describe(ctx.normalization, ctx.log1p);
         ctx.report.input_meta.mode = ctx.mode.clone();
         ctx.report.input_meta.timecourse = ctx.timecourse;
 
@@ -59,6 +75,96 @@ impl Stage for Stage2H5ad {
     }
 }
 
+// Cell Ranger .h5: only rows of the selected feature type are scored; the
+// rest (antibody capture, CRISPR guides, peaks) are dropped and remapped away.
+fn load_tenx5(ctx: &mut Ctx) -> Result<()> {
+    let summary = tenx_h5::read_tenx_h5_summary(&ctx.input)?;
+    let selection = features::select_feature_type(summary.features, &ctx.feature_type)?;
+    let nnz = match &selection.row_map {
+        None => summary.nnz,
+        Some(row_map) => tenx_h5::read_tenx_h5_indices(&ctx.input)?
+            .iter()
+            .filter(|&&row| row_map.get(row as usize).copied().flatten().is_some())
+            .count(),
+    };
+    info!(
+        nrows = summary.nrows,
+        ncols = summary.ncols,
+        genes = selection.table.symbols.len(),
+        nnz,
+        "tenx_h5_summary"
+    );
+
+    let (gene_index, warnings) = build_gene_index(&selection.table.symbols);
+    ctx.notes.extend(selection.dropped_warning());
+
+    ctx.gene_id_index = build_id_index(&selection.table.ids);
+    ctx.gene_ids = selection.table.ids;
+    ctx.genes = selection.table.symbols;
+    ctx.cells = summary.cells;
+    ctx.nnz = nnz;
+    ctx.gene_index = gene_index;
+    ctx.feature_row_map = selection.row_map;
+    ctx.antibody_features = selection.antibody;
+    ctx.warnings = warnings;
+
+    ctx.input_meta.genes = Some(ctx.genes.len() as u64);
+    ctx.input_meta.cells = Some(ctx.cells.len() as u64);
+    ctx.input_meta.nnz = Some(nnz as u64);
+
+    ctx.report.input_meta.genes = ctx.input_meta.genes;
+    ctx.report.input_meta.cells = ctx.input_meta.cells;
+    ctx.report.input_meta.nnz = ctx.input_meta.nnz;
+    ctx.report.input_meta.normalization = normalize::describe(ctx.normalization, ctx.log1p);
+    ctx.report.input_meta.mode = ctx.mode.clone();
+    ctx.report.input_meta.timecourse = ctx.timecourse;
+
+    Ok(())
+}
+
+// Loom matrices are dense genes x cells; the summary pass counts nonzeros
+// and samples values, so auto normalization applies as for h5ad.
+fn load_loom(ctx: &mut Ctx) -> Result<()> {
+    let summary = loom::read_loom_summary(&ctx.input)?;
+    info!(
+        nrows = summary.nrows,
+        ncols = summary.ncols,
+        nnz = summary.nnz,
+        values = summary.values.as_str(),
+        "loom_summary"
+    );
+
+    let (gene_index, mut warnings) = build_gene_index(&summary.genes);
+    warnings.extend(normalize::resolve_for_values(
+        &mut ctx.normalization,
+        ctx.normalization_auto,
+        summary.values,
+        "loom /matrix",
+    ));
+    ctx.matrix_values = Some(summary.values);
+
+    ctx.gene_id_index = build_id_index(&summary.gene_ids);
+    ctx.gene_ids = summary.gene_ids;
+    ctx.genes = summary.genes;
+    ctx.cells = summary.cells;
+    ctx.nnz = summary.nnz;
+    ctx.gene_index = gene_index;
+    ctx.warnings = warnings;
+
+    ctx.input_meta.genes = Some(summary.nrows as u64);
+    ctx.input_meta.cells = Some(summary.ncols as u64);
+    ctx.input_meta.nnz = Some(summary.nnz as u64);
+
+    ctx.report.input_meta.genes = ctx.input_meta.genes;
+    ctx.report.input_meta.cells = ctx.input_meta.cells;
+    ctx.report.input_meta.nnz = ctx.input_meta.nnz;
+    ctx.report.input_meta.normalization = normalize::describe(ctx.normalization, ctx.log1p);
+    ctx.report.input_meta.mode = ctx.mode.clone();
+    ctx.report.input_meta.timecourse = ctx.timecourse;
+
+    Ok(())
+}
+
 fn build_gene_index(genes: &[String]) -> (HashMap<String, usize>, Vec<String>) {
     let mut index = HashMap::new();
     let mut warnings = Vec::new();
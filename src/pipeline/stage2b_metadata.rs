@@ -0,0 +1,80 @@
+use anyhow::Result;
+use tracing::info;
+
+use crate::ctx::Ctx;
+use crate::metadata::{self, SampleMetadata, Species};
+use crate::pipeline::Stage;
+
+pub struct Stage2bMetadata;
+
+impl Stage2bMetadata {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Stage for Stage2bMetadata {
+    fn name(&self) -> &'static str {
+        "stage2b_metadata"
+    }
+
+    fn run(&self, ctx: &mut Ctx) -> Result<()> {
+        let sample_id = ctx.sample_id.clone().unwrap_or_else(|| {
+            ctx.input
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("sample")
+                .to_string()
+        });
+        let condition = ctx
+            .condition
+            .clone()
+            .unwrap_or_else(|| metadata::UNKNOWN.to_string());
+        let inferred = metadata::infer_species(&ctx.genes);
+        let species = ctx.species.unwrap_or(inferred);
+        if ctx
+            .species
+            .is_some_and(|s| inferred != Species::Unknown && s != inferred)
+        {
+            ctx.notes.push(format!(
+                "--species {} overrides inferred species {}",
+                species.as_str(),
+                inferred.as_str()
+            ));
+        }
+
+        let cells = match &ctx.metadata_path {
+            Some(path) => {
+                let (cells, unmatched) =
+                    metadata::read_cell_metadata(path, &ctx.cells, &sample_id, &condition)?;
+                if unmatched > 0 {
+                    ctx.warnings.push(format!(
+                        "{} cells missing from metadata file (run-level sample/condition used)",
+                        unmatched
+                    ));
+                }
+                Some(cells)
+            }
+            None => None,
+        };
+
+        info!(
+            sample_id = %sample_id,
+            condition = %condition,
+            species = species.as_str(),
+            per_cell = cells.is_some(),
+            "metadata_ready"
+        );
+
+        ctx.report.input_meta.sample_id = Some(sample_id.clone());
+        ctx.report.input_meta.condition = Some(condition.clone());
+        ctx.report.input_meta.species = Some(species.as_str().to_string());
+        ctx.metadata = SampleMetadata {
+            sample_id,
+            condition,
+            species,
+            cells,
+        };
+        Ok(())
+    }
+}
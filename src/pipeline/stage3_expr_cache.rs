@@ -1,8 +1,14 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
 use tracing::info;
 
 use crate::ctx::Ctx;
+use crate::expr::cell_qc;
+use crate::expr::filter;
+use crate::expr::normalize::{self, CellNormalizer};
 use crate::expr::writer;
+use crate::geneset::build_id_index;
 use crate::pipeline::Stage;
 
 pub struct Stage3ExprCache;
@@ -19,8 +25,99 @@ impl Stage for Stage3ExprCache {
     }
 
     fn run(&self, ctx: &mut Ctx) -> Result<()> {
-        writer::ensure_expr_cache(ctx)?;
-        info!(expr = %ctx.expr_path.display(), "expr_cache_ready");
+        if ctx.shared_cache.is_some() {
+            // Scoring reduces the cell-major shared cache directly; no expr.bin.
+            info!(cache = ?ctx.shared_cache_path, "expr_cache_skipped_shared_cache");
+        } else {
+            writer::ensure_expr_cache(ctx)?;
+            info!(expr = %ctx.expr_path.display(), "expr_cache_ready");
+        }
+
+        ctx.cell_normalizer = None;
+        if ctx.filters.is_active() {
+            apply_filters(ctx)?;
+        }
+        let cell_qc = cell_qc::compute(&ctx.expr_reader()?)?;
+        ctx.cell_normalizer = CellNormalizer::new(ctx.normalization, ctx.log1p, &cell_qc.libsize);
+        info!(
+            cells = cell_qc.n_cells(),
+            detected_genes = cell_qc.detected_genes,
+            "cell_qc_ready"
+        );
+        ctx.cell_qc = cell_qc;
+        ctx.report.input_meta.normalization = normalize::describe(ctx.normalization, ctx.log1p);
+        info!(
+            normalization = ctx.normalization.as_str(),
+            log1p = ctx.normalization.applies_log1p(ctx.log1p),
+            "normalization_ready"
+        );
         Ok(())
     }
 }
+
+// Filters are planned on the full source, written as their own expr cache,
+// and only then narrow the context so later stages see scored cells only.
+fn apply_filters(ctx: &mut Ctx) -> Result<()> {
+    let mut plan = filter::plan(
+        &ctx.filters,
+        &ctx.genes,
+        &ctx.gene_ids,
+        &ctx.cells,
+        &ctx.expr_reader()?,
+    )?;
+    info!(
+        cells_kept = plan.summary.cells_kept,
+        cells_input = plan.summary.cells_input,
+        genes_kept = plan.summary.genes_kept,
+        genes_input = plan.summary.genes_input,
+        nnz = plan.nnz,
+        "filters_planned"
+    );
+    writer::ensure_filtered_expr_cache(ctx, &plan)?;
+
+    ctx.genes = keep(&ctx.genes, &plan.gene_keep);
+    if ctx.gene_ids.len() == plan.gene_keep.len() {
+        ctx.gene_ids = keep(&ctx.gene_ids, &plan.gene_keep);
+    }
+    // Excluded cells are listed in input order, like the dropped mask entries.
+    let dropped = plan.cell_keep.iter().enumerate().filter(|(_, k)| !**k);
+    for (cell, (idx, _)) in plan.excluded.iter_mut().zip(dropped) {
+        cell.sample = ctx.metadata.cell_sample(idx).to_string();
+        cell.condition = ctx.metadata.cell_condition(idx).to_string();
+    }
+    ctx.cells = keep(&ctx.cells, &plan.cell_keep);
+    if let Some(cells) = &mut ctx.metadata.cells {
+        cells.sample = keep(&cells.sample, &plan.cell_keep);
+        cells.condition = keep(&cells.condition, &plan.cell_keep);
+    }
+    // Duplicate symbols were already reported on load; first kept row wins.
+    let mut gene_index = HashMap::with_capacity(ctx.genes.len());
+    for (i, symbol) in ctx.genes.iter().enumerate() {
+        gene_index.entry(symbol.clone()).or_insert(i);
+    }
+    ctx.gene_index = gene_index;
+    ctx.gene_id_index = build_id_index(&ctx.gene_ids);
+    ctx.nnz = plan.nnz;
+
+    ctx.input_meta.genes = Some(ctx.genes.len() as u64);
+    ctx.input_meta.cells = Some(ctx.cells.len() as u64);
+    ctx.input_meta.nnz = Some(ctx.nnz as u64);
+    ctx.report.input_meta.genes = ctx.input_meta.genes;
+    ctx.report.input_meta.cells = ctx.input_meta.cells;
+    ctx.report.input_meta.nnz = ctx.input_meta.nnz;
+    ctx.report.input_meta.filters = Some(plan.summary.clone());
+
+    ctx.warnings.extend(plan.warnings);
+    ctx.filter_summary = Some(plan.summary);
+    ctx.excluded_cells = plan.excluded;
+    Ok(())
+}
+
+fn keep(values: &[String], mask: &[bool]) -> Vec<String> {
+    values
+        .iter()
+        .zip(mask)
+        .filter(|(_, k)| **k)
+        .map(|(v, _)| v.clone())
+        .collect()
+}
@@ -2,7 +2,9 @@ use anyhow::Result;
 use tracing::info;
 
 use crate::ctx::Ctx;
-use crate::geneset::{load_builtin, load_user, merge_defs, resolve_collection};
+use crate::geneset::{
+    SymbolResolver, load_builtin, load_user, merge_defs, resolve_collection_with,
+};
 use crate::pipeline::Stage;
 use crate::schema::v1::GenesetCoverage;
 
@@ -20,14 +22,15 @@ impl Stage for Stage4Geneset {
     }
 
     fn run(&self, ctx: &mut Ctx) -> Result<()> {
-        let mut collection = load_builtin()?;
+        let mut collection = load_builtin(ctx.geneset_version, ctx.metadata.species)?;
         if let Some(path) = &ctx.geneset_path {
             let user_defs = load_user(path)?;
             let merged = merge_defs(collection.defs, user_defs);
             collection.defs = merged;
         }
 
-        let resolved = resolve_collection(collection, &ctx.gene_index);
+        let resolver = SymbolResolver::new(&ctx.gene_index).with_ids(&ctx.gene_id_index);
+        let resolved = resolve_collection_with(collection, &resolver);
         let mut coverage = Vec::with_capacity(resolved.resolved.len());
         let mut warnings = Vec::new();
 
@@ -47,6 +50,7 @@ impl Stage for Stage4Geneset {
                 found: found as u64,
                 total: total as u64,
                 fraction,
+                matches: gs.coverage_matches(),
             });
         }
 
@@ -54,7 +58,11 @@ impl Stage for Stage4Geneset {
         ctx.report.explainability.geneset_coverage = coverage.clone();
         ctx.genesets = Some(resolved);
 
-        info!("geneset_resolved");
+        info!(
+            version = ctx.geneset_version.as_str(),
+            species = ctx.metadata.species.as_str(),
+            "geneset_resolved"
+        );
         Ok(())
     }
 }
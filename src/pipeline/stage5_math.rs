@@ -2,6 +2,7 @@ use anyhow::{Result, bail};
 use tracing::info;
 
 use crate::ctx::Ctx;
+use crate::fusion;
 use crate::pipeline::Stage;
 
 pub struct Stage5Math;
@@ -18,17 +19,14 @@ impl Stage for Stage5Math {
     }
 
     fn run(&self, ctx: &mut Ctx) -> Result<()> {
-        let header = ctx
-            .expr_header
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("expr header missing"))?;
-        if header.n_cells as usize != ctx.cells.len() {
+        let expr = ctx.expr_reader()?;
+        if expr.n_cells() != ctx.cells.len() {
             bail!("expr header n_cells does not match ctx");
         }
-        if header.n_genes as usize != ctx.genes.len() {
+        if expr.n_genes() != ctx.genes.len() {
             bail!("expr header n_genes does not match ctx");
         }
-        if header.nnz as usize != ctx.nnz {
+        if expr.nnz() != ctx.nnz {
             bail!("expr header nnz does not match ctx");
         }
         if ctx.genesets.is_none() {
@@ -36,6 +34,7 @@ impl Stage for Stage5Math {
         }
 
         ctx.scratch_cell_buf.resize(ctx.cells.len(), 0.0);
+        fusion::ensure_fused(ctx)?;
 
         info!("math_primitives_ready");
         Ok(())
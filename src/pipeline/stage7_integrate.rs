@@ -3,7 +3,7 @@ use tracing::info;
 
 use crate::ctx::Ctx;
 use crate::pipeline::Stage;
-use crate::scores::integrated::compute_integrated;
+use crate::scores::integrated::compute_integrated_with;
 
 pub struct Stage7Integrate;
 
@@ -23,7 +23,7 @@ impl Stage for Stage7Integrate {
             .axis_raw
             .as_ref()
             .ok_or_else(|| anyhow::anyhow!("axis raw scores missing"))?;
-        let (integrated, contrib) = compute_integrated(axis, ctx.mode.clone())?;
+        let (integrated, contrib) = compute_integrated_with(axis, ctx.mode.clone(), &ctx.profile)?;
         ctx.integrated_scores = Some(integrated);
         ctx.pfs_contributions = Some(contrib);
         info!("integrated_scores_ready");
@@ -2,7 +2,11 @@ use anyhow::Result;
 use tracing::info;
 
 use crate::ctx::Ctx;
+use crate::fusion::{self, FusionMode};
+use crate::io::clusters;
+use crate::metrics::mito::compute_mito_axis;
 use crate::metrics::proteostasis_extension::compute_extension;
+use crate::parallel;
 use crate::pipeline::Stage;
 
 pub struct Stage8bProteostasisExtension;
@@ -19,9 +23,46 @@ impl Stage for Stage8bProteostasisExtension {
     }
 
     fn run(&self, ctx: &mut Ctx) -> Result<()> {
-        let result = compute_extension(ctx)?;
-        ctx.proteostasis_extension = Some(result);
+        if let Some(source) = &ctx.clusters {
+            let assignment = clusters::load_clusters(source, &ctx.input, &ctx.cells)?;
+            if assignment.unassigned > 0 {
+                ctx.warnings.push(format!(
+                    "{} cells missing from cluster annotation (labelled {})",
+                    assignment.unassigned,
+                    clusters::UNASSIGNED_CLUSTER
+                ));
+            }
+            info!(
+                unassigned = assignment.unassigned,
+                unknown_barcodes = assignment.unknown_barcodes,
+                "clusters_loaded"
+            );
+            ctx.cell_clusters = Some(assignment.labels);
+        }
+
+        let mito = fusion::mito_enabled(ctx);
+        if mito {
+            fusion::ensure_fused(ctx)?;
+        } else if ctx.fusion == FusionMode::MitoProteo {
+            ctx.warnings.push(
+                "--fusion mito+proteo needs the fusion feature; mitochondrial axis skipped"
+                    .to_string(),
+            );
+        }
+
+        // Both only read the fused pass and the matrix.
+        let shared = &*ctx;
+        let (extension, mito_axis) = parallel::join(
+            ctx.threads,
+            || compute_extension(shared),
+            || mito.then(|| compute_mito_axis(shared)).transpose(),
+        );
+        ctx.proteostasis_extension = Some(extension?);
         info!("proteostasis_extension_ready");
+        if let Some(mito_axis) = mito_axis? {
+            info!(targets = mito_axis.summary.targets.len(), "mito_axis_ready");
+            ctx.mito_axis = Some(mito_axis);
+        }
         Ok(())
     }
 }
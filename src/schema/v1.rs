@@ -1,3 +1,4 @@
+use crate::metrics::mito::MitoAxisSummary;
 use crate::metrics::proteostasis_extension::aggregate::ProteostasisExtensionSummary;
 use serde::{Deserialize, Serialize};
 
@@ -10,8 +11,10 @@ pub enum Mode {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Normalization {
+    pub method: String,
     pub log1p: bool,
     pub cp10k: bool,
+    pub cpm: bool,
     pub raw: bool,
 }
 
@@ -23,6 +26,29 @@ pub struct InputMeta {
     pub normalization: Normalization,
     pub mode: Mode,
     pub timecourse: bool,
+    #[serde(default)]
+    pub sample_id: Option<String>,
+    #[serde(default)]
+    pub condition: Option<String>,
+    #[serde(default)]
+    pub species: Option<String>,
+    #[serde(default)]
+    pub filters: Option<FilterSummary>,
+}
+
+// Cells are attributed to the first filter that removes them, in the order
+// --cells, --exclude-cells, --min-umis, --min-genes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FilterSummary {
+    pub cells_input: u64,
+    pub cells_kept: u64,
+    pub genes_input: u64,
+    pub genes_kept: u64,
+    pub removed_by_cells: u64,
+    pub removed_by_exclude_cells: u64,
+    pub removed_by_min_umis: u64,
+    pub removed_by_min_genes: u64,
+    pub genes_removed_by_exclude_genes: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,6 +70,10 @@ pub struct PerSampleScore {
     pub pii_raw: Option<f64>,
     #[serde(rename = "PFS_raw")]
     pub pfs_raw: Option<f64>,
+    #[serde(rename = "IPS_raw", default)]
+    pub ips_raw: Option<f64>,
+    #[serde(rename = "IPS_ratio", default)]
+    pub ips_ratio: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -78,6 +108,15 @@ pub struct GenesetCoverage {
     pub found: u64,
     pub total: u64,
     pub fraction: f64,
+    #[serde(default)]
+    pub matches: Vec<GeneMatchOut>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneMatchOut {
+    pub gene: String,
+    pub key: String,
+    pub via: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -122,17 +161,30 @@ pub struct TimecourseResult {
     pub trajectory: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoringProfileMeta {
+    pub name: String,
+    pub version: String,
+    pub hash: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProteoQcV1 {
     pub tool: String,
     pub version: String,
     pub schema_version: String,
     pub input_meta: InputMeta,
+    #[serde(default)]
+    pub scoring_profile: Option<ScoringProfileMeta>,
+    #[serde(default)]
+    pub geneset_version: Option<String>,
     pub scores: Scores,
     pub risk_flags: Vec<RiskFlag>,
     pub explainability: Explainability,
     pub timecourse: Option<TimecourseResult>,
     pub proteostasis_extension: Option<ProteostasisExtensionSummary>,
+    #[serde(default)]
+    pub mito_axis: Option<MitoAxisSummary>,
 }
 
 impl ProteoQcV1 {
@@ -146,13 +198,21 @@ impl ProteoQcV1 {
                 cells: None,
                 nnz: None,
                 normalization: Normalization {
+                    method: "raw".to_string(),
                     log1p,
                     cp10k: false,
+                    cpm: false,
                     raw: true,
                 },
                 mode,
                 timecourse,
+                sample_id: None,
+                condition: None,
+                species: None,
+                filters: None,
             },
+            scoring_profile: None,
+            geneset_version: None,
             scores: Scores {
                 per_sample: None,
                 per_cell_tsv_path: None,
@@ -166,6 +226,7 @@ impl ProteoQcV1 {
             },
             timecourse: None,
             proteostasis_extension: None,
+            mito_axis: None,
         }
     }
 }
@@ -1,171 +1,126 @@
+use std::collections::HashMap;
+
 use anyhow::{Result, bail};
 use tracing::warn;
 
 use crate::ctx::Ctx;
-use crate::geneset::ResolvedGeneset;
+use crate::fusion::{self, FusedReduction};
+use crate::geneset::{ResolvedGeneset, SymbolResolver};
 use crate::math::reduce::GeneSetReducer;
 use crate::math::stats::trimmed_mean;
 use crate::schema::v1::Mode;
 use crate::scores::AxisRawScores;
+use crate::scores::profile::{AXES, ScoringProfile};
+
+// Constitutive catalytic subunits, the reference IPS_ratio is taken against.
+// Resolved directly rather than as a geneset, so they never count toward
+// geneset coverage or confidence.
+pub const CONSTITUTIVE_GENES: &[&str] = &["PSMB5", "PSMB6", "PSMB7"];
 
-#[cfg(feature = "fusion")]
-use crate::fusion;
+// Fused target id; the prefix keeps it apart from geneset ids.
+pub const CONSTITUTIVE_TARGET: &str = "ref:proteasome_constitutive";
+
+pub fn resolve_constitutive(ctx: &Ctx) -> Vec<usize> {
+    let resolver = SymbolResolver::new(&ctx.gene_index).with_ids(&ctx.gene_id_index);
+    CONSTITUTIVE_GENES
+        .iter()
+        .filter_map(|g| resolver.resolve(g))
+        .collect()
+}
 
 pub fn compute_axis_raw(ctx: &mut Ctx) -> Result<AxisRawScores> {
     compute_axis_raw_with_mode(ctx, ctx.mode.clone())
 }
 
 pub fn compute_axis_raw_with_mode(ctx: &mut Ctx, mode: Mode) -> Result<AxisRawScores> {
+    fusion::ensure_fused(ctx)?;
     let mut warnings = std::mem::take(&mut ctx.warnings);
     let mut scratch = std::mem::take(&mut ctx.scratch_cell_buf);
+    let constitutive_genes = resolve_constitutive(ctx);
 
     let result = (|| -> Result<AxisRawScores> {
+        if let Some(fused) = ctx.fused.as_ref().filter(|_| fusion::enabled(ctx)) {
+            return compute_axis_raw_fused(fused, mode, &ctx.profile, &mut warnings);
+        }
+
         let reader = ctx.expr_reader()?;
         let genesets = ctx
             .genesets
             .as_ref()
             .ok_or_else(|| anyhow::anyhow!("genesets not resolved"))?;
 
-        #[cfg(feature = "fusion")]
-        {
-            if ctx.fusion != "off" {
-                return compute_axis_raw_fusion(
-                    &reader,
-                    mode,
-                    genesets.resolved.as_slice(),
+        let mut reducer = GeneSetReducer::new(&reader, ctx.threads, ctx.cache_block, ctx.prefetch);
+
+        // Every geneset the profile uses is reduced up front, concurrently;
+        // the terms are then added in profile order.
+        let mut used: Vec<&ResolvedGeneset> = Vec::new();
+        let ids = AXES
+            .iter()
+            .flat_map(|axis| ctx.profile.axis_terms(axis))
+            .map(|term| term.geneset.as_str());
+        for gs in ids.filter_map(|id| find_geneset(&genesets.resolved, id)) {
+            if !gs.gene_ids.is_empty() && !used.iter().any(|u| u.id == gs.id) {
+                used.push(gs);
+            }
+        }
+        let mut gene_lists = used
+            .iter()
+            .map(|gs| gs.gene_ids.as_slice())
+            .collect::<Vec<_>>();
+        if !constitutive_genes.is_empty() {
+            gene_lists.push(&constitutive_genes);
+        }
+        let mut lists = reducer.per_cell_raw_many(&gene_lists)?;
+        let constitutive_values = if constitutive_genes.is_empty() {
+            None
+        } else {
+            lists.pop()
+        };
+        let reduced = used
+            .iter()
+            .map(|gs| gs.id.as_str())
+            .zip(lists)
+            .collect::<HashMap<_, _>>();
+
+        let len = if matches!(mode, Mode::Cell) {
+            reader.n_cells()
+        } else {
+            1
+        };
+        let mut axes: [Vec<f32>; 6] = std::array::from_fn(|_| vec![0.0f32; len]);
+        for (axis, out) in AXES.iter().zip(axes.iter_mut()) {
+            for term in ctx.profile.axis_terms(axis) {
+                add_weighted(
+                    &genesets.resolved,
+                    &reduced,
+                    &term.geneset,
+                    term.weight,
+                    out,
+                    &mut scratch,
                     &mut warnings,
+                    mode.clone(),
                 );
             }
         }
 
-        let mut reducer = GeneSetReducer::new(&reader, ctx.threads, ctx.cache_block, ctx.prefetch);
-
-        let n_cells = reader.n_cells();
-        let cell_mode = matches!(mode, Mode::Cell);
-
-        let mut pcs = vec![0.0f32; if cell_mode { n_cells } else { 1 }];
-        let mut utp = vec![0.0f32; if cell_mode { n_cells } else { 1 }];
-        let mut cls = vec![0.0f32; if cell_mode { n_cells } else { 1 }];
-        let mut erad = vec![0.0f32; if cell_mode { n_cells } else { 1 }];
-        let mut ribo = vec![0.0f32; if cell_mode { n_cells } else { 1 }];
-
-        compute_weighted(
-            &mut reducer,
-            genesets.resolved.as_slice(),
-            "proteasome_core",
-            0.6,
-            &mut pcs,
-            &mut scratch,
-            &mut warnings,
-            mode.clone(),
-        )?;
-        compute_weighted(
-            &mut reducer,
-            genesets.resolved.as_slice(),
-            "proteasome_regulator",
-            0.4,
-            &mut pcs,
-            &mut scratch,
-            &mut warnings,
-            mode.clone(),
-        )?;
-
-        compute_weighted(
-            &mut reducer,
-            genesets.resolved.as_slice(),
-            "ubiquitin_axis",
-            0.5,
-            &mut utp,
-            &mut scratch,
-            &mut warnings,
-            mode.clone(),
-        )?;
-        compute_weighted(
-            &mut reducer,
-            genesets.resolved.as_slice(),
-            "e3_ligases",
-            0.35,
-            &mut utp,
-            &mut scratch,
-            &mut warnings,
-            mode.clone(),
-        )?;
-        compute_weighted(
-            &mut reducer,
-            genesets.resolved.as_slice(),
-            "dubs",
-            -0.15,
-            &mut utp,
-            &mut scratch,
-            &mut warnings,
-            mode.clone(),
-        )?;
-
-        compute_weighted(
-            &mut reducer,
-            genesets.resolved.as_slice(),
-            "chaperone_hsp70",
-            0.45,
-            &mut cls,
-            &mut scratch,
-            &mut warnings,
-            mode.clone(),
-        )?;
-        compute_weighted(
-            &mut reducer,
-            genesets.resolved.as_slice(),
-            "chaperone_hsp90",
-            0.35,
-            &mut cls,
-            &mut scratch,
-            &mut warnings,
-            mode.clone(),
-        )?;
-        compute_weighted(
-            &mut reducer,
-            genesets.resolved.as_slice(),
-            "chaperone_hsp40",
-            0.20,
-            &mut cls,
-            &mut scratch,
-            &mut warnings,
-            mode.clone(),
-        )?;
-
-        compute_weighted(
-            &mut reducer,
-            genesets.resolved.as_slice(),
-            "erad",
-            1.0,
-            &mut erad,
-            &mut scratch,
-            &mut warnings,
-            mode.clone(),
-        )?;
-
-        compute_weighted(
-            &mut reducer,
-            genesets.resolved.as_slice(),
-            "ribosome_load",
-            1.0,
-            &mut ribo,
-            &mut scratch,
-            &mut warnings,
-            mode,
-        )?;
+        let mut constitutive = vec![0.0f32; len];
+        if let Some(values) = &constitutive_values {
+            accumulate(values, 1.0, &mut constitutive, &mut scratch, mode.clone());
+        }
 
-        check_nan(&pcs)?;
-        check_nan(&utp)?;
-        check_nan(&cls)?;
-        check_nan(&erad)?;
-        check_nan(&ribo)?;
+        for out in axes.iter().chain([&constitutive]) {
+            check_nan(out)?;
+        }
 
+        let [pcs, utp, cls, erad, ribo, ips] = axes;
         Ok(AxisRawScores {
             pcs,
             utp,
             cls,
             erad,
             ribo,
+            ips,
+            constitutive,
         })
     })();
 
@@ -174,218 +129,130 @@ pub fn compute_axis_raw_with_mode(ctx: &mut Ctx, mode: Mode) -> Result<AxisRawSc
     result
 }
 
-#[cfg(feature = "fusion")]
-fn compute_axis_raw_fusion(
-    reader: &crate::expr::reader::ExprReader<'_>,
+fn compute_axis_raw_fused(
+    fused: &FusedReduction,
     mode: Mode,
-    resolved: &[ResolvedGeneset],
+    profile: &ScoringProfile,
     warnings: &mut Vec<String>,
 ) -> Result<AxisRawScores> {
-    let n_cells = reader.n_cells();
-    let cell_mode = matches!(mode, Mode::Cell);
-
-    let target_ids = [
-        "proteasome_core",
-        "proteasome_regulator",
-        "ubiquitin_axis",
-        "e3_ligases",
-        "dubs",
-        "chaperone_hsp70",
-        "chaperone_hsp90",
-        "chaperone_hsp40",
-        "erad",
-        "ribosome_load",
-    ];
-
-    let plan = fusion::build_plan(reader.n_genes(), n_cells, resolved, &target_ids);
-    let mut out = vec![0.0f32; plan.targets.len() * n_cells];
-    fusion::fused_reduce(reader, &plan, &mut out)?;
-
-    let mut per_target: Vec<Vec<f32>> = Vec::new();
-    for t in 0..plan.targets.len() {
-        let start = t * n_cells;
-        let mut v = out[start..start + n_cells].to_vec();
-        let denom = plan.gene_counts[t].max(1) as f32;
-        for x in v.iter_mut() {
-            *x /= denom;
+    let len = if matches!(mode, Mode::Cell) {
+        fused.n_cells
+    } else {
+        1
+    };
+    let mut axes: [Vec<f32>; 6] = std::array::from_fn(|_| vec![0.0f32; len]);
+    for (axis, out) in AXES.iter().zip(axes.iter_mut()) {
+        for term in profile.axis_terms(axis) {
+            add_weighted_fused(
+                fused,
+                &term.geneset,
+                term.weight,
+                out,
+                warnings,
+                mode.clone(),
+            );
         }
-        per_target.push(v);
     }
 
-    let mut pcs = vec![0.0f32; if cell_mode { n_cells } else { 1 }];
-    let mut utp = vec![0.0f32; if cell_mode { n_cells } else { 1 }];
-    let mut cls = vec![0.0f32; if cell_mode { n_cells } else { 1 }];
-    let mut erad = vec![0.0f32; if cell_mode { n_cells } else { 1 }];
-    let mut ribo = vec![0.0f32; if cell_mode { n_cells } else { 1 }];
-
-    let mut map = std::collections::HashMap::new();
-    for (i, id) in plan.targets.iter().enumerate() {
-        map.insert(id.as_str(), i);
+    // A reference without genes leaves the ratio at 0, with no warning.
+    let mut constitutive = vec![0.0f32; len];
+    if let Some(target) = fused
+        .target_index(CONSTITUTIVE_TARGET)
+        .filter(|&t| fused.gene_counts[t] > 0)
+    {
+        let mut means = fused.target_means(target);
+        accumulate_fused(&mut means, 1.0, &mut constitutive, mode);
     }
 
-    add_weighted_from_target(
-        "proteasome_core",
-        0.6,
-        &per_target,
-        &map,
-        &mut pcs,
-        warnings,
-        mode.clone(),
-    )?;
-    add_weighted_from_target(
-        "proteasome_regulator",
-        0.4,
-        &per_target,
-        &map,
-        &mut pcs,
-        warnings,
-        mode.clone(),
-    )?;
-    add_weighted_from_target(
-        "ubiquitin_axis",
-        0.5,
-        &per_target,
-        &map,
-        &mut utp,
-        warnings,
-        mode.clone(),
-    )?;
-    add_weighted_from_target(
-        "e3_ligases",
-        0.35,
-        &per_target,
-        &map,
-        &mut utp,
-        warnings,
-        mode.clone(),
-    )?;
-    add_weighted_from_target(
-        "dubs",
-        -0.15,
-        &per_target,
-        &map,
-        &mut utp,
-        warnings,
-        mode.clone(),
-    )?;
-    add_weighted_from_target(
-        "chaperone_hsp70",
-        0.45,
-        &per_target,
-        &map,
-        &mut cls,
-        warnings,
-        mode.clone(),
-    )?;
-    add_weighted_from_target(
-        "chaperone_hsp90",
-        0.35,
-        &per_target,
-        &map,
-        &mut cls,
-        warnings,
-        mode.clone(),
-    )?;
-    add_weighted_from_target(
-        "chaperone_hsp40",
-        0.20,
-        &per_target,
-        &map,
-        &mut cls,
-        warnings,
-        mode.clone(),
-    )?;
-    add_weighted_from_target(
-        "erad",
-        1.0,
-        &per_target,
-        &map,
-        &mut erad,
-        warnings,
-        mode.clone(),
-    )?;
-    add_weighted_from_target(
-        "ribosome_load",
-        1.0,
-        &per_target,
-        &map,
-        &mut ribo,
-        warnings,
-        mode,
-    )?;
+    for out in axes.iter().chain([&constitutive]) {
+        check_nan(out)?;
+    }
 
+    let [pcs, utp, cls, erad, ribo, ips] = axes;
     Ok(AxisRawScores {
         pcs,
         utp,
         cls,
         erad,
         ribo,
+        ips,
+        constitutive,
     })
 }
 
-#[cfg(feature = "fusion")]
-fn add_weighted_from_target(
+// Same warnings and arithmetic as add_weighted, on the shared sums.
+fn add_weighted_fused(
+    fused: &FusedReduction,
     id: &str,
     weight: f32,
-    per_target: &[Vec<f32>],
-    map: &std::collections::HashMap<&str, usize>,
     out: &mut [f32],
     warnings: &mut Vec<String>,
     mode: Mode,
-) -> Result<()> {
-    if let Some(&idx) = map.get(id) {
-        if matches!(mode, Mode::Cell) {
-            for (o, v) in out.iter_mut().zip(per_target[idx].iter()) {
-                *o += weight * *v;
-            }
-        } else {
-            let mean = trimmed_mean(&mut per_target[idx].clone(), SAMPLE_TRIM_P);
-            out[0] += weight * mean;
+) {
+    let Some(target) = fused.target_index(id) else {
+        warn!("missing geneset '{}'", id);
+        warnings.push(format!("missing geneset '{}'", id));
+        return;
+    };
+    if fused.gene_counts[target] == 0 {
+        warn!("geneset '{}' resolved to 0 genes", id);
+        warnings.push(format!("geneset '{}' resolved to 0 genes", id));
+        return;
+    }
+    let mut means = fused.target_means(target);
+    accumulate_fused(&mut means, weight, out, mode);
+}
+
+fn accumulate_fused(means: &mut [f32], weight: f32, out: &mut [f32], mode: Mode) {
+    if matches!(mode, Mode::Cell) {
+        for (o, v) in out.iter_mut().zip(means.iter()) {
+            *o += weight * *v;
         }
-        Ok(())
     } else {
-        warnings.push(format!("missing geneset '{}'", id));
-        Ok(())
+        let mean = trimmed_mean(means, SAMPLE_TRIM_P);
+        out[0] += weight * mean;
     }
 }
 
 const SAMPLE_TRIM_P: f32 = 0.0;
 
-fn compute_weighted(
-    reducer: &mut GeneSetReducer<'_>,
+fn find_geneset<'a>(resolved: &'a [ResolvedGeneset], id: &str) -> Option<&'a ResolvedGeneset> {
+    resolved.iter().find(|g| g.id == id)
+}
+
+fn add_weighted(
     resolved: &[ResolvedGeneset],
+    reduced: &HashMap<&str, Vec<f32>>,
     id: &str,
     weight: f32,
     out: &mut [f32],
     scratch: &mut Vec<f32>,
     warnings: &mut Vec<String>,
     mode: Mode,
-) -> Result<()> {
-    let geneset = resolved.iter().find(|g| g.id == id);
-    if let Some(gs) = geneset {
-        if gs.gene_ids.is_empty() {
-            warn!("geneset '{}' resolved to 0 genes", id);
-            warnings.push(format!("geneset '{}' resolved to 0 genes", id));
-            return Ok(());
-        }
-        let cell_mode = matches!(mode, Mode::Cell);
-        if cell_mode {
-            scratch.resize(out.len(), 0.0);
-            reducer.per_cell_raw(&gs.gene_ids, scratch)?;
-            for (o, v) in out.iter_mut().zip(scratch.iter()) {
-                *o += weight * *v;
-            }
-        } else {
-            scratch.resize(reducer.expr.n_cells(), 0.0);
-            reducer.per_cell_raw(&gs.gene_ids, scratch)?;
-            let mean = trimmed_mean(scratch, SAMPLE_TRIM_P);
-            out[0] += weight * mean;
-        }
-        Ok(())
-    } else {
+) {
+    let Some(gs) = find_geneset(resolved, id) else {
         warn!("missing geneset '{}'", id);
         warnings.push(format!("missing geneset '{}'", id));
-        Ok(())
+        return;
+    };
+    let Some(values) = reduced.get(gs.id.as_str()) else {
+        warn!("geneset '{}' resolved to 0 genes", id);
+        warnings.push(format!("geneset '{}' resolved to 0 genes", id));
+        return;
+    };
+    accumulate(values, weight, out, scratch, mode);
+}
+
+fn accumulate(values: &[f32], weight: f32, out: &mut [f32], scratch: &mut Vec<f32>, mode: Mode) {
+    if matches!(mode, Mode::Cell) {
+        for (o, v) in out.iter_mut().zip(values.iter()) {
+            *o += weight * *v;
+        }
+    } else {
+        scratch.clear();
+        scratch.extend_from_slice(values);
+        let mean = trimmed_mean(scratch, SAMPLE_TRIM_P);
+        out[0] += weight * mean;
     }
 }
 
@@ -2,59 +2,110 @@ use anyhow::{Result, bail};
 
 use crate::math::stats::{mad, median, robust_z};
 use crate::schema::v1::Mode;
+use crate::scores::profile::{AXES, INTEGRATED, ScoringProfile, builtin_profile};
 use crate::scores::{AxisRawScores, IntegratedScores, PfsContributions};
 
 pub fn compute_integrated(
     axis: &AxisRawScores,
     mode: Mode,
+) -> Result<(IntegratedScores, PfsContributions)> {
+    compute_integrated_with(axis, mode, builtin_profile())
+}
+
+pub fn compute_integrated_with(
+    axis: &AxisRawScores,
+    mode: Mode,
+    profile: &ScoringProfile,
 ) -> Result<(IntegratedScores, PfsContributions)> {
     let n = axis.pcs.len();
-    if axis.utp.len() != n || axis.cls.len() != n || axis.erad.len() != n || axis.ribo.len() != n {
+    if axis.utp.len() != n
+        || axis.cls.len() != n
+        || axis.erad.len() != n
+        || axis.ribo.len() != n
+        || axis.ips.len() != n
+        || axis.constitutive.len() != n
+    {
         bail!("axis raw vectors length mismatch");
     }
 
+    // Values are slotted as AXES followed by INTEGRATED.
+    let slot = |name: &str| {
+        AXES.iter()
+            .chain(INTEGRATED.iter())
+            .position(|s| *s == name)
+            .expect("profile inputs are validated")
+    };
+    let plan = profile
+        .integrated
+        .iter()
+        .map(|def| {
+            let terms = def
+                .terms
+                .iter()
+                .map(|t| (slot(&t.input), t.weight))
+                .collect::<Vec<_>>();
+            (slot(&def.score), terms)
+        })
+        .collect::<Vec<_>>();
+    let (capacity_slot, pii_slot, pfs_slot) = (slot("capacity"), slot("pii"), slot("pfs"));
+    let pfs_terms = plan
+        .iter()
+        .find(|(score, _)| *score == pfs_slot)
+        .map(|(_, terms)| terms.as_slice())
+        .unwrap_or(&[]);
+    // PFS contributions are reported for the pii, utp, ribo and pcs terms.
+    let contrib_slots = [pii_slot, slot("utp"), slot("ribo"), slot("pcs")];
+
     let mut capacity_raw = vec![0.0f32; n];
     let mut pii_raw = vec![0.0f32; n];
     let mut pfs_raw = vec![0.0f32; n];
+    let mut contrib: [Vec<f32>; 4] = std::array::from_fn(|_| vec![0.0f32; n]);
 
-    let mut c_pii = vec![0.0f32; n];
-    let mut c_utp = vec![0.0f32; n];
-    let mut c_ribo = vec![0.0f32; n];
-    let mut c_pcs = vec![0.0f32; n];
-
+    let mut values = [0.0f32; AXES.len() + INTEGRATED.len()];
     for i in 0..n {
-        let pcs = axis.pcs[i];
-        let cls = axis.cls[i];
-        let erad = axis.erad[i];
-        let ribo = axis.ribo[i];
-        let utp = axis.utp[i];
-
-        if pcs.is_nan() || cls.is_nan() || erad.is_nan() || ribo.is_nan() || utp.is_nan() {
+        values[..AXES.len()].copy_from_slice(&[
+            axis.pcs[i],
+            axis.utp[i],
+            axis.cls[i],
+            axis.erad[i],
+            axis.ribo[i],
+            axis.ips[i],
+        ]);
+        if values[..AXES.len()].iter().any(|v| v.is_nan()) {
             bail!("NaN encountered in axis raw inputs");
         }
 
-        let capacity = 0.55 * pcs + 0.25 * cls + 0.20 * erad;
-        let pii = ribo - capacity;
-        let pfs = 0.40 * pii + 0.25 * utp + 0.20 * ribo - 0.15 * pcs;
+        for (score, terms) in &plan {
+            values[*score] = weighted_sum(&values, terms);
+        }
 
-        capacity_raw[i] = capacity;
-        pii_raw[i] = pii;
-        pfs_raw[i] = pfs;
+        capacity_raw[i] = values[capacity_slot];
+        pii_raw[i] = values[pii_slot];
+        pfs_raw[i] = values[pfs_slot];
 
-        c_pii[i] = 0.40 * pii;
-        c_utp[i] = 0.25 * utp;
-        c_ribo[i] = 0.20 * ribo;
-        c_pcs[i] = -0.15 * pcs;
+        for &(input, weight) in pfs_terms {
+            if let Some(k) = contrib_slots.iter().position(|&s| s == input) {
+                contrib[k][i] = weight * values[input];
+            }
+        }
     }
+    let [c_pii, c_utp, c_ribo, c_pcs] = contrib;
+    let ips_ratio = axis
+        .ips
+        .iter()
+        .zip(axis.constitutive.iter())
+        .map(|(&ips, &constitutive)| switching_ratio(ips, constitutive))
+        .collect::<Vec<_>>();
 
-    let (capacity_z, pii_z, pfs_z) = if matches!(mode, Mode::Cell) {
+    let (capacity_z, pii_z, pfs_z, ips_z) = if matches!(mode, Mode::Cell) {
         (
             Some(zscore(&capacity_raw)?),
             Some(zscore(&pii_raw)?),
             Some(zscore(&pfs_raw)?),
+            Some(zscore(&axis.ips)?),
         )
     } else {
-        (None, None, None)
+        (None, None, None, None)
     };
 
     Ok((
@@ -65,6 +116,8 @@ pub fn compute_integrated(
             capacity_z,
             pii_z,
             pfs_z,
+            ips_ratio,
+            ips_z,
         },
         PfsContributions {
             c_pii,
@@ -75,6 +128,31 @@ pub fn compute_integrated(
     ))
 }
 
+// Immuno/constitutive switching: IPS share of IPS + PSMB5/6/7. Negative or
+// zero totals (no catalytic subunit signal) map to 0.
+pub fn switching_ratio(ips: f32, constitutive: f32) -> f32 {
+    let total = ips.max(0.0) + constitutive.max(0.0);
+    if total > 0.0 {
+        ips.max(0.0) / total
+    } else {
+        0.0
+    }
+}
+
+// Starts from the first term rather than 0.0 so the result matches the
+// equivalent hand-written expression bit for bit.
+fn weighted_sum(values: &[f32], terms: &[(usize, f32)]) -> f32 {
+    let mut iter = terms.iter();
+    let Some(&(first, w)) = iter.next() else {
+        return 0.0;
+    };
+    let mut acc = w * values[first];
+    for &(input, w) in iter {
+        acc += w * values[input];
+    }
+    acc
+}
+
 fn zscore(values: &[f32]) -> Result<Vec<f32>> {
     let mut scratch = values.to_vec();
     let med = median(&mut scratch);
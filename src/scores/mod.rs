@@ -1,6 +1,8 @@
 pub mod axis_raw;
 pub mod integrated;
+pub mod profile;
 pub mod risk;
+pub mod rules;
 pub mod timecourse;
 
 #[derive(Debug, Clone)]
@@ -10,6 +12,10 @@ pub struct AxisRawScores {
     pub cls: Vec<f32>,
     pub erad: Vec<f32>,
     pub ribo: Vec<f32>,
+    pub ips: Vec<f32>,
+    // Constitutive catalytic subunits (PSMB5/6/7) that the immunoproteasome
+    // replaces; the denominator side of IPS_ratio.
+    pub constitutive: Vec<f32>,
 }
 
 #[derive(Debug, Clone)]
@@ -20,6 +26,8 @@ pub struct IntegratedScores {
     pub capacity_z: Option<Vec<f32>>,
     pub pii_z: Option<Vec<f32>>,
     pub pfs_z: Option<Vec<f32>>,
+    pub ips_ratio: Vec<f32>,
+    pub ips_z: Option<Vec<f32>>,
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+pub const AXES: [&str; 6] = ["pcs", "utp", "cls", "erad", "ribo", "ips"];
+pub const INTEGRATED: [&str; 3] = ["capacity", "pii", "pfs"];
+
+// `ips` postdates the five-axis v1 schema; profiles that leave it out score
+// it from the immunoproteasome geneset alone.
+fn default_axis_terms(axis: &str) -> Option<&'static [GenesetTerm]> {
+    static IPS: OnceLock<Vec<GenesetTerm>> = OnceLock::new();
+    (axis == "ips").then(|| {
+        IPS.get_or_init(|| {
+            vec![GenesetTerm {
+                geneset: "immunoproteasome".to_string(),
+                weight: 1.0,
+            }]
+        })
+        .as_slice()
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesetTerm {
+    pub geneset: String,
+    pub weight: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AxisDef {
+    pub axis: String,
+    pub terms: Vec<GenesetTerm>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InputTerm {
+    pub input: String,
+    pub weight: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IntegratedDef {
+    pub score: String,
+    pub terms: Vec<InputTerm>,
+}
+
+// Axes are weighted sums of geneset means; integrated scores are weighted
+// sums of axes and of integrated scores defined earlier in the list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScoringProfile {
+    pub name: String,
+    pub version: String,
+    pub axes: Vec<AxisDef>,
+    pub integrated: Vec<IntegratedDef>,
+}
+
+impl ScoringProfile {
+    pub fn axis_terms(&self, axis: &str) -> &[GenesetTerm] {
+        self.axes
+            .iter()
+            .find(|a| a.axis == axis)
+            .map(|a| a.terms.as_slice())
+            .or_else(|| default_axis_terms(axis))
+            .unwrap_or(&[])
+    }
+
+    pub fn integrated_terms(&self, score: &str) -> &[InputTerm] {
+        self.integrated
+            .iter()
+            .find(|d| d.score == score)
+            .map(|d| d.terms.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn genesets(&self) -> Vec<&str> {
+        let mut seen = HashSet::new();
+        let mut out = Vec::new();
+        for axis in AXES {
+            for term in self.axis_terms(axis) {
+                if seen.insert(term.geneset.as_str()) {
+                    out.push(term.geneset.as_str());
+                }
+            }
+        }
+        out
+    }
+
+    // CRC64 of the canonical JSON form, so formatting changes do not alter it.
+    pub fn hash(&self) -> String {
+        let canonical = serde_json::to_vec(self).unwrap_or_default();
+        format!(
+            "crc64:{:016x}",
+            kira_shared_sc_cache::crc64_ecma(&canonical)
+        )
+    }
+
+    fn validate(&self, source: &str) -> Result<()> {
+        if self.name.trim().is_empty() {
+            bail!("{}: profile name is empty", source);
+        }
+        let mut axes = HashSet::new();
+        for axis in &self.axes {
+            if !AXES.contains(&axis.axis.as_str()) {
+                bail!(
+                    "{}: unknown axis '{}' (expected one of {})",
+                    source,
+                    axis.axis,
+                    AXES.join(", ")
+                );
+            }
+            if !axes.insert(axis.axis.as_str()) {
+                bail!("{}: axis '{}' defined twice", source, axis.axis);
+            }
+            if axis.terms.is_empty() {
+                bail!("{}: axis '{}' has no terms", source, axis.axis);
+            }
+            check_weights(source, &axis.axis, axis.terms.iter().map(|t| t.weight))?;
+        }
+        for axis in AXES {
+            if !axes.contains(axis) && default_axis_terms(axis).is_none() {
+                bail!("{}: axis '{}' is not defined", source, axis);
+            }
+        }
+
+        let mut available = AXES.iter().copied().collect::<HashSet<_>>();
+        for def in &self.integrated {
+            if !INTEGRATED.contains(&def.score.as_str()) {
+                bail!(
+                    "{}: unknown integrated score '{}' (expected one of {})",
+                    source,
+                    def.score,
+                    INTEGRATED.join(", ")
+                );
+            }
+            if available.contains(def.score.as_str()) {
+                bail!("{}: integrated score '{}' defined twice", source, def.score);
+            }
+            if def.terms.is_empty() {
+                bail!("{}: integrated score '{}' has no terms", source, def.score);
+            }
+            for term in &def.terms {
+                if !available.contains(term.input.as_str()) {
+                    bail!(
+                        "{}: integrated score '{}' uses '{}' before it is defined",
+                        source,
+                        def.score,
+                        term.input
+                    );
+                }
+            }
+            check_weights(source, &def.score, def.terms.iter().map(|t| t.weight))?;
+            available.insert(def.score.as_str());
+        }
+        for score in INTEGRATED {
+            if !available.contains(score) {
+                bail!("{}: integrated score '{}' is not defined", source, score);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_weights(source: &str, name: &str, weights: impl Iterator<Item = f32>) -> Result<()> {
+    for w in weights {
+        if !w.is_finite() {
+            bail!("{}: '{}' has a non-finite weight", source, name);
+        }
+    }
+    Ok(())
+}
+
+pub fn builtin_profile() -> &'static ScoringProfile {
+    static PROFILE: OnceLock<ScoringProfile> = OnceLock::new();
+    PROFILE.get_or_init(|| {
+        parse_profile(
+            include_str!("../../assets/profiles/proteoqc_default_v1.json"),
+            "built-in profile",
+        )
+        .expect("built-in scoring profile is valid")
+    })
+}
+
+pub fn load_profile(path: &Path) -> Result<ScoringProfile> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read scoring profile {}", path.display()))?;
+    parse_profile(&content, &path.display().to_string())
+}
+
+fn parse_profile(content: &str, source: &str) -> Result<ScoringProfile> {
+    let profile: ScoringProfile = serde_json::from_str(content)
+        .with_context(|| format!("{}: invalid scoring profile JSON", source))?;
+    profile.validate(source)?;
+    Ok(profile)
+}
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::scores::profile::{AXES, INTEGRATED};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl CompareOp {
+    fn holds(self, lhs: f32, rhs: f32) -> bool {
+        match self {
+            Self::Gt => lhs > rhs,
+            Self::Ge => lhs >= rhs,
+            Self::Lt => lhs < rhs,
+            Self::Le => lhs <= rhs,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Condition {
+    pub var: String,
+    pub op: CompareOp,
+    pub value: f32,
+}
+
+impl Condition {
+    pub fn holds(&self, value: f32) -> bool {
+        self.op.holds(value, self.value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleScope {
+    Cells,
+    Sample,
+}
+
+#[derive(Debug, Clone)]
+pub struct RuleExpr {
+    pub source: String,
+    pub conditions: Vec<Condition>,
+    pub scope: RuleScope,
+    pub min_fraction: f32,
+}
+
+impl RuleExpr {
+    // Counts rows where every condition holds; vectors are looked up by name.
+    pub fn count_matches(&self, table: &HashMap<String, Vec<f32>>) -> Result<(usize, usize)> {
+        let mut columns = Vec::with_capacity(self.conditions.len());
+        for cond in &self.conditions {
+            let column = table
+                .get(&cond.var)
+                .with_context(|| format!("score '{}' not available", cond.var))?;
+            columns.push((column.as_slice(), cond));
+        }
+        let n = columns.first().map(|(c, _)| c.len()).unwrap_or(0);
+        if columns.iter().any(|(c, _)| c.len() != n) {
+            bail!(
+                "rule '{}' compares vectors of different lengths",
+                self.source
+            );
+        }
+        let count = (0..n)
+            .filter(|&i| {
+                columns
+                    .iter()
+                    .all(|(c, cond)| cond.op.holds(c[i], cond.value))
+            })
+            .count();
+        Ok((count, n))
+    }
+
+    pub fn fires(&self, count: usize, total: usize) -> bool {
+        count > 0 && fraction(count, total) >= self.min_fraction
+    }
+
+    pub fn vars(&self) -> impl Iterator<Item = &str> {
+        self.conditions.iter().map(|c| c.var.as_str())
+    }
+}
+
+// A rule carries separate expressions for cell and sample mode; a rule with
+// no expression for the current mode is not reported.
+#[derive(Debug, Clone)]
+pub struct RiskRule {
+    pub name: String,
+    pub cell: Option<RuleExpr>,
+    pub sample: Option<RuleExpr>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    rules: Vec<RuleDef>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDef {
+    name: String,
+    cell: Option<ExprDef>,
+    sample: Option<ExprDef>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExprDef {
+    when: String,
+    over: Option<String>,
+    min_fraction: Option<f32>,
+}
+
+pub fn builtin_rules() -> &'static [RiskRule] {
+    static RULES: OnceLock<Vec<RiskRule>> = OnceLock::new();
+    RULES.get_or_init(|| {
+        parse_rules(
+            include_str!("../../assets/rules/risk_rules_v1.json"),
+            "built-in rules",
+        )
+        .expect("built-in risk rules are valid")
+    })
+}
+
+pub fn load_rules(path: &Path) -> Result<Vec<RiskRule>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read risk rules {}", path.display()))?;
+    parse_rules(&content, &path.display().to_string())
+}
+
+// User rules replace built-in rules of the same name; new names are appended.
+pub fn merge_rules(builtin: &[RiskRule], user: Vec<RiskRule>) -> Vec<RiskRule> {
+    let mut merged = builtin.to_vec();
+    for rule in user {
+        match merged.iter_mut().find(|r| r.name == rule.name) {
+            Some(existing) => *existing = rule,
+            None => merged.push(rule),
+        }
+    }
+    merged
+}
+
+fn parse_rules(content: &str, source: &str) -> Result<Vec<RiskRule>> {
+    let file: RulesFile = serde_json::from_str(content)
+        .with_context(|| format!("{}: invalid risk rules JSON", source))?;
+    let mut rules: Vec<RiskRule> = Vec::with_capacity(file.rules.len());
+    for def in file.rules {
+        if def.name.trim().is_empty() {
+            bail!("{}: rule name is empty", source);
+        }
+        if rules.iter().any(|r| r.name == def.name) {
+            bail!("{}: rule '{}' defined twice", source, def.name);
+        }
+        if def.cell.is_none() && def.sample.is_none() {
+            bail!(
+                "{}: rule '{}' has no cell or sample expression",
+                source,
+                def.name
+            );
+        }
+        let context = |mode: &str| format!("{}: rule '{}' ({} mode)", source, def.name, mode);
+        let cell = def
+            .cell
+            .map(|e| parse_expr(e, RuleScope::Cells))
+            .transpose()
+            .with_context(|| context("cell"))?;
+        let sample = def
+            .sample
+            .map(|e| parse_expr(e, RuleScope::Sample))
+            .transpose()
+            .with_context(|| context("sample"))?;
+        if cell.as_ref().is_some_and(|e| e.scope == RuleScope::Sample) {
+            bail!(
+                "{}: cell mode rules are evaluated over cells",
+                context("cell")
+            );
+        }
+        rules.push(RiskRule {
+            name: def.name,
+            cell,
+            sample,
+        });
+    }
+    Ok(rules)
+}
+
+fn parse_expr(def: ExprDef, default_scope: RuleScope) -> Result<RuleExpr> {
+    let scope = match def.over.as_deref() {
+        None => default_scope,
+        Some("cells") => RuleScope::Cells,
+        Some("sample") => RuleScope::Sample,
+        Some(other) => bail!("unknown scope '{}' (expected cells or sample)", other),
+    };
+    let min_fraction = def.min_fraction.unwrap_or(0.0);
+    if !(0.0..=1.0).contains(&min_fraction) {
+        bail!("min_fraction must be within [0, 1]");
+    }
+
+    let mut conditions = Vec::new();
+    for part in def.when.split("&&") {
+        let cond = parse_condition(part.trim())?;
+        if scope == RuleScope::Sample && cond.var.ends_with("_z") {
+            bail!("'{}' is not available at sample scope", cond.var);
+        }
+        conditions.push(cond);
+    }
+
+    Ok(RuleExpr {
+        source: def.when.trim().to_string(),
+        conditions,
+        scope,
+        min_fraction,
+    })
+}
+
+fn parse_condition(text: &str) -> Result<Condition> {
+    let ops = [
+        (">=", CompareOp::Ge),
+        ("<=", CompareOp::Le),
+        (">", CompareOp::Gt),
+        ("<", CompareOp::Lt),
+    ];
+    let Some((pos, token, op)) = ops
+        .iter()
+        .find_map(|&(token, op)| text.find(token).map(|pos| (pos, token, op)))
+    else {
+        bail!(
+            "'{}' is not a comparison (expected <score> <op> <number>)",
+            text
+        );
+    };
+    let var = text[..pos].trim();
+    let value = text[pos + token.len()..].trim();
+    if !score_names().any(|name| name == var) {
+        bail!(
+            "unknown score '{}' (expected one of {})",
+            var,
+            score_names().collect::<Vec<_>>().join(", ")
+        );
+    }
+    let value = value
+        .parse::<f32>()
+        .ok()
+        .filter(|v| v.is_finite())
+        .with_context(|| format!("'{}' is not a number", value))?;
+    Ok(Condition {
+        var: var.to_string(),
+        op,
+        value,
+    })
+}
+
+pub const IPS_RATIO: &str = "IPS_ratio";
+pub const IPS_SWITCH_RULE: &str = "immunoproteasome_switch";
+
+// The ImmunoproteasomeSwitch regime reuses the IPS_ratio bound of this rule,
+// so a --risk-rules override moves both; without it the regime is never set.
+pub fn ips_switch_condition(rules: &[RiskRule], cell_mode: bool) -> Option<&Condition> {
+    let rule = rules.iter().find(|r| r.name == IPS_SWITCH_RULE)?;
+    let expr = if cell_mode { &rule.cell } else { &rule.sample };
+    expr.as_ref()?
+        .conditions
+        .iter()
+        .find(|c| c.var == IPS_RATIO)
+}
+
+// Rule variables use the report spelling: PCS_raw, PFS_z, Capacity_raw, ...
+pub fn score_name(score: &str, suffix: &str) -> String {
+    let base = match score {
+        "ribo" => "Ribo".to_string(),
+        "capacity" => "Capacity".to_string(),
+        other => other.to_ascii_uppercase(),
+    };
+    format!("{}_{}", base, suffix)
+}
+
+fn score_names() -> impl Iterator<Item = String> {
+    AXES.iter()
+        .chain(INTEGRATED.iter())
+        .flat_map(|s| [score_name(s, "raw"), score_name(s, "z")])
+        .chain(std::iter::once(IPS_RATIO.to_string()))
+}
+
+pub fn fraction(count: usize, total: usize) -> f32 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f32 / total as f32
+    }
+}
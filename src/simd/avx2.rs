@@ -1,8 +1,8 @@
-#![cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
 #![allow(unsafe_op_in_unsafe_fn)]
 
 use core::arch::x86_64::*;
 
+#[target_feature(enable = "avx2")]
 pub unsafe fn sum_f32(slice: &[f32]) -> f32 {
     let mut i = 0usize;
     let mut acc = _mm256_setzero_ps();
@@ -24,6 +24,7 @@ pub unsafe fn sum_f32(slice: &[f32]) -> f32 {
     sum
 }
 
+#[target_feature(enable = "avx2")]
 pub unsafe fn add_scaled(dst: &mut [f32], src: &[f32], scale: f32) {
     let n = dst.len().min(src.len());
     let mut i = 0usize;
@@ -42,6 +43,7 @@ pub unsafe fn add_scaled(dst: &mut [f32], src: &[f32], scale: f32) {
     }
 }
 
+#[target_feature(enable = "avx2")]
 pub unsafe fn abs_diff(dst: &mut [f32], src: &[f32], center: f32) {
     let n = dst.len().min(src.len());
     let mut i = 0usize;
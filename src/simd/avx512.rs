@@ -0,0 +1,59 @@
+#![allow(unsafe_op_in_unsafe_fn)]
+
+use core::arch::x86_64::*;
+
+#[target_feature(enable = "avx512f")]
+pub unsafe fn sum_f32(slice: &[f32]) -> f32 {
+    let mut i = 0usize;
+    let mut acc = _mm512_setzero_ps();
+    while i + 16 <= slice.len() {
+        let v = _mm512_loadu_ps(slice.as_ptr().add(i));
+        acc = _mm512_add_ps(acc, v);
+        i += 16;
+    }
+    let mut lanes = [0f32; 16];
+    _mm512_storeu_ps(lanes.as_mut_ptr(), acc);
+    let mut sum = lanes.iter().sum::<f32>();
+    while i < slice.len() {
+        sum += slice[i];
+        i += 1;
+    }
+    sum
+}
+
+#[target_feature(enable = "avx512f")]
+pub unsafe fn add_scaled(dst: &mut [f32], src: &[f32], scale: f32) {
+    let n = dst.len().min(src.len());
+    let mut i = 0usize;
+    let scale_v = _mm512_set1_ps(scale);
+    while i + 16 <= n {
+        let d = _mm512_loadu_ps(dst.as_ptr().add(i));
+        let s = _mm512_loadu_ps(src.as_ptr().add(i));
+        let mul = _mm512_mul_ps(s, scale_v);
+        let out = _mm512_add_ps(d, mul);
+        _mm512_storeu_ps(dst.as_mut_ptr().add(i), out);
+        i += 16;
+    }
+    while i < n {
+        dst[i] += src[i] * scale;
+        i += 1;
+    }
+}
+
+#[target_feature(enable = "avx512f")]
+pub unsafe fn abs_diff(dst: &mut [f32], src: &[f32], center: f32) {
+    let n = dst.len().min(src.len());
+    let mut i = 0usize;
+    let center_v = _mm512_set1_ps(center);
+    while i + 16 <= n {
+        let s = _mm512_loadu_ps(src.as_ptr().add(i));
+        let diff = _mm512_sub_ps(s, center_v);
+        let abs = _mm512_abs_ps(diff);
+        _mm512_storeu_ps(dst.as_mut_ptr().add(i), abs);
+        i += 16;
+    }
+    while i < n {
+        dst[i] = (src[i] - center).abs();
+        i += 1;
+    }
+}
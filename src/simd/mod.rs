@@ -1,77 +1,148 @@
-#[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+#[cfg(target_arch = "x86_64")]
 mod avx2;
-#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+#[cfg(target_arch = "x86_64")]
+mod avx512;
+#[cfg(target_arch = "aarch64")]
 mod neon;
 mod scalar;
+#[cfg(target_arch = "x86_64")]
+mod sse41;
 
-#[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
-pub fn backend_name() -> &'static str {
-    "avx2"
-}
+use std::sync::atomic::{AtomicU8, Ordering};
 
-#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
-pub fn backend_name() -> &'static str {
-    "neon"
-}
+use anyhow::{Result, bail};
 
-#[cfg(not(any(
-    all(target_arch = "x86_64", target_feature = "avx2"),
-    all(target_arch = "aarch64", target_feature = "neon")
-)))]
-pub fn backend_name() -> &'static str {
-    "scalar"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Scalar,
+    Sse41,
+    Avx2,
+    Avx512,
+    Neon,
 }
 
-#[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
-pub fn sum_f32(slice: &[f32]) -> f32 {
-    unsafe { avx2::sum_f32(slice) }
-}
+impl Backend {
+    pub const ALL: [Backend; 5] = [
+        Backend::Scalar,
+        Backend::Sse41,
+        Backend::Avx2,
+        Backend::Avx512,
+        Backend::Neon,
+    ];
 
-#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
-pub fn sum_f32(slice: &[f32]) -> f32 {
-    unsafe { neon::sum_f32(slice) }
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Scalar => "scalar",
+            Self::Sse41 => "sse4.1",
+            Self::Avx2 => "avx2",
+            Self::Avx512 => "avx512",
+            Self::Neon => "neon",
+        }
+    }
+
+    pub fn is_supported(self) -> bool {
+        match self {
+            Self::Scalar => true,
+            #[cfg(target_arch = "x86_64")]
+            Self::Sse41 => std::arch::is_x86_feature_detected!("sse4.1"),
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx2 => std::arch::is_x86_feature_detected!("avx2"),
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx512 => std::arch::is_x86_feature_detected!("avx512f"),
+            #[cfg(target_arch = "aarch64")]
+            Self::Neon => std::arch::is_aarch64_feature_detected!("neon"),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    fn from_u8(v: u8) -> Option<Self> {
+        Self::ALL.get(v as usize).copied()
+    }
 }
 
-#[cfg(not(any(
-    all(target_arch = "x86_64", target_feature = "avx2"),
-    all(target_arch = "aarch64", target_feature = "neon")
-)))]
-pub fn sum_f32(slice: &[f32]) -> f32 {
-    scalar::sum_f32(slice)
+// Index into Backend::ALL; UNSET until the first call or an override.
+const UNSET: u8 = u8::MAX;
+static ACTIVE: AtomicU8 = AtomicU8::new(UNSET);
+
+// Widest backend the running CPU supports.
+pub fn detect() -> Backend {
+    [
+        Backend::Avx512,
+        Backend::Avx2,
+        Backend::Sse41,
+        Backend::Neon,
+    ]
+    .into_iter()
+    .find(|b| b.is_supported())
+    .unwrap_or(Backend::Scalar)
 }
 
-#[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
-pub fn add_scaled(dst: &mut [f32], src: &[f32], scale: f32) {
-    unsafe { avx2::add_scaled(dst, src, scale) }
+pub fn backend() -> Backend {
+    if let Some(b) = Backend::from_u8(ACTIVE.load(Ordering::Relaxed)) {
+        return b;
+    }
+    let b = detect();
+    ACTIVE.store(b as u8, Ordering::Relaxed);
+    b
 }
 
-#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
-pub fn add_scaled(dst: &mut [f32], src: &[f32], scale: f32) {
-    unsafe { neon::add_scaled(dst, src, scale) }
+// Overrides runtime detection. Only backends the CPU supports are accepted,
+// which is what makes the unsafe calls below sound.
+pub fn set_backend(backend: Backend) -> Result<()> {
+    if !backend.is_supported() {
+        bail!(
+            "simd backend {} is not supported by this CPU (detected: {})",
+            backend.as_str(),
+            detect().as_str()
+        );
+    }
+    ACTIVE.store(backend as u8, Ordering::Relaxed);
+    Ok(())
 }
 
-#[cfg(not(any(
-    all(target_arch = "x86_64", target_feature = "avx2"),
-    all(target_arch = "aarch64", target_feature = "neon")
-)))]
-pub fn add_scaled(dst: &mut [f32], src: &[f32], scale: f32) {
-    scalar::add_scaled(dst, src, scale)
+pub fn backend_name() -> &'static str {
+    backend().as_str()
 }
 
-#[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
-pub fn abs_diff(dst: &mut [f32], src: &[f32], center: f32) {
-    unsafe { avx2::abs_diff(dst, src, center) }
+pub fn sum_f32(slice: &[f32]) -> f32 {
+    match backend() {
+        #[cfg(target_arch = "x86_64")]
+        Backend::Sse41 => unsafe { sse41::sum_f32(slice) },
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx2 => unsafe { avx2::sum_f32(slice) },
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx512 => unsafe { avx512::sum_f32(slice) },
+        #[cfg(target_arch = "aarch64")]
+        Backend::Neon => unsafe { neon::sum_f32(slice) },
+        _ => scalar::sum_f32(slice),
+    }
 }
 
-#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
-pub fn abs_diff(dst: &mut [f32], src: &[f32], center: f32) {
-    unsafe { neon::abs_diff(dst, src, center) }
+pub fn add_scaled(dst: &mut [f32], src: &[f32], scale: f32) {
+    match backend() {
+        #[cfg(target_arch = "x86_64")]
+        Backend::Sse41 => unsafe { sse41::add_scaled(dst, src, scale) },
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx2 => unsafe { avx2::add_scaled(dst, src, scale) },
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx512 => unsafe { avx512::add_scaled(dst, src, scale) },
+        #[cfg(target_arch = "aarch64")]
+        Backend::Neon => unsafe { neon::add_scaled(dst, src, scale) },
+        _ => scalar::add_scaled(dst, src, scale),
+    }
 }
 
-#[cfg(not(any(
-    all(target_arch = "x86_64", target_feature = "avx2"),
-    all(target_arch = "aarch64", target_feature = "neon")
-)))]
 pub fn abs_diff(dst: &mut [f32], src: &[f32], center: f32) {
-    scalar::abs_diff(dst, src, center)
+    match backend() {
+        #[cfg(target_arch = "x86_64")]
+        Backend::Sse41 => unsafe { sse41::abs_diff(dst, src, center) },
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx2 => unsafe { avx2::abs_diff(dst, src, center) },
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx512 => unsafe { avx512::abs_diff(dst, src, center) },
+        #[cfg(target_arch = "aarch64")]
+        Backend::Neon => unsafe { neon::abs_diff(dst, src, center) },
+        _ => scalar::abs_diff(dst, src, center),
+    }
 }
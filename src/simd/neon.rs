@@ -1,8 +1,8 @@
-#![cfg(all(target_arch = "aarch64", target_feature = "neon"))]
 #![allow(unsafe_op_in_unsafe_fn)]
 
 use core::arch::aarch64::*;
 
+#[target_feature(enable = "neon")]
 pub unsafe fn sum_f32(slice: &[f32]) -> f32 {
     let mut i = 0usize;
     let mut acc = vdupq_n_f32(0.0);
@@ -24,6 +24,7 @@ pub unsafe fn sum_f32(slice: &[f32]) -> f32 {
     sum
 }
 
+#[target_feature(enable = "neon")]
 pub unsafe fn add_scaled(dst: &mut [f32], src: &[f32], scale: f32) {
     let n = dst.len().min(src.len());
     let mut i = 0usize;
@@ -42,6 +43,7 @@ pub unsafe fn add_scaled(dst: &mut [f32], src: &[f32], scale: f32) {
     }
 }
 
+#[target_feature(enable = "neon")]
 pub unsafe fn abs_diff(dst: &mut [f32], src: &[f32], center: f32) {
     let n = dst.len().min(src.len());
     let mut i = 0usize;
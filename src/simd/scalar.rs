@@ -1,5 +1,3 @@
-#![allow(dead_code)]
-
 pub fn sum_f32(slice: &[f32]) -> f32 {
     let mut sum = 0.0;
     for v in slice {
@@ -0,0 +1,60 @@
+#![allow(unsafe_op_in_unsafe_fn)]
+
+use core::arch::x86_64::*;
+
+#[target_feature(enable = "sse4.1")]
+pub unsafe fn sum_f32(slice: &[f32]) -> f32 {
+    let mut i = 0usize;
+    let mut acc = _mm_setzero_ps();
+    while i + 4 <= slice.len() {
+        let v = _mm_loadu_ps(slice.as_ptr().add(i));
+        acc = _mm_add_ps(acc, v);
+        i += 4;
+    }
+    let mut lanes = [0f32; 4];
+    _mm_storeu_ps(lanes.as_mut_ptr(), acc);
+    let mut sum = lanes.iter().sum::<f32>();
+    while i < slice.len() {
+        sum += slice[i];
+        i += 1;
+    }
+    sum
+}
+
+#[target_feature(enable = "sse4.1")]
+pub unsafe fn add_scaled(dst: &mut [f32], src: &[f32], scale: f32) {
+    let n = dst.len().min(src.len());
+    let mut i = 0usize;
+    let scale_v = _mm_set1_ps(scale);
+    while i + 4 <= n {
+        let d = _mm_loadu_ps(dst.as_ptr().add(i));
+        let s = _mm_loadu_ps(src.as_ptr().add(i));
+        let mul = _mm_mul_ps(s, scale_v);
+        let out = _mm_add_ps(d, mul);
+        _mm_storeu_ps(dst.as_mut_ptr().add(i), out);
+        i += 4;
+    }
+    while i < n {
+        dst[i] += src[i] * scale;
+        i += 1;
+    }
+}
+
+#[target_feature(enable = "sse4.1")]
+pub unsafe fn abs_diff(dst: &mut [f32], src: &[f32], center: f32) {
+    let n = dst.len().min(src.len());
+    let mut i = 0usize;
+    let center_v = _mm_set1_ps(center);
+    let sign_mask = _mm_set1_ps(-0.0);
+    while i + 4 <= n {
+        let s = _mm_loadu_ps(src.as_ptr().add(i));
+        let diff = _mm_sub_ps(s, center_v);
+        let abs = _mm_andnot_ps(sign_mask, diff);
+        _mm_storeu_ps(dst.as_mut_ptr().add(i), abs);
+        i += 4;
+    }
+    while i < n {
+        dst[i] = (src[i] - center).abs();
+        i += 1;
+    }
+}
@@ -44,6 +44,7 @@ fn make_genesets() -> GenesetCollection {
             gene_ids: vec![0],
             missing: vec![],
             total: 1,
+            matches: Vec::new(),
         },
         ResolvedGeneset {
             id: "proteasome_regulator".into(),
@@ -51,6 +52,7 @@ fn make_genesets() -> GenesetCollection {
             gene_ids: vec![1],
             missing: vec![],
             total: 1,
+            matches: Vec::new(),
         },
         ResolvedGeneset {
             id: "ubiquitin_axis".into(),
@@ -58,6 +60,7 @@ fn make_genesets() -> GenesetCollection {
             gene_ids: vec![2],
             missing: vec![],
             total: 1,
+            matches: Vec::new(),
         },
         ResolvedGeneset {
             id: "e3_ligases".into(),
@@ -65,6 +68,7 @@ fn make_genesets() -> GenesetCollection {
             gene_ids: vec![1],
             missing: vec![],
             total: 1,
+            matches: Vec::new(),
         },
         ResolvedGeneset {
             id: "dubs".into(),
@@ -72,6 +76,7 @@ fn make_genesets() -> GenesetCollection {
             gene_ids: vec![0],
             missing: vec![],
             total: 1,
+            matches: Vec::new(),
         },
         ResolvedGeneset {
             id: "chaperone_hsp70".into(),
@@ -79,6 +84,7 @@ fn make_genesets() -> GenesetCollection {
             gene_ids: vec![0],
             missing: vec![],
             total: 1,
+            matches: Vec::new(),
         },
         ResolvedGeneset {
             id: "chaperone_hsp90".into(),
@@ -86,6 +92,7 @@ fn make_genesets() -> GenesetCollection {
             gene_ids: vec![1],
             missing: vec![],
             total: 1,
+            matches: Vec::new(),
         },
         ResolvedGeneset {
             id: "chaperone_hsp40".into(),
@@ -93,6 +100,7 @@ fn make_genesets() -> GenesetCollection {
             gene_ids: vec![2],
             missing: vec![],
             total: 1,
+            matches: Vec::new(),
         },
         ResolvedGeneset {
             id: "erad".into(),
@@ -100,6 +108,7 @@ fn make_genesets() -> GenesetCollection {
             gene_ids: vec![1],
             missing: vec![],
             total: 1,
+            matches: Vec::new(),
         },
         ResolvedGeneset {
             id: "ribosome_load".into(),
@@ -107,6 +116,7 @@ fn make_genesets() -> GenesetCollection {
             gene_ids: vec![2],
             missing: vec![],
             total: 1,
+            matches: Vec::new(),
         },
     ];
     GenesetCollection {
@@ -0,0 +1,186 @@
+use std::fs;
+use std::path::Path;
+
+use assert_cmd::Command;
+use kira_proteoqc::io::shared_cache::SharedCache;
+use predicates::prelude::PredicateBooleanExt;
+use predicates::str::contains;
+use tempfile::TempDir;
+
+fn write_10x(dir: &Path, features: &str, barcodes: &str, mtx: &str) {
+    fs::write(dir.join("features.tsv"), features).unwrap();
+    fs::write(dir.join("barcodes.tsv"), barcodes).unwrap();
+    fs::write(dir.join("matrix.mtx"), mtx).unwrap();
+}
+
+fn write_small_10x(dir: &Path) {
+    // Entries are deliberately out of order within cell 1.
+    let mtx =
+        "%%MatrixMarket matrix coordinate integer general\n3 2 4\n3 1 2\n1 1 5\n2 2 1\n1 2 3\n";
+    let features = "g1\tGeneA\ng2\tGeneB\ng3\tGeneC\n";
+    let barcodes = "cell1\ncell2\n";
+    write_10x(dir, features, barcodes, mtx);
+}
+
+#[test]
+fn cache_build_writes_sorted_cell_major_cache() {
+    let tmp = TempDir::new().unwrap();
+    write_small_10x(tmp.path());
+
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("cache").arg("build").arg("--input").arg(tmp.path());
+    cmd.assert().success().stdout(contains("nnz: 4"));
+
+    let cache = SharedCache::open(&tmp.path().join("kira-organelle.bin")).unwrap();
+    assert_eq!(cache.genes, vec!["GeneA", "GeneB", "GeneC"]);
+    assert_eq!(cache.barcodes, vec!["cell1", "cell2"]);
+    assert_eq!(cache.col_ptr(), &[0, 2, 4]);
+    assert_eq!(cache.cell(0), (&[0u32, 2][..], &[5u32, 2][..]));
+    assert_eq!(cache.cell(1), (&[0u32, 1][..], &[3u32, 1][..]));
+}
+
+#[test]
+fn cache_build_uses_detected_prefix_and_feeds_pipeline_mode() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let mtx = "%%MatrixMarket matrix coordinate integer general\n2 2 2\n1 1 1\n2 2 1\n";
+    fs::write(dir.join("S1_matrix.mtx"), mtx).unwrap();
+    fs::write(dir.join("S1_features.tsv"), "g1\tGeneA\ng2\tGeneB\n").unwrap();
+    fs::write(dir.join("S1_barcodes.tsv"), "cell1\ncell2\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("cache").arg("build").arg("--input").arg(dir);
+    cmd.assert().success();
+    assert!(dir.join("S1.kira-organelle.bin").exists());
+
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("validate")
+        .arg("--input")
+        .arg(dir)
+        .arg("--run-mode")
+        .arg("pipeline");
+    cmd.assert().success().stdout(contains("cells: 2"));
+}
+
+#[test]
+fn cache_build_refuses_to_overwrite_without_force() {
+    let tmp = TempDir::new().unwrap();
+    write_small_10x(tmp.path());
+    fs::write(tmp.path().join("kira-organelle.bin"), b"old").unwrap();
+
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("cache").arg("build").arg("--input").arg(tmp.path());
+    cmd.assert().failure().stderr(contains("--force"));
+
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("cache")
+        .arg("build")
+        .arg("--input")
+        .arg(tmp.path())
+        .arg("--force");
+    cmd.assert().success();
+    assert!(SharedCache::open(&tmp.path().join("kira-organelle.bin")).is_ok());
+}
+
+#[test]
+fn cache_build_rejects_non_integer_counts() {
+    let tmp = TempDir::new().unwrap();
+    let mtx = "%%MatrixMarket matrix coordinate real general\n2 2 2\n1 1 0.5\n2 2 1\n";
+    write_10x(tmp.path(), "g1\tGeneA\ng2\tGeneB\n", "cell1\ncell2\n", mtx);
+
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("cache").arg("build").arg("--input").arg(tmp.path());
+    cmd.assert()
+        .failure()
+        .stderr(contains("not a non-negative integer count"));
+    let leftovers: Vec<_> = fs::read_dir(tmp.path())
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().contains("kira-organelle"))
+        .collect();
+    assert!(leftovers.is_empty());
+}
+
+#[test]
+fn cache_inspect_prints_header_and_samples() {
+    let tmp = TempDir::new().unwrap();
+    write_small_10x(tmp.path());
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("cache").arg("build").arg("--input").arg(tmp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("cache")
+        .arg("inspect")
+        .arg("--input")
+        .arg(tmp.path())
+        .arg("--sample")
+        .arg("2");
+    cmd.assert()
+        .success()
+        .stdout(contains("version: 1.0"))
+        .stdout(contains("genes: 3"))
+        .stdout(contains("nnz: 4"))
+        .stdout(contains(" ok\n"))
+        .stdout(contains("validation: ok"))
+        .stdout(contains("- GeneB\n"))
+        .stdout(contains("GeneC").not())
+        .stdout(contains("- cell2\n"));
+}
+
+#[test]
+fn cache_inspect_reports_header_crc_mismatch() {
+    let tmp = TempDir::new().unwrap();
+    write_small_10x(tmp.path());
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("cache").arg("build").arg("--input").arg(tmp.path());
+    cmd.assert().success();
+
+    let path = tmp.path().join("kira-organelle.bin");
+    let mut bytes = fs::read(&path).unwrap();
+    bytes[120] ^= 0xff;
+    fs::write(&path, bytes).unwrap();
+
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("cache").arg("inspect").arg("--input").arg(&path);
+    cmd.assert()
+        .failure()
+        .stdout(contains("mismatch"))
+        .stdout(contains("validation: failed"));
+}
+
+#[test]
+fn cache_inspect_verifies_expr_cache_payload() {
+    let tmp = TempDir::new().unwrap();
+    write_small_10x(tmp.path());
+    // validate builds its expr cache in the working directory.
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.current_dir(tmp.path())
+        .arg("validate")
+        .arg("--input")
+        .arg(tmp.path());
+    cmd.assert().success();
+
+    let path = tmp.path().join("expr.bin");
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("cache").arg("inspect").arg("--input").arg(&path);
+    cmd.assert()
+        .success()
+        .stdout(contains("genes: 3"))
+        .stdout(contains("nnz: 4"))
+        .stdout(contains(" ok\n"))
+        .stdout(contains("validation: ok"));
+
+    let mut bytes = fs::read(&path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    fs::write(&path, bytes).unwrap();
+
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("cache").arg("inspect").arg("--input").arg(&path);
+    cmd.assert()
+        .failure()
+        .stdout(contains("data_crc64"))
+        .stdout(contains("mismatch"))
+        .stdout(contains("validation: failed"));
+}
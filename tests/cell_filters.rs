@@ -0,0 +1,317 @@
+use std::fs;
+use std::path::Path;
+
+use assert_cmd::Command;
+use kira_proteoqc::ctx::{Ctx, RunMode};
+use kira_proteoqc::expr::filter::{ExclusionReason, FilterOptions};
+use kira_proteoqc::pipeline::Pipeline;
+use kira_proteoqc::pipeline::stage1_input::Stage1Input;
+use kira_proteoqc::pipeline::stage2_h5ad::Stage2H5ad;
+use kira_proteoqc::pipeline::stage2b_metadata::Stage2bMetadata;
+use kira_proteoqc::pipeline::stage3_expr_cache::Stage3ExprCache;
+use kira_proteoqc::schema::v1::Mode;
+use predicates::str::contains;
+use serde_json::Value;
+use tempfile::TempDir;
+
+// 4 genes x 5 cells. Per-cell totals: C1 6, C2 7, C3 1, C4 9, C5 4.
+// MT-CO1 (row 3) holds C4's 5 counts.
+fn write_10x(dir: &Path) {
+    fs::write(
+        dir.join("matrix.mtx"),
+        "%%MatrixMarket matrix coordinate integer general\n4 5 9\n\
+         1 1 5\n2 1 1\n2 2 7\n4 3 1\n1 4 2\n3 4 5\n4 4 2\n1 5 3\n2 5 1\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("features.tsv"),
+        "g1\tGeneA\ng2\tGeneB\ng3\tMT-CO1\ng4\tGeneD\n",
+    )
+    .unwrap();
+    fs::write(dir.join("barcodes.tsv"), "C1\nC2\nC3\nC4\nC5\n").unwrap();
+}
+
+fn load(input: &Path, out: &Path, filters: FilterOptions) -> Ctx {
+    let mut ctx = Ctx::new(
+        input.to_path_buf(),
+        out.to_path_buf(),
+        Mode::Cell,
+        false,
+        None,
+        true,
+        false,
+        false,
+        "0.0.0-test",
+    );
+    ctx.filters = filters;
+    Pipeline::new(vec![
+        Box::new(Stage1Input::new()),
+        Box::new(Stage2H5ad::new()),
+        Box::new(Stage2bMetadata::new()),
+        Box::new(Stage3ExprCache::new()),
+    ])
+    .run(&mut ctx)
+    .unwrap();
+    ctx
+}
+
+#[test]
+fn filters_narrow_cells_genes_and_expr_cache() {
+    let tmp = TempDir::new().unwrap();
+    write_10x(tmp.path());
+    fs::write(tmp.path().join("drop.txt"), "C2\n").unwrap();
+
+    let ctx = load(
+        tmp.path(),
+        &tmp.path().join("out"),
+        FilterOptions {
+            exclude_cells: Some(tmp.path().join("drop.txt")),
+            min_umis: Some(2.0),
+            min_genes: Some(2),
+            exclude_genes: Some("^MT-".to_string()),
+            ..FilterOptions::default()
+        },
+    );
+
+    // C4 keeps 2 genes once MT-CO1 is gone; C3 has 1 count.
+    assert_eq!(ctx.genes, vec!["GeneA", "GeneB", "GeneD"]);
+    assert_eq!(ctx.gene_ids, vec!["g1", "g2", "g4"]);
+    assert_eq!(ctx.cells, vec!["C1", "C4", "C5"]);
+    assert_eq!(ctx.gene_index.get("GeneD"), Some(&2));
+    assert_eq!(ctx.nnz, 6);
+    assert_eq!(ctx.cell_qc.libsize, vec![6.0, 4.0, 4.0]);
+
+    let summary = ctx.filter_summary.as_ref().unwrap();
+    assert_eq!((summary.cells_input, summary.cells_kept), (5, 3));
+    assert_eq!((summary.genes_input, summary.genes_kept), (4, 3));
+    assert_eq!(summary.removed_by_exclude_cells, 1);
+    assert_eq!(summary.removed_by_min_umis, 1);
+    assert_eq!(summary.removed_by_min_genes, 0);
+    assert_eq!(summary.genes_removed_by_exclude_genes, 1);
+
+    let reasons = ctx
+        .excluded_cells
+        .iter()
+        .map(|c| (c.barcode.as_str(), c.reason))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reasons,
+        vec![
+            ("C2", ExclusionReason::ExcludeCells),
+            ("C3", ExclusionReason::MinUmis)
+        ]
+    );
+
+    let reader = ctx.expr_reader().unwrap();
+    assert_eq!(reader.n_genes(), 3);
+    assert_eq!(reader.n_cells(), 3);
+    let gene_d = reader.gene_entries(2).unwrap().collect::<Vec<_>>();
+    assert_eq!(gene_d, vec![(1, 2.0)]);
+    assert!(ctx.expr_path.ends_with("expr.filtered.bin"));
+}
+
+#[test]
+fn filters_subset_the_shared_cache_in_pipeline_mode() {
+    let tmp = TempDir::new().unwrap();
+    write_10x(tmp.path());
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("cache").arg("build").arg("--input").arg(tmp.path());
+    cmd.assert().success();
+
+    let mut ctx = Ctx::new(
+        tmp.path().to_path_buf(),
+        tmp.path().join("out"),
+        Mode::Cell,
+        false,
+        None,
+        true,
+        false,
+        false,
+        "0.0.0-test",
+    );
+    ctx.run_mode = RunMode::Pipeline;
+    ctx.filters = FilterOptions {
+        min_umis: Some(2.0),
+        exclude_genes: Some("^MT-".to_string()),
+        ..FilterOptions::default()
+    };
+    Pipeline::new(vec![
+        Box::new(Stage1Input::new()),
+        Box::new(Stage2H5ad::new()),
+        Box::new(Stage3ExprCache::new()),
+    ])
+    .run(&mut ctx)
+    .unwrap();
+
+    assert!(ctx.shared_cache_used);
+    assert!(ctx.shared_cache.is_none());
+    assert_eq!(ctx.cells, vec!["C1", "C2", "C4", "C5"]);
+    let reader = ctx.expr_reader().unwrap();
+    assert!(!reader.is_cell_major());
+    let gene_a = reader.gene_entries(0).unwrap().collect::<Vec<_>>();
+    assert_eq!(gene_a, vec![(0, 5.0), (2, 2.0), (3, 3.0)]);
+    let gene_d = reader.gene_entries(2).unwrap().collect::<Vec<_>>();
+    assert_eq!(gene_d, vec![(2, 2.0)]);
+}
+
+#[test]
+fn cell_list_keeps_listed_barcodes_and_reports_unknown_ones() {
+    let tmp = TempDir::new().unwrap();
+    write_10x(tmp.path());
+    fs::write(
+        tmp.path().join("keep.tsv"),
+        "# QC-passing cells\nC5\tTcell\nC1\tTcell\nC9\tTcell\n",
+    )
+    .unwrap();
+
+    let ctx = load(
+        tmp.path(),
+        &tmp.path().join("out"),
+        FilterOptions {
+            cells: Some(tmp.path().join("keep.tsv")),
+            ..FilterOptions::default()
+        },
+    );
+    assert_eq!(ctx.cells, vec!["C1", "C5"]);
+    assert_eq!(ctx.genes.len(), 4);
+    assert_eq!(ctx.filter_summary.as_ref().unwrap().removed_by_cells, 3);
+    assert!(
+        ctx.warnings
+            .iter()
+            .any(|w| w.starts_with("1 barcodes from --cells"))
+    );
+}
+
+#[test]
+fn exclude_genes_accepts_a_gene_list_file() {
+    let tmp = TempDir::new().unwrap();
+    write_10x(tmp.path());
+    fs::write(tmp.path().join("genes.txt"), "GeneA\ng4\n").unwrap();
+
+    let ctx = load(
+        tmp.path(),
+        &tmp.path().join("out"),
+        FilterOptions {
+            exclude_genes: Some(tmp.path().join("genes.txt").display().to_string()),
+            ..FilterOptions::default()
+        },
+    );
+    assert_eq!(ctx.genes, vec!["GeneB", "MT-CO1"]);
+    assert_eq!(ctx.cells.len(), 5);
+}
+
+#[test]
+fn run_reports_filters_and_excluded_cells_in_pipeline_outputs() {
+    let tmp = TempDir::new().unwrap();
+    let out = TempDir::new().unwrap();
+    write_10x(tmp.path());
+
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.args([
+        "run",
+        "--input",
+        tmp.path().to_str().unwrap(),
+        "--out",
+        out.path().to_str().unwrap(),
+        "--mode",
+        "cell",
+        "--run-mode",
+        "pipeline",
+        "--min-umis",
+        "5",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(contains("Filters: kept 3/5 cells"))
+        .stdout(contains("--min-umis -2"));
+
+    let dir = out.path().join("kira-proteoqc");
+    let summary: Value =
+        serde_json::from_slice(&fs::read(dir.join("summary.json")).unwrap()).unwrap();
+    assert_eq!(summary["input"]["n_cells"], 3);
+    assert_eq!(summary["input"]["filters"]["removed_by_min_umis"], 2);
+
+    let tsv = fs::read_to_string(dir.join("proteoqc.tsv")).unwrap();
+    let rows = tsv.lines().skip(1).collect::<Vec<_>>();
+    assert_eq!(rows.len(), 5);
+    let width = tsv.lines().next().unwrap().split('\t').count();
+    let excluded = rows
+        .iter()
+        .map(|r| r.split('\t').collect::<Vec<_>>())
+        .filter(|f| f[13] == "Excluded")
+        .collect::<Vec<_>>();
+    assert_eq!(excluded.len(), 2);
+    for fields in &excluded {
+        assert_eq!(fields.len(), width);
+        assert_eq!(fields[14], "EXCLUDED_MIN_UMIS");
+        assert_eq!(fields[7], "NaN");
+    }
+    assert_eq!(excluded[0][0], "C3");
+    assert_eq!(excluded[0][4], "1");
+    assert_eq!(excluded[1][0], "C5");
+}
+
+#[test]
+fn filters_removing_every_cell_fail() {
+    let tmp = TempDir::new().unwrap();
+    write_10x(tmp.path());
+
+    // validate builds its expr cache in the working directory.
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.current_dir(tmp.path()).args([
+        "validate",
+        "--input",
+        tmp.path().to_str().unwrap(),
+        "--min-genes",
+        "10",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("cell filters removed every cell"));
+}
+
+#[test]
+fn excluded_rows_keep_per_barcode_metadata() {
+    let tmp = TempDir::new().unwrap();
+    let out = TempDir::new().unwrap();
+    write_10x(tmp.path());
+    let meta = tmp.path().join("cells.tsv");
+    fs::write(
+        &meta,
+        "barcode\tsample\tcondition\nC1\tdonor1\tctrl\nC3\tdonor2\ttreated\nC5\tdonor3\tctrl\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.args([
+        "run",
+        "--input",
+        tmp.path().to_str().unwrap(),
+        "--out",
+        out.path().to_str().unwrap(),
+        "--mode",
+        "cell",
+        "--run-mode",
+        "pipeline",
+        "--min-umis",
+        "5",
+        "--sample-id",
+        "run",
+        "--metadata",
+        meta.to_str().unwrap(),
+    ]);
+    cmd.assert().success();
+
+    let tsv = fs::read_to_string(out.path().join("kira-proteoqc").join("proteoqc.tsv")).unwrap();
+    let rows = tsv
+        .lines()
+        .skip(1)
+        .map(|r| r.split('\t').collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let row = |barcode: &str| rows.iter().find(|f| f[0] == barcode).unwrap();
+    assert_eq!(row("C3")[13], "Excluded");
+    assert_eq!(&row("C3")[1..3], ["donor2", "treated"]);
+    assert_eq!(&row("C5")[1..3], ["donor3", "ctrl"]);
+    assert_eq!(&row("C1")[1..3], ["donor1", "ctrl"]);
+    assert_eq!(row("C2")[1], "run");
+}
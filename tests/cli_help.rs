@@ -1,4 +1,5 @@
 use assert_cmd::Command;
+use kira_proteoqc::cli::parse_byte_size;
 
 #[test]
 fn cli_help_smoke() {
@@ -6,3 +7,13 @@ fn cli_help_smoke() {
     cmd.arg("--help");
     cmd.assert().success();
 }
+
+#[test]
+fn byte_sizes_parse_with_binary_suffixes() {
+    assert_eq!(parse_byte_size("4096"), Ok(4096));
+    assert_eq!(parse_byte_size("512M"), Ok(512 << 20));
+    assert_eq!(parse_byte_size("8g"), Ok(8 << 30));
+    assert_eq!(parse_byte_size("2GB"), Ok(2 << 30));
+    assert!(parse_byte_size("0").is_err());
+    assert!(parse_byte_size("lots").is_err());
+}
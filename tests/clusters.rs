@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use assert_cmd::Command;
+use kira_proteoqc::io::clusters::{
+    ClusterSource, UNASSIGNED_CLUSTER, assign_clusters, read_cluster_tsv,
+};
+use serde_json::Value;
+use tempfile::TempDir;
+
+#[test]
+fn cluster_source_parses_file_and_obs() {
+    assert_eq!(
+        ClusterSource::parse("labels.tsv").unwrap(),
+        ClusterSource::File(PathBuf::from("labels.tsv"))
+    );
+    assert_eq!(
+        ClusterSource::parse("obs:leiden").unwrap(),
+        ClusterSource::Obs("leiden".to_string())
+    );
+    assert!(ClusterSource::parse("obs:").is_err());
+}
+
+#[test]
+fn cluster_tsv_skips_header_and_assigns_labels() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("clusters.tsv");
+    fs::write(&path, "barcode\tcell_type\nC1\tT\nC3\tB\nX9\tB\n").unwrap();
+    let pairs = read_cluster_tsv(&path).unwrap();
+    assert_eq!(pairs.len(), 3);
+
+    let cells = vec!["C1".to_string(), "C2".to_string(), "C3".to_string()];
+    let assignment = assign_clusters(&cells, &pairs).unwrap();
+    assert_eq!(assignment.labels, vec!["T", UNASSIGNED_CLUSTER, "B"]);
+    assert_eq!(assignment.unassigned, 1);
+    assert_eq!(assignment.unknown_barcodes, 1);
+}
+
+#[test]
+fn conflicting_or_disjoint_annotations_are_rejected() {
+    let cells = vec!["C1".to_string()];
+    let conflicting = vec![
+        ("C1".to_string(), "T".to_string()),
+        ("C1".to_string(), "B".to_string()),
+    ];
+    assert!(assign_clusters(&cells, &conflicting).is_err());
+    let disjoint = vec![("Z1".to_string(), "T".to_string())];
+    assert!(assign_clusters(&cells, &disjoint).is_err());
+}
+
+#[test]
+fn pipeline_reports_per_cluster_stats() {
+    let input = TempDir::new().unwrap();
+    let out = TempDir::new().unwrap();
+    write_10x(input.path());
+    let clusters = input.path().join("clusters.tsv");
+    fs::write(&clusters, "C1\tT\nC2\tB\nC3\tT\n").unwrap();
+    run_pipeline(input.path(), out.path(), &clusters);
+
+    let summary = out.path().join("kira-proteoqc").join("summary.json");
+    let v: Value = serde_json::from_slice(&fs::read(summary).unwrap()).unwrap();
+    let ext = &v["proteostasis_extension"];
+    let stats = ext["cluster_stats"].as_array().unwrap();
+    let names = stats
+        .iter()
+        .map(|c| c["cluster"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["B", "T"]);
+    assert_eq!(stats[1]["n_cells"], 2);
+
+    let top = ext["top_clusters_by_collapse_risk"].as_array().unwrap();
+    assert_eq!(top.len(), 2);
+    let medians = top
+        .iter()
+        .map(|c| c["collapse_risk_median"].as_f64().unwrap())
+        .collect::<Vec<_>>();
+    assert!(medians[0] >= medians[1]);
+
+    let tsv = fs::read_to_string(out.path().join("kira-proteoqc").join("proteoqc.tsv")).unwrap();
+    let column = tsv
+        .lines()
+        .next()
+        .unwrap()
+        .split('\t')
+        .position(|c| c == "cluster")
+        .unwrap();
+    let labels = tsv
+        .lines()
+        .skip(1)
+        .map(|l| l.split('\t').nth(column).unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec!["T", "B", "T"]);
+}
+
+fn run_pipeline(input: &Path, out: &Path, clusters: &Path) {
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.args([
+        "run",
+        "--input",
+        input.to_str().unwrap(),
+        "--out",
+        out.to_str().unwrap(),
+        "--mode",
+        "cell",
+        "--run-mode",
+        "pipeline",
+        "--clusters",
+        clusters.to_str().unwrap(),
+    ]);
+    cmd.assert().success();
+}
+
+fn write_10x(dir: &Path) {
+    fs::write(
+        dir.join("matrix.mtx"),
+        "%%MatrixMarket matrix coordinate integer general\n3 3 5\n1 1 5\n3 1 1\n2 2 7\n1 3 2\n2 3 3\n",
+    )
+    .unwrap();
+    fs::write(dir.join("features.tsv"), "g1\tG1\ng2\tG2\ng3\tG3\n").unwrap();
+    fs::write(dir.join("barcodes.tsv"), "C1\nC2\nC3\n").unwrap();
+}
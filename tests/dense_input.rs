@@ -0,0 +1,221 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use kira_proteoqc::ctx::{Ctx, InputFormat};
+use kira_proteoqc::expr::normalize::NormalizationMethod;
+use kira_proteoqc::io::dense::{self, DenseOrientation, looks_like_barcode};
+use kira_proteoqc::io::h5ad_layer::MatrixValues;
+use kira_proteoqc::pipeline::Pipeline;
+use kira_proteoqc::pipeline::stage1_input::Stage1Input;
+use kira_proteoqc::pipeline::stage2_h5ad::Stage2H5ad;
+use kira_proteoqc::pipeline::stage3_expr_cache::Stage3ExprCache;
+use kira_proteoqc::schema::v1::Mode;
+use tempfile::TempDir;
+
+fn load(path: &Path, out: &Path) -> Ctx {
+    let mut ctx = Ctx::new(
+        path.to_path_buf(),
+        out.to_path_buf(),
+        Mode::Cell,
+        false,
+        None,
+        true,
+        false,
+        false,
+        "0.0.0-test",
+    );
+    ctx.normalization_auto = true;
+    Pipeline::new(vec![
+        Box::new(Stage1Input::new()),
+        Box::new(Stage2H5ad::new()),
+        Box::new(Stage3ExprCache::new()),
+    ])
+    .run(&mut ctx)
+    .unwrap();
+    ctx
+}
+
+fn gene(ctx: &Ctx, g: usize) -> Vec<(u32, f32)> {
+    ctx.expr_reader()
+        .unwrap()
+        .gene_entries(g)
+        .unwrap()
+        .collect::<Vec<_>>()
+}
+
+#[test]
+fn detects_dense_and_loom_by_extension() {
+    for name in ["counts.csv", "counts.tsv", "counts.txt", "counts.CSV.gz"] {
+        assert_eq!(
+            InputFormat::detect(Path::new(name)),
+            InputFormat::DenseText,
+            "{}",
+            name
+        );
+    }
+    assert_eq!(
+        InputFormat::detect(Path::new("sample.loom")),
+        InputFormat::Loom
+    );
+    assert_eq!(
+        InputFormat::detect(Path::new("matrix.mtx.gz")),
+        InputFormat::Mtx10x
+    );
+}
+
+#[test]
+fn recognizes_sequencing_barcodes() {
+    assert!(looks_like_barcode("AAACCTGAGAAACCAT-1"));
+    assert!(looks_like_barcode("S1_AAACCTGAGAAACCAT.1"));
+    assert!(!looks_like_barcode("ACTB"));
+    assert!(!looks_like_barcode("cell_1"));
+}
+
+#[test]
+fn genes_by_cells_csv_from_write_csv() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("counts.csv");
+    fs::write(
+        &path,
+        "\"\",\"c1\",\"c2\",\"c3\"\n\"GeneA\",1,0,3\n\"GeneB\",0,0,2\n\"GeneC\",4,5,0\n",
+    )
+    .unwrap();
+
+    let ctx = load(&path, &tmp.path().join("out"));
+    assert_eq!(ctx.genes, vec!["GeneA", "GeneB", "GeneC"]);
+    assert_eq!(ctx.cells, vec!["c1", "c2", "c3"]);
+    assert_eq!(ctx.nnz, 5);
+    assert_eq!(ctx.matrix_values, Some(MatrixValues::Counts));
+    assert_eq!(ctx.normalization, NormalizationMethod::Cp10k);
+    assert_eq!(gene(&ctx, 0), vec![(0, 1.0), (2, 3.0)]);
+    assert_eq!(gene(&ctx, 1), vec![(2, 2.0)]);
+    assert_eq!(gene(&ctx, 2), vec![(0, 4.0), (1, 5.0)]);
+}
+
+#[test]
+fn cells_by_genes_gzipped_tsv_without_corner() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("counts.tsv.gz");
+    let text = "GeneA\tGeneB\n\
+                AAACCTGAGAAACCAT-1\t0.5\t0\n\
+                AAACCTGAGAAACGAG-1\t1.25\t2.5\n";
+    let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+    gz.write_all(text.as_bytes()).unwrap();
+    fs::write(&path, gz.finish().unwrap()).unwrap();
+
+    let summary = dense::read_dense_summary(&path).unwrap();
+    assert_eq!(summary.layout.delimiter, '\t');
+    assert_eq!(summary.layout.orientation, DenseOrientation::CellsByGenes);
+
+    let ctx = load(&path, &tmp.path().join("out"));
+    assert_eq!(ctx.genes, vec!["GeneA", "GeneB"]);
+    assert_eq!(ctx.cells, vec!["AAACCTGAGAAACCAT-1", "AAACCTGAGAAACGAG-1"]);
+    assert_eq!(ctx.matrix_values, Some(MatrixValues::Normalized));
+    assert_eq!(ctx.normalization, NormalizationMethod::Prenormalized);
+    assert_eq!(gene(&ctx, 0), vec![(0, 0.5), (1, 1.25)]);
+    assert_eq!(gene(&ctx, 1), vec![(1, 2.5)]);
+}
+
+#[test]
+fn corner_cell_names_the_orientation() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("counts.csv");
+    fs::write(&path, "cell,GeneA,GeneB\nc1,1,0\nc2,0,3\n").unwrap();
+
+    let summary = dense::read_dense_summary(&path).unwrap();
+    assert_eq!(summary.layout.delimiter, ',');
+    assert_eq!(summary.layout.orientation, DenseOrientation::CellsByGenes);
+    assert_eq!(summary.genes, vec!["GeneA", "GeneB"]);
+    assert_eq!(summary.cells, vec!["c1", "c2"]);
+    assert_eq!(summary.nnz, 2);
+}
+
+#[test]
+fn duplicate_genes_keep_first_occurrence() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("counts.tsv");
+    fs::write(
+        &path,
+        "gene\tc1\tc2\nGeneA\t1\t0\nGeneB\t2\t2\nGeneA\t0\t7\n",
+    )
+    .unwrap();
+
+    let ctx = load(&path, &tmp.path().join("out"));
+    assert_eq!(ctx.genes.len(), 3);
+    assert_eq!(ctx.gene_index.get("GeneA"), Some(&0));
+    assert!(
+        ctx.warnings
+            .iter()
+            .any(|w| w.contains("GeneA(x2, first_row=1)"))
+    );
+}
+
+#[test]
+fn ragged_lines_are_rejected() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("counts.csv");
+    fs::write(&path, ",c1,c2\nGeneA,1,0\nGeneB,2\n").unwrap();
+
+    let err = dense::read_dense_summary(&path).unwrap_err();
+    assert!(err.to_string().contains("line 3 has 1 values"));
+}
+
+#[cfg(feature = "hdf5")]
+mod loom_input {
+    use std::path::Path;
+
+    use hdf5::File;
+    use hdf5::types::VarLenUnicode;
+    use tempfile::TempDir;
+
+    use super::{gene, load};
+
+    fn write_strings(group: &hdf5::Group, name: &str, values: &[&str]) {
+        let values = values
+            .iter()
+            .map(|v| v.parse::<VarLenUnicode>().unwrap())
+            .collect::<Vec<_>>();
+        group
+            .new_dataset::<VarLenUnicode>()
+            .shape(values.len())
+            .create(name)
+            .unwrap()
+            .write(&values)
+            .unwrap();
+    }
+
+    // 3 genes x 2 cells, with a duplicated gene symbol.
+    fn write_loom(path: &Path) {
+        let file = File::create(path).unwrap();
+        file.new_dataset::<f32>()
+            .shape((3, 2))
+            .create("matrix")
+            .unwrap()
+            .write_raw(&[1.0f32, 0.0, 0.0, 2.0, 3.0, 4.0])
+            .unwrap();
+        let rows = file.create_group("row_attrs").unwrap();
+        write_strings(&rows, "Gene", &["GeneA", "GeneB", "GeneA"]);
+        write_strings(&rows, "Accession", &["ENSG1", "ENSG2", "ENSG3"]);
+        let cols = file.create_group("col_attrs").unwrap();
+        write_strings(&cols, "CellID", &["c1", "c2"]);
+    }
+
+    #[test]
+    fn loom_matrix_loads_into_expr_cache() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("sample.loom");
+        write_loom(&path);
+
+        let ctx = load(&path, &tmp.path().join("out"));
+        assert_eq!(ctx.genes, vec!["GeneA", "GeneB", "GeneA"]);
+        assert_eq!(ctx.gene_ids, vec!["ENSG1", "ENSG2", "ENSG3"]);
+        assert_eq!(ctx.cells, vec!["c1", "c2"]);
+        assert_eq!(ctx.nnz, 4);
+        assert!(ctx.warnings.iter().any(|w| w.contains("GeneA(x2")));
+        assert_eq!(gene(&ctx, 1), vec![(1, 2.0)]);
+        assert_eq!(gene(&ctx, 2), vec![(0, 3.0), (1, 4.0)]);
+    }
+}
@@ -0,0 +1,239 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use assert_cmd::Command;
+use kira_proteoqc::expr::layout::{ExprHeaderV1, LAYOUT_CSC, VERSION, write_header};
+use kira_proteoqc::expr::reader::{ExprReader, open_mmap};
+use kira_proteoqc::math::reduce::GeneSetReducer;
+use kira_proteoqc::math::sum::{self, compensated_sum};
+use kira_proteoqc::simd::{self, Backend};
+use tempfile::TempDir;
+
+const N_GENES: usize = 6;
+const N_CELLS: usize = 37;
+
+// Mixed magnitudes, so a different summation order changes the low bits.
+fn value(gene: usize, cell: usize) -> f32 {
+    let k = (gene * 7919 + cell * 104729) % 1000;
+    if k.is_multiple_of(11) {
+        10_000.0 + k as f32 * 0.37
+    } else {
+        k as f32 * 0.0013
+    }
+}
+
+fn write_expr(path: &Path) {
+    let mut gene_ptr = vec![0u64];
+    let mut cell_idx = Vec::new();
+    let mut values = Vec::new();
+    for g in 0..N_GENES {
+        for c in (0..N_CELLS).filter(|c| (c + g) % 4 != 0) {
+            cell_idx.push(c as u32);
+            values.push(value(g, c));
+        }
+        gene_ptr.push(cell_idx.len() as u64);
+    }
+    let header = ExprHeaderV1 {
+        version: VERSION,
+        n_genes: N_GENES as u32,
+        n_cells: N_CELLS as u32,
+        nnz: values.len() as u64,
+        layout: LAYOUT_CSC,
+    };
+
+    let file = File::create(path).unwrap();
+    let mut w = BufWriter::new(file);
+    write_header(&mut w, &header).unwrap();
+    for v in &gene_ptr {
+        w.write_all(&v.to_le_bytes()).unwrap();
+    }
+    for v in &cell_idx {
+        w.write_all(&v.to_le_bytes()).unwrap();
+    }
+    for v in &values {
+        w.write_all(&v.to_le_bytes()).unwrap();
+    }
+    w.flush().unwrap();
+}
+
+fn bits(values: &[f32]) -> Vec<u32> {
+    values.iter().map(|v| v.to_bits()).collect()
+}
+
+#[test]
+fn compensated_sum_keeps_cancelled_terms() {
+    let values = [1.0e8f32, 1.0, -1.0e8, 0.5];
+    assert_eq!(compensated_sum(&values), 1.5);
+    assert_eq!(compensated_sum(&[]), 0.0);
+}
+
+// The process-wide SIMD backend is switched here, so this stays one test.
+#[test]
+fn reducers_match_bit_for_bit_across_configurations() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("expr.bin");
+    write_expr(&path);
+    let (header, mmap) = open_mmap(&path).unwrap();
+    let reader = ExprReader::new(&header, &mmap);
+    sum::set_deterministic(true);
+
+    let backends = Backend::ALL
+        .into_iter()
+        .filter(|b| b.is_supported())
+        .collect::<Vec<_>>();
+    let mut expected: Option<(Vec<u32>, u32)> = None;
+    for &backend in &backends {
+        simd::set_backend(backend).unwrap();
+        for threads in [1, 3] {
+            for cache_block in [0, 5] {
+                // Geneset order must not matter either.
+                for genes in [[4usize, 0, 2, 5], [5, 2, 0, 4]] {
+                    let mut reducer = GeneSetReducer::new(&reader, threads, cache_block, false);
+                    let mut out = vec![0.0f32; N_CELLS];
+                    reducer.per_cell_raw(&genes, &mut out).unwrap();
+                    let sample = reducer.per_sample_raw(&genes).unwrap();
+
+                    let got = (bits(&out), sample.to_bits());
+                    match &expected {
+                        None => expected = Some(got),
+                        Some(e) => assert_eq!(
+                            &got,
+                            e,
+                            "backend={} threads={} cache_block={} genes={:?}",
+                            backend.as_str(),
+                            threads,
+                            cache_block,
+                            genes
+                        ),
+                    }
+                }
+            }
+        }
+    }
+    simd::set_backend(simd::detect()).unwrap();
+}
+
+#[cfg(feature = "fusion")]
+#[test]
+fn fused_reduce_matches_reducer_in_deterministic_mode() {
+    use kira_proteoqc::fusion;
+    use kira_proteoqc::geneset::ResolvedGeneset;
+
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("expr.bin");
+    write_expr(&path);
+    let (header, mmap) = open_mmap(&path).unwrap();
+    let reader = ExprReader::new(&header, &mmap);
+    sum::set_deterministic(true);
+
+    let resolved = vec![
+        ResolvedGeneset {
+            id: "a".into(),
+            axis: 'A',
+            gene_ids: vec![3, 1, 5],
+            missing: vec![],
+            total: 3,
+            matches: Vec::new(),
+        },
+        ResolvedGeneset {
+            id: "b".into(),
+            axis: 'B',
+            gene_ids: vec![0, 1, 2, 3, 4, 5],
+            missing: vec![],
+            total: 6,
+            matches: Vec::new(),
+        },
+    ];
+    let plan = fusion::build_plan(N_GENES, N_CELLS, &resolved, &["a", "b"]);
+    let mut fused = vec![0.0f32; 2 * N_CELLS];
+    fusion::fused_reduce(&reader, &plan, &mut fused).unwrap();
+
+    let mut reducer = GeneSetReducer::new(&reader, 1, 0, false);
+    for (t, gs) in resolved.iter().enumerate() {
+        let denom = plan.gene_counts[t] as f32;
+        let from_fused = fused[t * N_CELLS..(t + 1) * N_CELLS]
+            .iter()
+            .map(|v| v / denom)
+            .collect::<Vec<_>>();
+        let mut out = vec![0.0f32; N_CELLS];
+        reducer.per_cell_raw(&gs.gene_ids, &mut out).unwrap();
+        assert_eq!(bits(&out), bits(&from_fused), "{}", gs.id);
+    }
+}
+
+fn write_10x(dir: &Path) {
+    let genes = [
+        "PSMA1", "PSMA2", "PSMA3", "PSMC1", "PSMB8", "UBB", "UBE3A", "USP7", "HSPA1A", "HSPA5",
+        "HSP90AA1", "DNAJB1", "ATF4", "XBP1", "DERL1", "VCP", "RPLP0",
+    ];
+    let n_cells = 40;
+    let mut entries = Vec::new();
+    for g in 0..genes.len() {
+        for c in 0..n_cells {
+            let count = (g * 31 + c * 17) % 23;
+            if count > 3 {
+                entries.push(format!("{} {} {}", g + 1, c + 1, count));
+            }
+        }
+    }
+    fs::write(
+        dir.join("matrix.mtx"),
+        format!(
+            "%%MatrixMarket matrix coordinate integer general\n{} {} {}\n{}\n",
+            genes.len(),
+            n_cells,
+            entries.len(),
+            entries.join("\n")
+        ),
+    )
+    .unwrap();
+    let features = genes
+        .iter()
+        .enumerate()
+        .map(|(i, g)| format!("ENSG{:05}\t{}\n", i, g))
+        .collect::<String>();
+    fs::write(dir.join("features.tsv"), features).unwrap();
+    let barcodes = (0..n_cells)
+        .map(|c| format!("C{}\n", c))
+        .collect::<String>();
+    fs::write(dir.join("barcodes.tsv"), barcodes).unwrap();
+}
+
+fn run_tsv(input: &Path, extra: &[&str]) -> String {
+    let out = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.args([
+        "run",
+        "--input",
+        input.to_str().unwrap(),
+        "--out",
+        out.path().to_str().unwrap(),
+        "--mode",
+        "cell",
+        "--run-mode",
+        "pipeline",
+        "--deterministic",
+    ])
+    .args(extra);
+    cmd.assert().success();
+    fs::read_to_string(out.path().join("kira-proteoqc").join("proteoqc.tsv")).unwrap()
+}
+
+#[test]
+fn deterministic_runs_agree_across_cli_configurations() {
+    let tmp = TempDir::new().unwrap();
+    write_10x(tmp.path());
+
+    let reference = run_tsv(
+        tmp.path(),
+        &["--simd", "scalar", "--threads", "1", "--cache-block", "0"],
+    );
+    for extra in [
+        &["--threads", "3", "--cache-block", "7"][..],
+        &["--fusion", "off"][..],
+        &["--fusion", "off", "--threads", "3", "--cache-block", "7"][..],
+    ] {
+        assert_eq!(run_tsv(tmp.path(), extra), reference, "{:?}", extra);
+    }
+}
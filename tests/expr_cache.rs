@@ -2,7 +2,12 @@ use std::fs;
 use std::path::Path;
 
 use kira_proteoqc::ctx::Ctx;
+use kira_proteoqc::expr::layout::{
+    ENCODING_INDEX_VARINT, ENCODING_PLAIN, ENCODING_VALUES_U16, ExprEncoding, ExprHeaderV1,
+    LAYOUT_CSC, VERSION, VERSION_V2, write_header,
+};
 use kira_proteoqc::expr::reader;
+use kira_proteoqc::math::reduce::GeneSetReducer;
 use kira_proteoqc::pipeline::Pipeline;
 use kira_proteoqc::pipeline::stage1_input::Stage1Input;
 use kira_proteoqc::pipeline::stage3_expr_cache::Stage3ExprCache;
@@ -61,6 +66,367 @@ fn mtx_expr_cache_roundtrip() {
     assert_eq!(values, &[1.0, 2.0, 4.0, 3.0]);
 }
 
+fn build_expr(input: &Path, out: &Path, max_memory: Option<u64>) -> Vec<u8> {
+    let mut ctx = Ctx::new(
+        input.to_path_buf(),
+        out.to_path_buf(),
+        Mode::Cell,
+        false,
+        None,
+        true,
+        false,
+        false,
+        "0.0.0-test",
+    );
+    ctx.max_memory = max_memory;
+    let pipeline = Pipeline::new(vec![
+        Box::new(Stage1Input::new()),
+        Box::new(Stage3ExprCache::new()),
+    ]);
+    pipeline.run(&mut ctx).unwrap();
+    fs::read(&ctx.expr_path).unwrap()
+}
+
+#[test]
+fn mtx_memory_budget_gives_identical_expr_cache() {
+    let tmp = TempDir::new().unwrap();
+    // Unsorted entries, an explicit zero and a duplicate coordinate.
+    let mtx = "%%MatrixMarket matrix coordinate real general\n%comment\n4 3 9\n\
+               2 3 1.5\n1 2 2\n4 1 7\n1 1 1\n3 2 0\n2 1 3\n4 3 8\n2 3 4.25\n1 3 5\n";
+    let features = "g1\tG1\ng2\tG2\ng3\tG3\ng4\tG4\n";
+    let barcodes = "c1\nc2\nc3\n";
+    write_10x(tmp.path(), features, barcodes, mtx);
+
+    let unbounded = build_expr(tmp.path(), &tmp.path().join("a"), None);
+    for budget in [1, 16, 24, 40] {
+        let chunked = build_expr(
+            tmp.path(),
+            &tmp.path().join(format!("b{budget}")),
+            Some(budget),
+        );
+        assert_eq!(unbounded, chunked, "budget {budget}");
+    }
+
+    let (header, mmap) = reader::open_mmap(&tmp.path().join("a").join("expr.bin")).unwrap();
+    assert_eq!(header.nnz, 8);
+    let gene_ptr = reader::gene_ptr_slice(&mmap, &header);
+    let cell_idx = reader::cell_idx_slice(&mmap, &header);
+    let values = reader::values_slice(&mmap, &header);
+    assert_eq!(gene_ptr, &[0, 3, 6, 6, 8]);
+    assert_eq!(cell_idx, &[0, 1, 2, 0, 2, 2, 0, 2]);
+    assert_eq!(values, &[1.0, 2.0, 5.0, 3.0, 1.5, 4.25, 7.0, 8.0]);
+}
+
+#[test]
+fn mtx_spilled_build_reuses_stage1_counts_and_removes_runs() {
+    let tmp = TempDir::new().unwrap();
+    let mtx = "%%MatrixMarket matrix coordinate real general\n4 3 9\n\
+               2 3 1.5\n1 2 2\n4 1 7\n1 1 1\n3 2 0\n2 1 3\n4 3 8\n2 3 4.25\n1 3 5\n";
+    write_10x(
+        tmp.path(),
+        "g1\tG1\ng2\tG2\ng3\tG3\ng4\tG4\n",
+        "c1\nc2\nc3\n",
+        mtx,
+    );
+    let out = tmp.path().join("out");
+    let mut ctx = Ctx::new(
+        tmp.path().to_path_buf(),
+        out.clone(),
+        Mode::Cell,
+        false,
+        None,
+        true,
+        false,
+        false,
+        "0.0.0-test",
+    );
+    // 24 bytes holds one spilled entry plus its sort scratch, so every entry
+    // becomes its own run.
+    ctx.max_memory = Some(24);
+    Pipeline::new(vec![Box::new(Stage1Input::new())])
+        .run(&mut ctx)
+        .unwrap();
+    assert_eq!(ctx.mtx_gene_counts.as_deref(), Some(&[3, 3, 0, 2][..]));
+
+    Pipeline::new(vec![Box::new(Stage3ExprCache::new())])
+        .run(&mut ctx)
+        .unwrap();
+    let (header, mmap) = reader::open_mmap(&ctx.expr_path).unwrap();
+    assert_eq!(reader::gene_ptr_slice(&mmap, &header), &[0, 3, 6, 6, 8]);
+    assert_eq!(
+        reader::values_slice(&mmap, &header),
+        &[1.0, 2.0, 5.0, 3.0, 1.5, 4.25, 7.0, 8.0]
+    );
+    let leftovers = fs::read_dir(&out)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.contains(".run"))
+        .collect::<Vec<_>>();
+    assert!(leftovers.is_empty(), "{leftovers:?}");
+}
+
+fn run_cache(input: &Path, out: &Path, cache_dir: Option<&Path>) -> Ctx {
+    let mut ctx = Ctx::new(
+        input.to_path_buf(),
+        out.to_path_buf(),
+        Mode::Cell,
+        false,
+        None,
+        true,
+        false,
+        false,
+        "0.0.0-test",
+    );
+    ctx.cache_dir = cache_dir.map(Path::to_path_buf);
+    let pipeline = Pipeline::new(vec![
+        Box::new(Stage1Input::new()),
+        Box::new(Stage3ExprCache::new()),
+    ]);
+    pipeline.run(&mut ctx).unwrap();
+    ctx
+}
+
+#[test]
+fn expr_cache_v2_records_provenance_and_detects_stale_inputs() {
+    let tmp = TempDir::new().unwrap();
+    let features = "g1\tG1\ng2\tG2\n";
+    let barcodes = "c1\nc2\n";
+    write_10x(
+        tmp.path(),
+        features,
+        barcodes,
+        "%%MatrixMarket matrix coordinate integer general\n2 2 2\n1 1 1\n2 2 2\n",
+    );
+    let out = tmp.path().join("out");
+
+    let ctx = run_cache(tmp.path(), &out, None);
+    let header = ctx.expr_header.unwrap();
+    assert_eq!(header.version, VERSION_V2);
+    assert_ne!(header.source_fingerprint, 0);
+    let mmap = ctx.expr_mmap.as_ref().unwrap();
+    assert_eq!(reader::data_crc64(mmap, &header), header.data_crc64);
+
+    // Same shape and nnz, different values: the cache must be rebuilt.
+    fs::write(
+        tmp.path().join("matrix.mtx"),
+        "%%MatrixMarket matrix coordinate integer general\n2 2 2\n1 1 17\n2 2 29\n",
+    )
+    .unwrap();
+    let ctx = run_cache(tmp.path(), &out, None);
+    assert_ne!(
+        ctx.expr_header.unwrap().source_fingerprint,
+        header.source_fingerprint
+    );
+    assert_eq!(
+        reader::values_slice(ctx.expr_mmap.as_ref().unwrap(), &ctx.expr_header.unwrap()),
+        &[17.0, 29.0]
+    );
+}
+
+#[test]
+fn v1_expr_cache_is_readable_and_replaced() {
+    let tmp = TempDir::new().unwrap();
+    write_10x(
+        tmp.path(),
+        "g1\tG1\n",
+        "c1\n",
+        "%%MatrixMarket matrix coordinate integer general\n1 1 1\n1 1 5\n",
+    );
+    let out = tmp.path().join("out");
+    fs::create_dir_all(&out).unwrap();
+    let v1 = ExprHeaderV1 {
+        version: VERSION,
+        n_genes: 1,
+        n_cells: 1,
+        nnz: 1,
+        layout: LAYOUT_CSC,
+    };
+    let mut bytes = Vec::new();
+    write_header(&mut bytes, &v1).unwrap();
+    bytes.extend_from_slice(&0u64.to_le_bytes());
+    bytes.extend_from_slice(&1u64.to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(&1.0f32.to_le_bytes());
+    fs::write(out.join("expr.bin"), &bytes).unwrap();
+
+    let (header, mmap) = reader::open_mmap(&out.join("expr.bin")).unwrap();
+    assert_eq!(header.version, VERSION);
+    assert_eq!(header.header_size(), 32);
+    assert_eq!(reader::values_slice(&mmap, &header), &[1.0]);
+    drop(mmap);
+
+    let ctx = run_cache(tmp.path(), &out, None);
+    let header = ctx.expr_header.unwrap();
+    assert_eq!(header.version, VERSION_V2);
+    assert_eq!(
+        reader::values_slice(ctx.expr_mmap.as_ref().unwrap(), &header),
+        &[5.0]
+    );
+}
+
+#[test]
+fn cache_dir_is_shared_across_output_dirs() {
+    let tmp = TempDir::new().unwrap();
+    write_10x(
+        tmp.path(),
+        "g1\tG1\ng2\tG2\n",
+        "c1\nc2\n",
+        "%%MatrixMarket matrix coordinate integer general\n2 2 1\n2 1 3\n",
+    );
+    let cache_dir = tmp.path().join("cache");
+
+    let first = run_cache(tmp.path(), &tmp.path().join("out1"), Some(&cache_dir));
+    let second = run_cache(tmp.path(), &tmp.path().join("out2"), Some(&cache_dir));
+    assert_eq!(first.expr_path, second.expr_path);
+    assert!(first.expr_path.starts_with(&cache_dir));
+    let name = first.expr_path.file_name().unwrap().to_str().unwrap();
+    assert_eq!(
+        name,
+        format!(
+            "expr-{:016x}.bin",
+            first.expr_header.unwrap().source_fingerprint
+        )
+    );
+    assert!(!tmp.path().join("out2").join("expr.bin").exists());
+    assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 1);
+}
+
+// Gene 1 spans several index blocks with wide cell gaps (multi-byte
+// varints); gene 2 holds counts at and beyond the u16 range.
+fn write_counts_10x(dir: &Path, gene3_values: [&str; 3]) {
+    let n_cells = 1000;
+    let mut entries = Vec::new();
+    for cell in (0..n_cells).step_by(3) {
+        entries.push(format!("1 {} {}", cell + 1, cell % 7 + 1));
+    }
+    entries.push("2 5 1".to_string());
+    entries.push("2 999 2".to_string());
+    for (cell, value) in [2, 400, 1000].iter().zip(gene3_values) {
+        entries.push(format!("3 {} {}", cell, value));
+    }
+    let mtx = format!(
+        "%%MatrixMarket matrix coordinate real general\n3 {} {}\n{}\n",
+        n_cells,
+        entries.len(),
+        entries.join("\n")
+    );
+    let barcodes = (0..n_cells).map(|c| format!("c{c}\n")).collect::<String>();
+    write_10x(dir, "g1\tG1\ng2\tG2\ng3\tG3\n", &barcodes, &mtx);
+}
+
+fn run_encoded(input: &Path, out: &Path, encoding: ExprEncoding) -> Ctx {
+    let mut ctx = Ctx::new(
+        input.to_path_buf(),
+        out.to_path_buf(),
+        Mode::Cell,
+        false,
+        None,
+        true,
+        false,
+        false,
+        "0.0.0-test",
+    );
+    ctx.expr_encoding = encoding;
+    let pipeline = Pipeline::new(vec![
+        Box::new(Stage1Input::new()),
+        Box::new(Stage3ExprCache::new()),
+    ]);
+    pipeline.run(&mut ctx).unwrap();
+    ctx
+}
+
+fn assert_same_entries(plain: &Ctx, compact: &Ctx) {
+    let plain = plain.expr_reader().unwrap();
+    let compact = compact.expr_reader().unwrap();
+    for gene in 0..plain.n_genes() {
+        let expected = plain.gene_entries(gene).unwrap().collect::<Vec<_>>();
+        assert_eq!(
+            compact.gene_entries(gene).unwrap().collect::<Vec<_>>(),
+            expected
+        );
+        let (cells, values) = compact.gene_slice(gene).unwrap();
+        assert_eq!(
+            cells
+                .iter()
+                .copied()
+                .zip(values.iter().copied())
+                .collect::<Vec<_>>(),
+            expected
+        );
+        for first_cell in [0, 1, 3, 128, 384, 385, 998, 999, 5000] {
+            let tail = expected
+                .iter()
+                .copied()
+                .filter(|&(cell, _)| cell >= first_cell)
+                .collect::<Vec<_>>();
+            let from = compact.gene_entries_from(gene, first_cell).unwrap();
+            assert_eq!(
+                from.collect::<Vec<_>>(),
+                tail,
+                "gene {gene} from {first_cell}"
+            );
+        }
+    }
+
+    let genes = [0, 1, 2];
+    let mut expected = vec![0.0f32; plain.n_cells()];
+    GeneSetReducer::new(&plain, 1, 0, false)
+        .per_cell_raw(&genes, &mut expected)
+        .unwrap();
+    for (threads, cache_block) in [(1, 0), (1, 64), (3, 0)] {
+        let mut out = vec![0.0f32; compact.n_cells()];
+        GeneSetReducer::new(&compact, threads, cache_block, false)
+            .per_cell_raw(&genes, &mut out)
+            .unwrap();
+        assert_eq!(out, expected, "threads {threads} cache_block {cache_block}");
+    }
+}
+
+#[test]
+fn compact_counts_decode_to_plain_entries() {
+    let tmp = TempDir::new().unwrap();
+    write_counts_10x(tmp.path(), ["65534", "65535", "70000"]);
+
+    let plain = run_encoded(tmp.path(), &tmp.path().join("plain"), ExprEncoding::Plain);
+    let out = tmp.path().join("compact");
+    let compact = run_encoded(tmp.path(), &out, ExprEncoding::Compact);
+    let header = compact.expr_header.unwrap();
+    assert_eq!(header.encoding, ENCODING_INDEX_VARINT | ENCODING_VALUES_U16);
+    assert_eq!(header.n_overflow, 2);
+    assert_eq!(header.n_blocks, 3 + 1 + 1);
+    let plain_len = fs::metadata(&plain.expr_path).unwrap().len();
+    let compact_len = fs::metadata(&compact.expr_path).unwrap().len();
+    assert!(compact_len * 2 < plain_len, "{compact_len} vs {plain_len}");
+    assert_eq!(
+        reader::data_crc64(compact.expr_mmap.as_ref().unwrap(), &header),
+        header.data_crc64
+    );
+    assert_same_entries(&plain, &compact);
+
+    // The compact cache is reused as-is; asking for plain rebuilds it.
+    let reused = run_encoded(tmp.path(), &out, ExprEncoding::Compact);
+    assert_eq!(reused.expr_header.unwrap(), header);
+    let rebuilt = run_encoded(tmp.path(), &out, ExprEncoding::Plain);
+    assert_eq!(rebuilt.expr_header.unwrap().encoding, ENCODING_PLAIN);
+}
+
+#[test]
+fn compact_keeps_f32_values_for_non_integer_input() {
+    let tmp = TempDir::new().unwrap();
+    write_counts_10x(tmp.path(), ["1", "2.5", "3"]);
+
+    let plain = run_encoded(tmp.path(), &tmp.path().join("plain"), ExprEncoding::Plain);
+    let compact = run_encoded(
+        tmp.path(),
+        &tmp.path().join("compact"),
+        ExprEncoding::Compact,
+    );
+    let header = compact.expr_header.unwrap();
+    assert_eq!(header.encoding, ENCODING_INDEX_VARINT);
+    assert_eq!(header.n_overflow, 0);
+    assert_same_entries(&plain, &compact);
+}
+
 #[cfg(feature = "hdf5")]
 fn write_h5ad_csc(path: &Path, genes: &[&str], cells: &[&str]) {
     let file = File::create(path).unwrap();
@@ -251,6 +617,83 @@ fn h5ad_csr_transpose() {
     assert_eq!(values, &[5.0, 6.0]);
 }
 
+// Square CSR: cell 0 holds gene 1 and cell 1 holds gene 0, so reading it
+// gene-major would swap the values.
+#[cfg(feature = "hdf5")]
+fn write_h5ad_square_csr(path: &Path) {
+    let file = File::create(path).unwrap();
+    let x = file.create_group("X").unwrap();
+    x.new_dataset::<f32>()
+        .shape(2)
+        .create("data")
+        .unwrap()
+        .write(&[5.0f32, 6.0])
+        .unwrap();
+    x.new_dataset::<u32>()
+        .shape(2)
+        .create("indices")
+        .unwrap()
+        .write(&[1u32, 0])
+        .unwrap();
+    x.new_dataset::<u32>()
+        .shape(3)
+        .create("indptr")
+        .unwrap()
+        .write(&[0u32, 1, 2])
+        .unwrap();
+    let attr = x.new_attr::<String>().create("encoding-type").unwrap();
+    attr.write_scalar("csr_matrix").unwrap();
+
+    let genes: Vec<String> = vec!["G1".into(), "G2".into()];
+    file.create_group("var")
+        .unwrap()
+        .new_dataset::<String>()
+        .shape(2)
+        .create("gene_symbols")
+        .unwrap()
+        .write(&genes)
+        .unwrap();
+    let cells: Vec<String> = vec!["C1".into(), "C2".into()];
+    file.create_group("obs")
+        .unwrap()
+        .new_dataset::<String>()
+        .shape(2)
+        .create("_index")
+        .unwrap()
+        .write(&cells)
+        .unwrap();
+}
+
+#[cfg(feature = "hdf5")]
+#[test]
+fn h5ad_square_csr_follows_encoding_type() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("square.h5ad");
+    write_h5ad_square_csr(&path);
+
+    let mut ctx = Ctx::new(
+        path.clone(),
+        tmp.path().join("out"),
+        Mode::Cell,
+        false,
+        None,
+        true,
+        false,
+        false,
+        "0.0.0-test",
+    );
+    let pipeline = Pipeline::new(vec![
+        Box::new(Stage2H5ad::new()),
+        Box::new(Stage3ExprCache::new()),
+    ]);
+    pipeline.run(&mut ctx).unwrap();
+
+    let (header, mmap) = reader::open_mmap(&ctx.expr_path).unwrap();
+    assert_eq!(reader::gene_ptr_slice(&mmap, &header), &[0, 1, 2]);
+    assert_eq!(reader::cell_idx_slice(&mmap, &header), &[1, 0]);
+    assert_eq!(reader::values_slice(&mmap, &header), &[6.0, 5.0]);
+}
+
 #[test]
 fn cache_reuse_readonly() {
     let tmp = TempDir::new().unwrap();
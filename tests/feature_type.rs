@@ -0,0 +1,124 @@
+use std::fs;
+use std::path::Path;
+
+use assert_cmd::Command;
+use kira_proteoqc::ctx::Ctx;
+use kira_proteoqc::io::shared_cache::SharedCache;
+use kira_proteoqc::pipeline::Pipeline;
+use kira_proteoqc::pipeline::stage1_input::Stage1Input;
+use kira_proteoqc::pipeline::stage3_expr_cache::Stage3ExprCache;
+use kira_proteoqc::schema::v1::Mode;
+use predicates::str::contains;
+use tempfile::TempDir;
+
+// CITE-seq style: 2 genes, 2 antibodies and 1 CRISPR guide over 2 cells.
+fn write_multimodal(dir: &Path) {
+    let features = "g1\tGeneA\tGene Expression\n\
+                    ab1\tCD3\tAntibody Capture\n\
+                    g2\tGeneB\tGene Expression\n\
+                    ab2\tCD4\tAntibody Capture\n\
+                    cr1\tguide1\tCRISPR Guide Capture\n";
+    let mtx = "%%MatrixMarket matrix coordinate integer general\n5 2 6\n\
+               1 1 3\n2 1 50\n3 2 4\n4 2 70\n5 1 1\n3 1 2\n";
+    fs::write(dir.join("features.tsv"), features).unwrap();
+    fs::write(dir.join("barcodes.tsv"), "cell1\ncell2\n").unwrap();
+    fs::write(dir.join("matrix.mtx"), mtx).unwrap();
+}
+
+fn load(dir: &Path, feature_type: &str) -> Ctx {
+    let mut ctx = Ctx::new(
+        dir.to_path_buf(),
+        dir.join("out"),
+        Mode::Cell,
+        false,
+        None,
+        true,
+        false,
+        false,
+        "0.0.0-test",
+    );
+    ctx.feature_type = feature_type.to_string();
+    Pipeline::new(vec![
+        Box::new(Stage1Input::new()),
+        Box::new(Stage3ExprCache::new()),
+    ])
+    .run(&mut ctx)
+    .unwrap();
+    ctx
+}
+
+#[test]
+fn gene_expression_is_scored_by_default() {
+    let tmp = TempDir::new().unwrap();
+    write_multimodal(tmp.path());
+    let ctx = load(tmp.path(), "Gene Expression");
+
+    assert_eq!(ctx.genes, vec!["GeneA", "GeneB"]);
+    assert_eq!(ctx.nnz, 3);
+    assert_eq!(ctx.input_meta.genes, Some(2));
+    // Expected filtering is a note, not a data-quality warning.
+    assert_eq!(
+        ctx.notes,
+        vec!["dropped 3 features by feature_type: Antibody Capture x2, CRISPR Guide Capture x1"]
+    );
+    assert!(ctx.warnings.is_empty());
+
+    // Antibody counts no longer reach the per-cell library sizes.
+    assert_eq!(ctx.cell_qc.libsize, vec![5.0, 4.0]);
+    let reader = ctx.expr_reader().unwrap();
+    assert_eq!(
+        reader.gene_entries(1).unwrap().collect::<Vec<_>>(),
+        vec![(0, 2.0), (1, 4.0)]
+    );
+
+    let antibody = ctx.antibody_features.as_ref().unwrap();
+    assert_eq!(antibody.table.symbols, vec!["CD3", "CD4"]);
+    assert_eq!(antibody.rows, vec![1, 3]);
+}
+
+#[test]
+fn feature_type_override_selects_other_rows() {
+    let tmp = TempDir::new().unwrap();
+    write_multimodal(tmp.path());
+
+    let antibody = load(tmp.path(), "antibody capture");
+    assert_eq!(antibody.genes, vec!["CD3", "CD4"]);
+    assert_eq!(antibody.nnz, 2);
+    assert!(antibody.antibody_features.is_none());
+
+    let all = load(tmp.path(), "all");
+    assert_eq!(all.genes.len(), 5);
+    assert_eq!(all.nnz, 6);
+    assert!(all.feature_row_map.is_none());
+    assert!(all.warnings.is_empty());
+    assert!(all.notes.is_empty());
+}
+
+#[test]
+fn unknown_feature_type_is_an_error() {
+    let tmp = TempDir::new().unwrap();
+    write_multimodal(tmp.path());
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("validate")
+        .arg("--input")
+        .arg(tmp.path())
+        .arg("--feature-type")
+        .arg("Peaks");
+    cmd.assert()
+        .failure()
+        .stderr(contains("no 'Peaks' features in input"));
+}
+
+#[test]
+fn cache_build_keeps_gene_expression_rows() {
+    let tmp = TempDir::new().unwrap();
+    write_multimodal(tmp.path());
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.arg("cache").arg("build").arg("--input").arg(tmp.path());
+    cmd.assert().success().stdout(contains("nnz: 3"));
+
+    let cache = SharedCache::open(&tmp.path().join("kira-organelle.bin")).unwrap();
+    assert_eq!(cache.genes, vec!["GeneA", "GeneB"]);
+    assert_eq!(cache.cell(0), (&[0u32, 1][..], &[3u32, 2][..]));
+    assert_eq!(cache.cell(1), (&[1u32][..], &[4u32][..]));
+}
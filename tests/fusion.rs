@@ -6,9 +6,10 @@ mod fusion_tests {
     use kira_proteoqc::ctx::Ctx;
     use kira_proteoqc::expr::layout::{ExprHeaderV1, LAYOUT_CSC, VERSION, write_header};
     use kira_proteoqc::expr::reader::open_mmap;
+    use kira_proteoqc::fusion::FusionMode;
     use kira_proteoqc::geneset::{GenesetCollection, ResolvedGeneset};
     use kira_proteoqc::schema::v1::Mode;
-    use kira_proteoqc::scores::axis_raw::compute_axis_raw_with_mode;
+    use kira_proteoqc::scores::axis_raw::{CONSTITUTIVE_TARGET, compute_axis_raw_with_mode};
     use tempfile::TempDir;
 
     fn write_expr(path: &std::path::Path) {
@@ -46,6 +47,7 @@ mod fusion_tests {
                 gene_ids: vec![0],
                 missing: vec![],
                 total: 1,
+                matches: Vec::new(),
             },
             ResolvedGeneset {
                 id: "proteasome_regulator".into(),
@@ -53,6 +55,7 @@ mod fusion_tests {
                 gene_ids: vec![1],
                 missing: vec![],
                 total: 1,
+                matches: Vec::new(),
             },
             ResolvedGeneset {
                 id: "ubiquitin_axis".into(),
@@ -60,6 +63,7 @@ mod fusion_tests {
                 gene_ids: vec![0],
                 missing: vec![],
                 total: 1,
+                matches: Vec::new(),
             },
             ResolvedGeneset {
                 id: "e3_ligases".into(),
@@ -67,6 +71,7 @@ mod fusion_tests {
                 gene_ids: vec![1],
                 missing: vec![],
                 total: 1,
+                matches: Vec::new(),
             },
             ResolvedGeneset {
                 id: "dubs".into(),
@@ -74,6 +79,7 @@ mod fusion_tests {
                 gene_ids: vec![0],
                 missing: vec![],
                 total: 1,
+                matches: Vec::new(),
             },
             ResolvedGeneset {
                 id: "chaperone_hsp70".into(),
@@ -81,6 +87,7 @@ mod fusion_tests {
                 gene_ids: vec![0],
                 missing: vec![],
                 total: 1,
+                matches: Vec::new(),
             },
             ResolvedGeneset {
                 id: "chaperone_hsp90".into(),
@@ -88,6 +95,7 @@ mod fusion_tests {
                 gene_ids: vec![1],
                 missing: vec![],
                 total: 1,
+                matches: Vec::new(),
             },
             ResolvedGeneset {
                 id: "chaperone_hsp40".into(),
@@ -95,6 +103,7 @@ mod fusion_tests {
                 gene_ids: vec![0],
                 missing: vec![],
                 total: 1,
+                matches: Vec::new(),
             },
             ResolvedGeneset {
                 id: "erad".into(),
@@ -102,6 +111,7 @@ mod fusion_tests {
                 gene_ids: vec![1],
                 missing: vec![],
                 total: 1,
+                matches: Vec::new(),
             },
             ResolvedGeneset {
                 id: "ribosome_load".into(),
@@ -109,6 +119,7 @@ mod fusion_tests {
                 gene_ids: vec![0],
                 missing: vec![],
                 total: 1,
+                matches: Vec::new(),
             },
         ];
         GenesetCollection {
@@ -143,9 +154,9 @@ mod fusion_tests {
         ctx.nnz = 3;
         ctx.genesets = Some(make_genesets());
 
-        ctx.fusion = "off".to_string();
+        ctx.fusion = FusionMode::Off;
         let axis_scalar = compute_axis_raw_with_mode(&mut ctx, Mode::Cell).unwrap();
-        ctx.fusion = "proteo".to_string();
+        ctx.fusion = FusionMode::Proteo;
         let axis_fused = compute_axis_raw_with_mode(&mut ctx, Mode::Cell).unwrap();
         assert_eq!(axis_scalar.pcs, axis_fused.pcs);
         assert_eq!(axis_scalar.utp, axis_fused.utp);
@@ -153,4 +164,367 @@ mod fusion_tests {
         assert_eq!(axis_scalar.erad, axis_fused.erad);
         assert_eq!(axis_scalar.ribo, axis_fused.ribo);
     }
+
+    const GENES: &[&str] = &[
+        "PSMA1", "PSMA3", "PSMC1", "HSPA1A", "HSPA5", "DNAJB1", "ATF4", "XBP1", "DDIT3", "SQSTM1",
+        "UBC", "DERL1", "UBB", "RPLP0",
+    ];
+
+    fn write_10x(dir: &std::path::Path, n_cells: usize) {
+        write_10x_genes(dir, n_cells, GENES);
+    }
+
+    fn write_10x_genes(dir: &std::path::Path, n_cells: usize, genes: &[&str]) {
+        let mut entries = Vec::new();
+        for g in 0..genes.len() {
+            for c in 0..n_cells {
+                let count = (g * 31 + c * 17) % 13;
+                if count > 4 {
+                    entries.push(format!("{} {} {}", g + 1, c + 1, count));
+                }
+            }
+        }
+        std::fs::write(
+            dir.join("matrix.mtx"),
+            format!(
+                "%%MatrixMarket matrix coordinate integer general\n{} {} {}\n{}\n",
+                genes.len(),
+                n_cells,
+                entries.len(),
+                entries.join("\n")
+            ),
+        )
+        .unwrap();
+        let features = genes
+            .iter()
+            .enumerate()
+            .map(|(i, g)| format!("ENSG{:05}\t{}\n", i, g))
+            .collect::<String>();
+        std::fs::write(dir.join("features.tsv"), features).unwrap();
+        let barcodes = (0..n_cells)
+            .map(|c| format!("C{}\n", c))
+            .collect::<String>();
+        std::fs::write(dir.join("barcodes.tsv"), barcodes).unwrap();
+    }
+
+    fn load(dir: &std::path::Path, fusion: FusionMode, threads: usize) -> Ctx {
+        use kira_proteoqc::pipeline::Pipeline;
+        use kira_proteoqc::pipeline::stage1_input::Stage1Input;
+        use kira_proteoqc::pipeline::stage2_h5ad::Stage2H5ad;
+        use kira_proteoqc::pipeline::stage3_expr_cache::Stage3ExprCache;
+        use kira_proteoqc::pipeline::stage4_geneset::Stage4Geneset;
+        use kira_proteoqc::pipeline::stage5_math::Stage5Math;
+
+        let mut ctx = Ctx::new(
+            dir.to_path_buf(),
+            dir.join(format!("out-{}-{}", fusion.as_str(), threads)),
+            Mode::Cell,
+            false,
+            None,
+            true,
+            false,
+            false,
+            "0.0.0-test",
+        );
+        ctx.fusion = fusion;
+        ctx.threads = threads;
+        Pipeline::new(vec![
+            Box::new(Stage1Input::new()),
+            Box::new(Stage2H5ad::new()),
+            Box::new(Stage3ExprCache::new()),
+            Box::new(Stage4Geneset::new()),
+            Box::new(Stage5Math::new()),
+        ])
+        .run(&mut ctx)
+        .unwrap();
+        ctx
+    }
+
+    fn bits(values: &[f32]) -> Vec<u32> {
+        values.iter().map(|v| v.to_bits()).collect()
+    }
+
+    #[test]
+    fn fused_pass_covers_genesets_and_panels() {
+        let tmp = TempDir::new().unwrap();
+        write_10x(tmp.path(), 50);
+
+        let ctx = load(tmp.path(), FusionMode::Proteo, 1);
+        let fused = ctx.fused.as_ref().unwrap();
+        let collection = ctx.genesets.as_ref().unwrap();
+        // Genesets, then the constitutive proteasome reference.
+        assert_eq!(fused.targets.len(), collection.resolved.len() + 1);
+        assert_eq!(
+            fused.targets.last().map(String::as_str),
+            Some(CONSTITUTIVE_TARGET)
+        );
+        let ids = fused
+            .panels
+            .iter()
+            .map(|p| p.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            ["chaperone", "proteasome", "upr", "erad", "aggregation"]
+        );
+        assert_eq!(fused.panel("upr").unwrap().n_genes, 3);
+
+        let off = load(tmp.path(), FusionMode::Off, 1);
+        assert!(off.fused.is_none());
+    }
+
+    #[test]
+    fn fused_panels_match_dense_panel_rows() {
+        use kira_proteoqc::metrics::proteostasis_extension::compute_extension;
+
+        let tmp = TempDir::new().unwrap();
+        write_10x(tmp.path(), 50);
+        let fused = compute_extension(&load(tmp.path(), FusionMode::Proteo, 1)).unwrap();
+        let dense = compute_extension(&load(tmp.path(), FusionMode::Off, 1)).unwrap();
+
+        for (a, b) in [
+            (&fused.scores.chaperone_core, &dense.scores.chaperone_core),
+            (&fused.scores.proteasome_core, &dense.scores.proteasome_core),
+            (&fused.scores.upr_core, &dense.scores.upr_core),
+            (&fused.scores.erad_core, &dense.scores.erad_core),
+            (&fused.scores.agg_core, &dense.scores.agg_core),
+        ] {
+            assert_eq!(bits(a), bits(b));
+        }
+        assert!(fused.scores.erad_core.iter().all(|v| v.is_nan()));
+    }
+
+    #[test]
+    fn constitutive_reference_is_not_a_geneset() {
+        let tmp = TempDir::new().unwrap();
+        write_10x_genes(
+            tmp.path(),
+            40,
+            &[
+                "PSMA1", "PSMB5", "PSMB7", "PSMB8", "PSMB9", "HSPA1A", "RPLP0",
+            ],
+        );
+
+        let mut fused = load(tmp.path(), FusionMode::Proteo, 1);
+        let mut dense = load(tmp.path(), FusionMode::Off, 1);
+        let collection = fused.genesets.as_ref().unwrap();
+        assert!(
+            collection
+                .resolved
+                .iter()
+                .all(|g| !g.id.contains("constitutive"))
+        );
+
+        let a = compute_axis_raw_with_mode(&mut fused, Mode::Cell).unwrap();
+        let b = compute_axis_raw_with_mode(&mut dense, Mode::Cell).unwrap();
+        assert_eq!(bits(&a.constitutive), bits(&b.constitutive));
+        assert!(a.constitutive.iter().any(|&v| v > 0.0));
+        for ctx in [&fused, &dense] {
+            assert!(ctx.warnings.iter().all(|w| !w.contains("constitutive")));
+        }
+
+        // Without PSMB5/6/7 the reference stays at zero and adds no warning.
+        let bare = TempDir::new().unwrap();
+        write_10x_genes(bare.path(), 40, &["PSMA1", "PSMB8", "HSPA1A", "RPLP0"]);
+        let mut ctx = load(bare.path(), FusionMode::Proteo, 1);
+        let before = ctx.warnings.len();
+        let axis = compute_axis_raw_with_mode(&mut ctx, Mode::Cell).unwrap();
+        assert!(axis.constitutive.iter().all(|&v| v == 0.0));
+        assert!(
+            ctx.warnings[before..]
+                .iter()
+                .all(|w| !w.contains("constitutive"))
+        );
+    }
+
+    const MITO_GENES: &[&str] = &[
+        "PSMA1", "HSPA1A", "ATF4", "XBP1", "MT-CO1", "MT-ND1", "NDUFA1", "NDUFB2", "SDHA",
+        "UQCRC1", "COX4I1", "ATP5F1A", "LONP1", "CLPP", "HSPD1", "HSPE1", "YME1L1", "RPLP0",
+    ];
+
+    // Count written by write_10x_genes.
+    fn count(gene: usize, cell: usize) -> f64 {
+        let c = (gene * 31 + cell * 17) % 13;
+        if c > 4 { c as f64 } else { 0.0 }
+    }
+
+    #[test]
+    fn mito_targets_ride_the_fused_pass() {
+        use kira_proteoqc::metrics::mito::{MT_TARGET, compute_mito_axis};
+
+        let tmp = TempDir::new().unwrap();
+        let n_cells = 60;
+        write_10x_genes(tmp.path(), n_cells, MITO_GENES);
+
+        let ctx = load(tmp.path(), FusionMode::MitoProteo, 1);
+        let fused = ctx.fused.as_ref().unwrap();
+        let n_genesets = ctx.genesets.as_ref().unwrap().resolved.len();
+        assert_eq!(
+            &fused.targets[n_genesets..],
+            [
+                CONSTITUTIVE_TARGET,
+                "mito:mt",
+                "mito:oxphos_ci",
+                "mito:oxphos_cii",
+                "mito:oxphos_ciii",
+                "mito:oxphos_civ",
+                "mito:oxphos_cv",
+                "mito:proteostasis"
+            ]
+        );
+        assert_eq!(&fused.gene_counts[n_genesets..], [0, 2, 2, 1, 1, 1, 1, 5]);
+
+        // The MT- fraction is over stored counts, not normalized values.
+        let mito = compute_mito_axis(&ctx).unwrap();
+        for cell in 0..n_cells {
+            let total = (0..MITO_GENES.len()).map(|g| count(g, cell)).sum::<f64>();
+            let expected = (count(4, cell) + count(5, cell)) / total;
+            let got = mito.scores.mt_fraction[cell] as f64;
+            assert!((got - expected).abs() < 1e-6, "cell {}", cell);
+        }
+        assert!(mito.scores.mpi.iter().all(|v| *v >= 0.0));
+        assert_eq!(mito.summary.targets.len(), 7);
+        assert!(mito.summary.metrics.contains_key("MPI"));
+
+        let proteo = load(tmp.path(), FusionMode::Proteo, 1);
+        assert!(proteo.fused.unwrap().target_index(MT_TARGET).is_none());
+    }
+
+    #[test]
+    fn mito_axis_is_reported_in_outputs() {
+        use assert_cmd::Command;
+        use serde_json::Value;
+
+        let tmp = TempDir::new().unwrap();
+        write_10x_genes(tmp.path(), 40, MITO_GENES);
+        let run = |name: &str, extra: &[&str]| {
+            let out = tmp.path().join(name);
+            let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+            cmd.args([
+                "run",
+                "--input",
+                tmp.path().to_str().unwrap(),
+                "--out",
+                out.to_str().unwrap(),
+                "--mode",
+                "cell",
+            ])
+            .args(extra);
+            cmd.assert().success();
+            out
+        };
+
+        let pipeline = run(
+            "mito-pipeline",
+            &["--fusion", "mito+proteo", "--run-mode", "pipeline"],
+        )
+        .join("kira-proteoqc");
+        let tsv = std::fs::read_to_string(pipeline.join("proteoqc.tsv")).unwrap();
+        let header = tsv.lines().next().unwrap();
+        assert!(header.ends_with(
+            "\timmunoproteasome_ratio\tmt_fraction\toxphos_core\tmito_proteostasis_core\tMPI"
+        ));
+        assert!(
+            tsv.lines()
+                .all(|l| l.split('\t').count() == header.split('\t').count())
+        );
+        let summary: Value =
+            serde_json::from_slice(&std::fs::read(pipeline.join("summary.json")).unwrap()).unwrap();
+        assert_eq!(summary["mito_axis"]["targets"].as_array().unwrap().len(), 7);
+        assert!(summary["mito_axis"]["metrics"]["mt_fraction"]["median"].is_number());
+
+        let standalone = run(
+            "mito-standalone",
+            &["--fusion", "mito+proteo", "--json", "--tsv"],
+        );
+        let report: Value =
+            serde_json::from_slice(&std::fs::read(standalone.join("proteoqc.json")).unwrap())
+                .unwrap();
+        assert!(report["mito_axis"]["metrics"]["MPI"].is_object());
+        let tsv = std::fs::read_to_string(standalone.join("proteoqc.tsv")).unwrap();
+        assert!(tsv.lines().next().unwrap().ends_with("\tMPI"));
+
+        // Without mito+proteo the outputs keep their columns.
+        let proteo = run("proteo-pipeline", &["--run-mode", "pipeline"]).join("kira-proteoqc");
+        let tsv = std::fs::read_to_string(proteo.join("proteoqc.tsv")).unwrap();
+        assert!(
+            tsv.lines()
+                .next()
+                .unwrap()
+                .ends_with("\timmunoproteasome_ratio")
+        );
+        let summary: Value =
+            serde_json::from_slice(&std::fs::read(proteo.join("summary.json")).unwrap()).unwrap();
+        assert!(summary["mito_axis"].is_null());
+    }
+
+    #[test]
+    fn oxphos_targets_skip_assembly_factors() {
+        use kira_proteoqc::metrics::mito::resolve_targets;
+
+        let tmp = TempDir::new().unwrap();
+        let mut ctx = Ctx::new(
+            tmp.path().to_path_buf(),
+            tmp.path().join("out"),
+            Mode::Cell,
+            false,
+            None,
+            true,
+            false,
+            false,
+            "0.0.0-test",
+        );
+        ctx.genes = [
+            "NDUFA1", "NDUFAF1", "Ndufaf4", "SDHB", "SDHAF2", "COX4I1", "COX6C", "COX10", "COX17",
+            "Cox20", "ATP5F1A", "ATP5IF1",
+        ]
+        .iter()
+        .map(|g| g.to_string())
+        .collect();
+
+        let targets = resolve_targets(&ctx);
+        let genes = |id: &str| {
+            let target = targets.iter().find(|t| t.id == id).unwrap();
+            target
+                .genes
+                .iter()
+                .map(|&g| ctx.genes[g].as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(genes("mito:oxphos_ci"), ["NDUFA1"]);
+        assert_eq!(genes("mito:oxphos_cii"), ["SDHB"]);
+        assert_eq!(genes("mito:oxphos_civ"), ["COX4I1", "COX6C"]);
+        assert_eq!(genes("mito:oxphos_cv"), ["ATP5F1A"]);
+    }
+
+    #[cfg(feature = "fusion-mt")]
+    #[test]
+    fn sharded_fused_pass_matches_single_thread() {
+        let tmp = TempDir::new().unwrap();
+        write_10x(tmp.path(), 9000);
+
+        let single = load(tmp.path(), FusionMode::Proteo, 1);
+        let sharded = load(tmp.path(), FusionMode::Proteo, 4);
+        let (a, b) = (single.fused.unwrap(), sharded.fused.unwrap());
+        assert_eq!(bits(&a.sums), bits(&b.sums));
+        for (pa, pb) in a.panels.iter().zip(&b.panels) {
+            for cell in [0, 4095, 4096, 8191, 8999] {
+                assert_eq!(pa.cell_values(cell), pb.cell_values(cell));
+            }
+        }
+    }
+}
+
+#[test]
+fn unknown_fusion_value_is_rejected() {
+    use assert_cmd::Command;
+    use predicates::str::contains;
+
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.args([
+        "run", "--input", ".", "--out", ".", "--mode", "cell", "--fusion", "mito",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("invalid value 'mito'"));
 }
@@ -2,8 +2,11 @@ use std::collections::HashMap;
 use std::fs;
 
 use kira_proteoqc::geneset::{
-    GenesetCollection, GenesetDef, load_geneset_tsv, merge_defs, resolve_collection,
+    GenesetCollection, GenesetDef, GenesetVersion, MatchKind, SymbolResolver, build_id_index,
+    load_builtin, load_builtin_for, load_geneset_tsv, merge_defs, resolve_collection,
+    resolve_collection_with,
 };
+use kira_proteoqc::metadata::Species;
 use tempfile::TempDir;
 
 #[test]
@@ -26,6 +29,7 @@ fn geneset_resolution() {
         id: "set_a".to_string(),
         axis: 'A',
         genes: vec!["G1".to_string(), "G2".to_string(), "G3".to_string()],
+        aliases: Default::default(),
     }];
     let mut gene_index = HashMap::new();
     gene_index.insert("G1".to_string(), 2usize);
@@ -51,20 +55,162 @@ fn geneset_duplicate_override() {
             id: "set_a".to_string(),
             axis: 'A',
             genes: vec!["G1".to_string()],
+            aliases: Default::default(),
         },
         GenesetDef {
             id: "set_b".to_string(),
             axis: 'B',
             genes: vec!["G2".to_string()],
+            aliases: Default::default(),
         },
     ];
     let user = vec![GenesetDef {
         id: "set_a".to_string(),
         axis: 'A',
         genes: vec!["GX".to_string()],
+        aliases: Default::default(),
     }];
 
     let merged = merge_defs(builtin, user);
     assert_eq!(merged[0].genes, vec!["GX".to_string()]);
     assert_eq!(merged[1].id, "set_b");
 }
+
+#[test]
+fn resolution_is_case_insensitive_and_ortholog_aware() {
+    let mut gene_index = HashMap::new();
+    gene_index.insert("Psma1".to_string(), 0usize);
+    gene_index.insert("Atp5a1".to_string(), 1usize);
+    gene_index.insert("HSPA8".to_string(), 2usize);
+    let resolver = SymbolResolver::new(&gene_index);
+
+    assert_eq!(resolver.resolve("PSMA1"), Some(0));
+    assert_eq!(resolver.resolve("ATP5F1A"), Some(1));
+    assert_eq!(resolver.resolve("Hspa8"), Some(2));
+    assert_eq!(resolver.resolve("PSMB5"), None);
+}
+
+#[test]
+fn builtin_v2_extends_v1_sets() {
+    let v1 = load_builtin(GenesetVersion::V1, Species::Human).unwrap();
+    let v2 = load_builtin(GenesetVersion::V2, Species::Human).unwrap();
+    assert_eq!(v2.version, "v2");
+    assert_eq!(
+        v1.defs.iter().map(|d| (&d.id, d.axis)).collect::<Vec<_>>(),
+        v2.defs.iter().map(|d| (&d.id, d.axis)).collect::<Vec<_>>()
+    );
+    for (old, new) in v1.defs.iter().zip(v2.defs.iter()) {
+        assert!(new.genes.len() >= 5, "{} is too small", new.id);
+        for gene in &old.genes {
+            assert!(new.genes.contains(gene), "{} lost {}", new.id, gene);
+        }
+    }
+    let ribo = v2.defs.iter().find(|d| d.id == "ribosome_load").unwrap();
+    assert!(ribo.genes.len() >= 70);
+    let erad = v2.defs.iter().find(|d| d.id == "erad").unwrap();
+    assert_eq!(erad.aliases["UFD1"], vec!["UFD1L".to_string()]);
+
+    // HSPA6 has no mouse ortholog; every other gene maps by case.
+    let mouse = load_builtin(GenesetVersion::V2, Species::Mouse).unwrap();
+    for (h, m) in v2.defs.iter().zip(mouse.defs.iter()) {
+        assert_eq!(h.id, m.id);
+        let mapped = m.genes.iter().map(|g| g.to_uppercase()).collect::<Vec<_>>();
+        let expected = h
+            .genes
+            .iter()
+            .filter(|g| g.as_str() != "HSPA6")
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(mapped, expected, "{}", h.id);
+    }
+    assert_eq!(mouse.defs[0].genes[0], "Psma1");
+}
+
+#[test]
+fn builtin_mouse_asset_mirrors_human_sets() {
+    let human = load_builtin_for(Species::Human).unwrap();
+    let mouse = load_builtin_for(Species::Mouse).unwrap();
+    assert_eq!(human.defs.len(), mouse.defs.len());
+    for (h, m) in human.defs.iter().zip(mouse.defs.iter()) {
+        assert_eq!(h.id, m.id);
+        assert_eq!(h.genes.len(), m.genes.len());
+    }
+    let proteasome = &mouse.defs[0];
+    assert_eq!(proteasome.genes[0], "Psma1");
+
+    let gene_index = mouse.defs[0]
+        .genes
+        .iter()
+        .enumerate()
+        .map(|(i, g)| (g.clone(), i))
+        .collect::<HashMap<_, _>>();
+    let resolved = resolve_collection(human, &gene_index);
+    assert_eq!(
+        resolved.resolved[0].gene_ids.len(),
+        resolved.resolved[0].total
+    );
+}
+
+#[test]
+fn geneset_tsv_reads_alias_column() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("gs.tsv");
+    let content = "set_a\tA\tATP5F1A\tATP5A1, ATP5A\nset_a\tA\tENSG00000100567\n";
+    fs::write(&path, content).unwrap();
+
+    let defs = load_geneset_tsv(&path).unwrap();
+    assert_eq!(defs[0].genes, vec!["ATP5F1A", "ENSG00000100567"]);
+    assert_eq!(
+        defs[0].aliases.get("ATP5F1A").unwrap(),
+        &vec!["ATP5A1".to_string(), "ATP5A".to_string()]
+    );
+    assert!(!defs[0].aliases.contains_key("ENSG00000100567"));
+}
+
+#[test]
+fn resolution_by_id_symbol_and_alias_records_match_kind() {
+    let mut aliases = std::collections::BTreeMap::new();
+    aliases.insert("NEWNAME".to_string(), vec!["OLDNAME".to_string()]);
+    let defs = vec![GenesetDef {
+        id: "set_a".to_string(),
+        axis: 'A',
+        genes: vec![
+            "ENSG00000100567.9".to_string(),
+            "HSPA8".to_string(),
+            "NEWNAME".to_string(),
+            "ENSG00000000001".to_string(),
+        ],
+        aliases,
+    }];
+    let mut gene_index = HashMap::new();
+    gene_index.insert("PSMA3".to_string(), 0usize);
+    gene_index.insert("HSPA8".to_string(), 1usize);
+    gene_index.insert("OLDNAME".to_string(), 2usize);
+    let id_index = build_id_index(&[
+        "ENSG00000100567.12".to_string(),
+        "ENSG00000109971".to_string(),
+        "ENSG00000999999".to_string(),
+    ]);
+
+    let collection = GenesetCollection {
+        version: "v1".to_string(),
+        defs,
+        resolved: Vec::new(),
+    };
+    let resolver = SymbolResolver::new(&gene_index).with_ids(&id_index);
+    let resolved = resolve_collection_with(collection, &resolver);
+    let gs = &resolved.resolved[0];
+    assert_eq!(gs.gene_ids, vec![0, 1, 2]);
+    assert_eq!(gs.missing, vec!["ENSG00000000001".to_string()]);
+    let via = gs.matches.iter().map(|m| m.via).collect::<Vec<_>>();
+    assert_eq!(
+        via,
+        vec![MatchKind::Id, MatchKind::Symbol, MatchKind::Alias]
+    );
+    assert_eq!(gs.matches[2].key, "OLDNAME");
+
+    let coverage = gs.coverage_matches();
+    assert_eq!(coverage[0].gene, "ENSG00000100567.9");
+    assert_eq!(coverage[0].via, "id");
+    assert_eq!(coverage[2].via, "alias");
+}
@@ -4,6 +4,8 @@ use std::path::Path;
 
 use hdf5::File;
 use kira_proteoqc::ctx::Ctx;
+use kira_proteoqc::expr::normalize::NormalizationMethod;
+use kira_proteoqc::io::h5ad_layer::{H5adLayer, MatrixValues};
 use kira_proteoqc::pipeline::Pipeline;
 use kira_proteoqc::pipeline::stage2_h5ad::Stage2H5ad;
 use kira_proteoqc::schema::v1::Mode;
@@ -118,3 +120,89 @@ fn stage2_duplicate_gene_symbol_warns() {
     assert_eq!(ctx.gene_index.get("G1"), Some(&0));
     assert_eq!(ctx.warnings.len(), 1);
 }
+
+fn write_sparse(group: &hdf5::Group, data: &[f32], indices: &[u32], indptr: &[u32]) {
+    group
+        .new_dataset::<f32>()
+        .shape(data.len())
+        .create("data")
+        .unwrap()
+        .write(data)
+        .unwrap();
+    group
+        .new_dataset::<u32>()
+        .shape(indices.len())
+        .create("indices")
+        .unwrap()
+        .write(indices)
+        .unwrap();
+    group
+        .new_dataset::<u32>()
+        .shape(indptr.len())
+        .create("indptr")
+        .unwrap()
+        .write(indptr)
+        .unwrap();
+}
+
+#[test]
+fn stage2_reads_selected_layer_and_detects_counts() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("layers.h5ad");
+    // X holds non-integer values; layers/counts and raw/X hold integer counts.
+    write_h5ad(&path, &["G1", "G2"], &["C1", "C2", "C3"], "csr_matrix");
+    {
+        let file = File::open_rw(&path).unwrap();
+        let x = file.group("X").unwrap();
+        x.dataset("data").unwrap().write(&[0.5f32, 1.25]).unwrap();
+        let layers = file.create_group("layers").unwrap();
+        let counts = layers.create_group("counts").unwrap();
+        write_sparse(&counts, &[1.0, 2.0, 5.0], &[0, 1, 2], &[0, 2, 3]);
+        let raw = file.create_group("raw").unwrap();
+        let raw_x = raw.create_group("X").unwrap();
+        write_sparse(&raw_x, &[1.0, 2.0], &[0, 2], &[0, 1, 2, 2]);
+        let raw_var = raw.create_group("var").unwrap();
+        let genes: Vec<String> = ["G1", "G2", "G3"].iter().map(|s| s.to_string()).collect();
+        raw_var
+            .new_dataset::<String>()
+            .shape(genes.len())
+            .create("gene_symbols")
+            .unwrap()
+            .write(&genes)
+            .unwrap();
+    }
+
+    let run = |layer: H5adLayer| {
+        let mut ctx = Ctx::new(
+            path.clone(),
+            tmp.path().join("out"),
+            Mode::Cell,
+            false,
+            None,
+            true,
+            false,
+            false,
+            "0.0.0-test",
+        );
+        ctx.normalization_auto = true;
+        ctx.h5ad_layer = layer;
+        Pipeline::new(vec![Box::new(Stage2H5ad::new())])
+            .run(&mut ctx)
+            .unwrap();
+        ctx
+    };
+
+    let x = run(H5adLayer::X);
+    assert_eq!(x.matrix_values, Some(MatrixValues::Normalized));
+    assert_eq!(x.normalization, NormalizationMethod::Prenormalized);
+
+    let counts = run(H5adLayer::Layer("counts".to_string()));
+    assert_eq!(counts.nnz, 3);
+    assert_eq!(counts.matrix_values, Some(MatrixValues::Counts));
+    assert_eq!(counts.normalization, NormalizationMethod::Cp10k);
+
+    let raw = run(H5adLayer::Raw);
+    assert_eq!(raw.genes.len(), 3);
+    assert_eq!(raw.cells.len(), 3);
+    assert_eq!(raw.matrix_values, Some(MatrixValues::Counts));
+}
@@ -0,0 +1,109 @@
+use clap::Parser;
+use kira_proteoqc::cli::{Cli, Commands, NormalizeArg};
+use kira_proteoqc::expr::normalize::{NormalizationMethod, auto_method};
+use kira_proteoqc::io::h5ad_layer::{H5adLayer, MatrixValues, SparseOrientation, classify_values};
+
+#[test]
+fn parses_layer_selectors() {
+    assert_eq!(H5adLayer::parse("X").unwrap(), H5adLayer::X);
+    assert_eq!(H5adLayer::parse("raw").unwrap(), H5adLayer::Raw);
+    assert_eq!(H5adLayer::parse("raw/X").unwrap(), H5adLayer::Raw);
+    assert_eq!(
+        H5adLayer::parse("layers/counts").unwrap(),
+        H5adLayer::Layer("counts".to_string())
+    );
+    assert!(H5adLayer::parse("layers/").is_err());
+    assert!(H5adLayer::parse("layers/a/b").is_err());
+    assert!(H5adLayer::parse("obsm/X_pca").is_err());
+}
+
+#[test]
+fn layer_paths_follow_anndata_layout() {
+    assert_eq!(H5adLayer::X.matrix_path(), "X");
+    assert_eq!(H5adLayer::Raw.matrix_path(), "raw/X");
+    assert_eq!(H5adLayer::Raw.var_path(), "raw/var");
+    let counts = H5adLayer::Layer("counts".to_string());
+    assert_eq!(counts.matrix_path(), "layers/counts");
+    assert_eq!(counts.var_path(), "var");
+    assert_eq!(counts.to_string(), "layers/counts");
+    assert_eq!(H5adLayer::Raw.to_string(), "raw");
+}
+
+#[test]
+fn classifies_counts_and_normalized_values() {
+    assert_eq!(classify_values(&[1.0, 3.0, 120.0]), MatrixValues::Counts);
+    assert_eq!(classify_values(&[]), MatrixValues::Counts);
+    assert_eq!(classify_values(&[1.0, 0.25]), MatrixValues::Normalized);
+    assert_eq!(classify_values(&[2.0, -1.5]), MatrixValues::Normalized);
+}
+
+#[test]
+fn auto_normalization_follows_detected_values() {
+    assert_eq!(
+        auto_method(MatrixValues::Counts),
+        NormalizationMethod::Cp10k
+    );
+    assert_eq!(
+        auto_method(MatrixValues::Normalized),
+        NormalizationMethod::Prenormalized
+    );
+}
+
+#[test]
+fn run_defaults_to_auto_normalization_and_x_layer() {
+    let cli = Cli::parse_from([
+        "kira-proteoqc",
+        "run",
+        "--input",
+        "data.h5ad",
+        "--out",
+        "out",
+        "--mode",
+        "cell",
+    ]);
+    match cli.command {
+        Commands::Run(args) => {
+            assert!(matches!(args.normalize, NormalizeArg::Auto));
+            assert_eq!(args.h5ad_layer, "X");
+        }
+        _ => panic!("expected run command"),
+    }
+}
+
+#[test]
+fn sparse_orientation_follows_encoding_type() {
+    use SparseOrientation::{CellMajor, GeneMajor};
+    // Square matrices fit both lengths: the attribute decides, CSR without it.
+    assert_eq!(
+        SparseOrientation::resolve(Some("csr_matrix"), 3, 2, 2).unwrap(),
+        CellMajor
+    );
+    assert_eq!(
+        SparseOrientation::resolve(Some("csc_matrix"), 3, 2, 2).unwrap(),
+        GeneMajor
+    );
+    assert_eq!(
+        SparseOrientation::resolve(None, 3, 2, 2).unwrap(),
+        CellMajor
+    );
+
+    assert_eq!(
+        SparseOrientation::resolve(None, 4, 2, 3).unwrap(),
+        CellMajor
+    );
+    assert_eq!(
+        SparseOrientation::resolve(None, 3, 2, 3).unwrap(),
+        GeneMajor
+    );
+    for (encoding, len, expected) in [
+        (Some("csr_matrix"), 3, "expects indptr length 4"),
+        (Some("csc_matrix"), 4, "expects indptr length 3"),
+        (Some("coo_matrix"), 4, "unsupported sparse encoding-type"),
+        (None, 5, "matches neither"),
+    ] {
+        let err = SparseOrientation::resolve(encoding, len, 2, 3)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains(expected), "{encoding:?}: {err}");
+    }
+}
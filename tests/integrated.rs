@@ -1,6 +1,6 @@
 use kira_proteoqc::schema::v1::Mode;
 use kira_proteoqc::scores::AxisRawScores;
-use kira_proteoqc::scores::integrated::compute_integrated;
+use kira_proteoqc::scores::integrated::{compute_integrated, switching_ratio};
 
 fn assert_vec_close(a: &[f32], b: &[f32]) {
     assert_eq!(a.len(), b.len());
@@ -17,6 +17,8 @@ fn integration_math_basic() {
         cls: vec![2.0, 0.0],
         erad: vec![1.0, 1.0],
         ribo: vec![3.0, 2.0],
+        ips: vec![0.0; 2],
+        constitutive: vec![0.0; 2],
     };
 
     let (integrated, contrib) = compute_integrated(&axis, Mode::Sample).unwrap();
@@ -39,6 +41,8 @@ fn zscore_known_vector() {
         cls: vec![0.0, 0.0, 0.0],
         erad: vec![0.0, 0.0, 0.0],
         ribo: vec![0.0, 0.0, 0.0],
+        ips: vec![0.0; 3],
+        constitutive: vec![0.0; 3],
     };
 
     let (integrated, _contrib) = compute_integrated(&axis, Mode::Cell).unwrap();
@@ -59,6 +63,8 @@ fn mode_behavior_z() {
         cls: vec![1.0],
         erad: vec![1.0],
         ribo: vec![1.0],
+        ips: vec![0.0],
+        constitutive: vec![0.0],
     };
 
     let (cell_scores, _) = compute_integrated(&axis, Mode::Cell).unwrap();
@@ -66,3 +72,27 @@ fn mode_behavior_z() {
     let (sample_scores, _) = compute_integrated(&axis, Mode::Sample).unwrap();
     assert!(sample_scores.capacity_z.is_none());
 }
+
+#[test]
+fn ips_switching_ratio() {
+    assert_eq!(switching_ratio(1.0, 3.0), 0.25);
+    assert_eq!(switching_ratio(2.0, -1.0), 1.0);
+    assert_eq!(switching_ratio(-1.0, -1.0), 0.0);
+    assert_eq!(switching_ratio(0.0, 0.0), 0.0);
+
+    // The ratio is taken against PSMB5/6/7, not the PCS axis.
+    let axis = AxisRawScores {
+        pcs: vec![4.0, 4.0, 4.0],
+        utp: vec![0.0, 0.0, 0.0],
+        cls: vec![0.0, 0.0, 0.0],
+        erad: vec![0.0, 0.0, 0.0],
+        ribo: vec![0.0, 0.0, 0.0],
+        ips: vec![0.0, 1.0, 3.0],
+        constitutive: vec![1.0, 1.0, 1.0],
+    };
+    let (cell_scores, _) = compute_integrated(&axis, Mode::Cell).unwrap();
+    assert_vec_close(&cell_scores.ips_ratio, &[0.0, 0.5, 0.75]);
+    assert!(cell_scores.ips_z.is_some());
+    let (sample_scores, _) = compute_integrated(&axis, Mode::Sample).unwrap();
+    assert!(sample_scores.ips_z.is_none());
+}
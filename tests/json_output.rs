@@ -26,6 +26,8 @@ fn json_report_populated() {
         cls: vec![1.0, 1.0],
         erad: vec![0.2, 0.2],
         ribo: vec![2.0, 2.0],
+        ips: vec![0.0; 2],
+        constitutive: vec![0.0; 2],
     });
     ctx.integrated_scores = Some(IntegratedScores {
         capacity_raw: vec![1.0, 1.0],
@@ -34,6 +36,8 @@ fn json_report_populated() {
         capacity_z: Some(vec![0.0, 0.0]),
         pii_z: Some(vec![0.0, 0.0]),
         pfs_z: Some(vec![0.0, 0.0]),
+        ips_ratio: vec![0.0; 2],
+        ips_z: Some(vec![0.0; 2]),
     });
     ctx.pfs_contributions = Some(PfsContributions {
         c_pii: vec![0.4, 0.4],
@@ -57,4 +61,12 @@ fn json_report_populated() {
     assert_eq!(json["scores"]["per_cell_tsv_path"], "proteoqc.tsv");
     assert!(json["risk_flags"].is_array());
     assert!(json["explainability"]["pfs_contributions"].is_object());
+    assert_eq!(json["scoring_profile"]["name"], "proteoqc_default");
+    assert_eq!(json["geneset_version"], "v1");
+    assert!(
+        json["scoring_profile"]["hash"]
+            .as_str()
+            .unwrap()
+            .starts_with("crc64:")
+    );
 }
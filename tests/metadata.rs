@@ -0,0 +1,141 @@
+use std::fs;
+use std::path::Path;
+
+use assert_cmd::Command;
+use kira_proteoqc::ctx::Ctx;
+use kira_proteoqc::metadata::{Species, infer_species, read_cell_metadata};
+use kira_proteoqc::pipeline::Pipeline;
+use kira_proteoqc::pipeline::stage1_input::Stage1Input;
+use kira_proteoqc::pipeline::stage2b_metadata::Stage2bMetadata;
+use kira_proteoqc::schema::v1::Mode;
+use serde_json::Value;
+use tempfile::TempDir;
+
+fn symbols(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn species_is_inferred_from_symbol_casing() {
+    let human = symbols(&["HSPA8", "PSMA1", "MT-CO1", "ACTB", "GAPDH"]);
+    assert_eq!(infer_species(&human), Species::Human);
+    let mouse = symbols(&["Hspa8", "Psma1", "mt-Co1", "Actb", "Gapdh"]);
+    assert_eq!(infer_species(&mouse), Species::Mouse);
+    let ensembl = symbols(&["ENSMUSG00000000001", "ENSMUSG00000000028"]);
+    assert_eq!(infer_species(&ensembl), Species::Mouse);
+}
+
+#[test]
+fn species_is_unknown_without_evidence() {
+    assert_eq!(
+        infer_species(&symbols(&["G1", "G2", "G3"])),
+        Species::Unknown
+    );
+    let mixed = symbols(&["HSPA8", "Hspa8", "PSMA1", "Psma1"]);
+    assert_eq!(infer_species(&mixed), Species::Unknown);
+}
+
+#[test]
+fn cell_metadata_falls_back_to_run_values() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("meta.tsv");
+    fs::write(
+        &path,
+        "barcode\tcondition\tsample\nC1\ttreated\tS1\nC2\t\tS2\n",
+    )
+    .unwrap();
+    let cells = symbols(&["C1", "C2", "C3"]);
+    let (meta, unmatched) = read_cell_metadata(&path, &cells, "run", "ctrl").unwrap();
+    assert_eq!(meta.sample, vec!["S1", "S2", "run"]);
+    assert_eq!(meta.condition, vec!["treated", "ctrl", "ctrl"]);
+    assert_eq!(unmatched, 1);
+}
+
+#[test]
+fn pipeline_outputs_carry_metadata() {
+    let input = TempDir::new().unwrap();
+    let out = TempDir::new().unwrap();
+    write_10x(input.path());
+    let meta = input.path().join("meta.tsv");
+    fs::write(&meta, "cell_id\tcondition\nC2\tstim\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.args([
+        "run",
+        "--input",
+        input.path().to_str().unwrap(),
+        "--out",
+        out.path().to_str().unwrap(),
+        "--mode",
+        "cell",
+        "--run-mode",
+        "pipeline",
+        "--sample-id",
+        "donor7",
+        "--condition",
+        "ctrl",
+        "--metadata",
+        meta.to_str().unwrap(),
+    ]);
+    cmd.assert().success();
+
+    let tsv = fs::read_to_string(out.path().join("kira-proteoqc").join("proteoqc.tsv")).unwrap();
+    let rows = tsv
+        .lines()
+        .skip(1)
+        .map(|l| l.split('\t').take(4).collect::<Vec<_>>().join(","))
+        .collect::<Vec<_>>();
+    assert_eq!(rows, vec!["C1,donor7,ctrl,mouse", "C2,donor7,stim,mouse"]);
+
+    let summary = out.path().join("kira-proteoqc").join("summary.json");
+    let v: Value = serde_json::from_slice(&fs::read(summary).unwrap()).unwrap();
+    assert_eq!(v["input"]["sample_id"], "donor7");
+    assert_eq!(v["input"]["condition"], "ctrl");
+    assert_eq!(v["input"]["species"], "mouse");
+}
+
+fn write_10x(dir: &Path) {
+    fs::write(
+        dir.join("matrix.mtx"),
+        "%%MatrixMarket matrix coordinate integer general\n3 2 3\n1 1 5\n3 1 1\n2 2 7\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("features.tsv"),
+        "g1\tHspa8\ng2\tPsma1\ng3\tmt-Co1\n",
+    )
+    .unwrap();
+    fs::write(dir.join("barcodes.tsv"), "C1\nC2\n").unwrap();
+}
+
+#[test]
+fn explicit_species_override_is_a_note() {
+    let input = TempDir::new().unwrap();
+    write_10x(input.path());
+    let mut ctx = Ctx::new(
+        input.path().to_path_buf(),
+        input.path().join("out"),
+        Mode::Cell,
+        false,
+        None,
+        true,
+        false,
+        false,
+        "0.0.0-test",
+    );
+    ctx.species = Some(Species::Human);
+    Pipeline::new(vec![
+        Box::new(Stage1Input::new()),
+        Box::new(Stage2bMetadata::new()),
+    ])
+    .run(&mut ctx)
+    .unwrap();
+
+    assert_eq!(ctx.metadata.species, Species::Human);
+    assert_eq!(
+        ctx.notes,
+        vec!["--species human overrides inferred species mouse"]
+    );
+    // Notes do not count against per-cell confidence; warnings do.
+    assert!(ctx.warnings.is_empty());
+}
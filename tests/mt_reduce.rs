@@ -9,16 +9,27 @@ mod mt_tests {
     use tempfile::TempDir;
 
     fn write_expr(path: &std::path::Path) {
+        write_csc(path, 5, &[vec![(0, 1.0), (4, 2.0)], vec![(2, 3.0)]]);
+    }
+
+    fn write_csc(path: &std::path::Path, n_cells: usize, genes: &[Vec<(u32, f32)>]) {
+        let mut gene_ptr: Vec<u64> = vec![0];
+        let mut cell_idx: Vec<u32> = Vec::new();
+        let mut values: Vec<f32> = Vec::new();
+        for gene in genes {
+            for &(cell, value) in gene {
+                cell_idx.push(cell);
+                values.push(value);
+            }
+            gene_ptr.push(cell_idx.len() as u64);
+        }
         let header = ExprHeaderV1 {
             version: VERSION,
-            n_genes: 2,
-            n_cells: 5,
-            nnz: 3,
+            n_genes: genes.len() as u32,
+            n_cells: n_cells as u32,
+            nnz: values.len() as u64,
             layout: LAYOUT_CSC,
         };
-        let gene_ptr: Vec<u64> = vec![0, 2, 3];
-        let cell_idx: Vec<u32> = vec![0, 4, 2];
-        let values: Vec<f32> = vec![1.0, 2.0, 3.0];
 
         let file = File::create(path).unwrap();
         let mut w = BufWriter::new(file);
@@ -52,4 +63,127 @@ mod mt_tests {
         reducer_sc.per_cell_raw(&[0, 1], &mut out_sc).unwrap();
         assert_eq!(out_mt, out_sc);
     }
+
+    // Spans several 4096-cell shards.
+    fn write_large(path: &std::path::Path) -> usize {
+        let n_cells = 10_000;
+        let genes = (0..6)
+            .map(|g| {
+                (0..n_cells as u32)
+                    .filter(|c| (c + g) % 3 != 0)
+                    .map(|c| (c, ((c * 7 + g * 13) % 17) as f32 * 0.25))
+                    .collect()
+            })
+            .collect::<Vec<Vec<(u32, f32)>>>();
+        write_csc(path, n_cells, &genes);
+        n_cells
+    }
+
+    #[test]
+    fn genesets_reduced_together_match_one_at_a_time() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("expr.bin");
+        let n_cells = write_large(&path);
+        let (header, mmap) = open_mmap(&path).unwrap();
+        let reader = ExprReader::new(&header, &mmap);
+
+        let genesets: [&[usize]; 4] = [&[0, 1], &[5, 2, 3], &[4], &[0, 1, 2, 3, 4, 5]];
+        let mut parallel = GeneSetReducer::new(&reader, 4, 0, false);
+        let many = parallel.per_cell_raw_many(&genesets).unwrap();
+        assert_eq!(many.len(), genesets.len());
+
+        let mut single = GeneSetReducer::new(&reader, 1, 0, false);
+        for (genes, got) in genesets.iter().zip(&many) {
+            let mut expected = vec![0.0f32; n_cells];
+            single.per_cell_raw(genes, &mut expected).unwrap();
+            assert_eq!(got, &expected, "{:?}", genes);
+        }
+    }
+
+    #[test]
+    fn mt_reduce_propagates_errors() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("expr.bin");
+        write_csc(
+            &path,
+            5000,
+            &[vec![(1, 1.0), (4500, f32::NAN)], vec![(2, 3.0)]],
+        );
+        let (header, mmap) = open_mmap(&path).unwrap();
+        let reader = ExprReader::new(&header, &mmap);
+
+        let mut reducer = GeneSetReducer::new(&reader, 2, 0, false);
+        let mut out = vec![0.0f32; reader.n_cells()];
+        let err = reducer.per_cell_raw(&[0, 1], &mut out).unwrap_err();
+        assert!(err.to_string().contains("NaN"));
+        assert!(reducer.per_cell_raw_many(&[&[1], &[0]]).is_err());
+    }
+
+    #[test]
+    fn threaded_pipeline_run_matches_single_thread() {
+        use assert_cmd::Command;
+
+        let input = TempDir::new().unwrap();
+        let genes = [
+            "PSMA1", "PSMB8", "UBB", "HSPA1A", "HSPA5", "DNAJB1", "ATF4", "XBP1", "DERL1", "VCP",
+        ];
+        let n_cells = 60;
+        let mut entries = Vec::new();
+        for g in 0..genes.len() {
+            for c in 0..n_cells {
+                let count = (g * 29 + c * 11) % 19;
+                if count > 5 {
+                    entries.push(format!("{} {} {}", g + 1, c + 1, count));
+                }
+            }
+        }
+        std::fs::write(
+            input.path().join("matrix.mtx"),
+            format!(
+                "%%MatrixMarket matrix coordinate integer general\n{} {} {}\n{}\n",
+                genes.len(),
+                n_cells,
+                entries.len(),
+                entries.join("\n")
+            ),
+        )
+        .unwrap();
+        let features = genes
+            .iter()
+            .enumerate()
+            .map(|(i, g)| format!("ENSG{:05}\t{}\n", i, g))
+            .collect::<String>();
+        std::fs::write(input.path().join("features.tsv"), features).unwrap();
+        let barcodes = (0..n_cells)
+            .map(|c| format!("C{}\n", c))
+            .collect::<String>();
+        std::fs::write(input.path().join("barcodes.tsv"), barcodes).unwrap();
+
+        let run = |threads: &str| {
+            let out = TempDir::new().unwrap();
+            let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+            cmd.args([
+                "run",
+                "--input",
+                input.path().to_str().unwrap(),
+                "--out",
+                out.path().to_str().unwrap(),
+                "--mode",
+                "cell",
+                "--run-mode",
+                "pipeline",
+                "--fusion",
+                "off",
+                "--cache-block",
+                "0",
+                "--threads",
+                threads,
+            ]);
+            cmd.assert().success();
+            let dir = out.path().join("kira-proteoqc");
+            ["proteoqc.tsv", "panels_report.tsv", "summary.json"]
+                .map(|f| std::fs::read_to_string(dir.join(f)).unwrap())
+        };
+        assert_eq!(run("4"), run("1"));
+    }
 }
@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::Path;
+
+use kira_proteoqc::ctx::Ctx;
+use kira_proteoqc::expr::normalize::{NormalizationMethod, describe};
+use kira_proteoqc::math::reduce::GeneSetReducer;
+use kira_proteoqc::pipeline::Pipeline;
+use kira_proteoqc::pipeline::stage1_input::Stage1Input;
+use kira_proteoqc::pipeline::stage3_expr_cache::Stage3ExprCache;
+use kira_proteoqc::schema::v1::Mode;
+use tempfile::TempDir;
+
+fn write_10x(dir: &Path) {
+    fs::write(
+        dir.join("matrix.mtx"),
+        "%%MatrixMarket matrix coordinate integer general\n3 2 4\n1 1 1\n1 2 2\n3 1 3\n2 2 4\n",
+    )
+    .unwrap();
+    fs::write(dir.join("features.tsv"), "g1\tG1\ng2\tG2\ng3\tG3\n").unwrap();
+    fs::write(dir.join("barcodes.tsv"), "c1\nc2\n").unwrap();
+}
+
+fn run(dir: &Path, method: NormalizationMethod, log1p: bool) -> Ctx {
+    let mut ctx = Ctx::new(
+        dir.to_path_buf(),
+        dir.join("out"),
+        Mode::Cell,
+        false,
+        None,
+        log1p,
+        false,
+        false,
+        "0.0.0-test",
+    );
+    ctx.normalization = method;
+    Pipeline::new(vec![
+        Box::new(Stage1Input::new()),
+        Box::new(Stage3ExprCache::new()),
+    ])
+    .run(&mut ctx)
+    .unwrap();
+    ctx
+}
+
+fn gene_means(ctx: &Ctx, genes: &[usize]) -> Vec<f32> {
+    let reader = ctx.expr_reader().unwrap();
+    let mut reducer = GeneSetReducer::new(&reader, 1, 0, false);
+    let mut out = vec![0.0f32; reader.n_cells()];
+    reducer.per_cell_raw(genes, &mut out).unwrap();
+    out
+}
+
+#[test]
+fn library_sizes_are_computed_from_raw_cache() {
+    let tmp = TempDir::new().unwrap();
+    write_10x(tmp.path());
+    let ctx = run(tmp.path(), NormalizationMethod::Cp10k, true);
+    assert_eq!(ctx.cell_qc.libsize, vec![4.0, 6.0]);
+}
+
+#[test]
+fn raw_without_log1p_is_identity() {
+    let tmp = TempDir::new().unwrap();
+    write_10x(tmp.path());
+    let ctx = run(tmp.path(), NormalizationMethod::Raw, false);
+    assert!(ctx.cell_normalizer.is_none());
+    assert_eq!(gene_means(&ctx, &[0]), vec![1.0, 2.0]);
+}
+
+#[test]
+fn cp10k_log1p_scales_by_library_size() {
+    let tmp = TempDir::new().unwrap();
+    write_10x(tmp.path());
+    let ctx = run(tmp.path(), NormalizationMethod::Cp10k, true);
+    let got = gene_means(&ctx, &[0]);
+    let expected = [
+        (1.0f32 * 1.0e4 / 4.0).ln_1p(),
+        (2.0f32 * 1.0e4 / 6.0).ln_1p(),
+    ];
+    for (g, e) in got.iter().zip(expected.iter()) {
+        assert!((g - e).abs() < 1e-4, "{g} != {e}");
+    }
+}
+
+#[test]
+fn cpm_without_log1p_sums_to_target() {
+    let tmp = TempDir::new().unwrap();
+    write_10x(tmp.path());
+    let ctx = run(tmp.path(), NormalizationMethod::Cpm, false);
+    let all = gene_means(&ctx, &[0, 1, 2]);
+    for v in all {
+        assert!((v * 3.0 - 1.0e6).abs() < 1.0);
+    }
+}
+
+#[test]
+fn prenormalized_ignores_log1p() {
+    let tmp = TempDir::new().unwrap();
+    write_10x(tmp.path());
+    let ctx = run(tmp.path(), NormalizationMethod::Prenormalized, true);
+    assert!(ctx.cell_normalizer.is_none());
+    assert_eq!(gene_means(&ctx, &[2]), vec![3.0, 0.0]);
+    assert!(!ctx.report.input_meta.normalization.log1p);
+    assert_eq!(ctx.report.input_meta.normalization.method, "prenormalized");
+}
+
+#[test]
+fn report_describes_applied_normalization() {
+    let n = describe(NormalizationMethod::Cp10k, true);
+    assert_eq!(n.method, "cp10k");
+    assert!(n.cp10k && n.log1p && !n.cpm && !n.raw);
+    let n = describe(NormalizationMethod::Raw, false);
+    assert!(n.raw && !n.cp10k && !n.log1p);
+}
@@ -0,0 +1,33 @@
+use anyhow::bail;
+use kira_proteoqc::parallel;
+
+#[test]
+fn try_map_keeps_item_order() {
+    let items = (0..100).collect::<Vec<u32>>();
+    for threads in [0, 1, 4] {
+        let out = parallel::try_map(threads, &items, |v| Ok(v * 2)).unwrap();
+        assert_eq!(out, items.iter().map(|v| v * 2).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn try_map_propagates_errors() {
+    let items = (0..100).collect::<Vec<u32>>();
+    for threads in [0, 1, 4] {
+        let err = parallel::try_map(threads, &items, |&v| {
+            if v == 57 {
+                bail!("item {} failed", v);
+            }
+            Ok(v)
+        })
+        .unwrap_err();
+        assert_eq!(err.to_string(), "item 57 failed");
+    }
+}
+
+#[test]
+fn join_returns_both_results() {
+    for threads in [0, 1] {
+        assert_eq!(parallel::join(threads, || 1, || "b"), (1, "b"));
+    }
+}
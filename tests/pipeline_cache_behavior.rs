@@ -3,8 +3,11 @@ use std::path::Path;
 
 use crc::{CRC_64_ECMA_182, Crc};
 use kira_proteoqc::ctx::{Ctx, RunMode};
+use kira_proteoqc::io::shared_cache::write_shared_cache;
+use kira_proteoqc::math::reduce::GeneSetReducer;
 use kira_proteoqc::pipeline::Pipeline;
 use kira_proteoqc::pipeline::stage1_input::Stage1Input;
+use kira_proteoqc::pipeline::stage3_expr_cache::Stage3ExprCache;
 use kira_proteoqc::schema::v1::Mode;
 use tempfile::TempDir;
 
@@ -23,6 +26,82 @@ fn pipeline_mode_uses_cache_when_present() {
     assert_eq!(ctx.nnz, 3);
 }
 
+#[test]
+fn pipeline_mode_reads_shared_cache_in_place() {
+    let tmp = TempDir::new().unwrap();
+    let genes = ["G1", "G2", "G3", "G4", "G5"].map(String::from);
+    let barcodes = ["C1", "C2", "C3", "C4", "C5", "C6"].map(String::from);
+    let col_ptr = [0u64, 3, 3, 5, 9, 10, 13];
+    let row_idx = [0u32, 2, 4, 1, 2, 0, 1, 3, 4, 2, 0, 3, 4];
+    let values = [5u32, 1, 7, 2, 9, 3, 3, 1, 4, 6, 8, 2, 1];
+    write_shared_cache(
+        &tmp.path().join("kira-organelle.bin"),
+        &genes,
+        &barcodes,
+        &col_ptr,
+        &row_idx,
+        &values,
+    )
+    .unwrap();
+    let stages = || {
+        Pipeline::new(vec![
+            Box::new(Stage1Input::new()),
+            Box::new(Stage3ExprCache::new()),
+        ])
+    };
+
+    let mut direct = make_ctx(tmp.path());
+    stages().run(&mut direct).unwrap();
+    assert!(direct.expr_header.is_none());
+    assert!(!tmp.path().join("out").join("expr.bin").exists());
+
+    // Reference: the same cache transposed into expr.bin in small chunks.
+    let mut built = make_ctx(tmp.path());
+    built.output.out_dir = tmp.path().join("built");
+    built.expr_path = built.output.out_dir.join("expr.bin");
+    built.max_memory = Some(24);
+    Pipeline::new(vec![Box::new(Stage1Input::new())])
+        .run(&mut built)
+        .unwrap();
+    built.shared_cache = None;
+    Pipeline::new(vec![Box::new(Stage3ExprCache::new())])
+        .run(&mut built)
+        .unwrap();
+    assert!(built.expr_header.is_some());
+
+    assert_eq!(direct.cell_qc.libsize, built.cell_qc.libsize);
+    assert_eq!(direct.cell_qc.nnz, built.cell_qc.nnz);
+    assert_eq!(
+        direct.cell_qc.expressed_genes,
+        built.cell_qc.expressed_genes
+    );
+    assert_eq!(direct.cell_qc.detected_genes, 5);
+    assert_eq!(built.cell_qc.detected_genes, 5);
+
+    let cell_major = direct.expr_reader().unwrap();
+    let gene_major = built.expr_reader().unwrap();
+    assert!(cell_major.is_cell_major());
+    for gene in 0..genes.len() {
+        assert_eq!(
+            cell_major.gene_slice(gene).unwrap(),
+            gene_major.gene_slice(gene).unwrap()
+        );
+        let tail = cell_major.gene_entries_from(gene, 3).unwrap();
+        assert!(tail.map(|(cell, _)| cell).all(|cell| cell >= 3));
+    }
+    for set in [vec![0, 2, 4], vec![4, 1], vec![3]] {
+        let mut a = vec![0.0f32; barcodes.len()];
+        let mut b = vec![0.0f32; barcodes.len()];
+        GeneSetReducer::new(&cell_major, 1, 0, false)
+            .per_cell_raw(&set, &mut a)
+            .unwrap();
+        GeneSetReducer::new(&gene_major, 1, 0, false)
+            .per_cell_raw(&set, &mut b)
+            .unwrap();
+        assert_eq!(a, b, "geneset {set:?}");
+    }
+}
+
 #[test]
 fn pipeline_mode_falls_back_to_mtx_when_cache_missing() {
     let tmp = TempDir::new().unwrap();
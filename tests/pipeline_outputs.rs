@@ -21,7 +21,7 @@ fn pipeline_tsv_header_order_is_exact() {
         .to_string();
     assert_eq!(
         header,
-        "barcode\tsample\tcondition\tspecies\tlibsize\tnnz\texpressed_genes\tproteostasis_load\tmisfolded_protein_burden\tchaperone_capacity\tproteasome_activity_proxy\tprotein_quality_balance\tstress_proteostasis_index\tregime\tflags\tconfidence\tchaperone_core\tproteasome_core\tupr_core\tagg_core\tCCI\tPCI\tUPR_A\tPLS\tSCI\tPCP\tchaperone_high\tproteasome_high\tupr_active\tproteotoxic_high\timbalance_high\tcollapse_risk"
+        "barcode\tsample\tcondition\tspecies\tlibsize\tnnz\texpressed_genes\tproteostasis_load\tmisfolded_protein_burden\tchaperone_capacity\tproteasome_activity_proxy\tprotein_quality_balance\tstress_proteostasis_index\tregime\tflags\tconfidence\tchaperone_core\tproteasome_core\tupr_core\tagg_core\tCCI\tPCI\tUPR_A\tPLS\tSCI\tPCP\tchaperone_high\tproteasome_high\tupr_active\tproteotoxic_high\timbalance_high\tcollapse_risk\tcluster\timmunoproteasome_proxy\timmunoproteasome_ratio"
     );
 }
 
@@ -37,6 +37,7 @@ fn pipeline_summary_json_schema_fields_exist() {
     assert_eq!(v["tool"]["name"], "kira-proteoqc");
     assert!(v["tool"]["version"].is_string());
     assert!(v["tool"]["simd"].is_string());
+    assert_eq!(v["geneset_version"], "v1");
     assert!(v["input"]["n_cells"].is_number());
     assert!(v["input"]["species"].is_string());
     assert!(v["distributions"]["proteostasis_load"]["median"].is_number());
@@ -52,6 +53,38 @@ fn pipeline_summary_json_schema_fields_exist() {
     assert!(v["proteostasis_extension"]["missingness"].is_object());
 }
 
+#[test]
+fn pipeline_tsv_reports_cell_qc_columns() {
+    let tmp = TempDir::new().unwrap();
+    let out = TempDir::new().unwrap();
+    write_10x(tmp.path());
+    run_pipeline(tmp.path(), out.path());
+
+    let tsv = fs::read_to_string(out.path().join("kira-proteoqc").join("proteoqc.tsv")).unwrap();
+    let rows = tsv
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let cols = line.split('\t').collect::<Vec<_>>();
+            (cols[0].to_string(), cols[4..7].join(","))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![
+            ("C1".to_string(), "6,2,2".to_string()),
+            ("C2".to_string(), "7,1,1".to_string()),
+        ]
+    );
+
+    let summary = out.path().join("kira-proteoqc").join("summary.json");
+    let v: Value = serde_json::from_slice(&fs::read(summary).unwrap()).unwrap();
+    assert_eq!(v["distributions"]["libsize"]["median"], 6.0);
+    assert_eq!(v["distributions"]["nnz"]["p99"], 1.0);
+    assert_eq!(v["distributions"]["expressed_genes"]["median"], 1.0);
+    assert_eq!(v["qc"]["empty_cell_fraction"], 0.0);
+}
+
 #[test]
 fn pipeline_step_json_matches_schema() {
     let tmp = TempDir::new().unwrap();
@@ -63,6 +96,7 @@ fn pipeline_step_json_matches_schema() {
     let v: Value = serde_json::from_slice(&fs::read(step).unwrap()).unwrap();
     assert_eq!(v["tool"]["name"], "kira-proteoqc");
     assert_eq!(v["tool"]["stage"], "proteostasis");
+    assert_eq!(v["geneset_version"], "v1");
     assert!(v["tool"]["version"].is_string());
     assert_eq!(v["artifacts"]["summary"], "summary.json");
     assert_eq!(v["artifacts"]["primary_metrics"], "proteoqc.tsv");
@@ -73,6 +107,13 @@ fn pipeline_step_json_matches_schema() {
     assert_eq!(v["cell_metrics"]["confidence_column"], "confidence");
     assert_eq!(v["cell_metrics"]["flag_column"], "flags");
     assert!(v["regimes"].is_array());
+    assert!(
+        v["regimes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|r| r == "ImmunoproteasomeSwitch")
+    );
 }
 
 #[test]
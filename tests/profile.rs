@@ -0,0 +1,123 @@
+use std::fs;
+
+use kira_proteoqc::schema::v1::Mode;
+use kira_proteoqc::scores::AxisRawScores;
+use kira_proteoqc::scores::integrated::{compute_integrated, compute_integrated_with};
+use kira_proteoqc::scores::profile::{builtin_profile, load_profile};
+use tempfile::TempDir;
+
+fn axis() -> AxisRawScores {
+    AxisRawScores {
+        pcs: vec![1.0, 2.3, -0.7, 0.0, 13.1],
+        utp: vec![0.5, 1.5, 0.01, -2.2, 7.7],
+        cls: vec![2.0, 0.0, 3.3, 0.9, -1.1],
+        erad: vec![1.0, 1.0, 0.2, 5.5, 0.3],
+        ribo: vec![3.0, 2.0, 1.7, 0.4, 9.9],
+        ips: vec![0.0; 5],
+        constitutive: vec![0.0; 5],
+    }
+}
+
+#[test]
+fn builtin_profile_matches_reference_formulas_bitwise() {
+    let axis = axis();
+    let (integrated, contrib) = compute_integrated(&axis, Mode::Cell).unwrap();
+    for i in 0..axis.pcs.len() {
+        let (pcs, utp, cls, erad, ribo) = (
+            axis.pcs[i],
+            axis.utp[i],
+            axis.cls[i],
+            axis.erad[i],
+            axis.ribo[i],
+        );
+        let capacity = 0.55 * pcs + 0.25 * cls + 0.20 * erad;
+        let pii = ribo - capacity;
+        let pfs = 0.40 * pii + 0.25 * utp + 0.20 * ribo - 0.15 * pcs;
+        assert_eq!(integrated.capacity_raw[i].to_bits(), capacity.to_bits());
+        assert_eq!(integrated.pii_raw[i].to_bits(), pii.to_bits());
+        assert_eq!(integrated.pfs_raw[i].to_bits(), pfs.to_bits());
+        assert_eq!(contrib.c_pii[i].to_bits(), (0.40 * pii).to_bits());
+        assert_eq!(contrib.c_pcs[i].to_bits(), (-0.15 * pcs).to_bits());
+    }
+}
+
+#[test]
+fn profile_file_round_trips_with_same_hash() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("profile.json");
+    fs::write(
+        &path,
+        serde_json::to_string_pretty(builtin_profile()).unwrap(),
+    )
+    .unwrap();
+
+    let loaded = load_profile(&path).unwrap();
+    assert_eq!(loaded.name, "proteoqc_default");
+    assert_eq!(loaded.hash(), builtin_profile().hash());
+    assert!(loaded.hash().starts_with("crc64:"));
+}
+
+#[test]
+fn custom_profile_changes_integrated_weights() {
+    let mut profile = builtin_profile().clone();
+    let pfs = profile
+        .integrated
+        .iter_mut()
+        .find(|d| d.score == "pfs")
+        .unwrap();
+    pfs.terms.retain(|t| t.input == "pii");
+    pfs.terms[0].weight = 1.0;
+    assert_ne!(profile.hash(), builtin_profile().hash());
+
+    let (integrated, contrib) = compute_integrated_with(&axis(), Mode::Cell, &profile).unwrap();
+    assert_eq!(integrated.pfs_raw, integrated.pii_raw);
+    assert!(contrib.c_utp.iter().all(|&v| v == 0.0));
+}
+
+#[test]
+fn invalid_profiles_are_rejected() {
+    let tmp = TempDir::new().unwrap();
+    let base = serde_json::to_value(builtin_profile()).unwrap();
+
+    let mut forward = base.clone();
+    forward["integrated"][0]["terms"][0]["input"] = "pfs".into();
+    let mut missing_axis = base.clone();
+    missing_axis["axes"].as_array_mut().unwrap().pop();
+    let mut unknown_field = base;
+    unknown_field["weights"] = 1.into();
+
+    for (name, value, expected) in [
+        ("forward", forward, "before it is defined"),
+        ("missing_axis", missing_axis, "axis 'ribo' is not defined"),
+        (
+            "unknown_field",
+            unknown_field,
+            "invalid scoring profile JSON",
+        ),
+    ] {
+        let path = tmp.path().join(format!("{name}.json"));
+        fs::write(&path, value.to_string()).unwrap();
+        let err = format!("{:#}", load_profile(&path).unwrap_err());
+        assert!(err.contains(expected), "{name}: {err}");
+    }
+}
+
+#[test]
+fn five_axis_profile_defaults_ips_to_immunoproteasome() {
+    let tmp = TempDir::new().unwrap();
+    let mut value = serde_json::to_value(builtin_profile()).unwrap();
+    value["axes"]
+        .as_array_mut()
+        .unwrap()
+        .retain(|axis| axis["axis"] != "ips");
+    let path = tmp.path().join("five_axis.json");
+    fs::write(&path, value.to_string()).unwrap();
+
+    let loaded = load_profile(&path).unwrap();
+    assert_eq!(loaded.axes.len(), 5);
+    let ips = loaded.axis_terms("ips");
+    assert_eq!(ips.len(), 1);
+    assert_eq!(ips[0].geneset, "immunoproteasome");
+    assert_eq!(ips[0].weight, 1.0);
+    assert!(loaded.genesets().contains(&"immunoproteasome"));
+}
@@ -1,6 +1,7 @@
 use kira_proteoqc::ctx::Ctx;
 use kira_proteoqc::schema::v1::Mode;
 use kira_proteoqc::scores::risk::compute_risk_flags;
+use kira_proteoqc::scores::rules::{builtin_rules, ips_switch_condition, load_rules, merge_rules};
 use kira_proteoqc::scores::{AxisRawScores, IntegratedScores};
 
 fn make_ctx(mode: Mode, axis: AxisRawScores, integrated: IntegratedScores) -> Ctx {
@@ -32,6 +33,8 @@ fn fragile_high_cell_only() {
         cls: zeros(3),
         erad: zeros(3),
         ribo: zeros(3),
+        ips: zeros(3),
+        constitutive: zeros(3),
     };
     let integrated = IntegratedScores {
         capacity_raw: zeros(3),
@@ -40,6 +43,8 @@ fn fragile_high_cell_only() {
         capacity_z: Some(zeros(3)),
         pii_z: Some(zeros(3)),
         pfs_z: Some(vec![0.0, 0.0, 2.0]),
+        ips_ratio: zeros(3),
+        ips_z: Some(zeros(3)),
     };
     let mut ctx = make_ctx(Mode::Cell, axis, integrated);
     let flags = compute_risk_flags(&mut ctx).unwrap();
@@ -56,6 +61,8 @@ fn proteasome_addiction_cell_only() {
         cls: zeros(3),
         erad: zeros(3),
         ribo: zeros(3),
+        ips: zeros(3),
+        constitutive: zeros(3),
     };
     let integrated = IntegratedScores {
         capacity_raw: zeros(3),
@@ -64,6 +71,8 @@ fn proteasome_addiction_cell_only() {
         capacity_z: Some(zeros(3)),
         pii_z: Some(zeros(3)),
         pfs_z: Some(vec![0.0, 0.0, 0.5]),
+        ips_ratio: zeros(3),
+        ips_z: Some(zeros(3)),
     };
     let mut ctx = make_ctx(Mode::Cell, axis, integrated);
     let flags = compute_risk_flags(&mut ctx).unwrap();
@@ -83,6 +92,8 @@ fn proteotoxic_stress_cell_only() {
         cls: vec![0.0, 1.0, 4.0],
         erad: zeros(3),
         ribo: zeros(3),
+        ips: zeros(3),
+        constitutive: zeros(3),
     };
     let integrated = IntegratedScores {
         capacity_raw: zeros(3),
@@ -91,6 +102,8 @@ fn proteotoxic_stress_cell_only() {
         capacity_z: Some(zeros(3)),
         pii_z: Some(vec![0.0, 0.0, 2.0]),
         pfs_z: Some(zeros(3)),
+        ips_ratio: zeros(3),
+        ips_z: Some(zeros(3)),
     };
     let mut ctx = make_ctx(Mode::Cell, axis, integrated);
     let flags = compute_risk_flags(&mut ctx).unwrap();
@@ -110,6 +123,8 @@ fn er_degradation_overdrive_cell_only() {
         cls: zeros(3),
         erad: vec![0.0, 1.0, 4.0],
         ribo: zeros(3),
+        ips: zeros(3),
+        constitutive: zeros(3),
     };
     let integrated = IntegratedScores {
         capacity_raw: zeros(3),
@@ -118,6 +133,8 @@ fn er_degradation_overdrive_cell_only() {
         capacity_z: Some(zeros(3)),
         pii_z: Some(vec![0.0, 0.0, 2.0]),
         pfs_z: Some(zeros(3)),
+        ips_ratio: zeros(3),
+        ips_z: Some(zeros(3)),
     };
     let mut ctx = make_ctx(Mode::Cell, axis, integrated);
     let flags = compute_risk_flags(&mut ctx).unwrap();
@@ -137,6 +154,8 @@ fn boundary_not_fired() {
         cls: zeros(3),
         erad: zeros(3),
         ribo: zeros(3),
+        ips: zeros(3),
+        constitutive: zeros(3),
     };
     let integrated = IntegratedScores {
         capacity_raw: zeros(3),
@@ -145,9 +164,251 @@ fn boundary_not_fired() {
         capacity_z: Some(zeros(3)),
         pii_z: Some(zeros(3)),
         pfs_z: Some(vec![0.0, 0.0, 1.5]),
+        ips_ratio: zeros(3),
+        ips_z: Some(zeros(3)),
     };
     let mut ctx = make_ctx(Mode::Cell, axis, integrated);
     let flags = compute_risk_flags(&mut ctx).unwrap();
     let fragile = flags.iter().find(|f| f.name == "fragile_high").unwrap();
     assert!(!fragile.fired);
 }
+
+#[test]
+fn immunoproteasome_switch_cell_and_sample() {
+    let axis = AxisRawScores {
+        pcs: vec![1.0, 1.0, 0.2, 1.0],
+        utp: zeros(4),
+        cls: zeros(4),
+        erad: zeros(4),
+        ribo: zeros(4),
+        ips: vec![0.1, 0.2, 3.0, 0.1],
+        constitutive: zeros(4),
+    };
+    let integrated = IntegratedScores {
+        capacity_raw: zeros(4),
+        pii_raw: zeros(4),
+        pfs_raw: zeros(4),
+        capacity_z: Some(zeros(4)),
+        pii_z: Some(zeros(4)),
+        pfs_z: Some(zeros(4)),
+        ips_ratio: vec![0.09, 0.17, 0.94, 0.09],
+        ips_z: Some(vec![-0.5, 0.0, 8.0, -0.5]),
+    };
+    let mut ctx = make_ctx(Mode::Cell, axis, integrated);
+    let flags = compute_risk_flags(&mut ctx).unwrap();
+    let switch = flags
+        .iter()
+        .find(|f| f.name == "immunoproteasome_switch")
+        .unwrap();
+    assert!(switch.fired);
+    assert_eq!(switch.threshold, "IPS_z > 1.0 && IPS_ratio > 0.5");
+    assert_eq!(switch.details.as_deref(), Some("fraction=0.2500"));
+
+    let axis = AxisRawScores {
+        pcs: vec![0.5],
+        utp: vec![0.0],
+        cls: vec![0.0],
+        erad: vec![0.0],
+        ribo: vec![0.0],
+        ips: vec![1.5],
+        constitutive: vec![0.0],
+    };
+    let integrated = IntegratedScores {
+        capacity_raw: vec![0.0],
+        pii_raw: vec![0.0],
+        pfs_raw: vec![0.0],
+        capacity_z: None,
+        pii_z: None,
+        pfs_z: None,
+        ips_ratio: vec![0.75],
+        ips_z: None,
+    };
+    let mut ctx = make_ctx(Mode::Sample, axis, integrated);
+    ctx.risk_rules
+        .retain(|r| r.name == "immunoproteasome_switch");
+    let flags = compute_risk_flags(&mut ctx).unwrap();
+    let switch = &flags[0];
+    assert!(switch.fired);
+    assert_eq!(
+        switch.details.as_deref(),
+        Some("IPS_raw=1.5000, IPS_ratio=0.7500")
+    );
+}
+
+#[test]
+fn flags_report_source_expression() {
+    let axis = AxisRawScores {
+        pcs: zeros(3),
+        utp: zeros(3),
+        cls: zeros(3),
+        erad: zeros(3),
+        ribo: zeros(3),
+        ips: zeros(3),
+        constitutive: zeros(3),
+    };
+    let integrated = IntegratedScores {
+        capacity_raw: zeros(3),
+        pii_raw: zeros(3),
+        pfs_raw: zeros(3),
+        capacity_z: Some(zeros(3)),
+        pii_z: Some(zeros(3)),
+        pfs_z: Some(vec![0.0, 0.0, 2.0]),
+        ips_ratio: zeros(3),
+        ips_z: Some(zeros(3)),
+    };
+    let mut ctx = make_ctx(Mode::Cell, axis, integrated);
+    let flags = compute_risk_flags(&mut ctx).unwrap();
+    let names = flags.iter().map(|f| f.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            "fragile_high",
+            "proteasome_addiction",
+            "proteotoxic_stress",
+            "er_degradation_overdrive",
+            "immunoproteasome_switch"
+        ]
+    );
+    assert_eq!(flags[0].threshold, "PFS_z > 1.5");
+    assert_eq!(flags[0].details.as_deref(), Some("fraction=0.3333"));
+}
+
+#[test]
+fn custom_rules_extend_and_override_builtins() {
+    let tmp = tempfile::TempDir::new().unwrap();
+    let path = tmp.path().join("rules.json");
+    std::fs::write(
+        &path,
+        r#"{"rules": [
+            {"name": "fragile_high", "cell": {"when": "PFS_z > 5.0"}},
+            {"name": "ribo_heavy", "cell": {"when": "Ribo_raw >= 2.0 && Capacity_raw < 1.0", "min_fraction": 0.5},
+             "sample": {"when": "Ribo_raw >= 2.0"}}
+        ]}"#,
+    )
+    .unwrap();
+    let rules = merge_rules(builtin_rules(), load_rules(&path).unwrap());
+    assert_eq!(rules.len(), 6);
+    assert_eq!(rules[0].cell.as_ref().unwrap().source, "PFS_z > 5.0");
+    assert!(rules[0].sample.is_none());
+
+    let axis = AxisRawScores {
+        pcs: zeros(4),
+        utp: zeros(4),
+        cls: zeros(4),
+        erad: zeros(4),
+        ribo: vec![2.0, 3.0, 1.0, 2.5],
+        ips: zeros(4),
+        constitutive: zeros(4),
+    };
+    let integrated = IntegratedScores {
+        capacity_raw: vec![0.0, 2.0, 0.0, 0.5],
+        pii_raw: zeros(4),
+        pfs_raw: zeros(4),
+        capacity_z: Some(zeros(4)),
+        pii_z: Some(zeros(4)),
+        pfs_z: Some(vec![0.0, 0.0, 2.0, 0.0]),
+        ips_ratio: zeros(4),
+        ips_z: Some(zeros(4)),
+    };
+    let mut ctx = make_ctx(Mode::Cell, axis, integrated);
+    ctx.risk_rules = rules;
+    let flags = compute_risk_flags(&mut ctx).unwrap();
+    let fragile = flags.iter().find(|f| f.name == "fragile_high").unwrap();
+    assert!(!fragile.fired);
+    let ribo = flags.iter().find(|f| f.name == "ribo_heavy").unwrap();
+    assert!(ribo.fired);
+    assert_eq!(ribo.threshold, "Ribo_raw >= 2.0 && Capacity_raw < 1.0");
+    assert_eq!(ribo.details.as_deref(), Some("fraction=0.5000"));
+
+    let axis = AxisRawScores {
+        pcs: vec![0.0],
+        utp: vec![0.0],
+        cls: vec![0.0],
+        erad: vec![0.0],
+        ribo: vec![2.0],
+        ips: vec![0.0],
+        constitutive: vec![0.0],
+    };
+    let integrated = IntegratedScores {
+        capacity_raw: vec![0.0],
+        pii_raw: vec![0.0],
+        pfs_raw: vec![0.0],
+        capacity_z: None,
+        pii_z: None,
+        pfs_z: None,
+        ips_ratio: vec![0.0],
+        ips_z: None,
+    };
+    let mut ctx = make_ctx(Mode::Sample, axis, integrated);
+    ctx.risk_rules = load_rules(&path).unwrap();
+    let flags = compute_risk_flags(&mut ctx).unwrap();
+    assert_eq!(flags.len(), 1);
+    assert!(flags[0].fired);
+    assert_eq!(flags[0].details.as_deref(), Some("Ribo_raw=2.0000"));
+}
+
+#[test]
+fn invalid_rules_are_rejected() {
+    let tmp = tempfile::TempDir::new().unwrap();
+    for (name, body, expected) in [
+        (
+            "op",
+            r#"{"rules": [{"name": "a", "cell": {"when": "PFS_z == 1"}}]}"#,
+            "not a comparison",
+        ),
+        (
+            "var",
+            r#"{"rules": [{"name": "a", "cell": {"when": "FOO_z > 1"}}]}"#,
+            "unknown score 'FOO_z'",
+        ),
+        (
+            "num",
+            r#"{"rules": [{"name": "a", "cell": {"when": "PFS_z > x"}}]}"#,
+            "'x' is not a number",
+        ),
+        (
+            "z",
+            r#"{"rules": [{"name": "a", "sample": {"when": "PFS_z > 1"}}]}"#,
+            "not available at sample scope",
+        ),
+        (
+            "empty",
+            r#"{"rules": [{"name": "a"}]}"#,
+            "no cell or sample expression",
+        ),
+    ] {
+        let path = tmp.path().join(format!("{name}.json"));
+        std::fs::write(&path, body).unwrap();
+        let err = format!("{:#}", load_rules(&path).unwrap_err());
+        assert!(err.contains(expected), "{name}: {err}");
+    }
+}
+
+#[test]
+fn regime_switch_threshold_follows_loaded_rules() {
+    let builtin = ips_switch_condition(builtin_rules(), true).unwrap();
+    assert!(!builtin.holds(0.5));
+    assert!(builtin.holds(0.6));
+
+    let tmp = tempfile::TempDir::new().unwrap();
+    let path = tmp.path().join("rules.json");
+    std::fs::write(
+        &path,
+        r#"{"rules": [
+            {"name": "immunoproteasome_switch",
+             "cell": {"when": "IPS_z > 1.0 && IPS_ratio >= 0.3"},
+             "sample": {"when": "IPS_ratio > 0.8"}}
+        ]}"#,
+    )
+    .unwrap();
+    let rules = merge_rules(builtin_rules(), load_rules(&path).unwrap());
+    assert!(ips_switch_condition(&rules, true).unwrap().holds(0.3));
+    assert!(!ips_switch_condition(&rules, false).unwrap().holds(0.5));
+
+    let without = builtin_rules()
+        .iter()
+        .filter(|r| r.name != "immunoproteasome_switch")
+        .cloned()
+        .collect::<Vec<_>>();
+    assert!(ips_switch_condition(&without, true).is_none());
+}
@@ -50,6 +50,7 @@ fn make_genesets() -> GenesetCollection {
             gene_ids: vec![0],
             missing: vec![],
             total: 1,
+            matches: Vec::new(),
         });
     }
     GenesetCollection {
@@ -90,6 +91,8 @@ fn risk_sample_mode_semantics() {
         cls: vec![0.0],
         erad: vec![0.0],
         ribo: vec![0.0],
+        ips: vec![0.0],
+        constitutive: vec![0.0],
     });
     ctx.integrated_scores = Some(IntegratedScores {
         capacity_raw: vec![0.0],
@@ -98,6 +101,8 @@ fn risk_sample_mode_semantics() {
         capacity_z: None,
         pii_z: None,
         pfs_z: None,
+        ips_ratio: vec![0.0],
+        ips_z: None,
     });
 
     let flags = compute_risk_flags(&mut ctx).unwrap();
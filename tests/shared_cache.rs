@@ -17,7 +17,8 @@ fn reads_valid_shared_cache_and_traverses_arrays() {
     assert_eq!(cache.header.nnz, 3);
     assert_eq!(cache.genes, vec!["G1", "G2", "G3"]);
     assert_eq!(cache.barcodes, vec!["C1", "C2"]);
-    assert_eq!(cache.col_ptr().unwrap(), vec![0, 2, 3]);
+    assert_eq!(cache.col_ptr(), &[0, 2, 3]);
+    assert_eq!(cache.cell(0), (&[0u32, 2][..], &[5u32, 1][..]));
     assert_eq!(cache.row_idx_at(0).unwrap(), 0);
     assert_eq!(cache.row_idx_at(1).unwrap(), 2);
     assert_eq!(cache.row_idx_at(2).unwrap(), 1);
@@ -0,0 +1,100 @@
+use std::fs;
+
+use assert_cmd::Command;
+use kira_proteoqc::simd::{self, Backend};
+use predicates::str::contains;
+use serde_json::Value;
+use tempfile::TempDir;
+
+fn values(n: usize) -> Vec<f32> {
+    (0..n).map(|i| (i % 7) as f32 * 0.5 - 1.0).collect()
+}
+
+// One test, because the backend is process-wide.
+#[test]
+fn every_supported_backend_matches_scalar() {
+    let supported = Backend::ALL
+        .into_iter()
+        .filter(|b| b.is_supported())
+        .collect::<Vec<_>>();
+    assert!(supported.contains(&Backend::Scalar));
+    assert!(supported.contains(&simd::detect()));
+
+    // Lengths around every vector width exercise the scalar tails.
+    for n in [0, 1, 3, 4, 7, 8, 15, 16, 17, 33, 100] {
+        let src = values(n);
+        let mut expected = None;
+        for &backend in &supported {
+            simd::set_backend(backend).unwrap();
+            assert_eq!(simd::backend_name(), backend.as_str());
+
+            let sum = simd::sum_f32(&src);
+            let mut scaled = vec![1.0f32; n];
+            simd::add_scaled(&mut scaled, &src, 0.5);
+            let mut diff = vec![0.0f32; n];
+            simd::abs_diff(&mut diff, &src, 0.25);
+
+            if backend == Backend::Scalar {
+                expected = Some((sum, scaled, diff));
+                continue;
+            }
+            let (e_sum, e_scaled, e_diff) = expected.as_ref().unwrap();
+            assert!((sum - e_sum).abs() < 1e-4, "{} n={}", backend.as_str(), n);
+            assert_eq!(&scaled, e_scaled, "{} n={}", backend.as_str(), n);
+            assert_eq!(&diff, e_diff, "{} n={}", backend.as_str(), n);
+        }
+    }
+}
+
+#[test]
+fn unsupported_backend_is_rejected() {
+    let Some(missing) = Backend::ALL.into_iter().find(|b| !b.is_supported()) else {
+        return;
+    };
+    let err = simd::set_backend(missing).unwrap_err();
+    assert!(err.to_string().contains("is not supported by this CPU"));
+}
+
+#[test]
+fn simd_override_is_reported_in_pipeline_summary() {
+    let tmp = TempDir::new().unwrap();
+    let out = TempDir::new().unwrap();
+    fs::write(
+        tmp.path().join("matrix.mtx"),
+        "%%MatrixMarket matrix coordinate integer general\n2 2 3\n1 1 3\n2 1 1\n2 2 4\n",
+    )
+    .unwrap();
+    fs::write(tmp.path().join("features.tsv"), "g1\tGeneA\ng2\tGeneB\n").unwrap();
+    fs::write(tmp.path().join("barcodes.tsv"), "C1\nC2\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.args([
+        "run",
+        "--simd",
+        "scalar",
+        "--input",
+        tmp.path().to_str().unwrap(),
+        "--out",
+        out.path().to_str().unwrap(),
+        "--mode",
+        "cell",
+        "--run-mode",
+        "pipeline",
+    ]);
+    cmd.assert().success();
+
+    let summary: Value = serde_json::from_slice(
+        &fs::read(out.path().join("kira-proteoqc").join("summary.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(summary["tool"]["simd"], "scalar");
+}
+
+#[test]
+fn unknown_simd_value_is_rejected() {
+    let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
+    cmd.args(["--simd", "avx9", "validate", "--input", "."]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("invalid value 'avx9'"));
+}
@@ -68,3 +68,31 @@ fn stage1_duplicate_gene_symbol_warns() {
     assert_eq!(ctx.gene_index.get("GeneX"), Some(&0));
     assert_eq!(ctx.warnings.len(), 1);
 }
+
+#[test]
+fn stage1_keeps_feature_ids_alongside_symbols() {
+    let tmp = TempDir::new().unwrap();
+    let mtx = "%%MatrixMarket matrix coordinate integer general\n2 1 1\n1 1 1\n";
+    let features =
+        "ENSG00000100567.12\tPSMA3\tGene Expression\nENSG00000173812\t\tGene Expression\n";
+    write_10x(tmp.path(), features, "cell1\n", mtx);
+
+    let mut ctx = Ctx::new(
+        tmp.path().to_path_buf(),
+        tmp.path().join("out"),
+        Mode::Cell,
+        false,
+        None,
+        true,
+        false,
+        false,
+        "0.0.0-test",
+    );
+    Pipeline::new(vec![Box::new(Stage1Input::new())])
+        .run(&mut ctx)
+        .unwrap();
+
+    assert_eq!(ctx.genes, vec!["PSMA3", "ENSG00000173812"]);
+    assert_eq!(ctx.gene_ids, vec!["ENSG00000100567", "ENSG00000173812"]);
+    assert_eq!(ctx.gene_id_index.get("ENSG00000100567"), Some(&0));
+}
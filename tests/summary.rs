@@ -24,6 +24,8 @@ fn summary_format() {
         cls: vec![1.0],
         erad: vec![1.0],
         ribo: vec![1.0],
+        ips: vec![0.0],
+        constitutive: vec![0.0],
     });
     ctx.integrated_scores = Some(IntegratedScores {
         capacity_raw: vec![1.0],
@@ -32,6 +34,8 @@ fn summary_format() {
         capacity_z: None,
         pii_z: None,
         pfs_z: None,
+        ips_ratio: vec![0.0],
+        ips_z: None,
     });
     ctx.risk_flags = vec![RiskFlag {
         name: "fragile_high".to_string(),
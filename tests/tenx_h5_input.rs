@@ -0,0 +1,169 @@
+use kira_proteoqc::ctx::InputFormat;
+use kira_proteoqc::io::features::{FeatureTable, GENE_EXPRESSION, select_feature_type};
+use std::path::Path;
+
+fn table(rows: &[(&str, &str, &str)]) -> FeatureTable {
+    FeatureTable {
+        ids: rows.iter().map(|r| r.0.to_string()).collect(),
+        symbols: rows.iter().map(|r| r.1.to_string()).collect(),
+        feature_types: rows.iter().map(|r| r.2.to_string()).collect(),
+    }
+}
+
+#[test]
+fn detects_tenx_h5_by_extension() {
+    assert_eq!(
+        InputFormat::detect(Path::new("filtered_feature_bc_matrix.h5")),
+        InputFormat::Tenx5
+    );
+    assert_eq!(InputFormat::detect(Path::new("x.h5ad")), InputFormat::H5ad);
+    assert_eq!(
+        InputFormat::detect(Path::new("sample_dir")),
+        InputFormat::Mtx10x
+    );
+}
+
+#[test]
+fn gene_expression_only_input_keeps_every_row() {
+    let selection = select_feature_type(
+        table(&[("g1", "A", GENE_EXPRESSION), ("g2", "B", GENE_EXPRESSION)]),
+        GENE_EXPRESSION,
+    )
+    .unwrap();
+    assert!(selection.row_map.is_none());
+    assert!(selection.dropped_warning().is_none());
+    assert_eq!(selection.table.symbols, vec!["A", "B"]);
+}
+
+#[test]
+fn other_feature_types_are_dropped_and_remapped() {
+    let selection = select_feature_type(
+        table(&[
+            ("g1", "A", GENE_EXPRESSION),
+            ("ab1", "CD3", "Antibody Capture"),
+            ("g2", "B", GENE_EXPRESSION),
+            ("ab2", "CD4", "Antibody Capture"),
+            ("cr1", "guide1", "CRISPR Guide Capture"),
+        ]),
+        GENE_EXPRESSION,
+    )
+    .unwrap();
+    assert_eq!(selection.table.symbols, vec!["A", "B"]);
+    assert_eq!(selection.table.ids, vec!["g1", "g2"]);
+    assert_eq!(
+        selection.row_map,
+        Some(vec![Some(0), None, Some(1), None, None])
+    );
+    assert_eq!(
+        selection.dropped_warning().unwrap(),
+        "dropped 3 features by feature_type: Antibody Capture x2, CRISPR Guide Capture x1"
+    );
+}
+
+#[test]
+fn input_without_gene_expression_rows_is_rejected() {
+    let err = select_feature_type(
+        table(&[("ab1", "CD3", "Antibody Capture")]),
+        GENE_EXPRESSION,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("no 'Gene Expression' features"));
+}
+
+#[cfg(feature = "hdf5")]
+mod hdf5_input {
+    use std::path::Path;
+
+    use hdf5::File;
+    use hdf5::types::FixedAscii;
+    use kira_proteoqc::ctx::Ctx;
+    use kira_proteoqc::pipeline::Pipeline;
+    use kira_proteoqc::pipeline::stage1_input::Stage1Input;
+    use kira_proteoqc::pipeline::stage2_h5ad::Stage2H5ad;
+    use kira_proteoqc::pipeline::stage3_expr_cache::Stage3ExprCache;
+    use kira_proteoqc::schema::v1::Mode;
+    use tempfile::TempDir;
+
+    fn fixed(values: &[&str]) -> Vec<FixedAscii<32>> {
+        values
+            .iter()
+            .map(|v| FixedAscii::from_ascii(v.as_bytes()).unwrap())
+            .collect()
+    }
+
+    fn write_strings(group: &hdf5::Group, name: &str, values: &[&str]) {
+        group
+            .new_dataset::<FixedAscii<32>>()
+            .shape(values.len())
+            .create(name)
+            .unwrap()
+            .write(&fixed(values))
+            .unwrap();
+    }
+
+    fn write_ints<T: hdf5::H5Type>(group: &hdf5::Group, name: &str, values: &[T]) {
+        group
+            .new_dataset::<T>()
+            .shape(values.len())
+            .create(name)
+            .unwrap()
+            .write(values)
+            .unwrap();
+    }
+
+    // 3 features (2 Gene Expression + 1 Antibody Capture) x 2 barcodes.
+    fn write_tenx_h5(path: &Path) {
+        let file = File::create(path).unwrap();
+        let matrix = file.create_group("matrix").unwrap();
+        write_strings(&matrix, "barcodes", &["AAAC-1", "AAAG-1"]);
+        write_ints::<i32>(&matrix, "data", &[4, 7, 2, 9]);
+        write_ints::<i64>(&matrix, "indices", &[0, 1, 1, 2]);
+        write_ints::<i64>(&matrix, "indptr", &[0, 2, 4]);
+        write_ints::<i32>(&matrix, "shape", &[3, 2]);
+        let features = matrix.create_group("features").unwrap();
+        write_strings(&features, "id", &["ENSG1", "CD3_TotalSeqB", "ENSG2"]);
+        write_strings(&features, "name", &["GENEA", "CD3", "GENEB"]);
+        write_strings(
+            &features,
+            "feature_type",
+            &["Gene Expression", "Antibody Capture", "Gene Expression"],
+        );
+    }
+
+    #[test]
+    fn tenx_h5_loads_gene_expression_rows_into_expr_cache() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("filtered_feature_bc_matrix.h5");
+        write_tenx_h5(&path);
+
+        let mut ctx = Ctx::new(
+            path,
+            tmp.path().join("out"),
+            Mode::Cell,
+            false,
+            None,
+            true,
+            false,
+            false,
+            "0.0.0-test",
+        );
+        Pipeline::new(vec![
+            Box::new(Stage1Input::new()),
+            Box::new(Stage2H5ad::new()),
+            Box::new(Stage3ExprCache::new()),
+        ])
+        .run(&mut ctx)
+        .unwrap();
+
+        assert_eq!(ctx.genes, vec!["GENEA", "GENEB"]);
+        assert_eq!(ctx.cells, vec!["AAAC-1", "AAAG-1"]);
+        assert_eq!(ctx.nnz, 2);
+        assert!(ctx.notes.iter().any(|w| w.contains("Antibody Capture x1")));
+
+        let reader = ctx.expr_reader().unwrap();
+        let genea = reader.gene_entries(0).unwrap().collect::<Vec<_>>();
+        let geneb = reader.gene_entries(1).unwrap().collect::<Vec<_>>();
+        assert_eq!(genea, vec![(0, 4.0)]);
+        assert_eq!(geneb, vec![(1, 9.0)]);
+    }
+}
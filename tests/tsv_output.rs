@@ -29,6 +29,8 @@ fn tsv_per_cell_format() {
         cls: vec![0.3, 0.4],
         erad: vec![0.5, 0.6],
         ribo: vec![0.7, 0.8],
+        ips: vec![0.0; 2],
+        constitutive: vec![0.0; 2],
     });
     ctx.integrated_scores = Some(IntegratedScores {
         capacity_raw: vec![1.1, 1.2],
@@ -37,6 +39,8 @@ fn tsv_per_cell_format() {
         capacity_z: Some(vec![0.0, 0.0]),
         pii_z: Some(vec![0.0, 0.0]),
         pfs_z: Some(vec![0.1, 0.2]),
+        ips_ratio: vec![0.0; 2],
+        ips_z: Some(vec![0.0; 2]),
     });
 
     write_tsv(&path, &ctx).unwrap();
@@ -45,5 +49,8 @@ fn tsv_per_cell_format() {
     assert_eq!(lines.len(), 3);
     assert!(lines[0].starts_with("cell_id\tPCS_raw"));
     assert!(lines[0].contains("\tCCI\t"));
-    assert!(lines[0].ends_with("\tcollapse_risk"));
+    assert!(lines[0].contains("\tRibo_raw\tCapacity_raw\t"));
+    assert!(lines[0].contains("\tPFS_z\tchaperone_core\t"));
+    assert!(lines[0].ends_with("\tcollapse_risk\tcluster\tIPS_raw\tIPS_z\tIPS_ratio"));
+    assert!(lines[1].contains("\tall_cells\t"));
 }
@@ -19,6 +19,9 @@ fn validate_command_ok() {
     write_10x(tmp.path(), features, barcodes, mtx);
 
     let mut cmd = Command::cargo_bin("kira-proteoqc").unwrap();
-    cmd.arg("validate").arg("--input").arg(tmp.path());
+    cmd.current_dir(tmp.path())
+        .arg("validate")
+        .arg("--input")
+        .arg(tmp.path());
     cmd.assert().success();
 }